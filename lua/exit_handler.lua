@@ -6,8 +6,11 @@ local function quit(confirm)
     end
 end
 
-local function detach_handler(is_remote)
-    if is_remote then
+local function detach_handler(is_remote, read_only)
+    -- An observer can't answer a prompt, and must not quit the session they are watching
+    if read_only then
+        vim.fn.chanclose(vim.g.neovide_channel_id)
+    elseif is_remote then
         local detach = vim.g.neovide_detach_on_quit or "prompt"
         local c
         if detach == "always_quit" then
@@ -1,8 +1,8 @@
 ---@class Args
 ---@field neovide_channel_id integer
 ---@field neovide_version string
----@field register_clipboard boolean
 ---@field register_right_click boolean
+---@field forward_notifications boolean
 ---@field enable_focus_command boolean
 ---@field global_variable_settings string[]
 ---@field option_settings string[]
@@ -13,6 +13,13 @@ local args = ...
 local M = {}
 M.private = {}
 
+-- This file runs again on every reconnect and for :NeovideRefreshState, so whatever can't simply be
+-- overwritten is only set up once, or cleared first
+local previous = _G["neovide"]
+M.private.watched_globals = previous and previous.private.watched_globals or {}
+M.private.notify = previous and previous.private.notify or vim.notify
+local group = vim.api.nvim_create_augroup("neovide", { clear = true })
+
 vim.g.neovide_channel_id = args.neovide_channel_id
 vim.g.neovide_version = args.neovide_version
 
@@ -47,7 +54,11 @@ if vim.fn.has("macunix") then
     end)
 end
 
-if args.register_clipboard and not vim.g.neovide_no_custom_clipboard then
+-- Called by Neovide after this file, since it's retried when it fails
+M.private.register_clipboard = function()
+    if vim.g.neovide_no_custom_clipboard then
+        return
+    end
     vim.g.clipboard = {
         name = "neovide",
         copy = {
@@ -61,7 +72,19 @@ if args.register_clipboard and not vim.g.neovide_no_custom_clipboard then
         cache_enabled = false,
     }
     vim.g.loaded_clipboard_provider = nil
-    vim.cmd.runtime("autoload/provider/clipboard.vim")
+    local ok, err = pcall(vim.cmd.runtime, "autoload/provider/clipboard.vim")
+    if not ok then
+        vim.g.clipboard = nil
+        error(err)
+    end
+end
+
+if args.forward_notifications then
+    local notify = M.private.notify
+    vim.notify = function(msg, level, opts)
+        pcall(rpcnotify, "neovide.notify", tostring(msg), level or vim.log.levels.INFO)
+        return notify(msg, level, opts)
+    end
 end
 
 if args.register_right_click then
@@ -77,6 +100,95 @@ vim.api.nvim_create_user_command("NeovideFocus", function()
     rpcnotify("neovide.focus_window")
 end, {})
 
+vim.api.nvim_create_user_command("NeovideReloadSettings", function()
+    rpcnotify("neovide.reload_settings")
+end, {})
+
+vim.api.nvim_create_user_command("NeovideRefreshState", function()
+    rpcnotify("neovide.refresh_state")
+end, {})
+
+vim.api.nvim_create_user_command("NeovideMultigrid", function(opts)
+    local multigrid = ({ on = true, off = false })[opts.args]
+    if multigrid == nil then
+        vim.notify("Usage: NeovideMultigrid on|off", vim.log.levels.ERROR)
+        return
+    end
+    rpcnotify("neovide.set_multigrid", multigrid)
+end, {
+    nargs = 1,
+    complete = function()
+        return { "on", "off" }
+    end,
+})
+
+vim.api.nvim_create_user_command("NeovideDumpSettings", function(opts)
+    if opts.args == "" then
+        rpcnotify("neovide.dump_settings")
+    else
+        rpcnotify("neovide.dump_settings", vim.fn.fnamemodify(opts.args, ":p"))
+    end
+end, { nargs = "?", complete = "file" })
+
+vim.api.nvim_create_user_command("NeovideSendInput", function(opts)
+    rpcnotify("neovide.send_input", opts.args)
+end, { nargs = 1 })
+
+vim.api.nvim_create_user_command("NeovideReconnectPause", function()
+    rpcnotify("neovide.pause_reconnect")
+end, {})
+
+vim.api.nvim_create_user_command("NeovideReconnectResume", function()
+    rpcnotify("neovide.resume_reconnect")
+end, {})
+
+vim.api.nvim_create_user_command("NeovideReconnectNow", function()
+    rpcnotify("neovide.reconnect_now")
+end, {})
+
+vim.api.nvim_create_user_command("NeovideReconnectNotice", function(opts)
+    rpcnotify("neovide.set_reconnect_notice", opts.args)
+end, { nargs = "?" })
+
+vim.api.nvim_create_user_command("NeovideReconnectState", function()
+    rpcnotify("neovide.reconnect_state")
+end, {})
+
+vim.api.nvim_create_user_command("NeovideSwitchToServer", function()
+    rpcnotify("neovide.switch_to_server")
+end, {})
+
+vim.api.nvim_create_user_command("NeovideSwitchServer", function(opts)
+    rpcnotify("neovide.switch_server", opts.args)
+end, { nargs = 1 })
+
+vim.api.nvim_create_user_command("NeovideConnectionInfo", function()
+    local address = rpcrequest("neovide.get_address")
+    if address == vim.NIL or address == nil then
+        address = "embedded"
+    end
+    local info = string.format(
+        "Connected to %s, channel %d, Neovim %s",
+        address,
+        vim.g.neovide_channel_id,
+        tostring(vim.version())
+    )
+    local last_ping = rpcrequest("neovide.get_last_ping")
+    if last_ping ~= vim.NIL and last_ping ~= nil then
+        info = info .. string.format(", alive %.1fs ago", last_ping / 1000)
+    end
+    vim.api.nvim_echo({ { info } }, true, {})
+end, {})
+
+vim.api.nvim_create_user_command("NeovideResizeGrid", function(opts)
+    local width, height = tonumber(opts.fargs[1]), tonumber(opts.fargs[2])
+    if not width or not height then
+        vim.notify("Usage: NeovideResizeGrid {columns} {lines}", vim.log.levels.ERROR)
+        return
+    end
+    rpcnotify("neovide.resize_grid", width, height)
+end, { nargs = "+" })
+
 vim.api.nvim_exec2(
     [[
 function! WatchGlobal(variable, callback)
@@ -87,14 +199,18 @@ endfunction
 )
 
 for _, global_variable_setting in ipairs(args.global_variable_settings) do
-    local callback = function()
-        rpcnotify("setting_changed", global_variable_setting, vim.g["neovide_" .. global_variable_setting])
+    if not M.private.watched_globals[global_variable_setting] then
+        local callback = function()
+            rpcnotify("setting_changed", global_variable_setting, vim.g["neovide_" .. global_variable_setting])
+        end
+        vim.fn.WatchGlobal("neovide_" .. global_variable_setting, callback)
+        M.private.watched_globals[global_variable_setting] = true
     end
-    vim.fn.WatchGlobal("neovide_" .. global_variable_setting, callback)
 end
 
 for _, option_setting in ipairs(args.option_settings) do
     vim.api.nvim_create_autocmd({ "OptionSet" }, {
+        group = group,
         pattern = option_setting,
         once = false,
         nested = true,
@@ -107,6 +223,7 @@ end
 -- Ignore initial values of lines and columns because they are set by neovim directly.
 -- See https://github.com/neovide/neovide/issues/2300
 vim.api.nvim_create_autocmd({ "VimEnter" }, {
+    group = group,
     once = true,
     nested = true,
     callback = function()
@@ -120,6 +237,7 @@ vim.api.nvim_create_autocmd({ "VimEnter" }, {
 
 -- Create auto command for retrieving exit code from neovim on quit.
 vim.api.nvim_create_autocmd({ "VimLeavePre" }, {
+    group = group,
     pattern = "*",
     once = true,
     nested = true,
@@ -137,6 +255,27 @@ M.private.dropfile = function(filename, tabs)
     }, {})
 end
 
+-- Returns the grid size last requested by Neovide as { columns, lines }, or nil
+M.grid_size = function()
+    return rpcrequest("neovide.get_grid_size")
+end
+
+-- Returns a description of every UI command Neovide accepts, as a list of
+-- { name, kind, arity, description }
+M.ui_commands = function()
+    return rpcrequest("neovide.get_ui_commands")
+end
+
+-- Returns what the attached Neovim supports as { version, api_level, ui_options }, or nil
+M.capabilities = function()
+    return rpcrequest("neovide.get_capabilities")
+end
+
+-- Quits Neovide with the exit code `code`, 0 by default
+M.quit = function(code)
+    rpcnotify("neovide.quit_neovide", code or 0)
+end
+
 M.disable_redraw = function()
     -- Wrap inside pcall to avoid errors if Neovide disconnects
     pcall(rpcnotify, "neovide.set_redraw", false)
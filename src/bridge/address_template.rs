@@ -0,0 +1,94 @@
+//! Expands the `${NAME}` placeholders of `--server` with the environment variables, again before
+//! every connection attempt, so that a socket whose name changes, for example with the PID of the
+//! server, is picked up by the next reconnect.
+
+use std::env;
+
+use crate::cmd_line::CmdLineSettings;
+
+/// Replaces every `${NAME}` in `address` with `lookup(NAME)`. A placeholder without a value, or
+/// without the closing brace, is kept as it is.
+pub fn expand_address(address: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(address.len());
+    let mut rest = address;
+    while let Some(start) = rest.find("${") {
+        let placeholder = &rest[start..];
+        let Some(end) = placeholder.find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let name = &placeholder[2..end];
+        match lookup(name) {
+            Some(value) => expanded.push_str(&value),
+            None => {
+                log::warn!("${{{name}}} in the server address {address} is not set");
+                expanded.push_str(&placeholder[..=end]);
+            }
+        }
+        rest = &placeholder[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// The `--server` to connect to now, with the placeholders expanded from the current environment.
+pub fn server_address(cmdline_settings: &CmdLineSettings) -> Option<String> {
+    let address = cmdline_settings.server.as_deref()?;
+    Some(expand_address(address, |name| env::var(name).ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use scoped_env::ScopedEnv;
+
+    use super::*;
+    use crate::{bridge::NeovimInstance, settings::Settings};
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "NVIM_PID" => Some("4242".to_owned()),
+            "HOST" => Some("devbox".to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_address() {
+        assert_eq!(
+            expand_address("/tmp/nvim.${NVIM_PID}.sock", lookup),
+            "/tmp/nvim.4242.sock"
+        );
+        assert_eq!(expand_address("${HOST}:${NVIM_PID}", lookup), "devbox:4242");
+        // Literal addresses, unset variables and unclosed placeholders are left alone
+        assert_eq!(expand_address("localhost:6666", lookup), "localhost:6666");
+        assert_eq!(
+            expand_address("/tmp/${UNSET}.sock", lookup),
+            "/tmp/${UNSET}.sock"
+        );
+        assert_eq!(expand_address("/tmp/${HOST", lookup), "/tmp/${HOST");
+        assert_eq!(expand_address("/tmp/$HOST", lookup), "/tmp/$HOST");
+    }
+
+    #[test]
+    fn test_changed_variable_changes_the_next_attempt() {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server = Some("/tmp/nvim.${NEOVIDE_TEST_SERVER_PID}.sock".to_owned());
+        settings.set(&cmdline_settings);
+        let target = || match NeovimInstance::from_settings(&settings) {
+            Ok(NeovimInstance::Server { address, .. }) => address,
+            _ => panic!("Expected a server instance"),
+        };
+
+        let first = {
+            let _env = ScopedEnv::set("NEOVIDE_TEST_SERVER_PID", "100");
+            target()
+        };
+        let second = {
+            let _env = ScopedEnv::set("NEOVIDE_TEST_SERVER_PID", "200");
+            target()
+        };
+        assert_eq!(first, "/tmp/nvim.100.sock");
+        assert_eq!(second, "/tmp/nvim.200.sock");
+    }
+}
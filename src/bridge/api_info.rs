@@ -1,4 +1,4 @@
-use std::{collections::HashSet, fmt, hash::Hash};
+use std::{cmp::Ordering, collections::HashSet, fmt, hash::Hash};
 
 use itertools::Itertools;
 use rmpv::{Utf8StringRef, Value, ValueRef};
@@ -16,7 +16,7 @@ impl fmt::Display for ApiInfoParseError {
 
 impl From<ValueRef<'_>> for ApiInfoParseError {
     fn from(value: ValueRef) -> Self {
-        Self(format!("{value}"))
+        Self(format!("unexpected value {value}"))
     }
 }
 
@@ -27,7 +27,7 @@ impl From<&str> for ApiInfoParseError {
 }
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ApiVersion {
     pub major: u64,
     pub minor: u64,
@@ -39,23 +39,52 @@ pub struct ApiVersion {
 }
 
 impl ApiVersion {
+    /// Whether this is at least `major.minor.patch` by semver precedence, so a prerelease like
+    /// `0.10.0-dev` doesn't satisfy `0.10.0`, while `0.11.0-dev` does.
     #[allow(dead_code)]
     pub fn has_version(&self, major: u64, minor: u64, patch: u64) -> bool {
-        let actual_major = self.major;
-        let actual_minor = self.minor;
-        let actual_patch = self.patch;
-        log::trace!("actual nvim version: {actual_major}.{actual_minor}.{actual_patch}");
+        log::trace!("actual nvim version: {self}");
         log::trace!("expect nvim version: {major}.{minor}.{patch}");
-        let ret = actual_major > major
-            || (actual_major == major && actual_minor > minor)
-            || (actual_major == major && actual_minor == minor && actual_patch >= patch);
+        let ret = match (self.major, self.minor, self.patch).cmp(&(major, minor, patch)) {
+            Ordering::Greater => true,
+            Ordering::Equal => !self.prerelease,
+            Ordering::Less => false,
+        };
         log::trace!("has desired nvim version: {ret}");
         ret
     }
 }
 
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if self.prerelease {
+            write!(f, "-dev")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a version like the `build` field, for example `v0.11.0-dev-1234+gabcdef`, into its
+/// major, minor and patch numbers and whether it's a prerelease. Build metadata after a `+` alone
+/// doesn't make it a prerelease.
+fn parse_version_string(version: &str) -> Option<(u64, u64, u64, bool)> {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let (core, suffix) = match version.find(['-', '+']) {
+        Some(index) => version.split_at(index),
+        None => (version, ""),
+    };
+    let mut numbers = core.split('.').map(|number| number.parse().ok());
+    let (major, minor, patch) = (numbers.next()??, numbers.next()??, numbers.next()??);
+    if numbers.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch, suffix.starts_with('-')))
+}
+
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ApiFunction {
     pub name: String,
     pub parameters: Vec<ApiParameter>,
@@ -80,7 +109,7 @@ impl PartialEq for ApiFunction {
 impl Eq for ApiFunction {}
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ApiParameterType {
     Nil,
     Boolean,
@@ -117,8 +146,10 @@ impl ApiParameterType {
             Some("LuaRef") => ApiParameterType::LuaRef,
             Some("void") => ApiParameterType::Void,
             Some(unknown) => {
-                if let Some(array_of) = unknown.strip_prefix("ArrayOf(") {
-                    let array_of = array_of.strip_suffix(')').unwrap();
+                let array_of = unknown
+                    .strip_prefix("ArrayOf(")
+                    .and_then(|array_of| array_of.strip_suffix(')'));
+                if let Some(array_of) = array_of {
                     let mut parts = array_of.split(',');
                     if let Some(name) = parts.next() {
                         let name = Box::new(Self::new(Some(name.trim())));
@@ -138,14 +169,14 @@ impl ApiParameterType {
 }
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ApiParameter {
     pub name: String,
     pub parameter_type: ApiParameterType,
 }
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ApiEvent {
     pub name: String,
     pub parameters: Vec<ApiParameter>,
@@ -167,7 +198,7 @@ impl PartialEq for ApiEvent {
 impl Eq for ApiEvent {}
 
 #[allow(unused)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ApiInformation {
     pub channel: u64,
     pub version: ApiVersion,
@@ -182,6 +213,62 @@ impl ApiInformation {
     pub fn has_event(&self, event_name: &str) -> bool {
         self.ui_events.iter().any(|event| event.name == event_name)
     }
+
+    /// Minimal information of a Neovim 0.11.0 on `channel`
+    #[cfg(test)]
+    pub fn for_test(channel: u64) -> Self {
+        Self {
+            channel,
+            version: ApiVersion {
+                major: 0,
+                minor: 11,
+                patch: 0,
+                prerelease: false,
+                api_level: 13,
+                api_compatible: 0,
+                api_prerelease: false,
+            },
+            functions: HashSet::new(),
+            ui_options: Vec::new(),
+            ui_events: HashSet::new(),
+        }
+    }
+}
+
+/// What the attached Neovim supports, announced through `UserEvent::NeovimCapabilities` after
+/// every attach, and kept in the `ConnectionState` for `neovide.capabilities()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeovimCapabilities {
+    pub version: String,
+    pub api_level: u64,
+    /// The `ext_*` and other options `nvim_ui_attach` accepts
+    pub ui_options: Vec<String>,
+}
+
+impl From<&NeovimCapabilities> for Value {
+    fn from(capabilities: &NeovimCapabilities) -> Self {
+        let ui_options = capabilities
+            .ui_options
+            .iter()
+            .map(|option| Value::from(option.as_str()))
+            .collect();
+        Value::Map(vec![
+            ("version".into(), capabilities.version.as_str().into()),
+            ("api_level".into(), capabilities.api_level.into()),
+            ("ui_options".into(), Value::Array(ui_options)),
+        ])
+    }
+}
+
+impl From<&ApiInformation> for NeovimCapabilities {
+    fn from(api_information: &ApiInformation) -> Self {
+        let version = &api_information.version;
+        Self {
+            version: version.to_string(),
+            api_level: version.api_level,
+            ui_options: api_information.ui_options.clone(),
+        }
+    }
 }
 
 fn parse_version(value: ValueRef) -> std::result::Result<ApiVersion, ApiInfoParseError> {
@@ -192,6 +279,7 @@ fn parse_version(value: ValueRef) -> std::result::Result<ApiVersion, ApiInfoPars
     let mut api_level = None;
     let mut api_compatible = None;
     let mut api_prerelase = None;
+    let mut build = None;
 
     let version: Vec<(ValueRef, ValueRef)> = value.try_into()?;
     for (k, v) in version {
@@ -203,17 +291,25 @@ fn parse_version(value: ValueRef) -> std::result::Result<ApiVersion, ApiInfoPars
             Some("prerelease") => prerelease = Some(v.try_into()?),
             Some("api_level") => api_level = Some(v.try_into()?),
             Some("api_compatible") => api_compatible = Some(v.try_into()?),
+            Some("build") => {
+                let v: Utf8StringRef = v.try_into()?;
+                build = v.as_str().and_then(parse_version_string);
+            }
             // api_prerelease should be a boolean value, but Neovim 0.10.0 sets it to nil for some reason, so assume nil means release
             Some("api_prerelease") => api_prerelase = Some(!v.to_owned().is_nil() && v.try_into()?),
             _ => {}
         }
     }
 
+    // Some builds only tell that they are a prerelease through the build string
+    let prerelease: bool = prerelease.ok_or("prerelease field is isssing")?;
+    let build_prerelease = build.is_some_and(|(.., prerelease)| prerelease);
+
     Ok(ApiVersion {
         major: major.ok_or("major field is missing")?,
         minor: minor.ok_or("minor field is missing")?,
         patch: patch.ok_or("patch field is missing")?,
-        prerelease: prerelease.ok_or("prerelease field is isssing")?,
+        prerelease: prerelease || build_prerelease,
         api_level: api_level.ok_or("api_level field is missing")?,
         api_compatible: api_compatible.ok_or("api_compatible field is missing")?,
         api_prerelease: api_prerelase.ok_or("api_prerelease field is missing")?,
@@ -343,10 +439,19 @@ fn parse_ui_events(value: ValueRef) -> std::result::Result<HashSet<ApiEvent>, Ap
         .collect::<std::result::Result<HashSet<_>, _>>()
 }
 
+/// Parses the `[channel, metadata]` returned by `nvim_get_api_info`. Since the response comes from
+/// another process, parsing is total: anything malformed is returned as an error, and never
+/// panics.
 pub fn parse_api_info(value: &[Value]) -> std::result::Result<ApiInformation, ApiInfoParseError> {
-    let channel = value[0].as_ref().try_into()?;
+    let [channel, metadata] = value else {
+        return Err(ApiInfoParseError(format!(
+            "expected the channel and the metadata, got {} values",
+            value.len()
+        )));
+    };
+    let channel = channel.as_ref().try_into()?;
 
-    let metadata: Vec<(ValueRef, ValueRef)> = value[1].as_ref().try_into()?;
+    let metadata: Vec<(ValueRef, ValueRef)> = metadata.as_ref().try_into()?;
 
     let mut version = None;
     let mut functions = None;
@@ -372,3 +477,250 @@ pub fn parse_api_info(value: &[Value]) -> std::result::Result<ApiInformation, Ap
         ui_events: ui_events.ok_or("ui_events field is missing")?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::window::UserEvent;
+
+    fn sample_api_info() -> Vec<Value> {
+        let version = Value::Map(vec![
+            ("major".into(), 0.into()),
+            ("minor".into(), 11.into()),
+            ("patch".into(), 2.into()),
+            ("prerelease".into(), false.into()),
+            ("api_level".into(), 13.into()),
+            ("api_compatible".into(), 0.into()),
+            ("api_prerelease".into(), false.into()),
+        ]);
+        let ui_options = ["rgb", "ext_multigrid", "ext_messages"]
+            .map(Value::from)
+            .to_vec();
+        vec![
+            3.into(),
+            Value::Map(vec![
+                ("version".into(), version),
+                ("functions".into(), Value::Array(Vec::new())),
+                ("ui_options".into(), Value::Array(ui_options)),
+                ("ui_events".into(), Value::Array(Vec::new())),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn test_capabilities_event_from_api_info() {
+        let api_information = parse_api_info(&sample_api_info()).unwrap();
+        let event = UserEvent::NeovimCapabilities(NeovimCapabilities::from(&api_information));
+
+        let UserEvent::NeovimCapabilities(capabilities) = event else {
+            panic!("unexpected event {event:?}");
+        };
+        assert_eq!(capabilities.version, "0.11.2");
+        assert_eq!(capabilities.api_level, 13);
+        assert_eq!(
+            capabilities.ui_options,
+            ["rgb", "ext_multigrid", "ext_messages"]
+        );
+
+        // What `neovide.capabilities()` returns
+        assert_eq!(
+            Value::from(&capabilities),
+            Value::Map(vec![
+                ("version".into(), "0.11.2".into()),
+                ("api_level".into(), 13.into()),
+                (
+                    "ui_options".into(),
+                    Value::Array(vec![
+                        "rgb".into(),
+                        "ext_multigrid".into(),
+                        "ext_messages".into()
+                    ])
+                ),
+            ])
+        );
+    }
+
+    fn version(major: u64, minor: u64, patch: u64, prerelease: bool) -> ApiVersion {
+        ApiVersion {
+            major,
+            minor,
+            patch,
+            prerelease,
+            ..ApiInformation::for_test(1).version
+        }
+    }
+
+    #[test]
+    fn test_prerelease_precedes_release() {
+        assert!(!version(0, 10, 0, true).has_version(0, 10, 0));
+        assert!(version(0, 10, 0, false).has_version(0, 10, 0));
+        assert!(version(0, 11, 0, true).has_version(0, 10, 0));
+        assert!(version(0, 10, 1, true).has_version(0, 10, 0));
+        assert!(!version(0, 9, 5, false).has_version(0, 10, 0));
+        assert!(version(1, 0, 0, true).has_version(0, 10, 0));
+        assert_eq!(version(0, 11, 0, true).to_string(), "0.11.0-dev");
+    }
+
+    #[test]
+    fn test_parse_nightly_version_strings() {
+        assert_eq!(
+            parse_version_string("v0.11.0-dev-1789+g1a2b3c4d5e"),
+            Some((0, 11, 0, true))
+        );
+        assert_eq!(parse_version_string("0.10.0-dev"), Some((0, 10, 0, true)));
+        assert_eq!(parse_version_string("v0.10.0"), Some((0, 10, 0, false)));
+        assert_eq!(
+            parse_version_string("v0.10.4+g9a8b7c"),
+            Some((0, 10, 4, false))
+        );
+        assert_eq!(parse_version_string("v0.10"), None);
+        assert_eq!(parse_version_string("nightly"), None);
+    }
+
+    #[test]
+    fn test_build_string_marks_prerelease() {
+        let version = Value::Map(vec![
+            ("major".into(), 0.into()),
+            ("minor".into(), 10.into()),
+            ("patch".into(), 0.into()),
+            ("prerelease".into(), false.into()),
+            ("api_level".into(), 12.into()),
+            ("api_compatible".into(), 0.into()),
+            ("api_prerelease".into(), Value::Nil),
+            ("build".into(), "v0.10.0-dev-2980+gd9afb2e".into()),
+        ]);
+        let version = parse_version(version.as_ref()).unwrap();
+        assert!(version.prerelease);
+        assert!(!version.has_version(0, 10, 0));
+    }
+
+    fn random_value(rng: &mut StdRng, depth: u32) -> Value {
+        const KEYS: [&str; 12] = [
+            "version",
+            "functions",
+            "ui_options",
+            "ui_events",
+            "major",
+            "name",
+            "parameters",
+            "since",
+            "return_type",
+            "method",
+            "build",
+            "prerelease",
+        ];
+        let leaf = depth == 0 || rng.random_bool(0.3);
+        match rng.random_range(0..if leaf { 6 } else { 8 }) {
+            0 => Value::Nil,
+            1 => rng.random::<bool>().into(),
+            2 => rng.random::<i64>().into(),
+            3 => rng.random_range(0..20u64).into(),
+            4 => rng.random::<f64>().into(),
+            5 => [
+                "ArrayOf(Integer",
+                "ArrayOf(",
+                "Integer",
+                "",
+                "v0.11.0-dev",
+                "ext_multigrid",
+            ][rng.random_range(0..6)]
+            .into(),
+            6 => Value::Array(
+                (0..rng.random_range(0..4))
+                    .map(|_| random_value(rng, depth - 1))
+                    .collect(),
+            ),
+            _ => Value::Map(
+                (0..rng.random_range(0..5))
+                    .map(|_| {
+                        let key = match rng.random_bool(0.8) {
+                            true => KEYS[rng.random_range(0..KEYS.len())].into(),
+                            false => random_value(rng, 0),
+                        };
+                        (key, random_value(rng, depth - 1))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Replaces a random node of `value`, so that most of a valid payload is kept.
+    fn mutate(rng: &mut StdRng, value: &mut Value) {
+        let children = match value {
+            Value::Array(values) => values.len(),
+            Value::Map(entries) => entries.len(),
+            _ => 0,
+        };
+        if children == 0 || rng.random_bool(0.2) {
+            *value = random_value(rng, 3);
+            return;
+        }
+        let index = rng.random_range(0..children);
+        match value {
+            Value::Array(values) => mutate(rng, &mut values[index]),
+            Value::Map(entries) => mutate(rng, &mut entries[index].1),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_known_bad_payloads_are_errors() {
+        let sample = sample_api_info();
+        let with_version =
+            |version: Value| vec![1.into(), Value::Map(vec![("version".into(), version)])];
+        let function = |parameter_type: &str| {
+            Value::Map(vec![
+                ("name".into(), "nvim_input".into()),
+                (
+                    "parameters".into(),
+                    Value::Array(vec![Value::Array(vec![
+                        parameter_type.into(),
+                        "keys".into(),
+                    ])]),
+                ),
+                ("since".into(), 1.into()),
+            ])
+        };
+        let corpus = vec![
+            vec![],
+            vec![1.into()],
+            vec![1.into(), sample[1].clone(), Value::Nil],
+            vec!["1".into(), sample[1].clone()],
+            vec![(-1).into(), sample[1].clone()],
+            vec![1.into(), Value::Array(Vec::new())],
+            with_version(Value::Array(Vec::new())),
+            with_version(Value::Map(vec![("major".into(), "0".into())])),
+            vec![
+                1.into(),
+                Value::Map(vec![(
+                    "functions".into(),
+                    Value::Array(vec![function("ArrayOf(Integer")]),
+                )]),
+            ],
+            vec![1.into(), Value::Map(vec![(Value::Nil, Value::Nil)])],
+        ];
+        for payload in corpus {
+            assert!(parse_api_info(&payload).is_err(), "{payload:?} was parsed");
+        }
+    }
+
+    #[test]
+    fn test_parsing_never_panics() {
+        let mut rng = StdRng::seed_from_u64(0x6e76);
+        for _ in 0..5000 {
+            let mut payload = sample_api_info();
+            if rng.random_bool(0.5) {
+                let index = rng.random_range(0..payload.len());
+                mutate(&mut rng, &mut payload[index]);
+            } else {
+                payload = (0..rng.random_range(0..4))
+                    .map(|_| random_value(&mut rng, 4))
+                    .collect();
+            }
+            // Only checks that it returns, either way
+            let _ = parse_api_info(&payload);
+        }
+    }
+}
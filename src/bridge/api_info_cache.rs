@@ -0,0 +1,64 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::api_info::ApiInformation;
+
+/// The last successfully checked `ApiInformation` of each server address, so that a quick
+/// reconnect can skip the full version check.
+#[derive(Default)]
+pub struct ApiInfoCache {
+    entries: HashMap<String, (ApiInformation, Instant)>,
+}
+
+impl ApiInfoCache {
+    pub fn insert(&mut self, address: &str, api_information: ApiInformation, now: Instant) {
+        self.entries
+            .insert(address.to_owned(), (api_information, now));
+    }
+
+    /// Returns the cached information when it was checked less than `ttl` ago.
+    pub fn get(&self, address: &str, now: Instant, ttl: Duration) -> Option<&ApiInformation> {
+        self.entries
+            .get(address)
+            .filter(|(_, checked_at)| now.duration_since(*checked_at) < ttl)
+            .map(|(api_information, _)| api_information)
+    }
+}
+
+pub static API_INFO_CACHE: Lazy<Mutex<ApiInfoCache>> =
+    Lazy::new(|| Mutex::new(ApiInfoCache::default()));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_information(channel: u64) -> ApiInformation {
+        ApiInformation::for_test(channel)
+    }
+
+    #[test]
+    fn test_cached_information_is_used_within_ttl() {
+        let start = Instant::now();
+        let ttl = Duration::from_secs(60);
+        let mut cache = ApiInfoCache::default();
+        cache.insert("localhost:6666", api_information(3), start);
+
+        let cached = cache.get("localhost:6666", start + Duration::from_secs(59), ttl);
+        assert_eq!(cached.map(|info| info.channel), Some(3));
+        assert!(cache.get("localhost:6666", start + ttl, ttl).is_none());
+        assert!(cache.get("localhost:7777", start, ttl).is_none());
+    }
+
+    #[test]
+    fn test_zero_ttl_disables_the_cache() {
+        let start = Instant::now();
+        let mut cache = ApiInfoCache::default();
+        cache.insert("localhost:6666", api_information(3), start);
+        assert!(cache.get("localhost:6666", start, Duration::ZERO).is_none());
+    }
+}
@@ -0,0 +1,214 @@
+//! The UI attach options, which `g:neovide_reconnect_attach_options` can override per `--server`
+//! address, for example to turn multigrid off for a server behind a slow link only.
+
+use std::collections::BTreeMap;
+
+use log::error;
+use nvim_rs::UiAttachOptions;
+use rmpv::Value;
+
+use crate::{
+    cmd_line::CmdLineSettings,
+    settings::{ParseFromValue, Settings},
+};
+
+use super::ReconnectSettings;
+
+/// The options of one address, `None` keeping the global default.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttachOverrides {
+    pub multigrid: Option<bool>,
+}
+
+impl AttachOverrides {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let Value::Map(options) = value else {
+            return Err(format!("expected a table of options, got {value}"));
+        };
+        let mut overrides = AttachOverrides::default();
+        for (name, value) in options {
+            match (name.as_str(), value.as_bool()) {
+                (Some("multigrid"), Some(multigrid)) => overrides.multigrid = Some(multigrid),
+                (Some("multigrid"), None) => {
+                    return Err(format!("multigrid expects a bool, got {value}"))
+                }
+                _ => return Err(format!("unknown attach option {name}")),
+            }
+        }
+        Ok(overrides)
+    }
+}
+
+/// The overrides keyed by the address, as it's written for `--server`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttachOptionsByAddress(pub BTreeMap<String, AttachOverrides>);
+
+impl ParseFromValue for AttachOptionsByAddress {
+    fn parse_from_value(&mut self, value: Value) {
+        let entries = match value {
+            Value::Map(entries) => entries,
+            // An empty Lua table is sent as a list
+            Value::Array(entries) if entries.is_empty() => Vec::new(),
+            _ => {
+                error!("Setting expected a table keyed by address, but received {value:?}");
+                return;
+            }
+        };
+        let mut by_address = BTreeMap::new();
+        for (address, options) in &entries {
+            let Some(address) = address.as_str() else {
+                error!("Setting expected an address as the key, but received {address:?}");
+                return;
+            };
+            match AttachOverrides::from_value(options) {
+                Ok(overrides) => {
+                    by_address.insert(address.to_owned(), overrides);
+                }
+                Err(message) => {
+                    error!("Invalid attach options for {address}: {message}");
+                    return;
+                }
+            }
+        }
+        self.0 = by_address;
+    }
+}
+
+impl From<AttachOptionsByAddress> for Value {
+    fn from(by_address: AttachOptionsByAddress) -> Self {
+        Value::Map(
+            by_address
+                .0
+                .into_iter()
+                .map(|(address, overrides)| {
+                    let options = overrides
+                        .multigrid
+                        .map(|multigrid| ("multigrid".into(), multigrid.into()))
+                        .into_iter()
+                        .collect();
+                    (address.into(), Value::Map(options))
+                })
+                .collect(),
+        )
+    }
+}
+
+pub fn ui_attach_options(multigrid: bool) -> UiAttachOptions {
+    let mut options = UiAttachOptions::new();
+    options.set_linegrid_external(true);
+    options.set_multigrid_external(multigrid);
+    options.set_rgb(true);
+    options
+}
+
+/// Whether to attach with multigrid to `address`, `None` for an embedded Neovim. Without an
+/// override for the address `--no-multigrid` decides.
+pub fn multigrid_for(settings: &Settings, address: Option<&str>) -> bool {
+    let by_address = settings.get::<ReconnectSettings>().attach_options;
+    address
+        .and_then(|address| by_address.0.get(address))
+        .and_then(|overrides| overrides.multigrid)
+        .unwrap_or(!settings.get::<CmdLineSettings>().no_multi_grid)
+}
+
+/// The attach options for `address`, `None` for an embedded Neovim.
+pub fn attach_options_for(settings: &Settings, address: Option<&str>) -> UiAttachOptions {
+    ui_attach_options(multigrid_for(settings, address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::recording_nvim::recording_nvim;
+
+    fn options_value(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(address, options)| (address.into(), options))
+                .collect(),
+        )
+    }
+
+    fn multigrid(multigrid: bool) -> Value {
+        Value::Map(vec![("multigrid".into(), multigrid.into())])
+    }
+
+    #[test]
+    fn test_parse_attach_options() {
+        let mut by_address = AttachOptionsByAddress::default();
+        by_address.parse_from_value(options_value(vec![
+            ("localhost:6666", multigrid(false)),
+            ("/tmp/nvim.sock", Value::Map(Vec::new())),
+        ]));
+        assert_eq!(
+            by_address.0.get("localhost:6666"),
+            Some(&AttachOverrides {
+                multigrid: Some(false)
+            })
+        );
+        assert_eq!(
+            by_address.0.get("/tmp/nvim.sock"),
+            Some(&AttachOverrides::default())
+        );
+        // Round trips through the value that the dump writes
+        let mut reloaded = AttachOptionsByAddress::default();
+        reloaded.parse_from_value(by_address.clone().into());
+        assert_eq!(reloaded, by_address);
+
+        // Invalid options keep the previous value
+        for invalid in [
+            Value::from("localhost:6666"),
+            options_value(vec![("localhost:6666", Value::from(true))]),
+            options_value(vec![(
+                "localhost:6666",
+                Value::Map(vec![("ext_popupmenu".into(), true.into())]),
+            )]),
+        ] {
+            let mut parsed = by_address.clone();
+            parsed.parse_from_value(invalid);
+            assert_eq!(parsed, by_address);
+        }
+
+        by_address.parse_from_value(Value::Array(Vec::new()));
+        assert!(by_address.0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_two_addresses_attach_with_different_options() {
+        let settings = Settings::new();
+        settings.register::<ReconnectSettings>();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.no_multi_grid = true;
+        settings.set(&cmdline_settings);
+        let mut reconnect_settings = settings.get::<ReconnectSettings>();
+        reconnect_settings
+            .attach_options
+            .parse_from_value(options_value(vec![
+                ("slow:6666", multigrid(false)),
+                ("fast:6666", multigrid(true)),
+            ]));
+        settings.set(&reconnect_settings);
+
+        let mut attached = Vec::new();
+        for address in ["slow:6666", "fast:6666", "other:6666"] {
+            let (nvim, requests) = recording_nvim();
+            let options = attach_options_for(&settings, Some(address));
+            nvim.ui_attach(80, 24, &options).await.unwrap();
+
+            let requests = requests.lock();
+            let (method, args) = &requests[0];
+            assert_eq!(method, "nvim_ui_attach");
+            let multigrid = args.as_array().unwrap()[2]
+                .as_map()
+                .unwrap()
+                .iter()
+                .find(|(key, _)| key.as_str() == Some("ext_multigrid"))
+                .and_then(|(_, value)| value.as_bool());
+            attached.push(multigrid);
+        }
+        // The address without overrides falls back to --no-multigrid
+        assert_eq!(attached, vec![Some(false), Some(true), Some(false)]);
+        assert!(!multigrid_for(&settings, None));
+    }
+}
@@ -1,8 +1,13 @@
-use std::error::Error;
+use std::{error::Error, future::Future, time::Duration};
 
+use nvim_rs::Neovim;
 use rmpv::Value;
 
-use crate::clipboard;
+use crate::{bridge::NeovimWriter, clipboard};
+
+/// How often registering the clipboard provider is retried before giving up on the clipboard.
+const CLIPBOARD_SETUP_RETRIES: u32 = 2;
+const CLIPBOARD_SETUP_DELAY: Duration = Duration::from_millis(200);
 
 pub fn get_clipboard_contents(register: &Value) -> Result<Value, Box<dyn Error + Send + Sync>> {
     let register = register.as_str().unwrap_or("+");
@@ -49,3 +54,89 @@ pub fn set_clipboard_contents(
 
     Ok(Value::Nil)
 }
+
+async fn register_clipboard_provider(nvim: &Neovim<NeovimWriter>) -> anyhow::Result<()> {
+    nvim.exec_lua("neovide.private.register_clipboard()", vec![])
+        .await?;
+    Ok(())
+}
+
+/// Calls `register` until it succeeds, at most `retries` more times. Returns whether the clipboard
+/// is available, a failure disables it instead of failing the launch.
+async fn register_with_retries<F, Fut>(mut register: F, retries: u32, delay: Duration) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let mut attempt = 0;
+    loop {
+        match register().await {
+            Ok(()) => return true,
+            Err(error) if attempt < retries => {
+                attempt += 1;
+                log::debug!("Registering the clipboard provider failed, retrying: {error:#}");
+                tokio::time::sleep(delay).await;
+            }
+            Err(error) => {
+                log::warn!("Clipboard disabled, registering the provider failed: {error:#}");
+                return false;
+            }
+        }
+    }
+}
+
+/// Registers Neovide as the clipboard provider of `nvim`, returns whether that worked.
+pub async fn setup_clipboard(nvim: &Neovim<NeovimWriter>) -> bool {
+    register_with_retries(
+        || register_clipboard_provider(nvim),
+        CLIPBOARD_SETUP_RETRIES,
+        CLIPBOARD_SETUP_DELAY,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_flaky_provider_setup_eventually_succeeds() {
+        let calls = Cell::new(0);
+        let registered = register_with_retries(
+            || {
+                calls.set(calls.get() + 1);
+                let result = if calls.get() < 3 {
+                    Err(anyhow!("provider not ready"))
+                } else {
+                    Ok(())
+                };
+                async move { result }
+            },
+            2,
+            Duration::ZERO,
+        )
+        .await;
+        assert!(registered);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failing_provider_setup_disables_the_clipboard() {
+        let calls = Cell::new(0);
+        let registered = register_with_retries(
+            || {
+                calls.set(calls.get() + 1);
+                async { Err(anyhow!("no provider")) }
+            },
+            2,
+            Duration::ZERO,
+        )
+        .await;
+        assert!(!registered);
+        assert_eq!(calls.get(), 3);
+    }
+}
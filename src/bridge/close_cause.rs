@@ -0,0 +1,116 @@
+//! Tells a server that closed the connection on purpose, for example because it quit, from a
+//! connection that broke, so that the former isn't reported as a crash.
+
+use nvim_rs::error::{DecodeError, LoopError};
+use tokio::task::JoinError;
+
+/// Why the connection to a server ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseCause {
+    /// The server closed the stream
+    Clean,
+    /// Reading failed, a ping timed out or the network changed
+    Dropped,
+    /// Neovide disconnected after the `--idle-timeout`
+    Idle,
+    /// The stream didn't frame into RPC messages anymore, for example because a message was cut
+    /// off, so the rest of it can't be trusted
+    Desync,
+    /// `:NeovideSwitchServer` moved the connection to another server
+    Switched,
+    /// The window is gone, so Neovide is quitting
+    Quit,
+}
+
+/// Whether `error` came from the bytes that were read, rather than from reading them.
+fn is_desync(error: &LoopError) -> bool {
+    match error {
+        LoopError::DecodeError(error, _) => !matches!(**error, DecodeError::ReaderError(_)),
+        LoopError::MsgidNotFound(_) => true,
+        LoopError::InternalSendResponseError(..) => false,
+    }
+}
+
+impl CloseCause {
+    /// Classifies the result of the I/O task. The stream ending where a message could start is a
+    /// clean close, bytes that aren't a message, or a response to no request, a desync, and an
+    /// aborted task or any other error a dropped connection.
+    pub fn of(io_result: &Result<Result<(), Box<LoopError>>, JoinError>) -> Self {
+        match io_result {
+            Ok(Ok(())) => CloseCause::Clean,
+            Ok(Err(error)) if error.is_channel_closed() => CloseCause::Clean,
+            Ok(Err(error)) if is_desync(error) => CloseCause::Desync,
+            _ => CloseCause::Dropped,
+        }
+    }
+
+    /// Whether to reconnect after the connection ended, `--no-reconnect-on-clean-close` quits
+    /// when the server shut down instead.
+    pub fn reconnects(self, no_reconnect_on_clean_close: bool) -> bool {
+        match self {
+            CloseCause::Quit => false,
+            CloseCause::Clean => !no_reconnect_on_clean_close,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, ErrorKind},
+        sync::Arc,
+    };
+
+    use nvim_rs::error::InvalidMessage;
+
+    use super::*;
+
+    fn read_error(kind: ErrorKind) -> Box<LoopError> {
+        Box::new(LoopError::DecodeError(
+            Arc::new(DecodeError::ReaderError(io::Error::from(kind))),
+            None,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_clean_close_and_abrupt_drop() {
+        assert_eq!(CloseCause::of(&Ok(Ok(()))), CloseCause::Clean);
+        assert_eq!(
+            CloseCause::of(&Ok(Err(read_error(ErrorKind::UnexpectedEof)))),
+            CloseCause::Clean
+        );
+        assert_eq!(
+            CloseCause::of(&Ok(Err(read_error(ErrorKind::ConnectionReset)))),
+            CloseCause::Dropped
+        );
+
+        let invalid = Box::new(LoopError::DecodeError(
+            Arc::new(DecodeError::InvalidMessage(
+                InvalidMessage::UnknownMessageType(7),
+            )),
+            None,
+        ));
+        assert_eq!(CloseCause::of(&Ok(Err(invalid))), CloseCause::Desync);
+        assert_eq!(
+            CloseCause::of(&Ok(Err(Box::new(LoopError::MsgidNotFound(3))))),
+            CloseCause::Desync
+        );
+
+        // A ping timeout or network change aborts the I/O task
+        let io_handle = tokio::spawn(std::future::pending::<Result<(), Box<LoopError>>>());
+        io_handle.abort();
+        assert_eq!(CloseCause::of(&io_handle.await), CloseCause::Dropped);
+    }
+
+    #[test]
+    fn test_only_a_clean_close_can_stop_reconnecting() {
+        assert!(CloseCause::Clean.reconnects(false));
+        assert!(!CloseCause::Clean.reconnects(true));
+        assert!(CloseCause::Dropped.reconnects(false));
+        assert!(CloseCause::Dropped.reconnects(true));
+        assert!(CloseCause::Idle.reconnects(true));
+        assert!(CloseCause::Desync.reconnects(true));
+        assert!(!CloseCause::Quit.reconnects(false));
+    }
+}
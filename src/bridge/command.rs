@@ -1,16 +1,194 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
 use tokio::process::Command as TokioCommand;
 
+use super::safe_profile::{safe_profile_args, safe_profile_dir, safe_profile_env};
 use crate::{cmd_line::CmdLineSettings, settings::*};
 
-pub fn create_nvim_command(settings: &Settings) -> TokioCommand {
-    let bin = settings
-        .get::<CmdLineSettings>()
+/// What identifies the contents of a file without reading them. Unlike the modification time, the
+/// change time can't be set back by the owner of the file, so a binary that was replaced while
+/// keeping its old modification time is still hashed again.
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Fingerprint {
+    device: u64,
+    inode: u64,
+    len: u64,
+    changed: (i64, i64),
+}
+
+#[cfg(unix)]
+fn fingerprint(metadata: &Metadata) -> Option<Fingerprint> {
+    use std::os::unix::fs::MetadataExt;
+    Some(Fingerprint {
+        device: metadata.dev(),
+        inode: metadata.ino(),
+        len: metadata.len(),
+        changed: (metadata.ctime(), metadata.ctime_nsec()),
+    })
+}
+
+/// Every timestamp of a file can be set on Windows, so nothing is cached there.
+#[cfg(not(unix))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Fingerprint;
+
+#[cfg(not(unix))]
+fn fingerprint(_metadata: &Metadata) -> Option<Fingerprint> {
+    None
+}
+
+/// The hashes of the binaries verified so far, which are only computed again when the file has
+/// changed since.
+static BINARY_HASHES: Lazy<Mutex<HashMap<PathBuf, (Fingerprint, String)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn nvim_bin(cmdline_settings: &CmdLineSettings) -> String {
+    cmdline_settings
         .neovim_bin
-        .unwrap_or("nvim".to_owned());
+        .clone()
+        .unwrap_or("nvim".to_owned())
+}
+
+/// Finds `bin` like the OS does when spawning it, a bare name is looked up in $PATH.
+pub fn resolve_binary(bin: &str) -> Option<PathBuf> {
+    let path = Path::new(bin);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_owned());
+    }
+    let names = if cfg!(windows) {
+        vec![bin.to_owned(), format!("{bin}.exe")]
+    } else {
+        vec![bin.to_owned()]
+    };
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        names
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// The lowercase hex SHA-256 of the file at `path`.
+fn sha256_hex(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    // Retries reads that were interrupted by a signal
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn binary_hash(path: &Path) -> Result<String> {
+    let fingerprint = fingerprint(&fs::metadata(path)?);
+    if let Some(fingerprint) = &fingerprint {
+        if let Some((cached, hash)) = BINARY_HASHES.lock().get(path) {
+            if cached == fingerprint {
+                return Ok(hash.clone());
+            }
+        }
+    }
+    let hash = sha256_hex(path)?;
+    if let Some(fingerprint) = fingerprint {
+        BINARY_HASHES
+            .lock()
+            .insert(path.to_owned(), (fingerprint, hash.clone()));
+    }
+    Ok(hash)
+}
+
+/// Checks the embedded Neovim binary against `--nvim-sha256`, when it's given. Returns the path
+/// of the binary that was verified, with the symlinks resolved, so that it's the one started.
+fn verify_nvim_binary(settings: &Settings) -> Result<Option<PathBuf>> {
+    let cmdline_settings = settings.get::<CmdLineSettings>();
+    let Some(expected) = &cmdline_settings.nvim_sha256 else {
+        return Ok(None);
+    };
+    if cmdline_settings.wsl {
+        bail!("--nvim-sha256 can't verify a Neovim binary that runs in WSL");
+    }
+    let bin = nvim_bin(&cmdline_settings);
+    let path = resolve_binary(&bin)
+        .and_then(|path| fs::canonicalize(path).ok())
+        .with_context(|| format!("Could not find {bin} to verify"))?;
+    let hash = binary_hash(&path)
+        .with_context(|| format!("Could not read {} to verify", path.display()))?;
+    if hash != *expected {
+        bail!(
+            "Refusing to start {}, its SHA-256 {hash} doesn't match --nvim-sha256 {expected}",
+            path.display()
+        );
+    }
+    log::info!("Verified the SHA-256 of {}", path.display());
+    Ok(Some(path))
+}
+
+/// The command for the embedded Neovim, after checking its binary against `--nvim-sha256`.
+pub fn create_nvim_command(settings: &Settings) -> Result<TokioCommand> {
+    let bin = match verify_nvim_binary(settings)? {
+        Some(path) => path
+            .into_os_string()
+            .into_string()
+            .map_err(|path| anyhow::anyhow!("The verified path {path:?} isn't valid UTF-8"))?,
+        None => nvim_bin(&settings.get::<CmdLineSettings>()),
+    };
+    Ok(nvim_command(settings, &bin, safe_profile_dir()))
+}
+
+/// The command for the embedded Neovim binary `bin`, hardened with the profile in `safe_profile`
+/// for `--safe-profile`.
+fn nvim_command(settings: &Settings, bin: &str, safe_profile: Option<&Path>) -> TokioCommand {
+    let cmdline_settings = settings.get::<CmdLineSettings>();
+    let safe_profile = safe_profile.filter(|_| cmdline_settings.safe_profile);
     let mut args = Vec::new();
     args.push("--embed".to_string());
-    args.extend(settings.get::<CmdLineSettings>().neovim_args);
-    create_platform_command(&bin, &args, settings)
+    if let Some(dir) = safe_profile {
+        args.extend(safe_profile_args(dir));
+    }
+    args.extend(cmdline_settings.neovim_args);
+    let mut command = create_platform_command(bin, &args, settings);
+    for key in &cmdline_settings.nvim_env_remove {
+        command.env_remove(key);
+    }
+    command.envs(cmdline_settings.nvim_env);
+    if let Some(dir) = safe_profile {
+        command.envs(safe_profile_env(dir));
+    }
+    if cmdline_settings.nvim_process_group {
+        use_new_process_group(&mut command);
+    }
+    command
+}
+
+/// Starts the process in a process group of its own, so that signals like the Ctrl-C of the
+/// terminal Neovide was started from don't reach it, and Neovide decides when it quits. On Unix
+/// it's a new session, which also leaves the controlling terminal, so that the hangup of a closed
+/// terminal doesn't reach it either.
+#[cfg(unix)]
+fn use_new_process_group(command: &mut TokioCommand) {
+    // SAFETY: setsid is async-signal-safe, and nothing else runs between the fork and the exec
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn use_new_process_group(command: &mut TokioCommand) {
+    use windows::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, CREATE_NO_WINDOW};
+    command.creation_flags(CREATE_NO_WINDOW.0 | CREATE_NEW_PROCESS_GROUP.0);
 }
 
 // Creates a shell command if needed on this platform
@@ -85,3 +263,149 @@ fn create_platform_command(
     result.args(args);
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    #[test]
+    fn test_nvim_env_is_applied_to_command() {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.nvim_env = vec![("TERM".to_string(), "xterm-256color".to_string())];
+        cmdline_settings.nvim_env_remove = vec!["NVIM_APPNAME".to_string()];
+        settings.set(&cmdline_settings);
+
+        let command = create_nvim_command(&settings).unwrap();
+        let envs = command.as_std().get_envs().collect::<Vec<_>>();
+        assert!(envs.contains(&(OsStr::new("TERM"), Some(OsStr::new("xterm-256color")))));
+        assert!(envs.contains(&(OsStr::new("NVIM_APPNAME"), None)));
+    }
+
+    #[test]
+    fn test_safe_profile_hardens_the_command() {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.safe_profile = true;
+        cmdline_settings.neovim_args = vec!["untrusted.txt".to_owned()];
+        settings.set(&cmdline_settings);
+        let dir = Path::new("/tmp/neovide-safe-profile-1");
+
+        let command = nvim_command(&settings, "nvim", Some(dir));
+        let args = command
+            .as_std()
+            .get_args()
+            .collect::<Vec<_>>()
+            .join(OsStr::new(" "));
+        let args = args.to_string_lossy();
+        assert!(
+            args.contains(
+                "--embed -u /tmp/neovide-safe-profile-1/init.vim -i NONE -n untrusted.txt"
+            ),
+            "{args}"
+        );
+        let envs = command.as_std().get_envs().collect::<Vec<_>>();
+        let config = dir.join("config");
+        assert!(envs.contains(&(OsStr::new("XDG_CONFIG_HOME"), Some(config.as_os_str()))));
+        assert!(envs.iter().any(|(key, _)| *key == "XDG_STATE_HOME"));
+
+        // Not hardened without the flag
+        cmdline_settings.safe_profile = false;
+        settings.set(&cmdline_settings);
+        let command = nvim_command(&settings, "nvim", Some(dir));
+        assert!(!command.as_std().get_args().any(|arg| arg == "-i"));
+    }
+
+    fn sha256_settings(bin: &Path, hash: &str) -> Settings {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.neovim_bin = Some(bin.to_string_lossy().into_owned());
+        cmdline_settings.nvim_sha256 = Some(hash.to_owned());
+        settings.set(&cmdline_settings);
+        settings
+    }
+
+    #[test]
+    fn test_mismatched_hash_blocks_launch() {
+        let bin = env::temp_dir().join(format!("neovide-sha256-{}", std::process::id()));
+        fs::write(&bin, b"abc").unwrap();
+        let matching = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let mismatched = "0".repeat(64);
+
+        // Starts the binary that was verified, not the name looked up again
+        let command = create_nvim_command(&sha256_settings(&bin, matching)).unwrap();
+        let verified = fs::canonicalize(&bin).unwrap();
+        assert_eq!(command.as_std().get_program(), verified.as_os_str());
+        let error = create_nvim_command(&sha256_settings(&bin, &mismatched)).unwrap_err();
+        assert!(error.to_string().contains("doesn't match --nvim-sha256"));
+        fs::remove_file(&bin).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replaced_binary_is_hashed_again() {
+        let bin = env::temp_dir().join(format!("neovide-sha256-replaced-{}", std::process::id()));
+        fs::write(&bin, b"abc").unwrap();
+        let matching = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let settings = sha256_settings(&bin, matching);
+        assert!(verify_nvim_binary(&settings).is_ok());
+
+        // Same length, and the modification time set back to what was verified
+        let modified = fs::metadata(&bin).unwrap().modified().unwrap();
+        fs::write(&bin, b"abd").unwrap();
+        File::options()
+            .write(true)
+            .open(&bin)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(verify_nvim_binary(&settings).is_err());
+        fs::remove_file(&bin).ok();
+    }
+
+    #[test]
+    fn test_unset_hash_is_not_verified() {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.neovim_bin = Some("/nonexistent/nvim".to_owned());
+        settings.set(&cmdline_settings);
+        assert_eq!(verify_nvim_binary(&settings).unwrap(), None);
+    }
+
+    /// The pid, and the process group and session the command runs in, as reported by the
+    /// process itself
+    #[cfg(target_os = "linux")]
+    async fn pid_group_and_session(mut command: TokioCommand) -> (String, String, String) {
+        let output = command
+            .args([
+                "-c",
+                "echo $$; cut -d' ' -f5 /proc/$$/stat; cut -d' ' -f6 /proc/$$/stat",
+            ])
+            .output()
+            .await
+            .unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        let mut lines = output.lines().map(str::to_owned);
+        (
+            lines.next().unwrap(),
+            lines.next().unwrap(),
+            lines.next().unwrap(),
+        )
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_child_leads_a_new_process_group() {
+        let mut command = TokioCommand::new("sh");
+        use_new_process_group(&mut command);
+        let (pid, group, session) = pid_group_and_session(command).await;
+        assert_eq!(pid, group);
+        assert_eq!(pid, session);
+
+        let (pid, group, session) = pid_group_and_session(TokioCommand::new("sh")).await;
+        assert_ne!(pid, group);
+        assert_ne!(pid, session);
+    }
+}
@@ -0,0 +1,120 @@
+//! `--connect-warmup`, which keeps the connecting overlay up after attaching until the pings to
+//! the server are fast, since on some links the first requests after connecting are very slow and the
+//! UI would look connected while it's still lagging.
+
+use std::{future::Future, time::Duration};
+
+use tokio::time::timeout;
+
+use crate::{clock::Clock, cmd_line::CmdLineSettings};
+
+/// A ping that takes longer is given up on, and counts as a slow one.
+const WARMUP_PING_TIMEOUT: Duration = Duration::from_secs(2);
+/// The warmup gives up after this many times the pings it needs.
+const WARMUP_ROUNDS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectWarmup {
+    /// A round trip below this is fast enough
+    pub threshold: Duration,
+    /// How many fast pings in a row make the connection stable
+    pub pings: u32,
+}
+
+impl ConnectWarmup {
+    /// The warmup, `None` when it's disabled.
+    pub fn from_settings(cmdline_settings: &CmdLineSettings) -> Option<Self> {
+        (cmdline_settings.connect_warmup > 0).then(|| Self {
+            threshold: Duration::from_millis(cmdline_settings.connect_warmup),
+            pings: cmdline_settings.connect_warmup_pings.max(1),
+        })
+    }
+
+    /// How many pings the warmup sends at most.
+    pub fn max_pings(&self) -> u32 {
+        self.pings.saturating_mul(WARMUP_ROUNDS)
+    }
+
+    /// Pings until `pings` round trips in a row are below the threshold, at most
+    /// [`max_pings`](Self::max_pings) times. Returns whether the connection got fast enough.
+    pub async fn run<F, Fut, T, E>(&self, clock: &dyn Clock, mut ping: F) -> bool
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut fast = 0;
+        for attempt in 1..=self.max_pings() {
+            let sent_at = clock.now();
+            let answered = matches!(timeout(WARMUP_PING_TIMEOUT, ping()).await, Ok(Ok(_)));
+            let rtt = clock.now().saturating_duration_since(sent_at);
+            log::debug!("Warmup ping {attempt}: {}ms", rtt.as_millis());
+            if !answered || rtt >= self.threshold {
+                fast = 0;
+                continue;
+            }
+            fast += 1;
+            if fast == self.pings {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, convert::Infallible};
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    fn warmup() -> ConnectWarmup {
+        ConnectWarmup {
+            threshold: Duration::from_millis(100),
+            pings: 2,
+        }
+    }
+
+    /// Pings that take the next of `rtts`, in milliseconds, on `clock`.
+    async fn run_with_rtts(clock: &MockClock, rtts: &[u64]) -> (bool, usize) {
+        let pings = RefCell::new(0);
+        let stable = warmup()
+            .run(clock, || {
+                let rtt = rtts[*pings.borrow()];
+                *pings.borrow_mut() += 1;
+                async move {
+                    clock.advance(Duration::from_millis(rtt));
+                    Ok::<_, Infallible>(())
+                }
+            })
+            .await;
+        (stable, pings.into_inner())
+    }
+
+    #[tokio::test]
+    async fn test_warmup_waits_for_fast_pings_in_a_row() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        // The single fast ping in between isn't enough
+        let (stable, pings) = run_with_rtts(&clock, &[900, 40, 400, 40, 10, 10]).await;
+        assert!(stable);
+        assert_eq!(pings, 5);
+        assert_eq!(clock.now() - start, Duration::from_millis(1390));
+    }
+
+    #[tokio::test]
+    async fn test_warmup_gives_up_after_the_pings() {
+        let clock = MockClock::new();
+        let (stable, pings) = run_with_rtts(&clock, &[300, 40, 300, 40, 300, 40]).await;
+        assert!(!stable);
+        assert_eq!(pings, 6);
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert_eq!(
+            ConnectWarmup::from_settings(&CmdLineSettings::default()),
+            None
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Mirrors the connection lifecycle on a broadcast channel, so that any number of independent
+//! subscribers, for example a statusline integration and a logger, see the same events. The window
+//! keeps getting its `UserEvent`s as before. The connection log is one of the subscribers.
+//!
+//! The channel keeps the last `CAPACITY` events. A subscriber that falls further behind gets
+//! `RecvError::Lagged` with the number of skipped events from its next `recv`, and then continues
+//! with the oldest event that's still kept. Events sent while nobody is subscribed are dropped.
+
+use once_cell::sync::Lazy;
+use tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+
+use crate::log_ring::CONNECTION_LOG_TARGET;
+
+const CAPACITY: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// A Neovim is being started or connected to
+    Connecting,
+    /// The UI of the session `session_id` is attached
+    Connected { session_id: u64 },
+    /// The session `session_id` got its first complete redraw after `Connected`, and is fully
+    /// usable
+    SessionReady { session_id: u64 },
+    /// The session `session_id` ended, either because Neovim quit or the connection was lost
+    Disconnected { session_id: u64 },
+    /// Waiting for reconnect attempt `attempt` to the `--server`
+    Reconnecting { attempt: u32 },
+}
+
+struct ConnectionEvents(Sender<ConnectionEvent>);
+
+impl ConnectionEvents {
+    fn new() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+
+    fn subscribe(&self) -> Receiver<ConnectionEvent> {
+        self.0.subscribe()
+    }
+
+    fn publish(&self, event: ConnectionEvent) {
+        // Only fails when nobody is subscribed
+        self.0.send(event).ok();
+    }
+}
+
+static CONNECTION_EVENTS: Lazy<ConnectionEvents> = Lazy::new(ConnectionEvents::new);
+
+/// Receives every connection event sent from now on.
+pub fn subscribe() -> Receiver<ConnectionEvent> {
+    CONNECTION_EVENTS.subscribe()
+}
+
+/// Sends `event` to the subscribers.
+pub fn publish(event: ConnectionEvent) {
+    CONNECTION_EVENTS.publish(event);
+}
+
+/// Logs the `events` with the `--instance-label`, until the channel closes.
+pub async fn log_connection_events(mut events: Receiver<ConnectionEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => log::debug!(target: CONNECTION_LOG_TARGET, "Connection event {event:?}"),
+            Err(RecvError::Lagged(skipped)) => {
+                log::debug!(target: CONNECTION_LOG_TARGET, "Skipped {skipped} connection events")
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_every_subscriber_receives_the_sequence() {
+        let events = ConnectionEvents::new();
+        let mut statusline = events.subscribe();
+        let mut logger = events.subscribe();
+        let sequence = [
+            ConnectionEvent::Connecting,
+            ConnectionEvent::Connected { session_id: 1 },
+            ConnectionEvent::Disconnected { session_id: 1 },
+            ConnectionEvent::Reconnecting { attempt: 1 },
+            ConnectionEvent::Connecting,
+            ConnectionEvent::Connected { session_id: 2 },
+        ];
+        for event in sequence.clone() {
+            events.publish(event);
+        }
+
+        for receiver in [&mut statusline, &mut logger] {
+            let mut received = Vec::new();
+            for _ in 0..sequence.len() {
+                received.push(receiver.recv().await.unwrap());
+            }
+            assert_eq!(received, sequence);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_skips_to_the_oldest_kept_event() {
+        let events = ConnectionEvents::new();
+        let mut receiver = events.subscribe();
+        for attempt in 0..CAPACITY as u32 + 2 {
+            events.publish(ConnectionEvent::Reconnecting { attempt });
+        }
+
+        assert!(matches!(receiver.recv().await, Err(RecvError::Lagged(2))));
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            ConnectionEvent::Reconnecting { attempt: 2 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_logger_keeps_going_after_lagging() {
+        let events = ConnectionEvents::new();
+        let logger = events.subscribe();
+        for attempt in 0..CAPACITY as u32 + 2 {
+            events.publish(ConnectionEvent::Reconnecting { attempt });
+        }
+        drop(events);
+
+        timeout(Duration::from_secs(1), log_connection_events(logger))
+            .await
+            .expect("The logger stopped at the lag, or never");
+    }
+}
@@ -0,0 +1,294 @@
+//! `--disconnect-sound` and `--reconnect-sound`, audible cues for watching a `--server` connection
+//! unattended. They follow the connection events, and are played by the sound player of the
+//! system in the background, so that a missing player or a file it can't play is only logged.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    process::Command,
+    sync::broadcast::{error::RecvError, Receiver},
+};
+
+use super::connection_events::ConnectionEvent;
+use crate::cmd_line::CmdLineSettings;
+
+/// How often each sound plays at most, so that a flapping connection doesn't play it every time.
+const MIN_SOUND_INTERVAL: Duration = Duration::from_secs(5);
+
+pub trait SoundPlayer {
+    fn play(&self, path: &Path);
+}
+
+/// Plays with `afplay` on macOS, the media player of PowerShell on Windows, and otherwise with
+/// `paplay` of PulseAudio or PipeWire, or `aplay` of ALSA when that isn't installed.
+pub struct SystemPlayer;
+
+impl SystemPlayer {
+    /// The players to try in order, the next one is only tried when the previous isn't installed.
+    fn commands(path: &Path) -> Vec<Command> {
+        if cfg!(target_os = "macos") {
+            vec![Self::player("afplay", path)]
+        } else if cfg!(windows) {
+            let mut command = Command::new("powershell");
+            let path = path.to_string_lossy().replace('\'', "''");
+            command.args([
+                "-NoProfile",
+                "-Command",
+                &format!("(New-Object Media.SoundPlayer '{path}').PlaySync()"),
+            ]);
+            // Neovide has no console for it to share, so it would open a window of its own
+            #[cfg(windows)]
+            command.creation_flags(windows::Win32::System::Threading::CREATE_NO_WINDOW.0);
+            vec![command]
+        } else {
+            vec![Self::player("paplay", path), Self::player("aplay", path)]
+        }
+    }
+
+    fn player(program: &str, path: &Path) -> Command {
+        let mut command = Command::new(program);
+        command.arg(path);
+        command
+    }
+}
+
+impl SoundPlayer for SystemPlayer {
+    fn play(&self, path: &Path) {
+        let commands = Self::commands(path);
+        let path = path.to_owned();
+        tokio::spawn(async move {
+            for mut command in commands {
+                match command.output().await {
+                    Ok(output) if output.status.success() => {}
+                    Ok(output) => log::warn!(
+                        "Could not play {}: {}",
+                        path.display(),
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ),
+                    Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                    Err(error) => log::warn!("Could not play {}: {error}", path.display()),
+                }
+                return;
+            }
+            log::warn!(
+                "Could not play {}, no sound player is installed",
+                path.display()
+            );
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Sound {
+    Disconnect,
+    Reconnect,
+}
+
+struct Cue {
+    path: PathBuf,
+    last_played: Option<Instant>,
+}
+
+impl Cue {
+    /// `None` without a `path`, or when it isn't a file.
+    fn new(path: Option<&str>, flag: &str) -> Option<Self> {
+        let path = PathBuf::from(path?);
+        if !path.is_file() {
+            log::warn!(
+                "The {flag} {} is not a file, not playing it",
+                path.display()
+            );
+            return None;
+        }
+        Some(Self {
+            path,
+            last_played: None,
+        })
+    }
+
+    fn play(&mut self, now: Instant, player: &impl SoundPlayer) -> bool {
+        if self
+            .last_played
+            .is_some_and(|last| now.saturating_duration_since(last) < MIN_SOUND_INTERVAL)
+        {
+            return false;
+        }
+        self.last_played = Some(now);
+        player.play(&self.path);
+        true
+    }
+}
+
+pub struct ConnectionSounds {
+    disconnect: Option<Cue>,
+    reconnect: Option<Cue>,
+    /// Whether the connection was lost since it was made, only a connect after that is a reconnect
+    disconnected: bool,
+}
+
+impl ConnectionSounds {
+    /// `None` when neither sound is set, or can be played.
+    pub fn from_settings(cmdline_settings: &CmdLineSettings) -> Option<Self> {
+        let disconnect = Cue::new(
+            cmdline_settings.disconnect_sound.as_deref(),
+            "--disconnect-sound",
+        );
+        let reconnect = Cue::new(
+            cmdline_settings.reconnect_sound.as_deref(),
+            "--reconnect-sound",
+        );
+        (disconnect.is_some() || reconnect.is_some()).then_some(Self {
+            disconnect,
+            reconnect,
+            disconnected: false,
+        })
+    }
+
+    /// Plays the sound for `event`, returns which one was played.
+    fn handle(
+        &mut self,
+        event: &ConnectionEvent,
+        now: Instant,
+        player: &impl SoundPlayer,
+    ) -> Option<Sound> {
+        let (sound, cue) = match event {
+            ConnectionEvent::Disconnected { .. } => {
+                self.disconnected = true;
+                (Sound::Disconnect, self.disconnect.as_mut()?)
+            }
+            ConnectionEvent::Connected { .. } if self.disconnected => {
+                self.disconnected = false;
+                (Sound::Reconnect, self.reconnect.as_mut()?)
+            }
+            _ => return None,
+        };
+        cue.play(now, player).then_some(sound)
+    }
+}
+
+/// Plays the sounds for the connection events from `events`, until the channel closes.
+pub async fn play_connection_sounds(
+    mut sounds: ConnectionSounds,
+    mut events: Receiver<ConnectionEvent>,
+    player: impl SoundPlayer,
+) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                sounds.handle(&event, Instant::now(), &player);
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs};
+
+    use super::*;
+
+    /// Records the sounds instead of playing them
+    #[derive(Default)]
+    struct RecordingPlayer(RefCell<Vec<PathBuf>>);
+
+    impl SoundPlayer for RecordingPlayer {
+        fn play(&self, path: &Path) {
+            self.0.borrow_mut().push(path.to_owned());
+        }
+    }
+
+    fn cue(name: &str) -> Cue {
+        Cue {
+            path: PathBuf::from(name),
+            last_played: None,
+        }
+    }
+
+    #[test]
+    fn test_every_transition_plays_its_sound() {
+        let mut sounds = ConnectionSounds {
+            disconnect: Some(cue("down.wav")),
+            reconnect: Some(cue("up.wav")),
+            disconnected: false,
+        };
+        let player = RecordingPlayer::default();
+        let start = Instant::now();
+        let events = [
+            ConnectionEvent::Connecting,
+            ConnectionEvent::Connected { session_id: 1 },
+            ConnectionEvent::Disconnected { session_id: 1 },
+            ConnectionEvent::Reconnecting { attempt: 1 },
+            ConnectionEvent::Connected { session_id: 2 },
+        ];
+        let played: Vec<_> = events
+            .iter()
+            .enumerate()
+            .filter_map(|(i, event)| {
+                sounds.handle(event, start + MIN_SOUND_INTERVAL * i as u32, &player)
+            })
+            .collect();
+
+        // The first connect isn't a reconnect
+        assert_eq!(played, [Sound::Disconnect, Sound::Reconnect]);
+        assert_eq!(
+            *player.0.borrow(),
+            [PathBuf::from("down.wav"), PathBuf::from("up.wav")]
+        );
+    }
+
+    #[test]
+    fn test_flapping_is_rate_limited() {
+        let mut sounds = ConnectionSounds {
+            disconnect: Some(cue("down.wav")),
+            reconnect: None,
+            disconnected: false,
+        };
+        let player = RecordingPlayer::default();
+        let start = Instant::now();
+        let disconnected = ConnectionEvent::Disconnected { session_id: 1 };
+        let reconnected = ConnectionEvent::Connected { session_id: 2 };
+
+        assert_eq!(
+            sounds.handle(&disconnected, start, &player),
+            Some(Sound::Disconnect)
+        );
+        // Without a reconnect sound, nothing plays
+        assert_eq!(sounds.handle(&reconnected, start, &player), None);
+        let soon = start + Duration::from_secs(1);
+        assert_eq!(sounds.handle(&disconnected, soon, &player), None);
+        let later = start + MIN_SOUND_INTERVAL;
+        assert_eq!(
+            sounds.handle(&disconnected, later, &player),
+            Some(Sound::Disconnect)
+        );
+        assert_eq!(player.0.borrow().len(), 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_alsa_is_tried_without_pulseaudio() {
+        let programs: Vec<_> = SystemPlayer::commands(Path::new("down.wav"))
+            .iter()
+            .map(|command| command.as_std().get_program().to_owned())
+            .collect();
+        assert_eq!(programs, ["paplay", "aplay"]);
+    }
+
+    #[test]
+    fn test_missing_file_is_skipped() {
+        assert!(Cue::new(Some("/nonexistent/neovide/down.wav"), "--disconnect-sound").is_none());
+        assert!(Cue::new(None, "--disconnect-sound").is_none());
+
+        let path = std::env::temp_dir().join(format!("neovide-sound-{}.wav", std::process::id()));
+        fs::write(&path, b"RIFF").unwrap();
+        let cue = Cue::new(path.to_str(), "--disconnect-sound");
+        fs::remove_file(&path).ok();
+        assert_eq!(cue.map(|cue| cue.path), Some(path));
+    }
+}
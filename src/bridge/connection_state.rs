@@ -0,0 +1,131 @@
+//! A snapshot of the connection to Neovim, updated by the bridge on every transition and read by
+//! the window, for example to fill in `--title-template`.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use super::NeovimCapabilities;
+use crate::window::{UserEvent, UserEventSender};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    #[default]
+    Connecting,
+    Connected,
+    Reconnecting,
+    Paused,
+    /// Disconnected by the `--idle-timeout` until the next input
+    Idle,
+    /// Running the fallback embedded Neovim while the server is unreachable
+    Fallback,
+}
+
+impl fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            ConnectionStatus::Connecting => "connecting",
+            ConnectionStatus::Connected => "connected",
+            ConnectionStatus::Reconnecting => "reconnecting",
+            ConnectionStatus::Paused => "paused",
+            ConnectionStatus::Idle => "idle",
+            ConnectionStatus::Fallback => "fallback",
+        };
+        f.write_str(status)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionState {
+    pub status: ConnectionStatus,
+    /// The `--server` address, `None` for an embedded Neovim
+    pub address: Option<String>,
+    /// The reconnect attempt that is waited for, 0 while connected
+    pub attempt: u32,
+    /// The backoff before that attempt, `None` while connected
+    pub backoff: Option<Duration>,
+    pub nvim_version: Option<String>,
+    /// The RPC channel of Neovide in the connected Neovim
+    pub channel: Option<u64>,
+    /// The round trip time of the last connection ping
+    pub rtt: Option<Duration>,
+    /// When a connection ping last succeeded, or the connection was made before the first one
+    pub last_ping_ok: Option<Instant>,
+    /// The id of the connected session, see `NeovimSession::session_id`
+    pub session_id: Option<u64>,
+    /// What the Neovim of the last attach supports
+    pub capabilities: Option<NeovimCapabilities>,
+}
+
+impl ConnectionState {
+    /// How long ago the connection was last known to be alive, `None` while not connected. It
+    /// keeps growing while the pings stall, until the ping timeout disconnects.
+    pub fn since_last_ping(&self, now: Instant) -> Option<Duration> {
+        self.last_ping_ok
+            .map(|last_ping_ok| now.saturating_duration_since(last_ping_ok))
+    }
+}
+
+static CONNECTION_STATE: Lazy<RwLock<ConnectionState>> =
+    Lazy::new(|| RwLock::new(ConnectionState::default()));
+
+pub fn connection_state() -> ConnectionState {
+    CONNECTION_STATE.read().clone()
+}
+
+/// The time since the last successful ping of the current connection.
+pub fn time_since_last_ping() -> Option<Duration> {
+    CONNECTION_STATE.read().since_last_ping(Instant::now())
+}
+
+/// Updates the shared state and lets the window know when it changed.
+pub fn update_connection_state(
+    proxy: &impl UserEventSender,
+    update: impl FnOnce(&mut ConnectionState),
+) {
+    let changed = {
+        let mut state = CONNECTION_STATE.write();
+        let previous = state.clone();
+        update(&mut state);
+        *state != previous
+    };
+    if changed {
+        proxy
+            .send_user_event(UserEvent::ConnectionStateChanged)
+            .ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+
+    #[test]
+    fn test_time_since_last_ping() {
+        let clock = MockClock::new();
+        let mut state = ConnectionState::default();
+        assert_eq!(state.since_last_ping(clock.now()), None);
+
+        // The pings every 5s succeed
+        for _ in 0..3 {
+            state.last_ping_ok = Some(clock.now());
+            clock.advance(Duration::from_secs(5));
+            assert_eq!(
+                state.since_last_ping(clock.now()),
+                Some(Duration::from_secs(5))
+            );
+        }
+
+        // Then they stall
+        clock.advance(Duration::from_secs(7));
+        assert_eq!(
+            state.since_last_ping(clock.now()),
+            Some(Duration::from_secs(12))
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Finds the sockets of running Neovim servers for `--server auto`.
+//!
+//! Neovim names its default `--listen` address `nvim.<pid>.<n>`, by default in
+//! `$XDG_RUNTIME_DIR` on Unix and as a named pipe on Windows.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const SOCKET_PREFIX: &str = "nvim.";
+
+/// The `--server` value that discovers the most recently used server.
+pub const AUTO_SERVER: &str = "auto";
+
+/// The directory that is scanned when `--server-discovery-dir` isn't given.
+pub fn default_discovery_dir() -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(r"\\.\pipe\")
+    } else {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+    }
+}
+
+fn is_nvim_socket(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(SOCKET_PREFIX))
+}
+
+/// The most recently modified Neovim socket in `dir`, if there is any.
+pub fn newest_socket(dir: &Path) -> Option<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            log::debug!("Could not scan {} for servers: {error}", dir.display());
+            return None;
+        }
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_nvim_socket(path))
+        .map(|path| {
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (path, modified)
+        })
+        .max_by_key(|(_, modified)| *modified)
+        .map(|(path, _)| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, time::Duration};
+
+    use super::*;
+
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("neovide-discovery-{name}-{}", std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn socket(&self, name: &str, age_secs: u64) -> PathBuf {
+            let path = self.0.join(name);
+            let file = File::create(&path).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+                .unwrap();
+            path
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn test_newest_socket_is_picked() {
+        let dir = TestDir::new("newest");
+        dir.socket("nvim.100.0", 300);
+        let newest = dir.socket("nvim.200.0", 10);
+        dir.socket("nvim.300.0", 60);
+        // Not a Neovim socket, even though it's the newest file
+        dir.socket("other.sock", 0);
+
+        assert_eq!(newest_socket(&dir.0), Some(newest));
+    }
+
+    #[test]
+    fn test_no_socket_found() {
+        let dir = TestDir::new("empty");
+        dir.socket("other.sock", 0);
+        assert_eq!(newest_socket(&dir.0), None);
+        assert_eq!(newest_socket(&dir.0.join("missing")), None);
+    }
+}
@@ -0,0 +1,123 @@
+//! `--server docker://CONTAINER`, which runs an embedded Neovim inside a running Docker container
+//! through `docker exec`. The container is looked up again on every connection attempt, since a
+//! restarted container keeps its name but gets a new id, and a container that isn't running fails
+//! the attempt, so that it's retried with the reconnect backoff.
+
+use std::io::{Error, Result};
+
+use tokio::process::Command;
+
+const DOCKER_SCHEME: &str = "docker://";
+
+/// The name or id of the container in a `docker://` address, `None` for any other address.
+pub fn docker_container(address: &str) -> Option<&str> {
+    address
+        .strip_prefix(DOCKER_SCHEME)
+        .filter(|container| !container.is_empty())
+}
+
+/// A `docker` command that doesn't open a console window, Neovide has none to share with it.
+#[cfg(target_os = "windows")]
+fn docker_command() -> Command {
+    let mut command = Command::new("docker");
+    command.creation_flags(windows::Win32::System::Threading::CREATE_NO_WINDOW.0);
+    command
+}
+
+#[cfg(not(target_os = "windows"))]
+fn docker_command() -> Command {
+    Command::new("docker")
+}
+
+fn inspect_command(container: &str) -> Command {
+    let mut command = docker_command();
+    command
+        .args(["inspect", "--type", "container", "--format"])
+        .arg("{{.Id}} {{.State.Running}}")
+        .arg(container);
+    command
+}
+
+/// The id from the output of `inspect_command`, an error when the container isn't running.
+fn parse_inspect(container: &str, output: &str) -> Result<String> {
+    match output.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [id, "true"] => Ok((*id).to_owned()),
+        [_, "false"] => Err(Error::other(format!(
+            "The Docker container {container} is not running"
+        ))),
+        _ => Err(Error::other(format!(
+            "Unexpected output of docker inspect for {container}: {output:?}"
+        ))),
+    }
+}
+
+/// Looks up the id of the running `container`.
+pub async fn resolve_container(container: &str) -> Result<String> {
+    let output = inspect_command(container).output().await.map_err(|error| {
+        Error::new(
+            error.kind(),
+            format!("Could not run docker to find the container {container}: {error}"),
+        )
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::other(format!(
+            "Could not find the Docker container {container}: {}",
+            stderr.trim()
+        )));
+    }
+    parse_inspect(container, &String::from_utf8_lossy(&output.stdout))
+}
+
+/// The command that runs an embedded Neovim in the container with `id`.
+pub fn docker_exec_command(id: &str) -> Command {
+    let mut command = docker_command();
+    command.args(["exec", "-i", id, "nvim", "--embed"]);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_docker_exec_command() {
+        assert_eq!(docker_container("docker://devbox"), Some("devbox"));
+        assert_eq!(docker_container("docker://"), None);
+        assert_eq!(docker_container("localhost:6666"), None);
+
+        let command = docker_exec_command("3f2a9c");
+        assert_eq!(command.as_std().get_program(), "docker");
+        assert_eq!(args(&command), ["exec", "-i", "3f2a9c", "nvim", "--embed"]);
+        assert_eq!(
+            args(&inspect_command("devbox")),
+            [
+                "inspect",
+                "--type",
+                "container",
+                "--format",
+                "{{.Id}} {{.State.Running}}",
+                "devbox"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stopped_container_fails_the_attempt() {
+        assert_eq!(parse_inspect("devbox", "3f2a9c true\n").unwrap(), "3f2a9c");
+        let error = parse_inspect("devbox", "3f2a9c false\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The Docker container devbox is not running"
+        );
+        assert!(parse_inspect("devbox", "").is_err());
+    }
+}
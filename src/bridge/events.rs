@@ -312,6 +312,8 @@ pub enum RedrawEvent {
     },
     Suspend,
     NeovideSetRedraw(bool),
+    /// The UI was attached again, so the windows of the previous attach are gone
+    NeovideCloseWindows,
 }
 
 fn unpack_color(packed_color: u64) -> Color4f {
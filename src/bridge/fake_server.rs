@@ -0,0 +1,121 @@
+//! A fake Neovim server for tests, connected to a real `NeovimSession` through a socket pair.
+
+use nvim_rs::{Handler, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+    task::JoinHandle,
+};
+
+use super::{
+    session::{IoBufferSizes, NeovimSession},
+    NeovimInstance, NeovimWriter,
+};
+
+#[derive(Clone)]
+pub struct TestHandler;
+
+#[async_trait::async_trait]
+impl Handler for TestHandler {
+    type Writer = NeovimWriter;
+}
+
+/// Answers the handshake and `nvim_get_api_info`, and every other request with nil, until the
+/// stream closes. Returns the methods of the requests.
+pub async fn fake_server(stream: UnixStream) -> Vec<String> {
+    stalling_fake_server(stream, None).await
+}
+
+/// A request that a [`stalling_fake_server`] never answers.
+#[derive(Clone, Copy)]
+pub enum Stall {
+    /// The `nvim_input` of these keys, like a busy Neovim
+    Input(&'static str),
+    /// Every request of this method, like a Neovim that stopped answering
+    Method(&'static str),
+}
+
+impl Stall {
+    fn matches(&self, method: &str, arguments: &[Value]) -> bool {
+        match self {
+            Stall::Input(keys) => {
+                method == "nvim_input" && arguments.first().and_then(Value::as_str) == Some(keys)
+            }
+            Stall::Method(stalled) => method == *stalled,
+        }
+    }
+}
+
+/// Like [`fake_server`], but never answers the requests of `stalled`.
+pub async fn stalling_fake_server(mut stream: UnixStream, stalled: Option<Stall>) -> Vec<String> {
+    let mut methods = Vec::new();
+    let mut received = Vec::new();
+    let mut chunk = [0; 1024];
+    while let Ok(read @ 1..) = stream.read(&mut chunk).await {
+        received.extend_from_slice(&chunk[..read]);
+        let mut rest = received.as_slice();
+        while let Ok(Value::Array(message)) = rmpv::decode::read_value(&mut rest) {
+            received = rest.to_vec();
+            rest = received.as_slice();
+            let [_, id, method, arguments] = message.as_slice() else {
+                continue;
+            };
+            let method = method.as_str().unwrap_or_default().to_owned();
+            let arguments = arguments.as_array().map(Vec::as_slice).unwrap_or_default();
+            if stalled.is_some_and(|stalled| stalled.matches(&method, arguments)) {
+                methods.push(method);
+                continue;
+            }
+            // The handshake evaluates `return '<message>'`
+            let result = if method == "nvim_get_api_info" {
+                // A channel, without any metadata
+                Value::Array(vec![1.into(), Value::Map(Vec::new())])
+            } else {
+                arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .and_then(|code| code.strip_prefix("return '"))
+                    .and_then(|code| code.strip_suffix('\''))
+                    .map_or(Value::Nil, Value::from)
+            };
+            let response = Value::Array(vec![1.into(), id.clone(), Value::Nil, result]);
+            let mut encoded = Vec::new();
+            rmpv::encode::write_value(&mut encoded, &response).unwrap();
+            if stream.write_all(&encoded).await.is_err() {
+                return methods;
+            }
+            methods.push(method);
+        }
+    }
+    methods
+}
+
+/// A session connected through one end of a socket pair, with a fake server on the other.
+/// Aborting the server closes the connection, like a server that went away.
+pub async fn socket_pair_session() -> (NeovimSession, JoinHandle<Vec<String>>) {
+    stalling_socket_pair_session(None).await
+}
+
+/// [`socket_pair_session`] with a [`stalling_fake_server`].
+pub async fn stalling_socket_pair_session(
+    stalled: Option<Stall>,
+) -> (NeovimSession, JoinHandle<Vec<String>>) {
+    use std::os::unix::{io::IntoRawFd, net::UnixStream};
+
+    let (client, server) = UnixStream::pair().unwrap();
+    server.set_nonblocking(true).unwrap();
+    let server = tokio::spawn(stalling_fake_server(
+        tokio::net::UnixStream::from_std(server).unwrap(),
+        stalled,
+    ));
+
+    let fd = client.into_raw_fd();
+    let session = NeovimSession::new(
+        NeovimInstance::descriptor(fd),
+        TestHandler,
+        IoBufferSizes::default(),
+    )
+    .await
+    .unwrap();
+    (session, server)
+}
@@ -0,0 +1,41 @@
+//! Lets `--fallback-embedded-after` switch from the fallback embedded Neovim back to the server.
+
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+
+static SWITCH_TO_SERVER: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Whether to start the fallback embedded Neovim after `failures` failed reconnect attempts in a
+/// row, `fallback_after` 0 never does.
+pub fn should_fall_back(failures: u32, fallback_after: u32) -> bool {
+    fallback_after > 0 && failures >= fallback_after
+}
+
+/// Asks a running fallback to quit its Neovim and reconnect to the server, does nothing when no
+/// fallback is running.
+pub fn switch_to_server() {
+    log::info!("Switch back to the server requested");
+    SWITCH_TO_SERVER.notify_waiters();
+}
+
+pub async fn switch_to_server_requested() {
+    SWITCH_TO_SERVER.notified().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_triggers_after_n_attempts() {
+        let triggered = (1..=5)
+            .map(|failures| should_fall_back(failures, 3))
+            .collect::<Vec<_>>();
+        assert_eq!(triggered, vec![false, false, true, true, true]);
+    }
+
+    #[test]
+    fn test_fallback_disabled_by_default() {
+        assert!(!should_fall_back(100, 0));
+    }
+}
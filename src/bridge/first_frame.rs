@@ -0,0 +1,97 @@
+//! Holds back the redraw events after a `ui_attach` until the first `flush` for
+//! `--coalesce-first-frame`, so that on a slow link the window shows the whole first frame at once
+//! instead of painting it as the messages trickle in.
+
+use std::time::Duration;
+
+use super::RedrawEvent;
+
+/// How long the first frame is held back at most, after which whatever arrived is shown.
+pub const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+pub struct FirstFrame {
+    /// `None` while the events are passed on right away
+    buffer: Option<Vec<RedrawEvent>>,
+}
+
+impl FirstFrame {
+    /// Starts holding back the events.
+    pub fn hold(&mut self) {
+        self.buffer.get_or_insert_with(Vec::new);
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.buffer.is_some()
+    }
+
+    /// Returns the events that can be sent now, all of the held back ones once `event` completes
+    /// the first frame.
+    pub fn push(&mut self, event: RedrawEvent) -> Vec<RedrawEvent> {
+        let Some(buffer) = self.buffer.as_mut() else {
+            return vec![event];
+        };
+        let completes_frame = matches!(event, RedrawEvent::Flush);
+        buffer.push(event);
+        if completes_frame {
+            self.release()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Stops holding back the events, and returns the ones held back so far.
+    pub fn release(&mut self) -> Vec<RedrawEvent> {
+        self.buffer.take().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_title(title: &str) -> RedrawEvent {
+        RedrawEvent::SetTitle {
+            title: title.to_owned(),
+        }
+    }
+
+    fn titles(events: &[RedrawEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                RedrawEvent::SetTitle { title } => title.clone(),
+                RedrawEvent::Flush => "flush".to_owned(),
+                other => format!("{other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_partial_frame_is_withheld_until_flush() {
+        let mut first_frame = FirstFrame::default();
+        assert_eq!(titles(&first_frame.push(set_title("before"))), ["before"]);
+
+        first_frame.hold();
+        assert!(first_frame.push(set_title("a")).is_empty());
+        assert!(first_frame.push(set_title("b")).is_empty());
+        assert_eq!(
+            titles(&first_frame.push(RedrawEvent::Flush)),
+            ["a", "b", "flush"]
+        );
+
+        // Later frames are passed on right away
+        assert!(!first_frame.is_held());
+        assert_eq!(titles(&first_frame.push(set_title("c"))), ["c"]);
+    }
+
+    #[test]
+    fn test_released_frame_is_not_held_anymore() {
+        let mut first_frame = FirstFrame::default();
+        first_frame.hold();
+        assert!(first_frame.push(set_title("a")).is_empty());
+        assert_eq!(titles(&first_frame.release()), ["a"]);
+        assert_eq!(titles(&first_frame.push(set_title("b"))), ["b"]);
+        assert!(first_frame.release().is_empty());
+    }
+}
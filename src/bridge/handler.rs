@@ -1,20 +1,34 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use async_trait::async_trait;
 use log::trace;
 use nvim_rs::{Handler, Neovim};
+use once_cell::sync::OnceCell;
 use rmpv::Value;
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+use tokio_util::sync::CancellationToken;
 use winit::event_loop::EventLoopProxy;
 
 use crate::{
     bridge::{
         clipboard::{get_clipboard_contents, set_clipboard_contents},
+        connection_state, current_address, current_grid_size,
         events::parse_redraw_event,
-        send_ui, NeovimWriter, ParallelCommand, RedrawEvent,
+        first_frame::FIRST_FRAME_TIMEOUT,
+        redraw_queue::RedrawQueue,
+        redraw_throttle::RedrawThrottle,
+        send_ui,
+        session_ready::FirstRedraw,
+        time_since_last_ping,
+        ui_command_info::ui_commands_value,
+        NeovimWriter, ParallelCommand, RedrawEvent, SerialCommand,
     },
+    cmd_line::{CmdLineSettings, NotificationLevel},
     error_handling::ResultPanicExplanation,
-    running_tracker::RunningTracker,
+    running_tracker::{QuitReason, RunningTracker},
     settings::Settings,
     window::{UserEvent, WindowCommand},
     LoggingSender,
@@ -28,6 +42,11 @@ pub struct NeovimHandler {
     running_tracker: RunningTracker,
     #[allow(dead_code)]
     settings: Arc<Settings>,
+    /// Started by the first redraw, since the handler is created outside of the runtime
+    redraw_queue: Arc<OnceCell<RedrawQueue>>,
+    first_redraw: Arc<FirstRedraw>,
+    /// Cancelled by the `VimLeavePre` of the session's Neovim, see `for_session`
+    leaving: CancellationToken,
 }
 
 impl NeovimHandler {
@@ -42,8 +61,58 @@ impl NeovimHandler {
             sender: LoggingSender::attach(sender, "neovim_handler"),
             running_tracker,
             settings,
+            redraw_queue: Arc::default(),
+            first_redraw: Arc::default(),
+            leaving: CancellationToken::new(),
+        }
+    }
+
+    /// The handler of a new session, whose Neovim starting to quit doesn't affect the others.
+    pub fn for_session(&self) -> Self {
+        Self {
+            leaving: CancellationToken::new(),
+            ..self.clone()
         }
     }
+
+    /// Cancelled once Neovim started to quit, from its `VimLeavePre`.
+    pub fn leaving(&self) -> CancellationToken {
+        self.leaving.clone()
+    }
+
+    /// Holds back the redraw events until the first frame is complete, or `FIRST_FRAME_TIMEOUT`
+    /// passed.
+    pub fn coalesce_first_frame(&self) {
+        self.redraw_queue().hold_first_frame();
+    }
+
+    fn redraw_queue(&self) -> &RedrawQueue {
+        self.redraw_queue.get_or_init(|| {
+            let sender = self.sender.clone();
+            let max_redraw_rate = self.settings.get::<CmdLineSettings>().max_redraw_rate;
+            RedrawQueue::start(
+                move |event| {
+                    let _ = sender.send(event);
+                },
+                FIRST_FRAME_TIMEOUT,
+                RedrawThrottle::new(max_redraw_rate),
+            )
+        })
+    }
+
+    /// Returns on the first `flush` from now on, for the session being attached.
+    pub fn watch_first_redraw(&self) -> oneshot::Receiver<()> {
+        self.first_redraw.watch()
+    }
+}
+
+/// Records the quit Neovim announces with `neovide.quit`, with the exit `code` it quits with.
+fn quit_from_neovim(running_tracker: &RunningTracker, code: u8) {
+    running_tracker.set_quit_reason(QuitReason::NvimRequested);
+    // Keeps the code a plugin asked for, rather than the one Neovim quits with
+    if running_tracker.quit_reason() != Some(QuitReason::PluginRequested) {
+        running_tracker.quit_with_code(code, "Quit from neovim");
+    }
 }
 
 #[async_trait]
@@ -67,10 +136,22 @@ impl Handler for NeovimHandler {
                 let error_code = arguments[0]
                     .as_i64()
                     .expect("Could not parse error code from neovim");
-                self.running_tracker
-                    .quit_with_code(error_code as u8, "Quit from neovim");
+                quit_from_neovim(&self.running_tracker, error_code as u8);
+                self.leaving.cancel();
                 Ok(Value::Nil)
             }
+            "neovide.get_address" => Ok(current_address().map_or(Value::Nil, Value::from)),
+            "neovide.get_grid_size" => Ok(current_grid_size().map_or(Value::Nil, |grid_size| {
+                Value::Array(vec![grid_size.width.into(), grid_size.height.into()])
+            })),
+            "neovide.get_ui_commands" => Ok(ui_commands_value()),
+            "neovide.get_capabilities" => Ok(connection_state()
+                .capabilities
+                .as_ref()
+                .map_or(Value::Nil, Value::from)),
+            "neovide.get_last_ping" => Ok(time_since_last_ping().map_or(Value::Nil, |elapsed| {
+                Value::from(elapsed.as_millis() as u64)
+            })),
             _ => Ok(Value::from("rpcrequest not handled")),
         }
     }
@@ -89,8 +170,15 @@ impl Handler for NeovimHandler {
                     let parsed_events = parse_redraw_event(events)
                         .unwrap_or_explained_panic("Could not parse event from neovim");
 
-                    for parsed_event in parsed_events {
-                        let _ = self.sender.send(parsed_event);
+                    if parsed_events
+                        .iter()
+                        .any(|event| matches!(event, RedrawEvent::Flush))
+                    {
+                        self.first_redraw.flushed();
+                    }
+                    let redraw_queue = self.redraw_queue();
+                    for event in parsed_events {
+                        redraw_queue.push(event);
                     }
                 }
             }
@@ -128,6 +216,88 @@ impl Handler for NeovimHandler {
             "neovide.exec_detach_handler" => {
                 send_ui(ParallelCommand::Quit);
             }
+            "neovide.reload_settings" => {
+                send_ui(ParallelCommand::ReloadSettings);
+            }
+            "neovide.refresh_state" => {
+                send_ui(ParallelCommand::RefreshNeovideState);
+            }
+            "neovide.dump_settings" => {
+                let path = arguments
+                    .first()
+                    .and_then(|path| path.as_str())
+                    .map(PathBuf::from);
+                send_ui(ParallelCommand::DumpSettings(path));
+            }
+            "neovide.pause_reconnect" => {
+                send_ui(ParallelCommand::PauseReconnect);
+            }
+            "neovide.resume_reconnect" => {
+                send_ui(ParallelCommand::ResumeReconnect);
+            }
+            "neovide.reconnect_now" => {
+                send_ui(ParallelCommand::ReconnectNow);
+            }
+            "neovide.send_input" => {
+                if let Some(keys) = arguments.first().and_then(Value::as_str) {
+                    send_ui(SerialCommand::SendRawInput(keys.to_owned()));
+                }
+            }
+            "neovide.set_reconnect_notice" => {
+                let notice = arguments
+                    .first()
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                send_ui(SerialCommand::SetReconnectNotice(notice.to_owned()));
+            }
+            "neovide.reconnect_state" => {
+                send_ui(ParallelCommand::ShowReconnectState);
+            }
+            "neovide.switch_to_server" => {
+                send_ui(ParallelCommand::SwitchToServer);
+            }
+            "neovide.switch_server" => {
+                if let Some(address) = arguments.first().and_then(Value::as_str) {
+                    send_ui(ParallelCommand::SwitchServer(address.to_owned()));
+                }
+            }
+            "neovide.quit_neovide" => {
+                let code = arguments.first().and_then(Value::as_i64).unwrap_or(0);
+                send_ui(ParallelCommand::QuitNeovide {
+                    code: code.clamp(0, u8::MAX.into()) as u8,
+                });
+            }
+            "neovide.notify" => {
+                if let [message, level] = arguments.as_slice() {
+                    let level = level.as_i64().and_then(NotificationLevel::from_vim);
+                    if let (Some(message), Some(level)) = (message.as_str(), level) {
+                        // The window knows whether it has the focus
+                        let _ =
+                            self.proxy
+                                .lock()
+                                .unwrap()
+                                .send_event(UserEvent::DesktopNotification {
+                                    message: message.to_owned(),
+                                    level,
+                                });
+                    }
+                }
+            }
+            "neovide.resize_grid" => {
+                if let [width, height] = arguments.as_slice() {
+                    if let (Some(width), Some(height)) = (width.as_u64(), height.as_u64()) {
+                        send_ui(SerialCommand::Resize { width, height });
+                    }
+                }
+            }
+            "neovide.ui_reattached" => {
+                let _ = self.sender.send(RedrawEvent::NeovideCloseWindows);
+            }
+            "neovide.set_multigrid" => {
+                if let Some(multigrid) = arguments.first().and_then(Value::as_bool) {
+                    send_ui(ParallelCommand::SetMultigrid(multigrid));
+                }
+            }
             "neovide.set_redraw" => {
                 if let Some(value) = arguments.first() {
                     let value = value.as_bool().unwrap_or(true);
@@ -138,3 +308,31 @@ impl Handler for NeovimHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quit_from_neovim() {
+        let running_tracker = RunningTracker::new();
+        quit_from_neovim(&running_tracker, 2);
+        assert_eq!(
+            running_tracker.quit_reason(),
+            Some(QuitReason::NvimRequested)
+        );
+        assert_eq!(running_tracker.exit_code(), 2);
+
+        // Closing the window makes Neovim quit as well, which doesn't change the reason
+        let running_tracker = RunningTracker::new();
+        running_tracker.set_quit_reason(QuitReason::UserClosed);
+        quit_from_neovim(&running_tracker, 0);
+        assert_eq!(running_tracker.quit_reason(), Some(QuitReason::UserClosed));
+
+        let running_tracker = RunningTracker::new();
+        running_tracker.set_quit_reason(QuitReason::PluginRequested);
+        running_tracker.quit_with_code(7, "requested by plugin");
+        quit_from_neovim(&running_tracker, 0);
+        assert_eq!(running_tracker.exit_code(), 7);
+    }
+}
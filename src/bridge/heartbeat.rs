@@ -0,0 +1,73 @@
+//! Sends `UserEvent::Heartbeat` every `--heartbeat-interval`, which the window logs, so that a
+//! supervisor can tell a wedged UI apart from one that just has nothing to log.
+
+use std::time::Duration;
+
+use tokio::time::{interval, MissedTickBehavior};
+
+use super::{connection_state, ConnectionStatus};
+use crate::window::{UserEvent, UserEventSender};
+
+/// Runs until the event loop is closed.
+pub async fn run_heartbeat(period: Duration, proxy: impl UserEventSender) {
+    let mut ticks = interval(period);
+    // A stalled runtime sends one late heartbeat instead of a burst of them
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        let state = connection_state();
+        let heartbeat = UserEvent::Heartbeat {
+            connected: state.status == ConnectionStatus::Connected,
+            rtt: state.rtt,
+        };
+        if proxy.send_user_event(heartbeat).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Sender};
+
+    use tokio::time::{timeout, Instant};
+    use winit::event_loop::EventLoopClosed;
+
+    use super::*;
+
+    struct ChannelSender(Sender<(Instant, UserEvent)>);
+
+    impl UserEventSender for ChannelSender {
+        fn send_user_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+            self.0
+                .send((Instant::now(), event))
+                .map_err(|error| EventLoopClosed(error.0 .1))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_fire_at_the_interval() {
+        let period = Duration::from_millis(20);
+        let (sender, receiver) = channel();
+        let started = Instant::now();
+        // Stops once the receiver is dropped after four heartbeats
+        let heartbeat = tokio::spawn(run_heartbeat(period, ChannelSender(sender)));
+
+        let beats =
+            tokio::task::spawn_blocking(move || receiver.iter().take(4).collect::<Vec<_>>())
+                .await
+                .unwrap();
+        timeout(Duration::from_secs(1), heartbeat)
+            .await
+            .expect("The heartbeat didn't stop")
+            .unwrap();
+
+        // Heartbeat n is due n intervals after the start, however late the previous one was
+        for (n, (sent_at, event)) in (1..).zip(beats) {
+            assert!(sent_at - started >= period * n, "Heartbeat {n} came early");
+            assert!(matches!(event, UserEvent::Heartbeat { .. }));
+        }
+    }
+}
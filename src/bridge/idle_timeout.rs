@@ -0,0 +1,104 @@
+//! `--idle-timeout`, which drops the connection to the server or quits once nobody has used the
+//! window for that long, so that a forgotten session doesn't hold on to the server.
+
+use std::{
+    future::pending,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use crate::{clock::Clock, cmd_line::CmdLineSettings};
+
+/// When the window last got keyboard or mouse input.
+#[derive(Default)]
+pub struct InputActivity {
+    last_input: Mutex<Option<Instant>>,
+    input: Notify,
+}
+
+impl InputActivity {
+    pub fn record(&self, now: Instant) {
+        *self.last_input.lock() = Some(now);
+        self.input.notify_waiters();
+    }
+
+    /// How long there has been no input at `now`, counting from `since` when the last input was
+    /// before that.
+    pub fn idle_for(&self, since: Instant, now: Instant) -> Duration {
+        let last_input = self.last_input.lock().map_or(since, |last| last.max(since));
+        now.saturating_duration_since(last_input)
+    }
+
+    /// Returns on the next input.
+    pub async fn wait_for_input(&self) {
+        self.input.notified().await;
+    }
+}
+
+pub static INPUT_ACTIVITY: Lazy<InputActivity> = Lazy::new(InputActivity::default);
+
+/// Called by the window for every keyboard, mouse and IME event.
+pub fn record_input() {
+    INPUT_ACTIVITY.record(Instant::now());
+}
+
+/// The `--idle-timeout`, `None` when it's disabled.
+pub fn idle_timeout(cmdline_settings: &CmdLineSettings) -> Option<Duration> {
+    (cmdline_settings.idle_timeout > 0).then(|| Duration::from_secs(cmdline_settings.idle_timeout))
+}
+
+/// Returns once there was no input for `timeout`, counting from `since`, never when `timeout` is
+/// `None`.
+pub async fn wait_until_idle(
+    timeout: Option<Duration>,
+    activity: &InputActivity,
+    since: Instant,
+    clock: &dyn Clock,
+) {
+    let Some(timeout) = timeout else {
+        return pending().await;
+    };
+    loop {
+        let idle = activity.idle_for(since, clock.now());
+        if idle >= timeout {
+            return;
+        }
+        // Input in the meantime moves the end, which is checked again after the sleep
+        clock.sleep(timeout - idle).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[tokio::test]
+    async fn test_input_postpones_the_timeout() {
+        let clock = MockClock::new();
+        let activity = InputActivity::default();
+        let start = clock.now();
+
+        wait_until_idle(Some(Duration::from_secs(60)), &activity, start, &clock).await;
+        assert_eq!(clock.now() - start, Duration::from_secs(60));
+
+        // Input 30s into the next minute restarts the count from there
+        let since = clock.now();
+        activity.record(since + Duration::from_secs(30));
+        wait_until_idle(Some(Duration::from_secs(60)), &activity, since, &clock).await;
+        assert_eq!(clock.now() - since, Duration::from_secs(90));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_timeout_never_ends() {
+        let clock = MockClock::new();
+        let activity = InputActivity::default();
+        let wait = wait_until_idle(None, &activity, clock.now(), &clock);
+        assert!(timeout(Duration::from_millis(20), wait).await.is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Sorts the errors of failed connection attempts into the kinds of `--reconnect-on`, so that an
+//! error that waiting won't fix, like rejected credentials, stops reconnecting instead of being
+//! retried forever.
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind},
+};
+
+use nvim_rs::error::HandshakeError;
+
+use crate::cmd_line::LaunchErrorKind;
+
+/// The connected Neovim is older than the version Neovide requires.
+#[derive(Debug)]
+pub struct UnsupportedVersion(pub String);
+
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for UnsupportedVersion {}
+
+fn io_error_kind(kind: ErrorKind) -> LaunchErrorKind {
+    match kind {
+        ErrorKind::ConnectionRefused | ErrorKind::NotFound | ErrorKind::AddrNotAvailable => {
+            LaunchErrorKind::Refused
+        }
+        ErrorKind::TimedOut => LaunchErrorKind::Timeout,
+        ErrorKind::PermissionDenied => LaunchErrorKind::Auth,
+        ErrorKind::InvalidData => LaunchErrorKind::Protocol,
+        _ => LaunchErrorKind::Network,
+    }
+}
+
+/// The kind of the first cause of `error` that tells one.
+pub fn classify(error: &anyhow::Error) -> LaunchErrorKind {
+    for cause in error.chain() {
+        if cause.is::<UnsupportedVersion>() {
+            return LaunchErrorKind::Version;
+        }
+        let handshake = cause.downcast_ref::<HandshakeError>().or_else(|| {
+            cause
+                .downcast_ref::<Box<HandshakeError>>()
+                .map(AsRef::as_ref)
+        });
+        if let Some(HandshakeError::UnexpectedResponse(_)) = handshake {
+            return LaunchErrorKind::Protocol;
+        }
+        if let Some(error) = cause.downcast_ref::<io::Error>() {
+            return io_error_kind(error.kind());
+        }
+    }
+    LaunchErrorKind::Other
+}
+
+/// Whether to keep reconnecting after `error`, with the kinds of `--reconnect-on`.
+pub fn is_retried(error: &anyhow::Error, reconnect_on: &[LaunchErrorKind]) -> bool {
+    reconnect_on.contains(&classify(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+    use crate::cmd_line::CmdLineSettings;
+
+    fn launch_error(error: impl Error + Send + Sync + 'static) -> anyhow::Error {
+        Err::<(), _>(error)
+            .context("Could not locate or start neovim process")
+            .unwrap_err()
+    }
+
+    #[test]
+    fn test_classify_launch_errors() {
+        let io_error = |kind, message| launch_error(io::Error::new(kind, message));
+        assert_eq!(
+            classify(&io_error(ErrorKind::ConnectionRefused, "refused")),
+            LaunchErrorKind::Refused
+        );
+        assert_eq!(
+            classify(&io_error(ErrorKind::TimedOut, "Handshake timeout")),
+            LaunchErrorKind::Timeout
+        );
+        assert_eq!(
+            classify(&io_error(ErrorKind::ConnectionReset, "reset")),
+            LaunchErrorKind::Network
+        );
+        assert_eq!(
+            classify(&io_error(ErrorKind::InvalidData, "SOCKS5 proxy: garbage")),
+            LaunchErrorKind::Protocol
+        );
+        assert_eq!(
+            classify(&launch_error(Box::new(HandshakeError::UnexpectedResponse(
+                "HTTP/1.1 400".to_owned()
+            )))),
+            LaunchErrorKind::Protocol
+        );
+        assert_eq!(
+            classify(&UnsupportedVersion("too old".to_owned()).into()),
+            LaunchErrorKind::Version
+        );
+        assert_eq!(classify(&anyhow!("unknown")), LaunchErrorKind::Other);
+    }
+
+    #[test]
+    fn test_auth_error_stops_while_refused_connection_retries() {
+        let reconnect_on = CmdLineSettings::default().reconnect_on;
+        let auth = launch_error(io::Error::new(
+            ErrorKind::PermissionDenied,
+            "The proxy rejected the credentials",
+        ));
+        let refused = launch_error(io::Error::from(ErrorKind::ConnectionRefused));
+        assert!(!is_retried(&auth, &reconnect_on));
+        assert!(is_retried(&refused, &reconnect_on));
+
+        // Unless the auth errors are retried as well
+        assert!(is_retried(
+            &auth,
+            &[LaunchErrorKind::Refused, LaunchErrorKind::Auth]
+        ));
+    }
+}
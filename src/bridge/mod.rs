@@ -1,129 +1,530 @@
+mod address_template;
 mod api_info;
+mod api_info_cache;
+mod attach_options;
 mod clipboard;
+mod close_cause;
 mod command;
+mod connect_warmup;
+pub mod connection_events;
+mod connection_sounds;
+mod connection_state;
+mod discovery;
+mod docker;
 mod events;
+#[cfg(all(test, unix))]
+mod fake_server;
+mod fallback;
+mod first_frame;
 mod handler;
+mod heartbeat;
+mod idle_timeout;
+mod launch_error;
+mod network_change;
+mod on_connect;
+mod pinned_address;
+mod preamble;
+#[cfg(unix)]
+mod pty;
+mod reconnect_budget;
+mod reconnect_notice;
+pub mod reconnect_observer;
+mod reconnect_pause;
+mod reconnect_settings;
+#[cfg(test)]
+mod recording_nvim;
+mod redraw_queue;
+mod redraw_throttle;
+mod safe_profile;
+mod server_allowlist;
+mod server_grid;
+mod server_switch;
 pub mod session;
+mod session_ready;
+mod sessions;
 mod setup;
+mod shutdown;
+mod socks5;
+mod spawn_error;
+mod startup_deadline;
+mod status_endpoint;
+mod ui_command_info;
+mod ui_command_recording;
 mod ui_commands;
+mod version_check;
+mod view_state;
 
-use std::{io::Error, ops::Add, sync::Arc, time::Duration};
+use std::{
+    future::Future,
+    io::Error,
+    net::SocketAddr,
+    ops::Add,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
 use itertools::Itertools;
 use log::{debug, info, warn};
-use nvim_rs::{error::CallError, Neovim, UiAttachOptions, Value};
+use nvim_rs::{error::CallError, Neovim, Value};
 use rmpv::Utf8String;
 use tokio::{
-    runtime::{Builder, Runtime},
+    process::Child,
+    runtime::{Builder, Handle, Runtime},
     select,
-    time::{interval, sleep, timeout},
+    task::JoinHandle,
+    time::{sleep, timeout},
 };
-use winit::event_loop::EventLoopProxy;
+use tokio_util::sync::CancellationToken;
+use winit::event_loop::{EventLoopClosed, EventLoopProxy};
 
 use crate::{
-    cmd_line::CmdLineSettings, editor::start_editor, running_tracker::RunningTracker, settings::*,
-    units::GridSize, window::UserEvent,
+    clock::{Clock, SuspendDetector, SystemClock},
+    cmd_line::{CmdLineSettings, HandleClipboard, IdleAction},
+    editor::start_editor,
+    log_ring::CONNECTION_LOG_TARGET,
+    running_tracker::{QuitReason, RunningTracker},
+    settings::*,
+    units::GridSize,
+    window::{request_redraw, UserEvent, UserEventSender, WindowCommand},
 };
-pub use handler::NeovimHandler;
-use session::{NeovimInstance, NeovimSession};
-use setup::{get_api_information, setup_neovide_specific_state};
+use address_template::server_address;
 use api_info::ApiInformation;
+use api_info_cache::API_INFO_CACHE;
+use attach_options::{attach_options_for, ui_attach_options};
+use close_cause::CloseCause;
+use connect_warmup::ConnectWarmup;
+use connection_events::{log_connection_events, publish, subscribe, ConnectionEvent};
+use connection_sounds::{play_connection_sounds, ConnectionSounds, SystemPlayer};
+use connection_state::update_connection_state;
+use discovery::{default_discovery_dir, newest_socket, AUTO_SERVER};
+use docker::docker_container;
+use fallback::{should_fall_back, switch_to_server_requested};
+pub use handler::NeovimHandler;
+use heartbeat::run_heartbeat;
+use idle_timeout::{idle_timeout, wait_until_idle, InputActivity, INPUT_ACTIVITY};
+use launch_error::{classify, is_retried, UnsupportedVersion};
+use network_change::network_changed;
+use on_connect::run_on_connect;
+use reconnect_budget::{ReconnectBudget, RECONNECT_GAVE_UP_EXIT_CODE};
+use reconnect_notice::clear_reconnect_notice;
+use reconnect_observer::{report_attempt_result, NoopObserver, ReconnectObserver};
+use reconnect_pause::{RECONNECT_NOW, RECONNECT_PAUSE};
+use safe_profile::create_safe_profile;
+use server_allowlist::ServerAllowlist;
+use server_grid::{attach_grid_size, server_grid_size};
+use server_switch::{server_switch_requested, take_server_switch};
+use session::{
+    is_server_running, probe_server, IoBufferSizes, NeovimSession, ProcessGuard, TcpOptions,
+};
+use session_ready::announce_ready;
+use sessions::{register_session, unregister_session};
+use setup::{get_api_information, quick_api_information, setup_neovide_specific_state};
+use shutdown::{quit_ignored, shutdown, terminate, ShutdownReason, ShutdownTimeouts};
+use startup_deadline::{with_deadline, LaunchPhase, LaunchProgress};
+use status_endpoint::run_status_endpoint;
+use ui_command_recording::{replay_ui_commands_file, start_recording};
+use version_check::{VersionCheckBackoff, VERSION_CHECK_ATTEMPTS};
+use view_state::{capture_view_state, restore_view_state, CaptureInterval};
 
+pub use api_info::NeovimCapabilities;
 pub use command::create_nvim_command;
+pub use connection_state::{
+    connection_state, time_since_last_ping, ConnectionState, ConnectionStatus,
+};
 pub use events::*;
-pub use session::NeovimWriter;
-use ui_commands::update_current_nvim;
-pub use ui_commands::{send_ui, start_ui_command_handler, ParallelCommand, SerialCommand};
+pub use idle_timeout::record_input;
+pub use preamble::Preamble;
+pub use reconnect_notice::{reconnect_notice, set_reconnect_notice};
+pub use reconnect_pause::is_reconnect_paused;
+pub use reconnect_settings::{ReconnectSettings, ReconnectSettingsChanged};
+pub use session::{NeovimInstance, NeovimWriter};
+pub use socks5::Socks5Proxy;
+pub use ui_commands::{
+    current_address, current_grid_size, is_read_only, send_ui, set_read_only,
+    start_ui_command_handler, CommandTimeout, ParallelCommand, RpcErrorReporter, SerialCommand,
+};
 
 const NEOVIM_REQUIRED_VERSION: &str = "0.10.0";
+const EMBEDDED_RETRY_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+// How long `--quit-after-seconds` lets Neovim quit on its own before closing the window anyway
+const QUIT_AFTER_GRACE: Duration = Duration::from_secs(2);
+// How long a connected server has to answer a ping before the connection counts as lost
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+// How long a disconnect waits for the buffered writes to go out before dropping them
+const DISCONNECT_FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+// How often an embedded session checks that the event loop is still there to show it
+const EVENT_LOOP_PROBE_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct NeovimRuntime {
     pub runtime: Runtime,
+    /// Cancelled by `request_shutdown`, which stops the tasks that `launch` started
+    shutdown: CancellationToken,
+    /// The tracker `launch` was given
+    running_tracker: Option<RunningTracker>,
+    /// Follows the reconnect loop of a `--server`
+    reconnect_observer: Arc<dyn ReconnectObserver>,
 }
 
-async fn neovim_instance(settings: &Settings) -> Result<NeovimInstance> {
-    if let Some(address) = settings.get::<CmdLineSettings>().server {
-        Ok(NeovimInstance::Server { address })
+fn check_server_allowlist(cmdline_settings: &CmdLineSettings, address: &str) -> Result<()> {
+    match ServerAllowlist::from_settings(cmdline_settings)? {
+        Some(allowlist) => allowlist.check(address),
+        None => Ok(()),
+    }
+}
+
+/// Resolves `--server-or-embedded` into `--server` when the server is running, otherwise the
+/// embedded Neovim is started as if the flag wasn't given.
+async fn resolve_server_or_embedded(settings: &Settings) {
+    let mut cmdline_settings = settings.get::<CmdLineSettings>();
+    let Some(address) = cmdline_settings.server_or_embedded.clone() else {
+        return;
+    };
+    let probe_timeout = match cmdline_settings.probe_timeout {
+        0 => DEFAULT_PROBE_TIMEOUT,
+        millis => Duration::from_millis(millis),
+    };
+    let options = TcpOptions::from_settings(&cmdline_settings);
+    if is_server_running(&address, probe_timeout, &options).await {
+        info!("Found a server running at {address}, connecting to it");
+        cmdline_settings.server = Some(address);
+        settings.set(&cmdline_settings);
     } else {
-        let cmd = create_nvim_command(settings);
-        Ok(NeovimInstance::Embedded(cmd))
+        info!("No server running at {address}, starting an embedded Neovim");
     }
 }
 
-pub async fn show_error_message(
+/// Resolves `--server auto` into the most recently used server socket, or into an embedded Neovim
+/// when no socket is found.
+fn resolve_auto_server(settings: &Settings) {
+    let mut cmdline_settings = settings.get::<CmdLineSettings>();
+    if cmdline_settings.server.as_deref() != Some(AUTO_SERVER) {
+        return;
+    }
+    let dir = cmdline_settings
+        .server_discovery_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(default_discovery_dir);
+    match newest_socket(&dir) {
+        Some(socket) => {
+            info!("Discovered the server at {}", socket.display());
+            cmdline_settings.server = Some(socket.to_string_lossy().into_owned());
+        }
+        None => {
+            info!(
+                "No server found in {}, starting an embedded Neovim",
+                dir.display()
+            );
+            cmdline_settings.server = None;
+        }
+    }
+    settings.set(&cmdline_settings);
+}
+
+impl NeovimInstance {
+    /// The instance Neovide itself would use for the command line in `settings`, the `--server-fd`
+    /// or the `--server` if one is given, and allowed by the server allowlist, otherwise an
+    /// embedded Neovim.
+    pub fn from_settings(settings: &Settings) -> Result<Self> {
+        let cmdline_settings = settings.get::<CmdLineSettings>();
+        if let Some(fd) = cmdline_settings.server_fd {
+            return Ok(Self::descriptor(fd));
+        }
+        match server_address(&cmdline_settings) {
+            Some(address) => {
+                check_server_allowlist(&cmdline_settings, &address)?;
+                if let Some(container) = docker_container(&address) {
+                    return Ok(Self::Docker {
+                        container: container.to_owned(),
+                    });
+                }
+                if !cmdline_settings.nvim_env.is_empty()
+                    || !cmdline_settings.nvim_env_remove.is_empty()
+                {
+                    log::warn!(
+                        "--nvim-env and --nvim-env-remove are ignored when connecting to a server"
+                    );
+                }
+                Ok(Self::server(address)
+                    .with_proxy(cmdline_settings.proxy.clone())
+                    .with_address_family(cmdline_settings.address_family)
+                    .with_pinned_ip(
+                        cmdline_settings.pin_resolved_ip,
+                        cmdline_settings.pin_reresolve_after,
+                    )
+                    .with_preamble(cmdline_settings.connection_preamble.clone()))
+            }
+            None => {
+                Ok(Self::embedded(create_nvim_command(settings)?)
+                    .with_pty(cmdline_settings.nvim_pty))
+            }
+        }
+    }
+}
+
+async fn neovim_instance(settings: &Settings) -> Result<NeovimInstance> {
+    NeovimInstance::from_settings(settings)
+}
+
+/// How a message shown by [`show_message`] is highlighted and prefixed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageSeverity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl MessageSeverity {
+    fn highlight(self) -> &'static str {
+        match self {
+            MessageSeverity::Error => "ErrorMsg",
+            MessageSeverity::Warning => "WarningMsg",
+            MessageSeverity::Info => "MoreMsg",
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            MessageSeverity::Error => "Error: ",
+            MessageSeverity::Warning => "Warning: ",
+            MessageSeverity::Info => "Info: ",
+        }
+    }
+}
+
+/// Echoes `lines` in Neovim, kept in the message history. The first line follows the prefix.
+pub async fn show_message(
     nvim: &Neovim<NeovimWriter>,
+    severity: MessageSeverity,
     lines: &[String],
 ) -> Result<(), Box<CallError>> {
-    let error_msg_highlight: Utf8String = "ErrorMsg".into();
+    let highlight: Utf8String = severity.highlight().into();
     let mut prepared_lines = lines
         .iter()
         .map(|l| {
             Value::Array(vec![
                 Value::String(l.clone().add("\n").into()),
-                Value::String(error_msg_highlight.clone()),
+                Value::String(highlight.clone()),
             ])
         })
         .collect_vec();
     prepared_lines.insert(
         0,
         Value::Array(vec![
-            Value::String("Error: ".into()),
-            Value::String(error_msg_highlight.clone()),
+            Value::String(severity.prefix().into()),
+            Value::String(highlight.clone()),
         ]),
     );
     nvim.echo(prepared_lines, true, vec![]).await
 }
 
+pub async fn show_error_message(
+    nvim: &Neovim<NeovimWriter>,
+    lines: &[String],
+) -> Result<(), Box<CallError>> {
+    show_message(nvim, MessageSeverity::Error, lines).await
+}
+
 async fn check_neovim_version(
     nvim: &Neovim<NeovimWriter>,
+    backoff: VersionCheckBackoff,
 ) -> Result<ApiInformation> {
-    for attempt in 0..5 {
-        match get_api_information(nvim).await {
+    check_api_information(|| get_api_information(nvim), backoff).await
+}
+
+/// Retries `fetch` until Neovim reports a supported version, and a channel for Neovide. The
+/// channel can still be 0 in rare handshake races, but it's needed to set up Neovide.
+async fn check_api_information<F, Fut>(
+    mut fetch: F,
+    backoff: VersionCheckBackoff,
+) -> Result<ApiInformation>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<ApiInformation>>,
+{
+    let mut missing_channel = false;
+    for attempt in 0..VERSION_CHECK_ATTEMPTS {
+        missing_channel = false;
+        match fetch().await {
+            Ok(info) if info.channel == 0 => {
+                debug!("Version check attempt {attempt}: no channel assigned yet");
+                missing_channel = true;
+            }
             Ok(info) if info.version.has_version(0, 10, 0) => return Ok(info),
             Ok(info) => {
-                debug!(
-                    "Version check attempt {attempt}: {}.{}.{}",
-                    info.version.major,
-                    info.version.minor,
-                    info.version.patch
-                );
+                debug!("Version check attempt {attempt}: {}", info.version);
             }
             Err(err) => {
                 debug!("Version check attempt {attempt} failed: {err}");
             }
         }
-        sleep(Duration::from_millis(500)).await;
+        if attempt + 1 < VERSION_CHECK_ATTEMPTS {
+            sleep(backoff.delay(attempt, rand::random())).await;
+        }
     }
-    Err(anyhow!(
+    if missing_channel {
+        return Err(anyhow!("Neovim did not assign a channel to Neovide"));
+    }
+    Err(UnsupportedVersion(format!(
         "Neovide requires nvim version {NEOVIM_REQUIRED_VERSION} or higher"
     ))
+    .into())
+}
+
+/// Fetches the API information once for `--skip-version-check`, accepting any version, but still
+/// requiring the channel.
+async fn unchecked_api_information<F, Fut>(fetch: F) -> Result<ApiInformation>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<ApiInformation>>,
+{
+    warn!(
+        "Skipping the Neovim version check because of --skip-version-check, Neovide isn't \
+         guaranteed to work with this Neovim"
+    );
+    let api_information = fetch().await?;
+    if api_information.channel == 0 {
+        return Err(anyhow!("Neovim did not assign a channel to Neovide"));
+    }
+    if !api_information.version.has_version(0, 10, 0) {
+        warn!(
+            "Neovim {} is older than the required {NEOVIM_REQUIRED_VERSION}",
+            api_information.version
+        );
+    }
+    Ok(api_information)
+}
+
+/// Checks the version, but when the same server passed the check less than `ttl` ago, a quick
+/// verification is tried first.
+async fn check_neovim_version_cached(
+    nvim: &Neovim<NeovimWriter>,
+    address: Option<&str>,
+    ttl: Duration,
+    backoff: VersionCheckBackoff,
+) -> Result<ApiInformation> {
+    let Some(address) = address else {
+        return check_neovim_version(nvim, backoff).await;
+    };
+    let cached = API_INFO_CACHE
+        .lock()
+        .get(address, Instant::now(), ttl)
+        .cloned();
+    if let Some(cached) = cached {
+        match quick_api_information(nvim, &cached).await {
+            Ok(api_information) if api_information.channel != 0 => {
+                debug!("Reused the cached API information of {address}");
+                return Ok(api_information);
+            }
+            Ok(_) => debug!("Quick version check of {address} returned no channel"),
+            Err(err) => debug!("Quick version check of {address} failed: {err:?}"),
+        }
+    }
+    let api_information = check_neovim_version(nvim, backoff).await?;
+    API_INFO_CACHE
+        .lock()
+        .insert(address, api_information.clone(), Instant::now());
+    Ok(api_information)
 }
 
 async fn launch(
     handler: NeovimHandler,
     grid_size: Option<GridSize<u32>>,
     settings: Arc<Settings>,
+    proxy: EventLoopProxy<UserEvent>,
 ) -> Result<NeovimSession> {
     let neovim_instance = neovim_instance(settings.as_ref()).await?;
+    let address = server_address(&settings.get::<CmdLineSettings>());
+    attach(
+        neovim_instance,
+        address.as_deref(),
+        handler,
+        grid_size,
+        settings,
+        proxy,
+    )
+    .await
+}
+
+/// Starts or connects to `neovim_instance` and attaches the UI, `address` is the server address
+/// used to cache the API information.
+async fn attach(
+    neovim_instance: NeovimInstance,
+    address: Option<&str>,
+    handler: NeovimHandler,
+    grid_size: Option<GridSize<u32>>,
+    settings: Arc<Settings>,
+    proxy: EventLoopProxy<UserEvent>,
+) -> Result<NeovimSession> {
+    let deadline = match settings.get::<CmdLineSettings>().startup_deadline {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let progress = LaunchProgress::default();
+    let phases = attach_phases(
+        neovim_instance,
+        address,
+        handler,
+        grid_size,
+        settings,
+        proxy,
+        &progress,
+    );
+    with_deadline(deadline, &progress, phases).await
+}
+
+async fn attach_phases(
+    neovim_instance: NeovimInstance,
+    address: Option<&str>,
+    handler: NeovimHandler,
+    grid_size: Option<GridSize<u32>>,
+    settings: Arc<Settings>,
+    proxy: EventLoopProxy<UserEvent>,
+    progress: &LaunchProgress,
+) -> Result<NeovimSession> {
+    let cmdline_settings = settings.get::<CmdLineSettings>();
+    let started_at = Instant::now();
 
-    let session = NeovimSession::new(neovim_instance, handler)
+    publish(ConnectionEvent::Connecting);
+    progress.enter(LaunchPhase::Spawn);
+    let buffer_sizes = IoBufferSizes::from_settings(&cmdline_settings);
+    let handler = handler.for_session();
+    let mut session = NeovimSession::new(neovim_instance, handler.clone(), buffer_sizes)
         .await
         .context("Could not locate or start neovim process")?;
+    session.leaving = handler.leaving();
+    // Until the UI is attached, bailing out kills the process again
+    let process = ProcessGuard::new(session.neovim_process.take());
 
     // Ensure the connected Neovim instance meets the minimum version and
     // retrieve API information for later setup
-    let api_information = check_neovim_version(&session.neovim).await?;
-
-    let cmdline_settings = settings.get::<CmdLineSettings>();
+    progress.enter(LaunchPhase::Version);
+    let api_information = if cmdline_settings.skip_version_check {
+        unchecked_api_information(|| get_api_information(&session.neovim)).await?
+    } else {
+        check_neovim_version_cached(
+            &session.neovim,
+            address,
+            Duration::from_secs(cmdline_settings.api_info_cache_ttl),
+            VersionCheckBackoff::from_settings(&cmdline_settings),
+        )
+        .await?
+    };
 
-    let should_handle_clipboard = cmdline_settings.wsl || cmdline_settings.server.is_some();
+    let should_handle_clipboard = should_handle_clipboard(&cmdline_settings);
     info!(
-        "Neovide registered to nvim with channel id {}",
-        api_information.channel
+        "Neovide registered to nvim with channel id {} in session {}",
+        api_information.channel,
+        session.session_id()
     );
-    // This is too verbose to keep enabled all the time
-    // log::info!("Api information {:#?}", api_information);
+    if let Some(dump) = api_info_dump(&cmdline_settings, &api_information) {
+        info!("{dump}");
+    }
+    progress.enter(LaunchPhase::Setup);
     setup_neovide_specific_state(
         &session.neovim,
         should_handle_clipboard,
@@ -132,157 +533,2936 @@ async fn launch(
     )
     .await?;
 
-    settings.read_initial_values(&session.neovim).await?;
+    read_settings(&settings, &session.neovim, &proxy).await?;
 
-    let mut options = UiAttachOptions::new();
-    options.set_linegrid_external(true);
-    options.set_multigrid_external(!cmdline_settings.no_multi_grid);
-    options.set_rgb(true);
+    let options = attach_options_for(&settings, address);
 
     // Triggers loading the user config
 
-    let grid_size = grid_size.map_or(DEFAULT_GRID_SIZE, |v| clamped_grid_size(&v));
+    progress.enter(LaunchPhase::Attach);
+    let server_grid = match address {
+        Some(address) if cmdline_settings.match_server_grid => {
+            server_grid_size(&session.neovim, address).await
+        }
+        _ => None,
+    };
+    let grid_size = attach_grid_size(grid_size, server_grid);
+    if server_grid.is_some() {
+        info!("Attaching with the grid size {grid_size:?} of the server");
+        proxy
+            .send_event(WindowCommand::ResizeGrid(grid_size).into())
+            .ok();
+    }
+    if cmdline_settings.coalesce_first_frame {
+        handler.coalesce_first_frame();
+    }
+    let first_redraw = handler.watch_first_redraw();
     let res = session
         .neovim
         .ui_attach(grid_size.width as i64, grid_size.height as i64, &options)
         .await
         .context("Could not attach ui to neovim process");
 
-    info!("Neovim process attached");
-    res.map(|()| session)
+    let session_id = session.session_id();
+    info!("Neovim process attached in session {session_id}");
+    if res.is_ok() {
+        let warmup = address.and(ConnectWarmup::from_settings(&cmdline_settings));
+        if warmup.is_some() {
+            progress.enter(LaunchPhase::Warmup);
+        }
+        let capabilities = NeovimCapabilities::from(&api_information);
+        let connected = |state: &mut ConnectionState| {
+            state.attempt = 0;
+            state.backoff = None;
+            state.nvim_version = Some(capabilities.version.clone());
+            state.channel = Some(api_information.channel);
+            state.rtt = None;
+            // Only a --server is pinged
+            state.last_ping_ok = address.map(|_| Instant::now());
+            state.session_id = Some(session_id);
+            state.capabilities = Some(capabilities.clone());
+        };
+        mark_connected(
+            &session.neovim,
+            address,
+            warmup,
+            &proxy,
+            &SystemClock,
+            connected,
+        )
+        .await;
+        if let (Some(address), true) = (address, cmdline_settings.restore_session_on_reconnect) {
+            restore_view_state(&session.neovim, address).await;
+        }
+        publish(ConnectionEvent::Connected { session_id });
+        tokio::spawn(announce_ready(
+            session_id,
+            first_redraw,
+            started_at,
+            (cmdline_settings.session_ready_timeout > 0)
+                .then(|| Duration::from_secs(cmdline_settings.session_ready_timeout)),
+        ));
+        proxy
+            .send_event(UserEvent::NeovimCapabilities(capabilities))
+            .ok();
+    }
+    res.map(|()| {
+        session.neovim_process = process.disarm();
+        session
+    })
+}
+
+/// Shows the session as connected, with a `warmup` only once the pings are fast enough.
+async fn mark_connected(
+    nvim: &Neovim<NeovimWriter>,
+    address: Option<&str>,
+    warmup: Option<ConnectWarmup>,
+    proxy: &impl UserEventSender,
+    clock: &dyn Clock,
+    update: impl FnOnce(&mut ConnectionState),
+) {
+    if let (Some(address), Some(warmup)) = (address, warmup) {
+        stabilize_connection(nvim, address, &warmup, proxy, clock).await;
+    }
+    update_connection_state(proxy, |state| {
+        state.status = ConnectionStatus::Connected;
+        update(state);
+    });
+}
+
+/// Shows that the connection to `address` is being stabilized, until the pings are fast enough
+/// for the `--connect-warmup`.
+async fn stabilize_connection(
+    nvim: &Neovim<NeovimWriter>,
+    address: &str,
+    warmup: &ConnectWarmup,
+    proxy: &impl UserEventSender,
+    clock: &dyn Clock,
+) {
+    proxy
+        .send_user_event(UserEvent::ConnectStabilizing {
+            address: address.to_owned(),
+        })
+        .ok();
+    proxy
+        .send_user_event(UserEvent::ReconnectTitle(format!(
+            "Stabilizing the connection to {address}"
+        )))
+        .ok();
+    request_redraw(proxy).ok();
+    if !warmup.run(clock, || nvim.get_api_info()).await {
+        warn!(
+            target: CONNECTION_LOG_TARGET,
+            "The connection to {address} is still slow after {} pings, using it anyway",
+            warmup.max_pings()
+        );
+    }
+}
+
+/// Reads the settings from Neovim, and tells the window once they are loaded.
+async fn read_settings(
+    settings: &Settings,
+    nvim: &Neovim<NeovimWriter>,
+    proxy: &impl UserEventSender,
+) -> Result<()> {
+    settings.read_initial_values(nvim).await?;
+    proxy.send_user_event(UserEvent::SettingsLoaded).ok();
+    Ok(())
+}
+
+/// The pretty printed API information when `--dump-api-info` is set, it's too verbose to log by
+/// default.
+fn api_info_dump(
+    cmdline_settings: &CmdLineSettings,
+    api_information: &ApiInformation,
+) -> Option<String> {
+    cmdline_settings.dump_api_info.then(|| {
+        format!("API information (--dump-api-info, this is verbose):\n{api_information:#?}")
+    })
+}
+
+/// Whether Neovide has to provide the clipboard, because Neovim runs somewhere without access to
+/// the local one. With `--handle-clipboard auto` that's a server, WSL, or an embedded Neovim
+/// started through ssh.
+fn should_handle_clipboard(cmdline_settings: &CmdLineSettings) -> bool {
+    match cmdline_settings.handle_clipboard {
+        HandleClipboard::Always => true,
+        HandleClipboard::Never => false,
+        HandleClipboard::Auto => {
+            let through_ssh = cmdline_settings.neovim_bin.as_deref().is_some_and(|bin| {
+                Path::new(bin)
+                    .file_stem()
+                    .is_some_and(|stem| stem.eq_ignore_ascii_case("ssh"))
+            });
+            cmdline_settings.wsl || is_remote_session(cmdline_settings) || through_ssh
+        }
+    }
+}
+
+/// Whether Neovim runs on the other end of a connection, from `--server` or `--server-fd`, rather
+/// than embedded.
+pub fn is_remote_session(cmdline_settings: &CmdLineSettings) -> bool {
+    cmdline_settings.server.is_some() || cmdline_settings.server_fd.is_some()
+}
+
+/// Takes the reconnect overlay down to show the connected session. Fails when the event loop is
+/// closed.
+fn show_session(proxy: &impl UserEventSender) -> Result<(), EventLoopClosed<UserEvent>> {
+    proxy.send_user_event(UserEvent::ReconnectStop)?;
+    request_redraw(proxy)
+}
+
+enum FallbackEnd {
+    /// The fallback Neovim quit on its own, so Neovide quits as well
+    Quit,
+    SwitchBack,
+}
+
+/// Runs an embedded Neovim while the server is unreachable, until it quits or
+/// `:NeovideSwitchToServer` is used.
+async fn run_fallback<P>(
+    connector: &dyn Connector,
+    grid_size: Option<GridSize<u32>>,
+    settings: &Arc<Settings>,
+    proxy: &P,
+    timeouts: &ShutdownTimeouts,
+    running_tracker: &RunningTracker,
+) -> Result<FallbackEnd>
+where
+    P: UserEventSender + Clone + Send + 'static,
+{
+    info!("Starting an embedded Neovim until the server is back");
+    let mut session = connector.launch_fallback(grid_size).await?;
+    update_connection_state(proxy, |state| state.status = ConnectionStatus::Fallback);
+    start_ui_command_handler(
+        session.neovim.clone(),
+        settings.clone(),
+        running_tracker.clone(),
+        RpcErrorReporter::new(proxy.clone()),
+    );
+    if show_session(proxy).is_err() {
+        running_tracker.request_quit();
+    }
+
+    let mut switching = false;
+    loop {
+        select! {
+            _ = &mut session.io_handle => break,
+            _ = running_tracker.wait_for_quit_request() => {
+                info!("Quitting the fallback Neovim, the window is gone");
+                switching = false;
+                break;
+            }
+            _ = switch_to_server_requested() => {
+                info!("Quitting the fallback Neovim to switch back to the server");
+                // Lets Neovim ask about unsaved changes, the switch only happens once it quits.
+                // Neovim doesn't answer when it quits, so an answer means the quit was cancelled.
+                switching = session.neovim.command("confirm qa").await.is_err();
+                if !switching {
+                    info!("The fallback Neovim didn't quit, staying with it");
+                }
+            }
+        }
+    }
+    shutdown(
+        session,
+        ShutdownReason::NeovimExited,
+        timeouts,
+        running_tracker,
+    )
+    .await;
+    if switching {
+        running_tracker.reset_quit();
+        Ok(FallbackEnd::SwitchBack)
+    } else {
+        Ok(FallbackEnd::Quit)
+    }
+}
+
+/// Quits after `delay`, whatever state the session is in. Neovim is asked to quit first, but the
+/// window is closed anyway when that doesn't happen within `grace`, for example while reconnecting.
+async fn quit_after(
+    delay: Duration,
+    grace: Duration,
+    running_tracker: RunningTracker,
+    proxy: impl UserEventSender,
+) {
+    sleep(delay).await;
+    let reason = (QuitReason::TimedOut, "quit-after timeout");
+    quit_now(grace, reason, &running_tracker, &proxy).await;
+}
+
+/// Asks Neovim to quit, and closes the window after `grace` even if it doesn't. The `reason` is
+/// recorded, and logged with its description.
+async fn quit_now(
+    grace: Duration,
+    (reason, description): (QuitReason, &str),
+    running_tracker: &RunningTracker,
+    proxy: &impl UserEventSender,
+) {
+    running_tracker.set_quit_reason(reason);
+    running_tracker.quit_with_code(0, description);
+    send_ui(ParallelCommand::Quit);
+    sleep(grace).await;
+    proxy.send_user_event(UserEvent::NeovimExited).ok();
+}
+
+/// Quits once there was no input for the `--idle-timeout`.
+async fn quit_when_idle(
+    idle_timeout: Duration,
+    grace: Duration,
+    activity: &InputActivity,
+    running_tracker: RunningTracker,
+    proxy: impl UserEventSender,
+    clock: &dyn Clock,
+) {
+    wait_until_idle(Some(idle_timeout), activity, clock.now(), clock).await;
+    info!("No input for {}s, quitting", idle_timeout.as_secs());
+    let reason = (QuitReason::Idle, "idle timeout");
+    quit_now(grace, reason, &running_tracker, &proxy).await;
+}
+
+/// Calls `attempt` until it succeeds, at most `retries + 1` times, waiting `delay` in between.
+async fn retry<T, F, Fut>(retries: u32, delay: Duration, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut remaining = retries;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(err) if remaining > 0 => {
+                remaining -= 1;
+                warn!("Launching neovim failed, retrying ({remaining} retries left): {err:?}");
+                sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
 }
 
-async fn run(session: NeovimSession, proxy: EventLoopProxy<UserEvent>) {
-    let mut session = session;
+/// Completes once the event loop can't receive events anymore, checking every `interval`.
+async fn event_loop_closed(proxy: &impl UserEventSender, interval: Duration) {
+    loop {
+        sleep(interval).await;
+        if proxy.send_user_event(UserEvent::Probe).is_err() {
+            return;
+        }
+    }
+}
 
-    if let Some(process) = session.neovim_process.as_mut() {
+/// Waits for an embedded Neovim to close its IO stream or to exit. Returns `true` when `closed`
+/// completed first, the event loop shut down or a shutdown was requested, since then nobody is
+/// left to show the session.
+async fn wait_for_session_end<T>(
+    io_handle: &mut JoinHandle<T>,
+    process: Option<&mut Child>,
+    leaving: &CancellationToken,
+    timeouts: &ShutdownTimeouts,
+    closed: impl Future<Output = ()>,
+) -> bool {
+    tokio::pin!(closed);
+    if let Some(process) = process {
         // We primarily wait for the stdio to finish, but due to bugs,
         // for example, this one in in Neovim 0.9.5
         // https://github.com/neovim/neovim/issues/26743
         // it does not always finish.
-        // So wait for some additional time, both to make the bug obvious and to prevent incomplete
-        // data.
+        // So the shutdown gives the stream some additional time, both to make the bug obvious and
+        // to prevent incomplete data. With `--io-drain=full` it waits until the stream closes.
         select! {
-            _ = &mut session.io_handle => {}
+            _ = &mut *io_handle => {}
             _ = process.wait() => {
-                // Wait a little bit more if we detect that Neovim exits before the stream, to
-                // allow us to finish reading from it.
-                log::info!("The Neovim process quit before the IO stream, waiting for a half second");
-                if timeout(Duration::from_millis(500), &mut session.io_handle)
-                        .await
-                        .is_err()
-                {
-                    log::info!("The IO stream was never closed, forcing Neovide to exit");
-                }
+                log::info!("The Neovim process quit before the IO stream, draining it");
             }
+            _ = quit_ignored(leaving, timeouts.ignored_quit) => {
+                warn!("Neovim ignored the quit request, terminating it");
+                terminate(process, timeouts.terminate).await;
+            }
+            _ = &mut closed => return true,
         };
     } else {
-        session.io_handle.await.ok();
+        select! {
+            _ = &mut *io_handle => {}
+            _ = &mut closed => return true,
+        };
     }
-    // Try to ensure that the stderr output has finished
-    if let Some(stderr_task) = &mut session.stderr_task {
-        timeout(Duration::from_millis(500), stderr_task).await.ok();
-    };
-    update_current_nvim(None);
-    proxy.send_event(UserEvent::NeovimExited).ok();
+    false
 }
 
-async fn run_server(mut session: NeovimSession) {
-    debug!("Monitoring server connection");
-    let mut ping_interval = interval(Duration::from_secs(5));
-    loop {
+/// What happens after an embedded Neovim crashed, set by `--restart-on-crash` and `--hold`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CrashAction {
+    Quit,
+    Restart,
+    Hold,
+}
+
+impl CrashAction {
+    fn from_settings(cmdline_settings: &CmdLineSettings) -> Self {
+        if cmdline_settings.restart_on_crash {
+            CrashAction::Restart
+        } else if cmdline_settings.hold {
+            CrashAction::Hold
+        } else {
+            CrashAction::Quit
+        }
+    }
+}
+
+/// Serves an embedded Neovim until it exits. Returns `true` when it crashed and a new one should
+/// be started, as `crash_action` asks for.
+async fn run(
+    mut session: NeovimSession,
+    timeouts: ShutdownTimeouts,
+    running_tracker: RunningTracker,
+    proxy: impl UserEventSender,
+    shutdown_requested: CancellationToken,
+    crash_action: CrashAction,
+) -> bool {
+    let closed = || async {
         select! {
-            _ = &mut session.io_handle => {
-                debug!("Server connection closed");
-                break;
+            _ = event_loop_closed(&proxy, EVENT_LOOP_PROBE_INTERVAL) => {}
+            _ = shutdown_requested.cancelled() => {}
+        }
+    };
+    let ended_early = wait_for_session_end(
+        &mut session.io_handle,
+        session.neovim_process.as_mut(),
+        &session.leaving,
+        &timeouts,
+        closed(),
+    )
+    .await;
+    // Nothing can answer Neovim anymore, so the quit phase of the shutdown kills it, which keeps
+    // its swap files for recovering unsaved changes
+    if ended_early && !shutdown_requested.is_cancelled() {
+        running_tracker.request_quit();
+    }
+    let report = shutdown(
+        session,
+        ShutdownReason::NeovimExited,
+        &timeouts,
+        &running_tracker,
+    )
+    .await;
+    if let (false, Some(code)) = (ended_early, report.crash_code) {
+        match crash_action {
+            CrashAction::Quit => {}
+            CrashAction::Restart => {
+                proxy
+                    .send_user_event(UserEvent::Notice(format!(
+                        "Neovim exited with code {code}, restarting it"
+                    )))
+                    .ok();
+                return true;
             }
-            _ = ping_interval.tick() => {
-                if timeout(Duration::from_secs(2), session.neovim.get_api_info()).await.is_err() {
-                    warn!("Connection ping timed out, aborting I/O task");
-                    session.io_handle.abort();
-                }
+            CrashAction::Hold => {
+                proxy
+                    .send_user_event(UserEvent::Notice(format!(
+                        "Neovim exited with code {code}, close the window to quit"
+                    )))
+                    .ok();
+                closed().await;
             }
         }
     }
+    // The window may still be there after a requested shutdown
+    if !ended_early || shutdown_requested.is_cancelled() {
+        proxy.send_user_event(UserEvent::NeovimExited).ok();
+    }
+    false
+}
 
-    if let Some(stderr_task) = &mut session.stderr_task {
-        timeout(Duration::from_millis(500), stderr_task).await.ok();
+/// Stops reading from the server, after trying to write out what's still buffered for it.
+async fn abort_io(session: &NeovimSession) {
+    if !session.flush(DISCONNECT_FLUSH_TIMEOUT).await {
+        debug!(
+            "Dropping the unwritten messages of session {}",
+            session.session_id()
+        );
     }
-    update_current_nvim(None);
-    debug!("Server session ended");
+    session.io_handle.abort();
 }
 
-async fn run_with_reconnect(
-    handler: NeovimHandler,
-    grid_size: Option<GridSize<u32>>,
-    settings: Arc<Settings>,
-    proxy: EventLoopProxy<UserEvent>,
-) {
-    let address = settings.get::<CmdLineSettings>().server.unwrap_or_default();
-    let mut wait = Duration::from_secs(1);
-    debug!("Starting reconnect loop for {address}");
-    loop {
-        debug!("Attempting connection to {address}");
-        match launch(handler.clone(), grid_size, settings.clone()).await {
-            Ok(session) => {
-                info!("Connected to {address}");
-                start_ui_command_handler(session.neovim.clone(), settings.clone());
-                proxy.send_event(UserEvent::ReconnectStop).ok();
-                proxy.send_event(UserEvent::RedrawRequested).ok();
-                run_server(session).await;
-                warn!("Connection to {address} lost");
-                wait = Duration::from_secs(1);
+/// Serves the session until it ends, or until `network_change` completes.
+#[allow(clippy::too_many_arguments)]
+async fn run_server(
+    mut session: NeovimSession,
+    address: &str,
+    timeouts: &ShutdownTimeouts,
+    running_tracker: &RunningTracker,
+    settings: &Settings,
+    proxy: &impl UserEventSender,
+    clock: &dyn Clock,
+    network_change: impl Future<Output = ()>,
+) -> CloseCause {
+    let session_id = session.session_id();
+    debug!("Monitoring server connection of session {session_id}");
+    let cmdline_settings = settings.get::<CmdLineSettings>();
+    tokio::pin!(network_change);
+    let idle_disconnect = match cmdline_settings.idle_action {
+        IdleAction::Disconnect => idle_timeout(&cmdline_settings),
+        IdleAction::Quit => None,
+    };
+    let idle = wait_until_idle(idle_disconnect, &INPUT_ACTIVITY, clock.now(), clock);
+    tokio::pin!(idle);
+    let restore_view = cmdline_settings.restore_session_on_reconnect;
+    let mut capture_interval = CaptureInterval::default();
+    let cause = loop {
+        // Read on every ping, so that a changed interval applies right away
+        let ping_interval = settings.get::<ReconnectSettings>().ping_interval.max(1);
+        select! {
+            result = &mut session.io_handle => {
+                let cause = CloseCause::of(&result);
+                debug!("Server connection closed ({cause:?})");
+                if cause == CloseCause::Desync {
+                    debug!(
+                        target: CONNECTION_LOG_TARGET,
+                        "The last bytes before the protocol desync: {}",
+                        session.recent_bytes()
+                    );
+                }
+                break cause;
             }
-            Err(err) => {
-                log::error!("Failed to connect: {err}");
+            _ = &mut network_change => {
+                warn!(target: CONNECTION_LOG_TARGET, "Network changed, reconnecting");
+                if restore_view {
+                    capture_view_state(&session.neovim, address).await;
+                }
+                abort_io(&session).await;
+                break CloseCause::Dropped;
+            }
+            _ = server_switch_requested() => {
+                info!(target: CONNECTION_LOG_TARGET, "Switching away from {address}");
+                if restore_view {
+                    capture_view_state(&session.neovim, address).await;
+                }
+                abort_io(&session).await;
+                break CloseCause::Switched;
+            }
+            _ = running_tracker.wait_for_quit_request() => {
+                info!(target: CONNECTION_LOG_TARGET, "Quitting, disconnecting from {address}");
+                abort_io(&session).await;
+                break CloseCause::Quit;
+            }
+            _ = &mut idle => {
+                info!(target: CONNECTION_LOG_TARGET, "No input for the idle timeout, disconnecting");
+                if restore_view {
+                    capture_view_state(&session.neovim, address).await;
+                }
+                abort_io(&session).await;
+                break CloseCause::Idle;
+            }
+            _ = clock.sleep(Duration::from_secs(ping_interval)) => {
+                let sent_at = clock.now();
+                let answered = select! {
+                    _ = session.neovim.get_api_info() => true,
+                    _ = clock.timeout(PING_TIMEOUT) => false,
+                };
+                if !answered {
+                    warn!(target: CONNECTION_LOG_TARGET, "Connection ping timed out, aborting I/O task");
+                    abort_io(&session).await;
+                    break CloseCause::Dropped;
+                }
+                let rtt = clock.now().saturating_duration_since(sent_at);
+                debug!(target: CONNECTION_LOG_TARGET, "Ping {}ms", rtt.as_millis());
+                update_connection_state(proxy, |state| {
+                    state.rtt = Some(rtt);
+                    state.last_ping_ok = Some(clock.now());
+                });
+                // The connection is usually lost without warning, so the state is captured
+                // while it's still there
+                if restore_view && capture_interval.is_due(clock.now()) {
+                    capture_view_state(&session.neovim, address).await;
+                }
             }
         }
-        proxy
-            .send_event(UserEvent::ReconnectStart {
-                address: address.clone(),
-                wait: wait.as_secs() as u64,
-            })
-            .ok();
-        proxy.send_event(UserEvent::RedrawRequested).ok();
-        debug!("Retrying in {}s", wait.as_secs());
-        sleep(wait).await;
-        if wait < Duration::from_secs(30) {
-            wait *= 2;
+    };
+
+    shutdown(
+        session,
+        ShutdownReason::ConnectionLost,
+        timeouts,
+        running_tracker,
+    )
+    .await;
+    debug!("Server session {session_id} ended");
+    cause
+}
+
+/// How long to wait before reconnect attempt `attempt`, doubling from
+/// `neovide_reconnect_initial_backoff` up to `neovide_reconnect_max_backoff`. The settings are
+/// passed on every attempt, so that reloaded values apply to the next attempt.
+fn reconnect_wait(attempt: u32, settings: &ReconnectSettings) -> Duration {
+    let initial = settings.initial_backoff.max(1);
+    let max = settings.max_backoff.max(initial);
+    let wait = initial.saturating_mul(2u64.saturating_pow(attempt));
+    Duration::from_secs(wait.min(max))
+}
+
+/// The reconnect attempt to continue from once a connection that was up for `uptime` is lost.
+/// Usually that's the first one again, but a connection that was up shorter than
+/// `neovide_reconnect_stable_time` keeps the backoff it reached, so that a server that keeps
+/// dropping the connection right after accepting it isn't hammered every initial backoff.
+fn attempt_after_connection(attempt: u32, uptime: Duration, settings: &ReconnectSettings) -> u32 {
+    if uptime < Duration::from_secs(settings.stable_time) {
+        attempt
+    } else {
+        0
+    }
+}
+
+/// The state while waiting `wait` for reconnect attempt `attempt`.
+fn enter_backoff(state: &mut ConnectionState, attempt: u32, wait: Duration) {
+    state.status = ConnectionStatus::Reconnecting;
+    state.attempt = attempt;
+    state.backoff = Some(wait);
+    state.rtt = None;
+    state.last_ping_ok = None;
+    state.session_id = None;
+}
+
+/// What `:NeovideReconnectState` echoes, for debugging the reconnect loop.
+fn reconnect_state_message(state: &ConnectionState, paused: bool) -> String {
+    let backoff = state
+        .backoff
+        .map_or("no backoff".to_owned(), |wait| format!("backoff {wait:?}"));
+    let paused = if paused { "paused" } else { "not paused" };
+    format!(
+        "Reconnect state: {}, attempt {}, {backoff}, {paused}",
+        state.status, state.attempt
+    )
+}
+
+fn reconnect_title(address: &str, remaining_secs: u64) -> String {
+    format!("Reconnecting to {address} ({remaining_secs}s)")
+}
+
+/// Waits for `wait`, announcing the remaining seconds through the window title, but stops early
+/// after a suspend. Pausing stops the countdown, and the wait ends once reconnecting is resumed.
+/// Returns how long it was paused, fails as soon as the event loop is closed.
+async fn count_down(
+    proxy: &impl UserEventSender,
+    address: &str,
+    wait: Duration,
+    clock: &dyn Clock,
+) -> Result<Duration, EventLoopClosed<UserEvent>> {
+    let mut remaining = wait;
+    let suspend = SuspendDetector::new(clock);
+    while !remaining.is_zero() {
+        // Rounded up, so that the title never shows 0s while there's still time left
+        let remaining_secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        proxy.send_user_event(UserEvent::ReconnectTitle(reconnect_title(
+            address,
+            remaining_secs,
+        )))?;
+        // The first step takes the fraction of a second, the others a whole one
+        let step = remaining - Duration::from_secs(remaining_secs - 1);
+        select! {
+            biased;
+            _ = RECONNECT_PAUSE.wait_until_paused() => {
+                let paused = wait_while_paused(proxy, address, clock).await?;
+                // Resuming reconnects right away, there's no point in waiting out the rest
+                announce_connecting(proxy, address)?;
+                return Ok(paused);
+            }
+            _ = clock.sleep(step) => {}
         }
+        // The timers stood still during the suspend, so the rest of the countdown is stale
+        if let Some(suspended) = suspend.suspended_for(clock) {
+            info!(
+                target: CONNECTION_LOG_TARGET,
+                "Resumed from a suspend of {}s, reconnecting now",
+                suspended.as_secs()
+            );
+            announce_connecting(proxy, address)?;
+            return Ok(Duration::ZERO);
+        }
+        remaining -= step;
     }
+    Ok(Duration::ZERO)
 }
 
-impl NeovimRuntime {
-    pub fn new() -> Result<Self, Error> {
-        let runtime = Builder::new_multi_thread().enable_all().build()?;
+/// While reconnecting is paused, shows that on the reconnect overlay and waits for it to be resumed.
+/// Returns how long it was paused.
+async fn wait_while_paused(
+    proxy: &impl UserEventSender,
+    address: &str,
+    clock: &dyn Clock,
+) -> Result<Duration, EventLoopClosed<UserEvent>> {
+    if !RECONNECT_PAUSE.is_paused() {
+        return Ok(Duration::ZERO);
+    }
+    let paused_at = clock.now();
+    debug!("Reconnecting to {address} is paused");
+    update_connection_state(proxy, |state| state.status = ConnectionStatus::Paused);
+    proxy.send_user_event(UserEvent::ReconnectPaused {
+        address: address.to_owned(),
+    })?;
+    proxy.send_user_event(UserEvent::ReconnectTitle(format!(
+        "Reconnect to {address} paused"
+    )))?;
+    request_redraw(proxy)?;
+    RECONNECT_PAUSE.wait_while_paused().await;
+    Ok(clock.now().saturating_duration_since(paused_at))
+}
 
-        Ok(Self { runtime })
+/// After the `--idle-timeout` disconnected, shows that on the reconnect overlay and waits for the
+/// next input.
+async fn wait_for_input_after_idle(
+    proxy: &impl UserEventSender,
+    address: &str,
+) -> Result<(), EventLoopClosed<UserEvent>> {
+    update_connection_state(proxy, |state| {
+        state.status = ConnectionStatus::Idle;
+        state.rtt = None;
+        state.last_ping_ok = None;
+        state.session_id = None;
+    });
+    proxy.send_user_event(UserEvent::IdleDisconnected {
+        address: address.to_owned(),
+    })?;
+    proxy.send_user_event(UserEvent::ReconnectTitle(format!(
+        "Disconnected from {address} while idle"
+    )))?;
+    request_redraw(proxy)?;
+    INPUT_ACTIVITY.wait_for_input().await;
+    debug!("Input after the idle timeout, reconnecting to {address}");
+    Ok(())
+}
+
+/// Shows the connecting overlay, for the first attempt, which is often the slowest one, and for
+/// an attempt made right away after a suspend.
+fn announce_connecting(
+    proxy: &impl UserEventSender,
+    address: &str,
+) -> Result<(), EventLoopClosed<UserEvent>> {
+    proxy.send_user_event(UserEvent::ConnectStart {
+        address: address.to_owned(),
+    })?;
+    proxy.send_user_event(UserEvent::ReconnectTitle(format!(
+        "Connecting to {address}"
+    )))?;
+    request_redraw(proxy)
+}
+
+/// Shows the reconnect overlay and waits for the next attempt, or until the reconnect now key is
+/// pressed or another server is switched to. Returns how long reconnecting was paused meanwhile.
+async fn wait_for_retry(
+    proxy: &impl UserEventSender,
+    address: &str,
+    wait: Duration,
+    clock: &dyn Clock,
+) -> Result<Duration, EventLoopClosed<UserEvent>> {
+    proxy.send_user_event(UserEvent::ReconnectStart {
+        address: address.to_owned(),
+        wait: wait.as_secs(),
+    })?;
+    request_redraw(proxy)?;
+    debug!(target: CONNECTION_LOG_TARGET, "Retrying in {}s", wait.as_secs());
+    select! {
+        result = count_down(proxy, address, wait, clock) => result,
+        _ = RECONNECT_NOW.notified() => Ok(Duration::ZERO),
+        // The switch is applied by the loop before the next attempt
+        _ = server_switch_requested() => Ok(Duration::ZERO),
     }
+}
 
-    pub fn launch(
-        &mut self,
-        event_loop_proxy: EventLoopProxy<UserEvent>,
-        grid_size: Option<GridSize<u32>>,
-        running_tracker: RunningTracker,
-        settings: Arc<Settings>,
-    ) -> Result<()> {
-        let handler = start_editor(event_loop_proxy.clone(), running_tracker, settings.clone());
-        if settings.get::<CmdLineSettings>().server.is_some() {
-            let proxy = event_loop_proxy.clone();
-            let settings_clone = settings.clone();
-            self.runtime.spawn(async move {
-                run_with_reconnect(handler, grid_size, settings_clone, proxy).await;
-            });
-        } else {
-            let session = self
-                .runtime
-                .block_on(launch(handler, grid_size, settings.clone()))?;
-            let nvim = session.neovim.clone();
+/// Waits for the next attempt after a dropped connection. The part of `wait` within the
+/// `--disconnect-grace` that's `grace_left` isn't shown, so that a connection that comes back
+/// right away never shows the reconnect overlay, the rest is shown as usual.
+async fn wait_for_retry_with_grace(
+    proxy: &impl UserEventSender,
+    address: &str,
+    wait: Duration,
+    grace_left: Duration,
+    clock: &dyn Clock,
+) -> Result<Duration, EventLoopClosed<UserEvent>> {
+    if grace_left.is_zero() {
+        return wait_for_retry(proxy, address, wait, clock).await;
+    }
+    let silent = wait.min(grace_left);
+    debug!(
+        target: CONNECTION_LOG_TARGET,
+        "Retrying in {}ms within the disconnect grace",
+        silent.as_millis()
+    );
+    select! {
+        _ = clock.sleep(silent) => {}
+        _ = RECONNECT_NOW.notified() => return Ok(Duration::ZERO),
+        _ = server_switch_requested() => return Ok(Duration::ZERO),
+    }
+    match wait.saturating_sub(silent) {
+        rest if rest.is_zero() => Ok(Duration::ZERO),
+        rest => wait_for_retry(proxy, address, rest, clock).await,
+    }
+}
+
+/// Stops at an error that `--reconnect-on` doesn't retry, and shows it until the window is
+/// closed.
+fn give_up_connecting(
+    proxy: &impl UserEventSender,
+    address: &str,
+    error: &anyhow::Error,
+    running_tracker: &RunningTracker,
+) {
+    let kind = classify(error);
+    log::error!(
+        target: CONNECTION_LOG_TARGET,
+        "Not reconnecting to {address} after the {kind:?} error"
+    );
+    running_tracker.quit_with_code(1, "connection error that isn't retried");
+    proxy
+        .send_user_event(UserEvent::ConnectFailed {
+            address: address.to_owned(),
+            message: format!("{error:#}"),
+        })
+        .ok();
+    proxy
+        .send_user_event(UserEvent::ReconnectTitle(format!(
+            "Could not connect to {address}"
+        )))
+        .ok();
+    request_redraw(proxy).ok();
+}
+
+/// Whether `--reconnect-max-duration` is used up, in which case Neovide quits with
+/// `RECONNECT_GAVE_UP_EXIT_CODE`.
+fn reconnect_budget_exhausted(
+    budget: &ReconnectBudget,
+    clock: &dyn Clock,
+    running_tracker: &RunningTracker,
+    proxy: &impl UserEventSender,
+) -> bool {
+    if !budget.is_exhausted(clock.now()) {
+        return false;
+    }
+    log::error!(
+        target: CONNECTION_LOG_TARGET,
+        "Giving up reconnecting, --reconnect-max-duration exceeded"
+    );
+    running_tracker.quit_with_code(
+        RECONNECT_GAVE_UP_EXIT_CODE,
+        "reconnect max duration exceeded",
+    );
+    proxy.send_user_event(UserEvent::NeovimExited).ok();
+    true
+}
+
+/// How the reconnect loop reaches Neovim, so that the loop can be run without a server.
+#[async_trait]
+trait Connector: Send + Sync {
+    /// Cheaply checks that something accepts connections at `target`, before the full launch.
+    async fn probe(&self, target: &str, probe_timeout: Duration) -> std::io::Result<()>;
+
+    /// Connects to the `--server` and attaches the UI.
+    async fn launch(&self, grid_size: Option<GridSize<u32>>) -> Result<NeovimSession>;
+
+    /// Starts the embedded Neovim of `--fallback-embedded-after` and attaches the UI.
+    async fn launch_fallback(&self, grid_size: Option<GridSize<u32>>) -> Result<NeovimSession>;
+
+    /// Returns when the network changes, never when `enabled` is false.
+    async fn network_changed(&self, enabled: bool) {
+        network_changed(enabled).await
+    }
+}
+
+/// Connects to the server for real, rendering through the window's event loop.
+struct ServerConnector {
+    handler: NeovimHandler,
+    settings: Arc<Settings>,
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+#[async_trait]
+impl Connector for ServerConnector {
+    async fn probe(&self, target: &str, probe_timeout: Duration) -> std::io::Result<()> {
+        let options = TcpOptions::from_settings(&self.settings.get::<CmdLineSettings>());
+        probe_server(target, probe_timeout, &options).await
+    }
+
+    async fn launch(&self, grid_size: Option<GridSize<u32>>) -> Result<NeovimSession> {
+        launch(
+            self.handler.clone(),
+            grid_size,
+            self.settings.clone(),
+            self.proxy.clone(),
+        )
+        .await
+    }
+
+    async fn launch_fallback(&self, grid_size: Option<GridSize<u32>>) -> Result<NeovimSession> {
+        let instance = NeovimInstance::embedded(create_nvim_command(&self.settings)?);
+        attach(
+            instance,
+            None,
+            self.handler.clone(),
+            grid_size,
+            self.settings.clone(),
+            self.proxy.clone(),
+        )
+        .await
+    }
+}
+
+async fn run_with_reconnect<P>(
+    connector: &dyn Connector,
+    grid_size: Option<GridSize<u32>>,
+    running_tracker: RunningTracker,
+    settings: Arc<Settings>,
+    proxy: P,
+    clock: Arc<dyn Clock>,
+    observer: Arc<dyn ReconnectObserver>,
+) where
+    P: UserEventSender + Clone + Send + Sync + 'static,
+{
+    let mut cmdline_settings = settings.get::<CmdLineSettings>();
+    let timeouts = ShutdownTimeouts::from_settings(&cmdline_settings);
+    let mut address = cmdline_settings.server.clone().unwrap_or_default();
+    let probe_timeout = Duration::from_millis(cmdline_settings.probe_timeout);
+    let disconnect_grace = Duration::from_millis(cmdline_settings.disconnect_grace);
+    // Until when the overlay isn't shown after the connection dropped
+    let mut grace_until = None;
+    let mut attempt = 0;
+    let mut failures = 0;
+    let mut budget = ReconnectBudget::new(cmdline_settings.reconnect_max_duration, clock.now());
+    update_connection_state(&proxy, |state| state.address = Some(address.clone()));
+    debug!("Starting reconnect loop for {address}");
+    // Shown until the first attempt succeeds, or the reconnect overlay replaces it
+    announce_connecting(&proxy, &address).ok();
+    loop {
+        if let Some(switched) = take_server_switch(&settings) {
+            info!(target: CONNECTION_LOG_TARGET, "Switching from {address} to {switched}");
+            cmdline_settings = settings.get::<CmdLineSettings>();
+            address = switched;
+            attempt = 0;
+            failures = 0;
+            budget.restart(clock.now());
+            update_connection_state(&proxy, |state| state.address = Some(address.clone()));
+            announce_connecting(&proxy, &address).ok();
+        }
+        match wait_while_paused(&proxy, &address, clock.as_ref()).await {
+            Ok(paused) => budget.exclude(paused),
+            Err(_) => {
+                running_tracker.request_quit();
+                observer.on_give_up(attempt);
+                break;
+            }
+        }
+        if reconnect_budget_exhausted(&budget, clock.as_ref(), &running_tracker, &proxy) {
+            observer.on_give_up(attempt);
+            break;
+        }
+        observer.on_attempt(attempt);
+        // Expanded again on every attempt, so that a changed environment variable applies
+        let target = server_address(&cmdline_settings).unwrap_or_default();
+        let probe = if probe_timeout.is_zero() {
+            Ok(())
+        } else {
+            connector.probe(&target, probe_timeout).await
+        };
+        let result = match probe {
+            Ok(()) => {
+                debug!(target: CONNECTION_LOG_TARGET, "Attempting connection to {target}");
+                // Keeps the size of the previous connection, which may have been resized since
+                let grid_size = current_grid_size().or(grid_size);
+                connector.launch(grid_size).await.map_err(Some)
+            }
+            Err(err) => {
+                debug!(target: CONNECTION_LOG_TARGET, "Probe of {target} failed: {err}");
+                Err(None)
+            }
+        };
+        report_attempt_result(observer.as_ref(), attempt, &result);
+        match result {
+            Ok(session) => {
+                let session_id = session.session_id();
+                info!(
+                    target: CONNECTION_LOG_TARGET,
+                    "Connected to {target} in session {session_id}"
+                );
+                start_ui_command_handler(
+                    session.neovim.clone(),
+                    settings.clone(),
+                    running_tracker.clone(),
+                    RpcErrorReporter::new(proxy.clone()),
+                );
+                // The expanded address, the one that was actually connected to
+                register_session(&target, session.neovim.clone());
+                // It was about the downtime that's over now
+                clear_reconnect_notice();
+                if let Some(command_line) = &cmdline_settings.on_connect_cmd {
+                    run_on_connect(command_line, &target);
+                }
+                // Without a window, the session ends right away
+                if show_session(&proxy).is_err() {
+                    running_tracker.request_quit();
+                }
+                let connected_at = clock.now();
+                let cause = run_server(
+                    session,
+                    &address,
+                    &timeouts,
+                    &running_tracker,
+                    &settings,
+                    &proxy,
+                    clock.as_ref(),
+                    connector.network_changed(cmdline_settings.reconnect_on_network_change),
+                )
+                .await;
+                unregister_session(&target);
+                grace_until = matches!(cause, CloseCause::Dropped | CloseCause::Desync)
+                    .then(|| clock.now() + disconnect_grace);
+                if cause == CloseCause::Idle {
+                    info!(
+                        target: CONNECTION_LOG_TARGET,
+                        "Disconnected from {address} while idle, ending session {session_id}"
+                    );
+                    if wait_for_input_after_idle(&proxy, &address).await.is_err() {
+                        running_tracker.request_quit();
+                        observer.on_give_up(attempt);
+                        break;
+                    }
+                    attempt = 0;
+                    failures = 0;
+                    budget.restart(clock.now());
+                    continue;
+                } else if cause == CloseCause::Quit {
+                    info!(
+                        target: CONNECTION_LOG_TARGET,
+                        "Quitting, ending session {session_id} with {address}"
+                    );
+                    observer.on_give_up(attempt);
+                    break;
+                } else if cause == CloseCause::Switched {
+                    info!(
+                        target: CONNECTION_LOG_TARGET,
+                        "Left {address} for another server, ending session {session_id}"
+                    );
+                    continue;
+                } else if cause == CloseCause::Clean {
+                    info!(
+                        target: CONNECTION_LOG_TARGET,
+                        "Server {address} shut down, ending session {session_id}"
+                    );
+                    proxy
+                        .send_user_event(UserEvent::Notice("Server shut down".to_owned()))
+                        .ok();
+                } else if cause == CloseCause::Desync {
+                    warn!(
+                        target: CONNECTION_LOG_TARGET,
+                        "Connection to {address} lost in session {session_id}: protocol desync"
+                    );
+                } else {
+                    warn!(
+                        target: CONNECTION_LOG_TARGET,
+                        "Connection to {address} lost in session {session_id}"
+                    );
+                }
+                if !cause.reconnects(cmdline_settings.no_reconnect_on_clean_close) {
+                    proxy.send_user_event(UserEvent::NeovimExited).ok();
+                    observer.on_give_up(attempt);
+                    break;
+                }
+                let uptime = clock.now().saturating_duration_since(connected_at);
+                attempt =
+                    attempt_after_connection(attempt, uptime, &settings.get::<ReconnectSettings>());
+                if attempt > 0 {
+                    info!(
+                        target: CONNECTION_LOG_TARGET,
+                        "Connection to {address} only lasted {}s, keeping the reconnect backoff",
+                        uptime.as_secs()
+                    );
+                }
+                failures = 0;
+                budget.restart(clock.now());
+            }
+            Err(Some(err)) => {
+                log::error!(target: CONNECTION_LOG_TARGET, "Failed to connect: {err}");
+                if !is_retried(&err, &cmdline_settings.reconnect_on) {
+                    give_up_connecting(&proxy, &address, &err, &running_tracker);
+                    observer.on_give_up(attempt);
+                    break;
+                }
+                failures += 1;
+            }
+            Err(None) => failures += 1,
+        }
+        if should_fall_back(failures, cmdline_settings.fallback_embedded_after) {
+            failures = 0;
+            let fallback = run_fallback(
+                connector,
+                current_grid_size().or(grid_size),
+                &settings,
+                &proxy,
+                &timeouts,
+                &running_tracker,
+            );
+            match fallback.await {
+                Ok(FallbackEnd::Quit) => {
+                    proxy.send_user_event(UserEvent::NeovimExited).ok();
+                    observer.on_give_up(attempt);
+                    break;
+                }
+                Ok(FallbackEnd::SwitchBack) => {
+                    attempt = 0;
+                    budget.restart(clock.now());
+                    continue;
+                }
+                Err(err) => log::error!("Failed to start the fallback Neovim: {err:?}"),
+            }
+        }
+        if reconnect_budget_exhausted(&budget, clock.as_ref(), &running_tracker, &proxy) {
+            observer.on_give_up(attempt);
+            break;
+        }
+        let wait = budget.cap(
+            reconnect_wait(attempt, &settings.get::<ReconnectSettings>()),
+            clock.now(),
+        );
+        attempt = attempt.saturating_add(1);
+        update_connection_state(&proxy, |state| enter_backoff(state, attempt, wait));
+        publish(ConnectionEvent::Reconnecting { attempt });
+        let grace_left = grace_until.map_or(Duration::ZERO, |until| {
+            until.saturating_duration_since(clock.now())
+        });
+        match wait_for_retry_with_grace(&proxy, &address, wait, grace_left, clock.as_ref()).await {
+            Ok(paused) => budget.exclude(paused),
+            Err(_) => {
+                // Nobody is left to show the session to
+                running_tracker.request_quit();
+                observer.on_give_up(attempt);
+                break;
+            }
+        }
+    }
+    debug!("Reconnect loop for {address} stopped");
+}
+
+impl NeovimRuntime {
+    pub fn new() -> Result<Self, Error> {
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+
+        Ok(Self {
+            runtime,
+            shutdown: CancellationToken::new(),
+            running_tracker: None,
+            reconnect_observer: Arc::new(NoopObserver),
+        })
+    }
+
+    /// Lets a program that embeds the bridge follow the reconnect loop, it has to be set before
+    /// `launch`.
+    #[allow(dead_code)] // Neovide itself doesn't observe the loop
+    pub fn set_reconnect_observer(&mut self, observer: Arc<dyn ReconnectObserver>) {
+        self.reconnect_observer = observer;
+    }
+
+    /// Spawns `task` on the runtime, it's dropped once a shutdown is requested.
+    fn spawn_cancellable(&self, task: impl Future<Output = ()> + Send + 'static) -> JoinHandle<()> {
+        let shutdown = self.shutdown.clone();
+        self.runtime.spawn(async move {
+            select! {
+                _ = task => {}
+                _ = shutdown.cancelled() => {}
+            }
+        })
+    }
+
+    /// Shuts Neovide down with the exit `code` because of `reason`, for programs that embed the
+    /// bridge. The tracker records the code and the reason, the tasks started by `launch` stop,
+    /// and an embedded Neovim is shut down the same way as when the window is closed, after which
+    /// the window is told that Neovim exited. It's idempotent, only the first request counts, so
+    /// it can be called from every path that tears the embedder down, for example
+    /// `runtime.request_shutdown(0, "editor closed by the host")` when the host closes the editor.
+    #[allow(dead_code)] // Called by embedders, Neovide itself quits through the window
+    pub fn request_shutdown(&self, code: u8, reason: &str) {
+        if self.shutdown.is_cancelled() {
+            return;
+        }
+        if let Some(running_tracker) = &self.running_tracker {
+            running_tracker.set_quit_reason(QuitReason::EmbedderRequested);
+            running_tracker.quit_with_code(code, reason);
+        }
+        self.shutdown.cancel();
+    }
+
+    /// A handle to the runtime the Neovim connection runs on, for spawning other tasks on it
+    /// instead of starting a second runtime, like the window does for desktop notifications.
+    pub fn handle(&self) -> Handle {
+        self.runtime.handle().clone()
+    }
+
+    /// Connects to Neovim and starts the tasks serving it. Returns the task of an embedded Neovim,
+    /// for waiting on it during the shutdown, and `None` for the reconnect loop of a `--server`.
+    pub fn launch(
+        &mut self,
+        event_loop_proxy: EventLoopProxy<UserEvent>,
+        grid_size: Option<GridSize<u32>>,
+        running_tracker: RunningTracker,
+        settings: Arc<Settings>,
+    ) -> Result<Option<JoinHandle<()>>> {
+        self.running_tracker = Some(running_tracker.clone());
+        let handler = start_editor(
+            event_loop_proxy.clone(),
+            running_tracker.clone(),
+            settings.clone(),
+        );
+        self.runtime
+            .block_on(resolve_server_or_embedded(settings.as_ref()));
+        resolve_auto_server(settings.as_ref());
+        let cmdline_settings = settings.get::<CmdLineSettings>();
+        // Subscribed before anything connects, so that the log has every event
+        self.spawn_cancellable(log_connection_events(subscribe()));
+        if cmdline_settings.read_only {
+            set_read_only(&event_loop_proxy, true);
+        }
+        if let Some(path) = &cmdline_settings.record_ui_commands {
+            start_recording(path)?;
+        }
+        if let Some(path) = &cmdline_settings.ui_commands_file {
+            self.spawn_cancellable(replay_ui_commands_file(path)?);
+        }
+        if cmdline_settings.safe_profile {
+            create_safe_profile(&running_tracker)?;
+        }
+        if cmdline_settings.quit_after_seconds > 0 {
+            self.spawn_cancellable(quit_after(
+                Duration::from_secs(cmdline_settings.quit_after_seconds),
+                QUIT_AFTER_GRACE,
+                running_tracker.clone(),
+                event_loop_proxy.clone(),
+            ));
+        }
+        if let Some(idle_timeout) = idle_timeout(&cmdline_settings) {
+            // Only the reconnect loop can disconnect, anything else quits
+            let reconnects = cmdline_settings.server.is_some();
+            if cmdline_settings.idle_action == IdleAction::Quit || !reconnects {
+                let running_tracker = running_tracker.clone();
+                let proxy = event_loop_proxy.clone();
+                self.spawn_cancellable(async move {
+                    quit_when_idle(
+                        idle_timeout,
+                        QUIT_AFTER_GRACE,
+                        &INPUT_ACTIVITY,
+                        running_tracker,
+                        proxy,
+                        &SystemClock,
+                    )
+                    .await;
+                });
+            }
+        }
+        if cmdline_settings.heartbeat_interval > 0 {
+            self.spawn_cancellable(run_heartbeat(
+                Duration::from_secs(cmdline_settings.heartbeat_interval),
+                event_loop_proxy.clone(),
+            ));
+        }
+        if cmdline_settings.status_port > 0 {
+            self.spawn_cancellable(run_status_endpoint(
+                SocketAddr::new(cmdline_settings.status_bind, cmdline_settings.status_port),
+                running_tracker.clone(),
+                // Subscribed before anything connects, so that no `SessionReady` is missed
+                subscribe(),
+            ));
+        }
+        if let Some(address) = server_address(&cmdline_settings) {
+            // Reject a disallowed server up front, so that the error is shown instead of being
+            // retried forever by the reconnect loop
+            check_server_allowlist(&cmdline_settings, &address)?;
+            if let Some(sounds) = ConnectionSounds::from_settings(&cmdline_settings) {
+                // Subscribed before the loop starts, so that no event is missed
+                let events = subscribe();
+                self.spawn_cancellable(play_connection_sounds(sounds, events, SystemPlayer));
+            }
+            let connector = ServerConnector {
+                handler,
+                settings: settings.clone(),
+                proxy: event_loop_proxy.clone(),
+            };
+            let proxy = event_loop_proxy.clone();
+            let settings_clone = settings.clone();
+            let shutdown_requested = self.shutdown.clone();
+            let observer = self.reconnect_observer.clone();
             self.runtime.spawn(async move {
-                start_ui_command_handler(nvim, settings);
-                run(session, event_loop_proxy).await;
+                select! {
+                    _ = run_with_reconnect(
+                        &connector,
+                        grid_size,
+                        running_tracker,
+                        settings_clone,
+                        proxy,
+                        Arc::new(SystemClock),
+                        observer,
+                    ) => {}
+                    _ = shutdown_requested.cancelled() => {
+                        event_loop_proxy.send_event(UserEvent::NeovimExited).ok();
+                    }
+                }
             });
+            Ok(None)
+        } else {
+            // The command is rebuilt by every launch, so each retry spawns a fresh process. The
+            // descriptor of --server-fd is closed by a failed attempt, so it's never retried.
+            let retries = match cmdline_settings.server_fd {
+                Some(_) => 0,
+                None => cmdline_settings.embedded_retries,
+            };
+            let session = self
+                .runtime
+                .block_on(retry(retries, EMBEDDED_RETRY_DELAY, || {
+                    launch(
+                        handler.clone(),
+                        grid_size,
+                        settings.clone(),
+                        event_loop_proxy.clone(),
+                    )
+                }))?;
+            let timeouts = ShutdownTimeouts::from_settings(&cmdline_settings);
+            let crash_action = CrashAction::from_settings(&cmdline_settings);
+            let shutdown_requested = self.shutdown.clone();
+            Ok(Some(self.runtime.spawn(async move {
+                let mut session = session;
+                loop {
+                    start_ui_command_handler(
+                        session.neovim.clone(),
+                        settings.clone(),
+                        running_tracker.clone(),
+                        RpcErrorReporter::new(event_loop_proxy.clone()),
+                    );
+                    let restart = run(
+                        session,
+                        timeouts.clone(),
+                        running_tracker.clone(),
+                        event_loop_proxy.clone(),
+                        shutdown_requested.clone(),
+                        crash_action,
+                    )
+                    .await;
+                    if !restart {
+                        return;
+                    }
+                    let grid_size = current_grid_size().or(grid_size);
+                    match launch(
+                        handler.clone(),
+                        grid_size,
+                        settings.clone(),
+                        event_loop_proxy.clone(),
+                    )
+                    .await
+                    {
+                        Ok(restarted) => {
+                            // The crash isn't the exit code anymore
+                            running_tracker.reset_quit();
+                            session = restarted;
+                        }
+                        Err(error) => {
+                            log::error!("Could not restart Neovim: {error:?}");
+                            event_loop_proxy.send_event(UserEvent::NeovimExited).ok();
+                            return;
+                        }
+                    }
+                }
+            })))
+        }
+    }
+
+    /// Gives the task of the embedded Neovim that `launch` returned up to `limit` to shut down
+    /// after the event loop did. Returns whether it finished.
+    pub fn wait_for(&self, task: JoinHandle<()>, limit: Duration) -> bool {
+        // The timer has to be made on the runtime, the caller isn't on it
+        self.runtime
+            .block_on(async { timeout(limit, task).await })
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use tokio::{net::TcpListener, spawn};
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::{
+        bridge::recording_nvim::{recording_nvim, Requests},
+        clock::MockClock,
+    };
+
+    fn server_or_embedded_settings(address: &str) -> Settings {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server_or_embedded = Some(address.to_owned());
+        settings.set(&cmdline_settings);
+        settings
+    }
+
+    #[tokio::test]
+    async fn test_server_or_embedded_connects_to_running_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let settings = server_or_embedded_settings(&address);
+
+        resolve_server_or_embedded(&settings).await;
+        assert_eq!(settings.get::<CmdLineSettings>().server, Some(address));
+    }
+
+    #[tokio::test]
+    async fn test_server_or_embedded_falls_back_to_embedded() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let settings = server_or_embedded_settings(&address);
+
+        resolve_server_or_embedded(&settings).await;
+        assert_eq!(settings.get::<CmdLineSettings>().server, None);
+    }
+
+    #[test]
+    fn test_auto_server_falls_back_to_embedded() {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server = Some(AUTO_SERVER.to_owned());
+        cmdline_settings.server_discovery_dir = Some("/nonexistent/neovide".to_owned());
+        settings.set(&cmdline_settings);
+
+        resolve_auto_server(&settings);
+        assert_eq!(settings.get::<CmdLineSettings>().server, None);
+    }
+
+    #[tokio::test]
+    async fn test_run_ends_when_the_event_loop_closes() {
+        let timeouts = ShutdownTimeouts::default();
+        let probe_interval = Duration::from_millis(10);
+        let mut io_handle = spawn(std::future::pending::<()>());
+        let ended = timeout(
+            Duration::from_secs(5),
+            wait_for_session_end(
+                &mut io_handle,
+                None,
+                &CancellationToken::new(),
+                &timeouts,
+                event_loop_closed(&ClosedSender, probe_interval),
+            ),
+        )
+        .await;
+        assert_eq!(ended, Ok(true));
+
+        // While the event loop is there, only the end of the session ends it
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut io_handle = spawn(sleep(Duration::from_millis(50)));
+        let sender = ChannelSender(sender);
+        let ended = wait_for_session_end(
+            &mut io_handle,
+            None,
+            &CancellationToken::new(),
+            &timeouts,
+            event_loop_closed(&sender, probe_interval),
+        )
+        .await;
+        assert!(!ended);
+        assert_eq!(receiver.try_recv(), Ok(UserEvent::Probe));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_only_a_leaving_neovim_is_terminated() {
+        let timeouts = ShutdownTimeouts {
+            ignored_quit: Some(Duration::from_millis(20)),
+            terminate: Duration::from_millis(20),
+            ..ShutdownTimeouts::default()
+        };
+        let mut process = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let mut io_handle = spawn(std::future::pending::<()>());
+        let leaving = CancellationToken::new();
+        let ended_early = {
+            let session_end = wait_for_session_end(
+                &mut io_handle,
+                Some(&mut process),
+                &leaving,
+                &timeouts,
+                std::future::pending(),
+            );
+            tokio::pin!(session_end);
+
+            // Asked to quit, but the quit was cancelled before VimLeavePre
+            assert!(timeout(Duration::from_millis(200), &mut session_end)
+                .await
+                .is_err());
+
+            leaving.cancel();
+            timeout(Duration::from_secs(5), session_end).await.unwrap()
+        };
+        assert!(!ended_early);
+        assert!(process.try_wait().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_requested_shutdown_stops_the_tasks() {
+        let mut runtime = NeovimRuntime::new().unwrap();
+        let running_tracker = RunningTracker::new();
+        runtime.running_tracker = Some(running_tracker.clone());
+        let task = runtime.spawn_cancellable(std::future::pending());
+
+        runtime.request_shutdown(3, "closed by the host");
+        runtime.request_shutdown(5, "closed again");
+        assert!(runtime.wait_for(task, Duration::from_secs(5)));
+        assert_eq!(running_tracker.exit_code(), 3);
+        assert_eq!(
+            running_tracker.quit_reason(),
+            Some(QuitReason::EmbedderRequested)
+        );
+    }
+
+    #[test]
+    fn test_instance_from_settings() {
+        let settings = Settings::new();
+        settings.set(&CmdLineSettings::default());
+        assert!(matches!(
+            NeovimInstance::from_settings(&settings),
+            Ok(NeovimInstance::Embedded(_))
+        ));
+
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server = Some("localhost:6666".to_owned());
+        settings.set(&cmdline_settings);
+        assert!(matches!(
+            NeovimInstance::from_settings(&settings),
+            Ok(NeovimInstance::Server { address, .. }) if address == "localhost:6666"
+        ));
+
+        cmdline_settings.server = Some("docker://devbox".to_owned());
+        settings.set(&cmdline_settings);
+        assert!(matches!(
+            NeovimInstance::from_settings(&settings),
+            Ok(NeovimInstance::Docker { container }) if container == "devbox"
+        ));
+
+        cmdline_settings.server = Some("localhost:6666".to_owned());
+        cmdline_settings.server_allowlist = Some("127.0.0.1:*".to_owned());
+        settings.set(&cmdline_settings);
+        assert!(NeovimInstance::from_settings(&settings).is_err());
+    }
+
+    struct ChannelSender(std::sync::mpsc::Sender<UserEvent>);
+
+    impl UserEventSender for ChannelSender {
+        fn send_user_event(
+            &self,
+            event: UserEvent,
+        ) -> Result<(), winit::event_loop::EventLoopClosed<UserEvent>> {
+            self.0.send(event).ok();
+            Ok(())
         }
-        Ok(())
+    }
+
+    // An event loop that has already shut down
+    struct ClosedSender;
+
+    impl UserEventSender for ClosedSender {
+        fn send_user_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+            Err(EventLoopClosed(event))
+        }
+    }
+
+    /// Records the events, and closes like the event loop does once `closes_at` matched `count`
+    /// of them.
+    #[derive(Clone)]
+    struct RecordingSender {
+        events: Arc<parking_lot::Mutex<Vec<UserEvent>>>,
+        matches_left: Arc<AtomicU32>,
+        closes_at: fn(&UserEvent) -> bool,
+    }
+
+    impl RecordingSender {
+        fn closing_at(closes_at: fn(&UserEvent) -> bool) -> Self {
+            Self::closing_at_nth(1, closes_at)
+        }
+
+        fn closing_at_nth(count: u32, closes_at: fn(&UserEvent) -> bool) -> Self {
+            Self {
+                events: Arc::default(),
+                matches_left: Arc::new(AtomicU32::new(count)),
+                closes_at,
+            }
+        }
+
+        fn events(&self) -> Vec<UserEvent> {
+            self.events.lock().clone()
+        }
+    }
+
+    impl UserEventSender for RecordingSender {
+        fn send_user_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+            if self.matches_left.load(Ordering::SeqCst) == 0 {
+                return Err(EventLoopClosed(event));
+            }
+            self.events.lock().push(event.clone());
+            if (self.closes_at)(&event) && self.matches_left.fetch_sub(1, Ordering::SeqCst) == 1 {
+                return Err(EventLoopClosed(event));
+            }
+            Ok(())
+        }
+    }
+
+    /// What a [`FakeConnector`] does on an attempt.
+    enum Attempt {
+        /// Nothing listens, so the probe fails
+        Refused,
+        /// The probe succeeds, but the launch fails with the error
+        Fails(&'static str),
+        /// Connects to a fake server, which answers every request
+        #[cfg(unix)]
+        Connects,
+        /// Fails to launch with an IO error of this kind
+        FailsWith(std::io::ErrorKind),
+        /// Connects, but the fake server goes away right after
+        #[cfg(unix)]
+        Drops,
+        /// Connects to a fake server, which never answers a ping
+        #[cfg(unix)]
+        StopsAnswering,
+    }
+
+    /// Goes through `attempts` in turn, and refuses every attempt after them.
+    #[derive(Default)]
+    struct FakeConnector {
+        attempts: parking_lot::Mutex<std::collections::VecDeque<Attempt>>,
+        probes: AtomicU32,
+        launches: AtomicU32,
+        fallbacks: AtomicU32,
+        /// Simulates a change of the network
+        network_change: tokio::sync::Notify,
+        /// The fake server of the fallback, when there is one
+        #[cfg(unix)]
+        fallback_server: Option<parking_lot::Mutex<Option<tokio::task::JoinHandle<Vec<String>>>>>,
+    }
+
+    impl FakeConnector {
+        fn new(attempts: impl IntoIterator<Item = Attempt>) -> Self {
+            Self {
+                attempts: parking_lot::Mutex::new(attempts.into_iter().collect()),
+                ..Self::default()
+            }
+        }
+
+        /// Launches the fallback with a fake server, which the test can stop.
+        #[cfg(unix)]
+        fn with_fallback(self) -> Self {
+            Self {
+                fallback_server: Some(parking_lot::Mutex::default()),
+                ..self
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Connector for FakeConnector {
+        async fn probe(&self, _target: &str, _probe_timeout: Duration) -> std::io::Result<()> {
+            self.probes.fetch_add(1, Ordering::SeqCst);
+            let mut attempts = self.attempts.lock();
+            match attempts.front() {
+                Some(Attempt::Refused) | None => {
+                    attempts.pop_front();
+                    Err(Error::from(std::io::ErrorKind::ConnectionRefused))
+                }
+                Some(_) => Ok(()),
+            }
+        }
+
+        async fn launch(&self, _grid_size: Option<GridSize<u32>>) -> Result<NeovimSession> {
+            self.launches.fetch_add(1, Ordering::SeqCst);
+            let attempt = self.attempts.lock().pop_front();
+            match attempt {
+                Some(Attempt::Fails(message)) => Err(anyhow!(message)),
+                Some(Attempt::FailsWith(kind)) => Err(Error::from(kind).into()),
+                #[cfg(unix)]
+                Some(Attempt::Connects) => Ok(fake_server::socket_pair_session().await.0),
+                #[cfg(unix)]
+                Some(Attempt::Drops) => {
+                    let (session, server) = fake_server::socket_pair_session().await;
+                    server.abort();
+                    Ok(session)
+                }
+                #[cfg(unix)]
+                Some(Attempt::StopsAnswering) => {
+                    let stall = fake_server::Stall::Method("nvim_get_api_info");
+                    Ok(fake_server::stalling_socket_pair_session(Some(stall))
+                        .await
+                        .0)
+                }
+                Some(Attempt::Refused) | None => {
+                    Err(Error::from(std::io::ErrorKind::ConnectionRefused).into())
+                }
+            }
+        }
+
+        async fn launch_fallback(
+            &self,
+            _grid_size: Option<GridSize<u32>>,
+        ) -> Result<NeovimSession> {
+            self.fallbacks.fetch_add(1, Ordering::SeqCst);
+            #[cfg(unix)]
+            if let Some(fallback_server) = &self.fallback_server {
+                let (session, server) = fake_server::socket_pair_session().await;
+                *fallback_server.lock() = Some(server);
+                return Ok(session);
+            }
+            Err(anyhow!("No fallback in the tests"))
+        }
+
+        async fn network_changed(&self, enabled: bool) {
+            if !enabled {
+                return std::future::pending().await;
+            }
+            self.network_change.notified().await
+        }
+    }
+
+    /// The settings of a `--server` at `address`, which the tests change further.
+    fn reconnect_settings(address: &str) -> (Arc<Settings>, CmdLineSettings) {
+        let settings = Arc::new(Settings::new());
+        settings.register::<ReconnectSettings>();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server = Some(address.to_owned());
+        cmdline_settings.probe_timeout = 500;
+        settings.set(&cmdline_settings);
+        (settings, cmdline_settings)
+    }
+
+    /// Runs the reconnect loop until `sender` closes, failing when it doesn't stop.
+    async fn run_reconnect_loop(
+        connector: &FakeConnector,
+        settings: Arc<Settings>,
+        sender: &RecordingSender,
+        clock: Arc<dyn Clock>,
+    ) -> RunningTracker {
+        let running_tracker = RunningTracker::new();
+        let run = run_with_reconnect(
+            connector,
+            None,
+            running_tracker.clone(),
+            settings,
+            sender.clone(),
+            clock,
+            Arc::new(NoopObserver),
+        );
+        timeout(Duration::from_secs(10), run)
+            .await
+            .expect("The reconnect loop didn't stop");
+        running_tracker
+    }
+
+    fn is_reconnect_start(event: &UserEvent) -> bool {
+        matches!(event, UserEvent::ReconnectStart { .. })
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_closed_port_goes_straight_to_the_backoff() {
+        let (settings, _) = reconnect_settings("127.0.0.1:6666");
+        let connector = FakeConnector::new([Attempt::Refused, Attempt::Fails("not Neovim")]);
+        let sender = RecordingSender::closing_at_nth(2, is_reconnect_start);
+        run_reconnect_loop(&connector, settings, &sender, Arc::new(MockClock::new())).await;
+
+        // Only the attempt whose probe succeeded was launched
+        assert_eq!(connector.probes.load(Ordering::SeqCst), 2);
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 1);
+        let initial_backoff = ReconnectSettings::default().initial_backoff;
+        assert_eq!(
+            sender.events().into_iter().find(is_reconnect_start),
+            Some(UserEvent::ReconnectStart {
+                address: "127.0.0.1:6666".to_owned(),
+                wait: initial_backoff,
+            })
+        );
+
+        // Without the probe, the launch finds out instead
+        let (settings, mut cmdline_settings) = reconnect_settings("127.0.0.1:6666");
+        cmdline_settings.probe_timeout = 0;
+        settings.set(&cmdline_settings);
+        let connector = FakeConnector::new([Attempt::Refused]);
+        let sender = RecordingSender::closing_at(is_reconnect_start);
+        run_reconnect_loop(&connector, settings, &sender, Arc::new(MockClock::new())).await;
+        assert_eq!(connector.probes.load(Ordering::SeqCst), 0);
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 1);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_auth_error_stops_the_loop_while_a_refused_connection_retries() {
+        let (settings, _) = reconnect_settings("127.0.0.1:6666");
+        let connector = FakeConnector::new([
+            Attempt::FailsWith(std::io::ErrorKind::ConnectionRefused),
+            Attempt::FailsWith(std::io::ErrorKind::PermissionDenied),
+        ]);
+        let sender = RecordingSender::closing_at(|_| false);
+        let running_tracker =
+            run_reconnect_loop(&connector, settings, &sender, Arc::new(MockClock::new())).await;
+
+        // Reconnecting stopped at the auth error, with nothing launched after it
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 2);
+        assert_eq!(running_tracker.exit_code(), 1);
+        assert!(sender.events().contains(&UserEvent::ReconnectTitle(
+            "Could not connect to 127.0.0.1:6666".to_owned()
+        )));
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_loop_stops_when_the_window_is_gone_after_connecting() {
+        let (settings, _) = reconnect_settings("127.0.0.1:6666");
+        let connector = FakeConnector::new([Attempt::Connects]);
+        let sender = RecordingSender::closing_at(|event| matches!(event, UserEvent::ReconnectStop));
+        let running_tracker =
+            run_reconnect_loop(&connector, settings, &sender, Arc::new(MockClock::new())).await;
+
+        // The session was left right away, instead of serving a window that's gone
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 1);
+        assert_eq!(running_tracker.quit_reason(), Some(QuitReason::UserClosed));
+        assert!(!sender.events().iter().any(is_reconnect_start));
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_reconnect_notice_is_cleared_by_the_next_connection() {
+        let (settings, _) = reconnect_settings("127.0.0.1:6666");
+        set_reconnect_notice("Maintenance in 5 min".to_owned());
+        let connector = FakeConnector::new([Attempt::Refused, Attempt::Connects]);
+        let sender = RecordingSender::closing_at(|event| matches!(event, UserEvent::ReconnectStop));
+        run_reconnect_loop(&connector, settings, &sender, Arc::new(MockClock::new())).await;
+
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 1);
+        assert_eq!(reconnect_notice(), None);
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_on_connect_cmd_gets_the_expanded_address() {
+        let _port = scoped_env::ScopedEnv::set("NEOVIDE_TEST_ON_CONNECT_PORT", "6666");
+        let (settings, mut cmdline_settings) =
+            reconnect_settings("127.0.0.1:${NEOVIDE_TEST_ON_CONNECT_PORT}");
+        let path = std::env::temp_dir().join(format!(
+            "neovide-on-connect-expanded-{}",
+            std::process::id()
+        ));
+        cmdline_settings.on_connect_cmd = Some(format!(
+            "sh -c 'printf %s \"$1\" > {}' hook",
+            path.display()
+        ));
+        settings.set(&cmdline_settings);
+        let connector = FakeConnector::new([Attempt::Connects]);
+        let sender = RecordingSender::closing_at(|event| matches!(event, UserEvent::ReconnectStop));
+        run_reconnect_loop(&connector, settings, &sender, Arc::new(MockClock::new())).await;
+
+        // The hook runs in the background
+        let address = timeout(Duration::from_secs(5), async {
+            loop {
+                match std::fs::read_to_string(&path) {
+                    Ok(address) if !address.is_empty() => return address,
+                    _ => sleep(Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await
+        .expect("The --on-connect-cmd didn't run");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(address, "127.0.0.1:6666");
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_warmup_defers_the_connected_signal() {
+        let (session, server) = fake_server::socket_pair_session().await;
+        let sender = RecordingSender::closing_at(|_| false);
+        let warmup = ConnectWarmup {
+            threshold: Duration::from_secs(1),
+            pings: 3,
+        };
+        update_connection_state(&ClosedSender, |state| {
+            state.status = ConnectionStatus::Connecting;
+        });
+        mark_connected(
+            &session.neovim,
+            Some("127.0.0.1:6666"),
+            Some(warmup),
+            &sender,
+            &MockClock::new(),
+            |state| state.attempt = 0,
+        )
+        .await;
+        assert_eq!(connection_state().status, ConnectionStatus::Connected);
+        session.io_handle.abort();
+        drop(session);
+
+        // Connected only after the pings, which the fake server answered right away
+        let events = sender.events();
+        assert!(matches!(events[0], UserEvent::ConnectStabilizing { .. }));
+        assert_eq!(events.last(), Some(&UserEvent::ConnectionStateChanged));
+        let pings = timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            pings
+                .iter()
+                .filter(|method| *method == "nvim_get_api_info")
+                .count(),
+            3
+        );
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_fallback_stays_when_the_switch_back_is_cancelled() {
+        let (settings, mut cmdline_settings) = reconnect_settings("127.0.0.1:6666");
+        cmdline_settings.fallback_embedded_after = 2;
+        settings.set(&cmdline_settings);
+        let connector = Arc::new(FakeConnector::default().with_fallback());
+        let sender = RecordingSender::closing_at(|event| matches!(event, UserEvent::NeovimExited));
+        let reconnect_loop = spawn({
+            let connector = connector.clone();
+            let sender = sender.clone();
+            async move {
+                run_with_reconnect(
+                    connector.as_ref(),
+                    None,
+                    RunningTracker::new(),
+                    settings,
+                    sender,
+                    Arc::new(MockClock::new()),
+                    Arc::new(NoopObserver),
+                )
+                .await;
+            }
+        });
+
+        // The fallback starts after the second failed attempt
+        timeout(Duration::from_secs(5), async {
+            while connector.fallbacks.load(Ordering::SeqCst) == 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The fallback didn't start");
+        assert_eq!(connector.probes.load(Ordering::SeqCst), 2);
+        sleep(Duration::from_millis(50)).await;
+
+        // The fake Neovim answers the `confirm qa`, like one that kept running
+        fallback::switch_to_server();
+        sleep(Duration::from_millis(50)).await;
+        assert!(!reconnect_loop.is_finished());
+
+        // So when it quits later, Neovide quits along with it instead of reconnecting
+        let server = connector.fallback_server.as_ref().unwrap().lock().take();
+        server.unwrap().abort();
+        timeout(Duration::from_secs(5), reconnect_loop)
+            .await
+            .expect("The reconnect loop didn't stop")
+            .unwrap();
+        assert_eq!(connector.fallbacks.load(Ordering::SeqCst), 1);
+        assert_eq!(connector.probes.load(Ordering::SeqCst), 2);
+        assert_eq!(sender.events().last(), Some(&UserEvent::NeovimExited));
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver(parking_lot::Mutex<Vec<String>>);
+
+    impl ReconnectObserver for RecordingObserver {
+        fn on_attempt(&self, attempt: u32) {
+            self.0.lock().push(format!("attempt {attempt}"));
+        }
+
+        fn on_success(&self, attempt: u32) {
+            self.0.lock().push(format!("success {attempt}"));
+        }
+
+        fn on_failure(&self, attempt: u32, error: Option<&anyhow::Error>) {
+            let reason = error.map_or("probe".to_owned(), |error| error.to_string());
+            self.0.lock().push(format!("failure {attempt} {reason}"));
+        }
+
+        fn on_give_up(&self, attempt: u32) {
+            self.0.lock().push(format!("give up {attempt}"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_network_change_reconnects() {
+        let (settings, mut cmdline_settings) = reconnect_settings("127.0.0.1:6666");
+        cmdline_settings.reconnect_on_network_change = true;
+        settings.set(&cmdline_settings);
+        let connector = Arc::new(FakeConnector::new([Attempt::Connects, Attempt::Connects]));
+        // The window is closed once the second connection is shown
+        let sender =
+            RecordingSender::closing_at_nth(2, |event| matches!(event, UserEvent::ReconnectStop));
+        let reconnect_loop = spawn({
+            let connector = connector.clone();
+            let sender = sender.clone();
+            async move {
+                run_with_reconnect(
+                    connector.as_ref(),
+                    None,
+                    RunningTracker::new(),
+                    settings,
+                    sender,
+                    Arc::new(MockClock::new()),
+                    Arc::new(NoopObserver),
+                )
+                .await;
+            }
+        });
+
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 1);
+        assert!(!reconnect_loop.is_finished());
+
+        // The server still answers, but the session is left for a new connection right away
+        connector.network_change.notify_one();
+        timeout(Duration::from_secs(5), reconnect_loop)
+            .await
+            .expect("The reconnect loop didn't stop")
+            .unwrap();
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 2);
+        let stops = sender
+            .events()
+            .into_iter()
+            .filter(|event| matches!(event, UserEvent::ReconnectStop))
+            .count();
+        assert_eq!(stops, 2);
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_unanswered_ping_reconnects_on_the_clock() {
+        let (settings, _) = reconnect_settings("127.0.0.1:6666");
+        let connector = Arc::new(FakeConnector::new([
+            Attempt::StopsAnswering,
+            Attempt::Connects,
+        ]));
+        let sender =
+            RecordingSender::closing_at_nth(2, |event| matches!(event, UserEvent::ReconnectStop));
+        let clock = Arc::new(MockClock::new());
+        let reconnect_loop = spawn({
+            let connector = connector.clone();
+            let sender = sender.clone();
+            let clock = clock.clone();
+            async move {
+                run_with_reconnect(
+                    connector.as_ref(),
+                    None,
+                    RunningTracker::new(),
+                    settings,
+                    sender,
+                    clock,
+                    Arc::new(NoopObserver),
+                )
+                .await;
+            }
+        });
+
+        // The ping waits for the clock, however long it really takes
+        sleep(Duration::from_millis(100)).await;
+        assert!(!reconnect_loop.is_finished());
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 1);
+
+        clock.advance(PING_TIMEOUT);
+        timeout(Duration::from_secs(5), reconnect_loop)
+            .await
+            .expect("The reconnect loop didn't stop")
+            .unwrap();
+        assert_eq!(connector.launches.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_observer_follows_the_loop() {
+        let (settings, _) = reconnect_settings("127.0.0.1:6666");
+        // Connected, then the connection is lost before it was stable, so the attempts keep
+        // counting
+        let connector = FakeConnector::new([
+            Attempt::Drops,
+            Attempt::Refused,
+            Attempt::Fails("handshake failed"),
+            Attempt::Connects,
+        ]);
+        // The window is closed once the second connection is shown
+        let sender =
+            RecordingSender::closing_at_nth(2, |event| matches!(event, UserEvent::ReconnectStop));
+        let observer = Arc::new(RecordingObserver::default());
+        let run = run_with_reconnect(
+            &connector,
+            None,
+            RunningTracker::new(),
+            settings,
+            sender.clone(),
+            Arc::new(MockClock::new()),
+            observer.clone(),
+        );
+        timeout(Duration::from_secs(10), run)
+            .await
+            .expect("The reconnect loop didn't stop");
+
+        assert_eq!(
+            *observer.0.lock(),
+            vec![
+                "attempt 0",
+                "success 0",
+                "attempt 1",
+                "failure 1 probe",
+                "attempt 2",
+                "failure 2 handshake failed",
+                "attempt 3",
+                "success 3",
+                "give up 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tasks_spawned_on_the_handle_run_on_the_runtime() {
+        let runtime = NeovimRuntime::new().unwrap();
+        let task = runtime
+            .handle()
+            .spawn(async { Handle::current().metrics().num_workers() });
+        let workers = runtime.runtime.block_on(task).unwrap();
+        assert_eq!(workers, runtime.runtime.metrics().num_workers());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_count_down_announces_reconnect_title() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        count_down(
+            &ChannelSender(sender),
+            "localhost:6666",
+            Duration::from_millis(2500),
+            &clock,
+        )
+        .await
+        .unwrap();
+        // Rounded up, and never showing 0s
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            ["3s", "2s", "1s"]
+                .map(|remaining| {
+                    UserEvent::ReconnectTitle(format!(
+                        "Reconnecting to localhost:6666 ({remaining})"
+                    ))
+                })
+                .to_vec()
+        );
+        assert_eq!(clock.now() - start, Duration::from_millis(2500));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        count_down(
+            &ChannelSender(sender),
+            "localhost:6666",
+            Duration::ZERO,
+            &clock,
+        )
+        .await
+        .unwrap();
+        assert_eq!(receiver.try_iter().next(), None);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_count_down_stops_while_paused() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = ChannelSender(sender);
+        reconnect_pause::pause_reconnect();
+        let resume = async {
+            sleep(Duration::from_millis(50)).await;
+            reconnect_pause::resume_reconnect();
+        };
+        let (result, ()) = tokio::join!(
+            count_down(&sender, "localhost:6666", Duration::from_secs(30), &clock),
+            resume
+        );
+        result.unwrap();
+
+        // Nothing was counted down while paused, the mock clock only advanced for the sleep of
+        // the first step, and resuming reconnected right away
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+        let events: Vec<_> = receiver
+            .try_iter()
+            .filter(|event| matches!(event, UserEvent::ReconnectTitle(_)))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "Reconnecting to localhost:6666 (30s)",
+                "Reconnect to localhost:6666 paused",
+                "Connecting to localhost:6666",
+            ]
+            .map(|title| UserEvent::ReconnectTitle(title.to_owned()))
+        );
+    }
+
+    /// A mock clock that suspends for an hour during the third second it sleeps.
+    struct SuspendingClock {
+        clock: MockClock,
+        sleeps: AtomicU32,
+    }
+
+    impl Clock for SuspendingClock {
+        fn now(&self) -> Instant {
+            self.clock.now()
+        }
+
+        fn wall_clock(&self) -> std::time::SystemTime {
+            self.clock.wall_clock()
+        }
+
+        fn sleep(&self, duration: Duration) -> crate::clock::Sleep {
+            if self.sleeps.fetch_add(1, Ordering::SeqCst) == 2 {
+                self.clock.suspend(Duration::from_secs(60 * 60));
+            }
+            self.clock.sleep(duration)
+        }
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_count_down_stops_after_a_suspend() {
+        let clock = SuspendingClock {
+            clock: MockClock::new(),
+            sleeps: AtomicU32::new(0),
+        };
+        let start = clock.now();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        count_down(
+            &ChannelSender(sender),
+            "localhost:6666",
+            Duration::from_secs(30),
+            &clock,
+        )
+        .await
+        .unwrap();
+
+        // Retried right after the suspend, instead of counting down the remaining 27s
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(
+            events[..3],
+            [
+                UserEvent::ReconnectTitle("Reconnecting to localhost:6666 (30s)".to_owned()),
+                UserEvent::ReconnectTitle("Reconnecting to localhost:6666 (29s)".to_owned()),
+                UserEvent::ReconnectTitle("Reconnecting to localhost:6666 (28s)".to_owned()),
+            ]
+        );
+        assert_eq!(
+            events[3],
+            UserEvent::ConnectStart {
+                address: "localhost:6666".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_connect_overlay_shows_before_the_first_attempt() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        announce_connecting(&ChannelSender(sender), "localhost:6666").unwrap();
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                UserEvent::ConnectStart {
+                    address: "localhost:6666".to_owned()
+                },
+                UserEvent::ReconnectTitle("Connecting to localhost:6666".to_owned()),
+                UserEvent::RedrawRequested,
+            ]
+        );
+        assert!(announce_connecting(&ClosedSender, "localhost:6666").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_settings_loaded_is_sent_for_every_connection() {
+        let settings = Settings::new();
+        settings.register::<ReconnectSettings>();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = ChannelSender(sender);
+
+        // The first connection and a reconnect, each to a new Neovim
+        for _ in 0..2 {
+            let (nvim, requests) = recording_nvim();
+            read_settings(&settings, &nvim, &sender).await.unwrap();
+            assert!(!requests.lock().is_empty());
+        }
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![UserEvent::SettingsLoaded, UserEvent::SettingsLoaded]
+        );
+    }
+
+    /// Settings that give up reconnecting to a server that never answers after a minute.
+    fn budget_settings() -> Arc<Settings> {
+        let (settings, mut cmdline_settings) = reconnect_settings("127.0.0.1:6666");
+        cmdline_settings.reconnect_max_duration = 60;
+        settings.set(&cmdline_settings);
+        settings
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_reconnecting_stops_once_the_budget_is_exceeded() {
+        let clock = Arc::new(MockClock::new());
+        let started_at = clock.now();
+        let sender = RecordingSender::closing_at(|_| false);
+        let connector = FakeConnector::new([]);
+        let running_tracker =
+            run_reconnect_loop(&connector, budget_settings(), &sender, clock.clone()).await;
+
+        assert_eq!(clock.now() - started_at, Duration::from_secs(60));
+        assert_eq!(running_tracker.exit_code(), RECONNECT_GAVE_UP_EXIT_CODE);
+        assert_eq!(sender.events().last(), Some(&UserEvent::NeovimExited));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_paused_time_does_not_count_against_the_budget() {
+        let clock = Arc::new(MockClock::new());
+        let started_at = clock.now();
+        let sender = RecordingSender::closing_at(|_| false);
+        let connector = FakeConnector::new([]);
+        reconnect_pause::pause_reconnect();
+        let paused_for_an_hour = async {
+            let paused = UserEvent::ReconnectTitle("Reconnect to 127.0.0.1:6666 paused".to_owned());
+            while !sender.events().contains(&paused) {
+                sleep(Duration::from_millis(10)).await;
+            }
+            clock.advance(Duration::from_secs(3600));
+            reconnect_pause::resume_reconnect();
+        };
+        let (running_tracker, ()) = tokio::join!(
+            run_reconnect_loop(&connector, budget_settings(), &sender, clock.clone()),
+            paused_for_an_hour
+        );
+
+        assert_eq!(clock.now() - started_at, Duration::from_secs(3600 + 60));
+        assert_eq!(running_tracker.exit_code(), RECONNECT_GAVE_UP_EXIT_CODE);
+    }
+
+    fn echoed_chunks(requests: &Requests) -> Vec<(String, String)> {
+        let requests = requests.lock();
+        let (method, args) = &requests[0];
+        assert_eq!(method, "nvim_echo");
+        args.as_array().unwrap()[0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|chunk| {
+                let chunk = chunk.as_array().unwrap();
+                (
+                    chunk[0].as_str().unwrap().to_owned(),
+                    chunk[1].as_str().unwrap().to_owned(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_message_severities() {
+        let expected = [
+            (MessageSeverity::Error, "Error: ", "ErrorMsg"),
+            (MessageSeverity::Warning, "Warning: ", "WarningMsg"),
+            (MessageSeverity::Info, "Info: ", "MoreMsg"),
+        ];
+        for (severity, prefix, highlight) in expected {
+            let (nvim, requests) = recording_nvim();
+            show_message(&nvim, severity, &["first".to_owned(), "second".to_owned()])
+                .await
+                .unwrap();
+
+            let expected_chunks: Vec<(String, String)> = [prefix, "first\n", "second\n"]
+                .iter()
+                .map(|text| (text.to_string(), highlight.to_owned()))
+                .collect();
+            assert_eq!(echoed_chunks(&requests), expected_chunks);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_message_is_highlighted_and_prefixed() {
+        let (nvim, requests) = recording_nvim();
+        show_error_message(&nvim, &["first".to_owned(), "second".to_owned()])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            echoed_chunks(&requests),
+            [
+                ("Error: ".to_owned(), "ErrorMsg".to_owned()),
+                ("first\n".to_owned(), "ErrorMsg".to_owned()),
+                ("second\n".to_owned(), "ErrorMsg".to_owned()),
+            ]
+        );
+    }
+
+    fn clipboard_settings(handle_clipboard: HandleClipboard) -> CmdLineSettings {
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.handle_clipboard = handle_clipboard;
+        cmdline_settings
+    }
+
+    #[test]
+    fn test_dump_api_info_flag() {
+        let api_information = ApiInformation::for_test(3);
+        let mut cmdline_settings = CmdLineSettings::default();
+        assert_eq!(api_info_dump(&cmdline_settings, &api_information), None);
+
+        cmdline_settings.dump_api_info = true;
+        let dump = api_info_dump(&cmdline_settings, &api_information).unwrap();
+        assert!(dump.contains("channel: 3"));
+    }
+
+    #[test]
+    fn test_handle_clipboard_auto() {
+        let mut cmdline_settings = clipboard_settings(HandleClipboard::Auto);
+        assert!(!should_handle_clipboard(&cmdline_settings));
+
+        cmdline_settings.neovim_bin = Some("/usr/bin/ssh".to_owned());
+        assert!(should_handle_clipboard(&cmdline_settings));
+        cmdline_settings.neovim_bin = Some("/usr/bin/nvim".to_owned());
+        assert!(!should_handle_clipboard(&cmdline_settings));
+
+        cmdline_settings.server_fd = Some(3);
+        assert!(should_handle_clipboard(&cmdline_settings));
+        cmdline_settings.server_fd = None;
+
+        cmdline_settings.server = Some("localhost:6666".to_owned());
+        assert!(should_handle_clipboard(&cmdline_settings));
+    }
+
+    #[test]
+    fn test_handle_clipboard_always() {
+        let cmdline_settings = clipboard_settings(HandleClipboard::Always);
+        assert!(should_handle_clipboard(&cmdline_settings));
+    }
+
+    #[test]
+    fn test_handle_clipboard_never() {
+        let mut cmdline_settings = clipboard_settings(HandleClipboard::Never);
+        cmdline_settings.server = Some("localhost:6666".to_owned());
+        cmdline_settings.wsl = true;
+        assert!(!should_handle_clipboard(&cmdline_settings));
+    }
+
+    #[tokio::test]
+    async fn test_quit_after_timeout() {
+        let running_tracker = RunningTracker::new();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let timer = spawn(quit_after(
+            Duration::from_millis(100),
+            Duration::ZERO,
+            running_tracker.clone(),
+            ChannelSender(sender),
+        ));
+
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(running_tracker.quit_reason(), None);
+        assert!(receiver.try_recv().is_err());
+
+        timeout(Duration::from_secs(1), timer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(running_tracker.quit_reason(), Some(QuitReason::TimedOut));
+        assert_eq!(receiver.try_recv(), Ok(UserEvent::NeovimExited));
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_quits() {
+        let clock = MockClock::new();
+        let activity = InputActivity::default();
+        let running_tracker = RunningTracker::new();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let start = clock.now();
+        // Typing 20s in delays the quit by that much
+        activity.record(start + Duration::from_secs(20));
+
+        quit_when_idle(
+            Duration::from_secs(300),
+            Duration::ZERO,
+            &activity,
+            running_tracker.clone(),
+            ChannelSender(sender),
+            &clock,
+        )
+        .await;
+        assert_eq!(clock.now() - start, Duration::from_secs(320));
+        assert_eq!(running_tracker.quit_reason(), Some(QuitReason::Idle));
+        assert_eq!(receiver.try_recv(), Ok(UserEvent::NeovimExited));
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_idle_timeout_disconnects_the_server() {
+        let (settings, mut cmdline_settings) = reconnect_settings("127.0.0.1:6666");
+        cmdline_settings.idle_timeout = 300;
+        cmdline_settings.idle_action = IdleAction::Disconnect;
+        settings.set(&cmdline_settings);
+        let clock = MockClock::new();
+        let start = clock.now();
+        let (session, _server) = fake_server::socket_pair_session().await;
+        let (sender, _receiver) = std::sync::mpsc::channel();
+
+        let cause = timeout(
+            Duration::from_secs(5),
+            run_server(
+                session,
+                "127.0.0.1:6666",
+                &ShutdownTimeouts::default(),
+                &RunningTracker::new(),
+                &settings,
+                &ChannelSender(sender),
+                &clock,
+                std::future::pending(),
+            ),
+        )
+        .await;
+        assert_eq!(cause, Ok(CloseCause::Idle));
+        assert!(clock.now() - start >= Duration::from_secs(300));
+    }
+
+    #[cfg(unix)]
+    #[serial]
+    #[tokio::test]
+    async fn test_view_state_is_not_captured_on_every_ping() {
+        let (settings, mut cmdline_settings) = reconnect_settings("127.0.0.1:6666");
+        cmdline_settings.restore_session_on_reconnect = true;
+        settings.set(&cmdline_settings);
+        let (session, server) = fake_server::socket_pair_session().await;
+        let (sender, _receiver) = std::sync::mpsc::channel();
+
+        // The mock clock makes every ping 5s later, so there are many of them meanwhile
+        let cause = run_server(
+            session,
+            "127.0.0.1:6666",
+            &ShutdownTimeouts::default(),
+            &RunningTracker::new(),
+            &settings,
+            &ChannelSender(sender),
+            &MockClock::new(),
+            sleep(Duration::from_millis(200)),
+        )
+        .await;
+        assert_eq!(cause, CloseCause::Dropped);
+
+        let methods = timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        let count = |method: &str| methods.iter().filter(|called| *called == method).count();
+        let (pings, captures) = (count("nvim_get_api_info"), count("nvim_exec_lua"));
+        assert!(pings > 12);
+        // About once every 6 pings, and once more for the network change
+        assert!(
+            captures < pings / 4,
+            "{captures} captures for {pings} pings"
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_reconnect_now_ends_the_wait() {
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let wait = spawn(async move {
+            wait_for_retry(
+                &ChannelSender(sender),
+                "localhost:6666",
+                Duration::from_secs(30),
+                &SystemClock,
+            )
+            .await
+        });
+        sleep(Duration::from_millis(20)).await;
+
+        // What both the key and the command end up calling
+        reconnect_pause::reconnect_now();
+        let result = timeout(Duration::from_secs(1), wait).await;
+        assert!(matches!(result, Ok(Ok(Ok(_)))));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_reconnect_within_the_grace_shows_no_overlay() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = ChannelSender(sender);
+
+        wait_for_retry_with_grace(
+            &sender,
+            "localhost:6666",
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            &clock,
+        )
+        .await
+        .unwrap();
+        assert_eq!(clock.now() - start, Duration::from_secs(1));
+        assert_eq!(receiver.try_iter().count(), 0);
+
+        // Only the wait after the grace is shown
+        wait_for_retry_with_grace(
+            &sender,
+            "localhost:6666",
+            Duration::from_secs(3),
+            Duration::from_secs(1),
+            &clock,
+        )
+        .await
+        .unwrap();
+        assert_eq!(clock.now() - start, Duration::from_secs(4));
+        assert!(receiver.try_iter().any(|event| event
+            == UserEvent::ReconnectStart {
+                address: "localhost:6666".to_owned(),
+                wait: 2,
+            }));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_closed_event_loop_stops_the_retry_promptly() {
+        let wait = wait_for_retry(
+            &ClosedSender,
+            "localhost:6666",
+            Duration::from_secs(30),
+            &SystemClock,
+        );
+        let result = timeout(Duration::from_secs(1), wait).await;
+        assert!(matches!(result, Ok(Err(_))));
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_full_backoff_cycle_on_mock_clock() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let settings = ReconnectSettings {
+            initial_backoff: 1,
+            max_backoff: 4,
+            ..Default::default()
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = ChannelSender(sender);
+
+        let mut expected_elapsed = Duration::ZERO;
+        for attempt in 0..4 {
+            let wait = reconnect_wait(attempt, &settings);
+            wait_for_retry(&sender, "localhost:6666", wait, &clock)
+                .await
+                .unwrap();
+            expected_elapsed += wait;
+            assert_eq!(clock.now() - start, expected_elapsed);
+        }
+        // 1s, 2s, then capped at 4s
+        assert_eq!(expected_elapsed, Duration::from_secs(11));
+
+        let titles = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                UserEvent::ReconnectTitle(title) => Some(title),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let expected_titles =
+            [1, 2, 1, 4, 3, 2, 1, 4, 3, 2, 1].map(|secs| reconnect_title("localhost:6666", secs));
+        assert_eq!(titles, expected_titles);
+    }
+
+    #[test]
+    fn test_reported_reconnect_state_follows_the_loop() {
+        let settings = ReconnectSettings::default();
+        let mut state = ConnectionState {
+            status: ConnectionStatus::Connected,
+            ..ConnectionState::default()
+        };
+        // The steps of `run_with_reconnect` after three failed attempts
+        let mut attempt = 0;
+        for _ in 0..3 {
+            let wait = reconnect_wait(attempt, &settings);
+            attempt += 1;
+            enter_backoff(&mut state, attempt, wait);
+        }
+        assert_eq!(
+            reconnect_state_message(&state, false),
+            "Reconnect state: reconnecting, attempt 3, backoff 4s, not paused"
+        );
+        state.status = ConnectionStatus::Paused;
+        assert_eq!(
+            reconnect_state_message(&state, true),
+            "Reconnect state: paused, attempt 3, backoff 4s, paused"
+        );
+        assert_eq!(
+            reconnect_state_message(&ConnectionState::default(), false),
+            "Reconnect state: connecting, attempt 0, no backoff, not paused"
+        );
+    }
+
+    #[test]
+    fn test_flapping_connection_keeps_growing_the_backoff() {
+        let settings = ReconnectSettings::default();
+        let mut attempt = 0;
+        let mut waits = Vec::new();
+        // Every reconnect succeeds right away, but the connection drops after 2s
+        for _ in 0..6 {
+            attempt = attempt_after_connection(attempt, Duration::from_secs(2), &settings);
+            waits.push(reconnect_wait(attempt, &settings).as_secs());
+            attempt += 1;
+        }
+        assert_eq!(waits, vec![1, 2, 4, 8, 16, 32]);
+
+        // Once a connection stays up, the backoff starts over
+        let stable = Duration::from_secs(settings.stable_time);
+        assert_eq!(attempt_after_connection(attempt, stable, &settings), 0);
+        let mut no_threshold = settings.clone();
+        no_threshold.stable_time = 0;
+        assert_eq!(
+            attempt_after_connection(attempt, Duration::ZERO, &no_threshold),
+            0
+        );
+    }
+
+    #[test]
+    fn test_reloaded_backoff_applies_to_the_next_attempt() {
+        let settings = Settings::new();
+        settings.set(&ReconnectSettings::default());
+        let waits = |attempts: std::ops::Range<u32>| {
+            attempts
+                .map(|attempt| reconnect_wait(attempt, &settings.get::<ReconnectSettings>()))
+                .map(|wait| wait.as_secs())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(waits(0..7), vec![1, 2, 4, 8, 16, 32, 32]);
+
+        // What `ReloadSettings` does after g:neovide_reconnect_* is changed
+        let mut reloaded = settings.get::<ReconnectSettings>();
+        reloaded.initial_backoff = 5;
+        reloaded.max_backoff = 10;
+        settings.set(&reloaded);
+        assert_eq!(waits(0..3), vec![5, 10, 10]);
+    }
+
+    #[test]
+    fn test_reconnect_title() {
+        assert_eq!(
+            reconnect_title("/tmp/nvim.sock", 8),
+            "Reconnecting to /tmp/nvim.sock (8s)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_version_check_retries_missing_channel() {
+        let attempts = AtomicU32::new(0);
+        let api_information = check_api_information(
+            || async {
+                let channel = match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => 0,
+                    _ => 7,
+                };
+                Ok(ApiInformation::for_test(channel))
+            },
+            VersionCheckBackoff::NONE,
+        )
+        .await
+        .unwrap();
+        assert_eq!(api_information.channel, 7);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_skipped_version_check_accepts_an_old_neovim() {
+        let old_neovim = || async {
+            let mut api_information = ApiInformation::for_test(7);
+            api_information.version.minor = 9;
+            Ok(api_information)
+        };
+        let checked = check_api_information(old_neovim, VersionCheckBackoff::NONE).await;
+        assert!(checked.unwrap_err().is::<UnsupportedVersion>());
+
+        let attempts = AtomicU32::new(0);
+        let api_information = unchecked_api_information(|| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            old_neovim()
+        })
+        .await
+        .unwrap();
+        assert_eq!(api_information.channel, 7);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // The channel is still needed to set up Neovide
+        let result = unchecked_api_information(|| async { Ok(ApiInformation::for_test(0)) }).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_version_check_fails_without_channel() {
+        let result = check_api_information(
+            || async { Ok(ApiInformation::for_test(0)) },
+            VersionCheckBackoff::NONE,
+        )
+        .await;
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("did not assign a channel"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_succeeds_on_second_attempt() {
+        let attempts = AtomicU32::new(0);
+        let result = retry(2, Duration::ZERO, || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(anyhow!("spawn failed"))
+            } else {
+                Ok("session")
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), "session");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_the_last_retry() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = retry(2, Duration::ZERO, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("spawn failed"))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_no_retries_by_default() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = retry(0, Duration::ZERO, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("spawn failed"))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    /// A session whose embedded Neovim exits with `code` right away.
+    #[cfg(unix)]
+    async fn session_exiting_with(code: i32) -> NeovimSession {
+        let mut session = fake_server::socket_pair_session().await.0;
+        session.neovim_process = Some(
+            tokio::process::Command::new("sh")
+                .args(["-c", &format!("exit {code}")])
+                .spawn()
+                .unwrap(),
+        );
+        session
+    }
+
+    #[cfg(unix)]
+    fn crash_timeouts() -> ShutdownTimeouts {
+        ShutdownTimeouts {
+            quit: Duration::from_millis(50),
+            io_drain: Some(Duration::from_millis(10)),
+            stderr_drain: Duration::from_millis(10),
+            ..ShutdownTimeouts::default()
+        }
+    }
+
+    #[cfg(unix)]
+    fn is_notice(event: &UserEvent) -> bool {
+        matches!(event, UserEvent::Notice(_))
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_crash_restarts_only_when_asked() {
+        let running_tracker = RunningTracker::new();
+        let sender = RecordingSender::closing_at(|_| false);
+        let restart = run(
+            session_exiting_with(3).await,
+            crash_timeouts(),
+            running_tracker.clone(),
+            sender.clone(),
+            CancellationToken::new(),
+            CrashAction::Restart,
+        )
+        .await;
+        assert!(restart);
+        assert!(matches!(
+            sender.events().as_slice(),
+            [UserEvent::Notice(notice)] if notice == "Neovim exited with code 3, restarting it"
+        ));
+
+        // A clean exit quits, even with --restart-on-crash
+        running_tracker.set_clean_exit_codes(vec![0, 3]);
+        let sender = RecordingSender::closing_at(|_| false);
+        let restart = run(
+            session_exiting_with(3).await,
+            crash_timeouts(),
+            running_tracker,
+            sender.clone(),
+            CancellationToken::new(),
+            CrashAction::Restart,
+        )
+        .await;
+        assert!(!restart);
+        assert!(matches!(
+            sender.events().as_slice(),
+            [UserEvent::NeovimExited]
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_crash_holds_the_window_until_it_closes() {
+        let sender = RecordingSender::closing_at(|_| false);
+        let shutdown_requested = CancellationToken::new();
+        let held = spawn(run(
+            session_exiting_with(3).await,
+            crash_timeouts(),
+            RunningTracker::new(),
+            sender.clone(),
+            shutdown_requested.clone(),
+            CrashAction::Hold,
+        ));
+        while !sender.events().iter().any(is_notice) {
+            sleep(Duration::from_millis(5)).await;
+        }
+        sleep(Duration::from_millis(20)).await;
+        assert!(!held.is_finished());
+
+        shutdown_requested.cancel();
+        assert!(!held.await.unwrap());
+        assert!(matches!(
+            sender.events().as_slice(),
+            [UserEvent::Notice(notice), UserEvent::NeovimExited]
+                if notice == "Neovim exited with code 3, close the window to quit"
+        ));
     }
 }
@@ -0,0 +1,102 @@
+//! Notices when the network changes, for example when moving from Wi-Fi to cellular, so that
+//! `--reconnect-on-network-change` can reconnect right away instead of waiting for a ping to time
+//! out.
+//!
+//! The local address the OS would use for the default route is polled, which works without any
+//! platform specific API. Where it can't be determined the change is never signaled, and the
+//! connection pings notice the lost connection instead.
+
+use std::{
+    future::{pending, Future},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::{net::UdpSocket, time::sleep};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// A documentation address (RFC 5737), connecting a UDP socket only looks up the route, nothing is
+// sent to it
+const ROUTE_PROBE_TARGET: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 9);
+
+/// The local address of the default route, `None` without one.
+async fn default_route_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.ok()?;
+    socket.connect(ROUTE_PROBE_TARGET).await.ok()?;
+    socket.local_addr().ok().map(|address| address.ip())
+}
+
+/// Returns as soon as `probe` returns something else than it did the first time, checking every
+/// `interval`.
+async fn wait_for_change<F, Fut>(mut probe: F, interval: Duration)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<IpAddr>>,
+{
+    let initial = probe().await;
+    loop {
+        sleep(interval).await;
+        let current = probe().await;
+        if current != initial {
+            log::info!("Network changed from {initial:?} to {current:?}");
+            return;
+        }
+    }
+}
+
+/// Returns when the network changes, never when `enabled` is false.
+pub async fn network_changed(enabled: bool) {
+    if !enabled {
+        return pending().await;
+    }
+    wait_for_change(default_route_address, POLL_INTERVAL).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    const WIFI: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+    const CELLULAR: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 64, 3, 7));
+
+    #[tokio::test]
+    async fn test_network_change_is_signaled() {
+        let polls = Arc::new(AtomicU32::new(0));
+        let probe = {
+            let polls = polls.clone();
+            move || {
+                let poll = polls.fetch_add(1, Ordering::SeqCst);
+                async move { Some(if poll < 3 { WIFI } else { CELLULAR }) }
+            }
+        };
+
+        timeout(
+            Duration::from_secs(1),
+            wait_for_change(probe, Duration::from_millis(1)),
+        )
+        .await
+        .expect("The change wasn't signaled");
+        assert_eq!(polls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_network_is_not_signaled() {
+        let unchanged = timeout(
+            Duration::from_millis(50),
+            wait_for_change(|| async { Some(WIFI) }, Duration::from_millis(1)),
+        )
+        .await;
+        assert!(unchanged.is_err());
+        assert!(timeout(Duration::from_millis(10), network_changed(false))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! `--on-connect-cmd`, a local command that's run every time the connection to the `--server` is
+//! made, the first one and after every reconnect, with the address as its last argument. It runs
+//! in the background, the connection doesn't wait for it. The command is only taken from the
+//! command line, so that a server or a config file can't make Neovide run anything.
+
+use std::process::ExitStatus;
+
+use tokio::{process::Command, task::JoinHandle};
+
+/// The command for `command_line`, split like a shell would but without one, and the address
+/// added. `None` when it's empty or can't be split.
+fn on_connect_command(command_line: &str, address: &str) -> Option<Command> {
+    let words = shlex::split(command_line)?;
+    let (program, args) = words.split_first()?;
+    let mut command = Command::new(program);
+    command.args(args).arg(address).kill_on_drop(false);
+    Some(command)
+}
+
+/// Starts the `--on-connect-cmd` for `address`, the task returns its exit status once it's done.
+pub fn run_on_connect(command_line: &str, address: &str) -> JoinHandle<Option<ExitStatus>> {
+    let command = on_connect_command(command_line, address);
+    let command_line = command_line.to_owned();
+    tokio::spawn(async move {
+        let Some(mut command) = command else {
+            log::warn!("Could not parse the --on-connect-cmd {command_line:?}");
+            return None;
+        };
+        let status = match command.status().await {
+            Ok(status) => status,
+            Err(error) => {
+                log::warn!("Could not run the --on-connect-cmd {command_line:?}: {error}");
+                return None;
+            }
+        };
+        if status.success() {
+            log::debug!("The --on-connect-cmd exited with {status}");
+        } else {
+            log::warn!("The --on-connect-cmd {command_line:?} exited with {status}");
+        }
+        Some(status)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_command_is_skipped() {
+        assert!(on_connect_command("", "localhost:6666").is_none());
+        assert!(on_connect_command("notify \"unterminated", "localhost:6666").is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_gets_the_address() {
+        let path = std::env::temp_dir().join(format!("neovide-on-connect-{}", std::process::id()));
+        let command_line = format!("sh -c 'printf %s \"$1\" > {}' hook", path.display());
+        let status = run_on_connect(&command_line, "localhost:6666")
+            .await
+            .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(status.unwrap().success());
+        assert_eq!(written, "localhost:6666");
+    }
+}
@@ -0,0 +1,146 @@
+//! `--pin-resolved-ip`, which keeps connecting to the IP address that the hostname of a TCP
+//! `--server` resolved to for the first successful connection, so that reconnects aren't
+//! redirected by a DNS change in the middle of a session. Once the pinned address failed
+//! `--pin-reresolve-after` times in a row, the hostname is resolved again.
+
+use std::{
+    future::Future,
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::{net::TcpStream, time::timeout};
+
+/// Shorter than the timeout of the whole connect, so that a pinned address that doesn't answer
+/// counts as a failure.
+const PINNED_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default)]
+struct AddressPin {
+    /// The `--server` address and the IP address it's pinned to
+    pinned: Option<(String, SocketAddr)>,
+    /// The failed connects to the pinned address in a row
+    failures: u32,
+}
+
+impl AddressPin {
+    fn target(&self, address: &str) -> Option<SocketAddr> {
+        match &self.pinned {
+            Some((pinned, ip)) if pinned == address => Some(*ip),
+            _ => None,
+        }
+    }
+
+    fn connected(&mut self, address: &str, ip: SocketAddr) {
+        if self.target(address) != Some(ip) {
+            log::info!("Pinned {address} to {ip}");
+        }
+        self.pinned = Some((address.to_owned(), ip));
+        self.failures = 0;
+    }
+
+    fn failed(&mut self, reresolve_after: u32) {
+        self.failures += 1;
+        if self.failures >= reresolve_after.max(1) {
+            if let Some((address, ip)) = self.pinned.take() {
+                log::info!(
+                    "{ip} failed {} times in a row, resolving {address} again",
+                    self.failures
+                );
+            }
+            self.failures = 0;
+        }
+    }
+}
+
+static ADDRESS_PIN: Lazy<Mutex<AddressPin>> = Lazy::new(Mutex::default);
+
+/// Connects to the IP address `address` is pinned to, or with `resolve_and_connect` when it isn't
+/// pinned yet, and pins it to the address that connection was made to. Addresses that are IP
+/// addresses already are just connected to.
+pub async fn connect_pinned<C, F>(
+    address: &str,
+    reresolve_after: u32,
+    resolve_and_connect: C,
+) -> Result<TcpStream>
+where
+    C: FnOnce() -> F,
+    F: Future<Output = Result<TcpStream>>,
+{
+    if address.parse::<SocketAddr>().is_ok() {
+        return resolve_and_connect().await;
+    }
+    let pinned = ADDRESS_PIN.lock().target(address);
+    if let Some(ip) = pinned {
+        log::debug!("Connecting to {address} at the pinned {ip}");
+        let connect = timeout(PINNED_CONNECT_TIMEOUT, TcpStream::connect(ip)).await;
+        return match connect {
+            Ok(Ok(stream)) => {
+                ADDRESS_PIN.lock().connected(address, ip);
+                Ok(stream)
+            }
+            Ok(Err(error)) => {
+                ADDRESS_PIN.lock().failed(reresolve_after);
+                Err(error)
+            }
+            Err(_) => {
+                ADDRESS_PIN.lock().failed(reresolve_after);
+                Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!("The pinned {ip} of {address} did not answer"),
+                ))
+            }
+        };
+    }
+    let stream = resolve_and_connect().await?;
+    if let Ok(ip) = stream.peer_addr() {
+        ADDRESS_PIN.lock().connected(address, ip);
+    }
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnects_use_the_pinned_address_until_the_threshold() {
+        let first: SocketAddr = "192.0.2.1:6666".parse().unwrap();
+        let second: SocketAddr = "192.0.2.2:6666".parse().unwrap();
+        let mut pin = AddressPin::default();
+        assert_eq!(pin.target("devbox:6666"), None);
+
+        pin.connected("devbox:6666", first);
+        assert_eq!(pin.target("devbox:6666"), Some(first));
+        assert_eq!(pin.target("other:6666"), None);
+
+        pin.failed(3);
+        pin.failed(3);
+        assert_eq!(pin.target("devbox:6666"), Some(first));
+        // A successful reconnect starts the count over
+        pin.connected("devbox:6666", first);
+        pin.failed(3);
+        pin.failed(3);
+        assert_eq!(pin.target("devbox:6666"), Some(first));
+        pin.failed(3);
+        assert_eq!(pin.target("devbox:6666"), None);
+
+        pin.connected("devbox:6666", second);
+        assert_eq!(pin.target("devbox:6666"), Some(second));
+    }
+
+    #[tokio::test]
+    async fn test_pins_the_first_connected_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let address = format!("localhost:{port}");
+        let stream = connect_pinned(&address, 3, || TcpStream::connect(("127.0.0.1", port)))
+            .await
+            .unwrap();
+        let pinned = ADDRESS_PIN.lock().target(&address);
+        assert_eq!(pinned, Some(stream.peer_addr().unwrap()));
+    }
+}
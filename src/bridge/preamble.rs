@@ -0,0 +1,167 @@
+//! `--connection-preamble`, for gateways and jump hosts that greet with a banner before the RPC
+//! stream of the `--server` starts. The banner is read and dropped before the handshake, either a
+//! fixed number of bytes, or everything up to and including a delimiter.
+
+use std::{
+    io::{Error, ErrorKind, Result},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt},
+    time::timeout,
+};
+
+/// The largest banner that's skipped, so that a server without the delimiter isn't read forever.
+const MAX_PREAMBLE_SIZE: usize = 64 * 1024;
+const PREAMBLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preamble {
+    /// Skips this many bytes
+    Bytes(usize),
+    /// Skips up to and including the first occurrence of this delimiter
+    Until(Vec<u8>),
+}
+
+impl Preamble {
+    /// Parses `bytes:N` or `until:DELIMITER`, where `\n`, `\r`, `\t` and `\\` in the delimiter are
+    /// unescaped.
+    pub fn parse(value: &str) -> std::result::Result<Self, String> {
+        if let Some(count) = value.strip_prefix("bytes:") {
+            let count = count
+                .parse::<usize>()
+                .map_err(|_| format!("expected a number of bytes, got `{count}`"))?;
+            if count == 0 || count > MAX_PREAMBLE_SIZE {
+                return Err(format!(
+                    "the preamble has to be between 1 and {MAX_PREAMBLE_SIZE} bytes"
+                ));
+            }
+            Ok(Preamble::Bytes(count))
+        } else if let Some(delimiter) = value.strip_prefix("until:") {
+            let delimiter = unescape(delimiter);
+            if delimiter.is_empty() {
+                return Err("the delimiter can't be empty".to_owned());
+            }
+            Ok(Preamble::Until(delimiter))
+        } else {
+            Err(format!(
+                "expected `bytes:N` or `until:DELIMITER`, got `{value}`"
+            ))
+        }
+    }
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let c = match (c, chars.clone().next()) {
+            ('\\', Some(escaped @ ('n' | 'r' | 't' | '\\'))) => {
+                chars.next();
+                match escaped {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    _ => '\\',
+                }
+            }
+            (c, _) => c,
+        };
+        let mut buffer = [0; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+    }
+    bytes
+}
+
+async fn read_preamble(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    preamble: &Preamble,
+) -> Result<Vec<u8>> {
+    match preamble {
+        Preamble::Bytes(count) => {
+            let mut banner = vec![0; *count];
+            reader.read_exact(&mut banner).await?;
+            Ok(banner)
+        }
+        Preamble::Until(delimiter) => {
+            let last = *delimiter.last().expect("The delimiter is not empty");
+            let mut banner = Vec::new();
+            while !banner.ends_with(delimiter) {
+                let limit = (MAX_PREAMBLE_SIZE - banner.len()) as u64;
+                if limit == 0 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("No preamble delimiter in the first {MAX_PREAMBLE_SIZE} bytes"),
+                    ));
+                }
+                let read = (&mut *reader)
+                    .take(limit)
+                    .read_until(last, &mut banner)
+                    .await?;
+                if read == 0 {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "The connection closed in the preamble",
+                    ));
+                }
+            }
+            Ok(banner)
+        }
+    }
+}
+
+/// Reads the banner of `preamble` from `reader`, leaving it at the start of the RPC stream.
+pub async fn skip_preamble(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    preamble: &Preamble,
+) -> Result<()> {
+    let banner = timeout(PREAMBLE_TIMEOUT, read_preamble(reader, preamble))
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "Timed out reading the preamble"))??;
+    log::debug!(
+        "Skipped the connection preamble {:?}",
+        String::from_utf8_lossy(&banner)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::BufReader;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_preamble() {
+        assert_eq!(Preamble::parse("bytes:12"), Ok(Preamble::Bytes(12)));
+        assert_eq!(
+            Preamble::parse("until:\\r\\n\\r\\n"),
+            Ok(Preamble::Until(b"\r\n\r\n".to_vec()))
+        );
+        assert_eq!(
+            Preamble::parse("until:READY\\\\"),
+            Ok(Preamble::Until(b"READY\\".to_vec()))
+        );
+        assert!(Preamble::parse("bytes:0").is_err());
+        assert!(Preamble::parse("until:").is_err());
+        assert!(Preamble::parse("12").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delimiter_leaves_the_rest_of_the_stream() {
+        let stream: &[u8] = b"Welcome\r\nto the gateway\r\n\r\n\x94\x00";
+        let mut reader = BufReader::new(stream);
+        let preamble = Preamble::Until(b"\r\n\r\n".to_vec());
+        skip_preamble(&mut reader, &preamble).await.unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"\x94\x00");
+
+        let mut reader = BufReader::new(&b"no delimiter"[..]);
+        let error = skip_preamble(&mut reader, &preamble).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,130 @@
+//! `--nvim-pty`, which starts the embedded Neovim with its stdin and stdout on a pseudo-terminal
+//! instead of pipes, for plugins that check whether Neovim runs in a terminal. The terminal is put
+//! in raw mode, so that the RPC messages pass through it unchanged.
+
+use std::{
+    fs::File,
+    io::{Error, Read, Result, Write},
+    mem::MaybeUninit,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    pin::Pin,
+    ptr::null_mut,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{unix::AsyncFd, AsyncRead, AsyncWrite, ReadBuf};
+
+/// Opens a pseudo-terminal in raw mode, returning its controlling side for Neovide and the
+/// terminal side for the child.
+pub fn open_pty() -> Result<(File, OwnedFd)> {
+    let (mut master, mut slave) = (-1, -1);
+    // SAFETY: Only writes the two descriptors, the name, settings and size are left out
+    if unsafe { libc::openpty(&mut master, &mut slave, null_mut(), null_mut(), null_mut()) } == -1 {
+        return Err(Error::last_os_error());
+    }
+    // SAFETY: openpty opened both descriptors, and nothing else owns them
+    let (master, slave) = unsafe { (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+    // The child only gets the terminal side, as its stdin and stdout
+    set_cloexec(master.as_raw_fd())?;
+    set_cloexec(slave.as_raw_fd())?;
+    make_raw(slave.as_raw_fd())?;
+    Ok((master, slave))
+}
+
+fn set_cloexec(fd: RawFd) -> Result<()> {
+    // SAFETY: Only changes the flags of a descriptor owned by the caller
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Turns off the echo, line editing and translation of the terminal.
+fn make_raw(fd: RawFd) -> Result<()> {
+    let mut termios = MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: tcgetattr fills the termios when it succeeds, which is checked before it's read
+    unsafe {
+        if libc::tcgetattr(fd, termios.as_mut_ptr()) == -1 {
+            return Err(Error::last_os_error());
+        }
+        let mut termios = termios.assume_init();
+        libc::cfmakeraw(&mut termios);
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) == -1 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn set_nonblocking(fd: RawFd) -> Result<()> {
+    // SAFETY: Only changes the flags of a descriptor owned by the caller
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) == -1 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Splits the controlling side of the terminal into a reader and a writer, which wait for it with
+/// the runtime like a socket does, instead of tying up a blocking thread.
+pub fn split_pty(master: File) -> Result<(PtyReader, PtyWriter)> {
+    set_nonblocking(master.as_raw_fd())?;
+    let master = Arc::new(AsyncFd::new(master)?);
+    Ok((PtyReader(master.clone()), PtyWriter(master)))
+}
+
+/// Reads the controlling side of the terminal, ending like a pipe once the child closed its side,
+/// which Linux reports as `EIO` instead.
+pub struct PtyReader(Arc<AsyncFd<File>>);
+
+impl AsyncRead for PtyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        loop {
+            let mut guard = ready!(self.0.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|master| master.get_ref().read(unfilled)) {
+                Ok(Ok(read)) => {
+                    buf.advance(read);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(error)) if error.raw_os_error() == Some(libc::EIO) => {
+                    return Poll::Ready(Ok(()))
+                }
+                Ok(Err(error)) => return Poll::Ready(Err(error)),
+                // Not readable after all, the readiness was cleared
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+/// Writes the controlling side of the terminal.
+pub struct PtyWriter(Arc<AsyncFd<File>>);
+
+impl AsyncWrite for PtyWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        loop {
+            let mut guard = ready!(self.0.poll_write_ready(cx))?;
+            match guard.try_io(|master| master.get_ref().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// The terminal is closed once both halves are dropped.
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,97 @@
+//! `--reconnect-max-duration`, which gives up reconnecting once the server has been unreachable
+//! for that long, however many attempts the backoff allowed in that time. The time reconnecting
+//! was paused doesn't count.
+
+use std::time::{Duration, Instant};
+
+/// The exit code when giving up, `EX_TEMPFAIL`, so that a wrapper script can tell it from Neovim
+/// quitting and start Neovide again later.
+pub const RECONNECT_GAVE_UP_EXIT_CODE: u8 = 75;
+
+pub struct ReconnectBudget {
+    /// `None` reconnects forever
+    limit: Option<Duration>,
+    started_at: Instant,
+}
+
+impl ReconnectBudget {
+    /// A budget of `limit_secs` starting `now`, 0 is unlimited.
+    pub fn new(limit_secs: u64, now: Instant) -> Self {
+        Self {
+            limit: (limit_secs > 0).then(|| Duration::from_secs(limit_secs)),
+            started_at: now,
+        }
+    }
+
+    /// Starts counting again, after a connection was lost.
+    pub fn restart(&mut self, now: Instant) {
+        self.started_at = now;
+    }
+
+    /// Doesn't count the time reconnecting was `paused` against the budget.
+    pub fn exclude(&mut self, paused: Duration) {
+        self.started_at += paused;
+    }
+
+    pub fn is_exhausted(&self, now: Instant) -> bool {
+        self.limit
+            .is_some_and(|limit| now.saturating_duration_since(self.started_at) >= limit)
+    }
+
+    /// Shortens `wait` so that it doesn't sleep past the end of the budget.
+    pub fn cap(&self, wait: Duration, now: Instant) -> Duration {
+        match self.limit {
+            Some(limit) => {
+                let remaining =
+                    limit.saturating_sub(now.saturating_duration_since(self.started_at));
+                wait.min(remaining)
+            }
+            None => wait,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_is_unlimited() {
+        let now = Instant::now();
+        let budget = ReconnectBudget::new(0, now);
+        let later = now + Duration::from_secs(60 * 60 * 24);
+        assert!(!budget.is_exhausted(later));
+        assert_eq!(
+            budget.cap(Duration::from_secs(30), later),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn test_restart_after_a_connection() {
+        let now = Instant::now();
+        let mut budget = ReconnectBudget::new(10, now);
+        let later = now + Duration::from_secs(10);
+        assert!(budget.is_exhausted(later));
+
+        budget.restart(later);
+        assert!(!budget.is_exhausted(later));
+        assert_eq!(
+            budget.cap(Duration::from_secs(30), later + Duration::from_secs(4)),
+            Duration::from_secs(6)
+        );
+    }
+
+    #[test]
+    fn test_paused_time_is_excluded() {
+        let now = Instant::now();
+        let mut budget = ReconnectBudget::new(10, now);
+        let later = now + Duration::from_secs(15);
+        budget.exclude(Duration::from_secs(8));
+        assert!(!budget.is_exhausted(later));
+        assert_eq!(
+            budget.cap(Duration::from_secs(30), later),
+            Duration::from_secs(3)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! A notice the server sets while still connected, like "Maintenance in 5 min", that's shown on
+//! the reconnect overlay once the connection is lost, since the server can't tell anything after
+//! that. It's cleared by the next successful connection.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+#[derive(Default)]
+pub struct ReconnectNotice(Mutex<Option<String>>);
+
+impl ReconnectNotice {
+    /// Replaces the notice, an empty one clears it.
+    pub fn set(&self, notice: String) {
+        *self.0.lock() = (!notice.is_empty()).then_some(notice);
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().take();
+    }
+
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().clone()
+    }
+}
+
+static RECONNECT_NOTICE: Lazy<ReconnectNotice> = Lazy::new(ReconnectNotice::default);
+
+pub fn set_reconnect_notice(notice: String) {
+    log::info!("Reconnect notice set to {notice:?}");
+    RECONNECT_NOTICE.set(notice);
+}
+
+pub fn clear_reconnect_notice() {
+    RECONNECT_NOTICE.clear();
+}
+
+pub fn reconnect_notice() -> Option<String> {
+    RECONNECT_NOTICE.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notice_is_kept_until_cleared() {
+        let notice = ReconnectNotice::default();
+        assert_eq!(notice.get(), None);
+        notice.set("Maintenance in 5 min".to_owned());
+        assert_eq!(notice.get().as_deref(), Some("Maintenance in 5 min"));
+        notice.set(String::new());
+        assert_eq!(notice.get(), None);
+
+        notice.set("Maintenance in 5 min".to_owned());
+        notice.clear();
+        assert_eq!(notice.get(), None);
+    }
+}
@@ -0,0 +1,34 @@
+//! Lets code that embeds the bridge follow the reconnect loop of a `--server` without listening
+//! for the `UserEvent`s sent to the window, which are still sent as well.
+
+use anyhow::Error;
+
+/// Called by the reconnect loop, `attempt` is the number of the attempt since the last successful
+/// connection, starting at 0. Every method does nothing by default.
+pub trait ReconnectObserver: Send + Sync {
+    fn on_attempt(&self, _attempt: u32) {}
+
+    fn on_success(&self, _attempt: u32) {}
+
+    /// `error` is `None` when the server didn't answer the probe.
+    fn on_failure(&self, _attempt: u32, _error: Option<&Error>) {}
+
+    /// The loop stopped for good, for example because Neovide is quitting.
+    fn on_give_up(&self, _attempt: u32) {}
+}
+
+pub struct NoopObserver;
+
+impl ReconnectObserver for NoopObserver {}
+
+/// Reports the result of a connection attempt, `Err(None)` being a failed probe.
+pub fn report_attempt_result<T>(
+    observer: &dyn ReconnectObserver,
+    attempt: u32,
+    result: &Result<T, Option<Error>>,
+) {
+    match result {
+        Ok(_) => observer.on_success(attempt),
+        Err(error) => observer.on_failure(attempt, error.as_ref()),
+    }
+}
@@ -0,0 +1,147 @@
+//! Lets the reconnect loop be paused, for example while a server is down for maintenance, and
+//! resumed again without waiting for the backoff. The backoff can also be cut short without
+//! pausing, with the reconnect now key.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+
+#[derive(Default)]
+pub struct ReconnectPause {
+    paused: AtomicBool,
+    paused_changed: Notify,
+    resumed: Notify,
+}
+
+impl ReconnectPause {
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Release);
+        self.paused_changed.notify_waiters();
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+        self.resumed.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// Returns immediately when not paused, otherwise as soon as `resume` is called.
+    pub async fn wait_while_paused(&self) {
+        loop {
+            let resumed = self.resumed.notified();
+            tokio::pin!(resumed);
+            // Register before checking, so that a resume in between isn't missed
+            resumed.as_mut().enable();
+            if !self.is_paused() {
+                return;
+            }
+            resumed.await;
+        }
+    }
+
+    /// Returns as soon as reconnecting is paused, immediately when it already is, so that a
+    /// running backoff can stop counting down.
+    pub async fn wait_until_paused(&self) {
+        loop {
+            let paused = self.paused_changed.notified();
+            tokio::pin!(paused);
+            paused.as_mut().enable();
+            if self.is_paused() {
+                return;
+            }
+            paused.await;
+        }
+    }
+}
+
+pub static RECONNECT_PAUSE: Lazy<ReconnectPause> = Lazy::new(ReconnectPause::default);
+
+pub fn pause_reconnect() {
+    log::info!("Reconnecting paused");
+    RECONNECT_PAUSE.pause();
+}
+
+pub fn resume_reconnect() {
+    log::info!("Reconnecting resumed");
+    RECONNECT_PAUSE.resume();
+}
+
+pub fn is_reconnect_paused() -> bool {
+    RECONNECT_PAUSE.is_paused()
+}
+
+/// Wakes the reconnect loop while it waits for the next attempt.
+pub static RECONNECT_NOW: Lazy<Notify> = Lazy::new(Notify::new);
+
+pub fn reconnect_now() {
+    log::info!("Reconnecting now");
+    RECONNECT_NOW.notify_waiters();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use tokio::{spawn, time::sleep};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pause_halts_attempts_until_resumed() {
+        let pause = Arc::new(ReconnectPause::default());
+        let attempts = Arc::new(AtomicU32::new(0));
+        pause.pause();
+
+        let reconnect_loop = spawn({
+            let pause = pause.clone();
+            let attempts = attempts.clone();
+            async move {
+                for _ in 0..3 {
+                    pause.wait_while_paused().await;
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(attempts.load(Ordering::SeqCst), 0);
+
+        pause.resume();
+        reconnect_loop.await.unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_paused() {
+        let pause = Arc::new(ReconnectPause::default());
+        let backoff = spawn({
+            let pause = pause.clone();
+            async move { pause.wait_until_paused().await }
+        });
+        sleep(Duration::from_millis(50)).await;
+        assert!(!backoff.is_finished());
+
+        pause.pause();
+        backoff.await.unwrap();
+        pause.wait_until_paused().await;
+    }
+
+    #[tokio::test]
+    async fn test_not_paused_returns_immediately() {
+        let pause = ReconnectPause::default();
+        pause.wait_while_paused().await;
+        pause.pause();
+        pause.resume();
+        pause.wait_while_paused().await;
+    }
+}
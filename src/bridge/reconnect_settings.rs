@@ -0,0 +1,29 @@
+//! The `g:neovide_reconnect_` settings of the reconnect loop. The settings of the overlay shown
+//! while reconnecting are the `ReconnectIndicatorSettings` of the renderer.
+
+use super::attach_options::AttachOptionsByAddress;
+use crate::settings::*;
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "reconnect"]
+pub struct ReconnectSettings {
+    pub initial_backoff: u64,
+    pub max_backoff: u64,
+    /// Seconds a connection has to stay up for the backoff to start over once it's lost
+    pub stable_time: u64,
+    pub ping_interval: u64,
+    /// The attach options of single `--server` addresses
+    pub attach_options: AttachOptionsByAddress,
+}
+
+impl Default for ReconnectSettings {
+    fn default() -> Self {
+        Self {
+            initial_backoff: 1,
+            max_backoff: 32,
+            stable_time: 10,
+            ping_interval: 5,
+            attach_options: AttachOptionsByAddress::default(),
+        }
+    }
+}
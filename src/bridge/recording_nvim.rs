@@ -0,0 +1,59 @@
+//! A fake Neovim for tests, that records the requests it gets.
+
+use std::sync::Arc;
+
+use nvim_rs::{Neovim, Value};
+use parking_lot::Mutex;
+use tokio::io::{duplex, split, AsyncReadExt, AsyncWriteExt};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+use super::NeovimWriter;
+
+#[derive(Clone)]
+struct TestHandler;
+
+#[async_trait::async_trait]
+impl nvim_rs::Handler for TestHandler {
+    type Writer = NeovimWriter;
+}
+
+pub type Requests = Arc<Mutex<Vec<(String, Value)>>>;
+
+/// A Neovim that answers every request with nil and records the method and arguments.
+pub fn recording_nvim() -> (Neovim<NeovimWriter>, Requests) {
+    let (client, mut server) = duplex(4096);
+    let (reader, writer) = split(client);
+    let writer: NeovimWriter = Box::new(writer.compat_write());
+    let (nvim, io) = Neovim::new(reader.compat(), writer, TestHandler);
+    tokio::spawn(io);
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    tokio::spawn({
+        let requests = requests.clone();
+        async move {
+            let mut received = Vec::new();
+            let mut chunk = [0; 1024];
+            while let Ok(read @ 1..) = server.read(&mut chunk).await {
+                received.extend_from_slice(&chunk[..read]);
+                let mut rest = received.as_slice();
+                while let Ok(message) = rmpv::decode::read_value(&mut rest) {
+                    received = rest.to_vec();
+                    rest = received.as_slice();
+                    let Value::Array(message) = message else {
+                        continue;
+                    };
+                    let [_, id, method, arguments] = message.as_slice() else {
+                        continue;
+                    };
+                    let method = method.as_str().unwrap_or_default().to_owned();
+                    requests.lock().push((method, arguments.clone()));
+                    let response = Value::Array(vec![1.into(), id.clone(), Value::Nil, Value::Nil]);
+                    let mut encoded = Vec::new();
+                    rmpv::encode::write_value(&mut encoded, &response).unwrap();
+                    server.write_all(&encoded).await.unwrap();
+                }
+            }
+        }
+    });
+    (nvim, requests)
+}
@@ -0,0 +1,230 @@
+//! Passes the redraw events of Neovim on to the editor from a single task, in the order they
+//! arrived. The events held back for `--coalesce-first-frame` and `--max-redraw-rate` are released
+//! by the same task, so a newer event can never overtake them.
+
+use std::{future::pending, time::Duration};
+
+use tokio::{
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{sleep_until, Instant},
+};
+
+use super::{first_frame::FirstFrame, redraw_throttle::RedrawThrottle, RedrawEvent};
+
+enum Item {
+    Event(RedrawEvent),
+    HoldFirstFrame,
+}
+
+#[derive(Clone)]
+pub struct RedrawQueue {
+    items: UnboundedSender<Item>,
+}
+
+impl RedrawQueue {
+    /// Starts the task that passes the events on to `send`, it has to run on the runtime. An
+    /// incomplete first frame is held back for at most `first_frame_timeout`, and the frames are
+    /// coalesced by the `throttle` when there is one.
+    pub fn start(
+        send: impl FnMut(RedrawEvent) + Send + 'static,
+        first_frame_timeout: Duration,
+        throttle: Option<RedrawThrottle>,
+    ) -> Self {
+        let (items, receiver) = unbounded_channel();
+        tokio::spawn(forward(receiver, send, first_frame_timeout, throttle));
+        Self { items }
+    }
+
+    pub fn push(&self, event: RedrawEvent) {
+        // Only fails once the runtime shuts down
+        self.items.send(Item::Event(event)).ok();
+    }
+
+    /// Holds back the events from now on until the first frame is complete.
+    pub fn hold_first_frame(&self) {
+        self.items.send(Item::HoldFirstFrame).ok();
+    }
+}
+
+/// Waits until `at`, forever without one.
+async fn sleep_until_some(at: Option<Instant>) {
+    match at {
+        Some(at) => sleep_until(at).await,
+        None => pending().await,
+    }
+}
+
+async fn forward(
+    mut items: UnboundedReceiver<Item>,
+    mut send: impl FnMut(RedrawEvent),
+    first_frame_timeout: Duration,
+    mut throttle: Option<RedrawThrottle>,
+) {
+    let mut first_frame = FirstFrame::default();
+    // When the first frame is shown anyway, while it's held back
+    let mut release_at = None;
+    // When the frame held back by the throttle is shown
+    let mut throttle_release_at = None;
+    loop {
+        let events = select! {
+            item = items.recv() => match item {
+                Some(Item::Event(event)) => first_frame.push(event),
+                Some(Item::HoldFirstFrame) => {
+                    first_frame.hold();
+                    release_at = Some(Instant::now() + first_frame_timeout);
+                    Vec::new()
+                }
+                None => return,
+            },
+            _ = sleep_until_some(throttle_release_at) => {
+                throttle_release_at = None;
+                if let Some(throttle) = throttle.as_mut() {
+                    throttle.release(Instant::now().into_std()).into_iter().for_each(&mut send);
+                }
+                continue;
+            }
+            _ = sleep_until_some(release_at) => {
+                let events = first_frame.release();
+                if !events.is_empty() {
+                    log::info!(
+                        "The first frame didn't complete within {}ms, showing {} events",
+                        first_frame_timeout.as_millis(),
+                        events.len()
+                    );
+                }
+                events
+            }
+        };
+        if !first_frame.is_held() {
+            release_at = None;
+        }
+        let Some(throttle) = throttle.as_mut() else {
+            events.into_iter().for_each(&mut send);
+            continue;
+        };
+        for event in events {
+            let (events, release) = throttle.push(event, Instant::now().into_std());
+            events.into_iter().for_each(&mut send);
+            if let Some(release) = release {
+                throttle_release_at.get_or_insert(release.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use tokio::time::sleep;
+
+    use super::*;
+
+    fn set_title(title: &str) -> RedrawEvent {
+        RedrawEvent::SetTitle {
+            title: title.to_owned(),
+        }
+    }
+
+    fn recording_queue(first_frame_timeout: Duration) -> (RedrawQueue, Receiver<String>) {
+        throttled_queue(first_frame_timeout, None)
+    }
+
+    fn throttled_queue(
+        first_frame_timeout: Duration,
+        throttle: Option<RedrawThrottle>,
+    ) -> (RedrawQueue, Receiver<String>) {
+        let (sender, receiver) = channel();
+        let queue = RedrawQueue::start(
+            move |event| {
+                let name = match event {
+                    RedrawEvent::SetTitle { title } => title,
+                    RedrawEvent::Flush => "flush".to_owned(),
+                    other => format!("{other:?}"),
+                };
+                sender.send(name).ok();
+            },
+            first_frame_timeout,
+            throttle,
+        );
+        (queue, receiver)
+    }
+
+    #[tokio::test]
+    async fn test_first_frame_is_sent_at_once() {
+        let (queue, receiver) = recording_queue(Duration::from_secs(10));
+        queue.push(set_title("before"));
+        queue.hold_first_frame();
+        queue.push(set_title("a"));
+        queue.push(set_title("b"));
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["before"]);
+
+        queue.push(RedrawEvent::Flush);
+        queue.push(set_title("c"));
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            ["a", "b", "flush", "c"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_released_frame_stays_ahead_of_newer_events() {
+        let (queue, receiver) = recording_queue(Duration::from_millis(10));
+        queue.hold_first_frame();
+        queue.push(set_title("a"));
+        sleep(Duration::from_millis(5)).await;
+        // Arrive around the timeout, and still come after the held back event
+        for title in ["b", "c", "d"] {
+            queue.push(set_title(title));
+            sleep(Duration::from_millis(3)).await;
+        }
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            ["a", "b", "c", "d"]
+        );
+
+        // The frame isn't held back anymore
+        queue.push(set_title("e"));
+        sleep(Duration::from_millis(5)).await;
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["e"]);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_of_a_completed_frame_releases_nothing_later() {
+        let (queue, receiver) = recording_queue(Duration::from_millis(50));
+        queue.hold_first_frame();
+        queue.push(RedrawEvent::Flush);
+        sleep(Duration::from_millis(10)).await;
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["flush"]);
+
+        // A second attach holds back again, with a timeout of its own
+        queue.hold_first_frame();
+        queue.push(set_title("a"));
+        sleep(Duration::from_millis(10)).await;
+        assert_eq!(receiver.try_iter().count(), 0);
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_throttled_frames_keep_their_order() {
+        let throttle = RedrawThrottle::new(50);
+        let (queue, receiver) = throttled_queue(Duration::from_secs(10), throttle);
+        for title in ["a", "b", "c"] {
+            queue.push(set_title(title));
+            queue.push(RedrawEvent::Flush);
+        }
+        sleep(Duration::from_millis(5)).await;
+        // The second frame is merged into the third, which waits for the interval
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["a", "flush", "b"]);
+
+        sleep(Duration::from_millis(40)).await;
+        queue.push(set_title("d"));
+        sleep(Duration::from_millis(5)).await;
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), ["c", "flush", "d"]);
+    }
+}
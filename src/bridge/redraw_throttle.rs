@@ -0,0 +1,187 @@
+//! `--max-redraw-rate`, which coalesces the frames Neovim sends faster than the rate, for example
+//! while a log scrolls by, so that drawing them doesn't starve the input. Only the `flush` that
+//! ends a frame is held back, together with the events after it, and the events of the skipped
+//! frames all end up in the frame that is shown. The last frame of a burst is always shown, once
+//! the interval has passed.
+
+use std::time::{Duration, Instant};
+
+use super::RedrawEvent;
+
+#[derive(Default)]
+struct State {
+    last_flush: Option<Instant>,
+    /// The events since a held back `flush`, `None` while they're passed on right away
+    held: Option<Vec<RedrawEvent>>,
+    /// Where the held back `flush` is in `held`
+    flush_index: usize,
+    /// When frames started to be coalesced, and how many were so far
+    throttling_since: Option<Instant>,
+    coalesced: u64,
+}
+
+pub struct RedrawThrottle {
+    max_rate: u32,
+    min_interval: Duration,
+    state: State,
+}
+
+impl RedrawThrottle {
+    /// A throttle for at most `max_rate` frames per second, `None` when it's 0.
+    pub fn new(max_rate: u32) -> Option<Self> {
+        (max_rate > 0).then(|| Self {
+            max_rate,
+            min_interval: Duration::from_secs(1) / max_rate,
+            state: State::default(),
+        })
+    }
+
+    /// Returns the events that can be sent now, and when [`RedrawThrottle::release`] has to be
+    /// called when it started holding back a frame.
+    pub fn push(
+        &mut self,
+        event: RedrawEvent,
+        now: Instant,
+    ) -> (Vec<RedrawEvent>, Option<Instant>) {
+        let state = &mut self.state;
+        let is_flush = matches!(event, RedrawEvent::Flush);
+        if let Some(held) = state.held.as_mut() {
+            let index = held.len();
+            held.push(event);
+            if is_flush {
+                // Only the latest flush is kept, which merges the frames in between into it
+                let previous = state.flush_index;
+                held.remove(previous);
+                state.flush_index = index - 1;
+                state.coalesced += 1;
+            }
+            return (Vec::new(), None);
+        }
+        if !is_flush {
+            return (vec![event], None);
+        }
+        let release_at = state.last_flush.map(|last| last + self.min_interval);
+        match release_at {
+            Some(release_at) if now < release_at => {
+                if state.throttling_since.is_none() {
+                    log::info!(
+                        "Neovim redraws faster than --max-redraw-rate {}/s, coalescing frames",
+                        self.max_rate
+                    );
+                    state.throttling_since = Some(now);
+                }
+                state.held = Some(vec![event]);
+                state.flush_index = 0;
+                (Vec::new(), Some(release_at))
+            }
+            _ => {
+                if let Some(since) = state.throttling_since.take() {
+                    log::info!(
+                        "Stopped coalescing frames after {}ms, {} were skipped",
+                        now.saturating_duration_since(since).as_millis(),
+                        std::mem::take(&mut state.coalesced)
+                    );
+                }
+                state.last_flush = Some(now);
+                (vec![event], None)
+            }
+        }
+    }
+
+    /// Returns the held back events, including the latest `flush`.
+    pub fn release(&mut self, now: Instant) -> Vec<RedrawEvent> {
+        let Some(held) = self.state.held.take() else {
+            return Vec::new();
+        };
+        self.state.last_flush = Some(now);
+        held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_title(title: &str) -> RedrawEvent {
+        RedrawEvent::SetTitle {
+            title: title.to_owned(),
+        }
+    }
+
+    fn names(events: &[RedrawEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                RedrawEvent::SetTitle { title } => title.clone(),
+                RedrawEvent::Flush => "flush".to_owned(),
+                other => format!("{other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_burst_is_coalesced_to_the_rate() {
+        let mut throttle = RedrawThrottle::new(20).unwrap();
+        let start = Instant::now();
+        let mut release_at = None;
+        let mut sent = Vec::new();
+        let mut flush_times = Vec::new();
+        // A frame every 5ms for a second, 200 frames/s
+        for frame in 0..200 {
+            let now = start + Duration::from_millis(frame * 5);
+            if release_at.is_some_and(|release_at| release_at <= now) {
+                release_at = None;
+                let released = throttle.release(now);
+                flush_times.extend(
+                    released
+                        .iter()
+                        .filter(|event| matches!(event, RedrawEvent::Flush))
+                        .map(|_| now),
+                );
+                sent.extend(released);
+            }
+            for event in [set_title(&frame.to_string()), RedrawEvent::Flush] {
+                let (events, release) = throttle.push(event, now);
+                flush_times.extend(
+                    events
+                        .iter()
+                        .filter(|event| matches!(event, RedrawEvent::Flush))
+                        .map(|_| now),
+                );
+                sent.extend(events);
+                release_at = release_at.or(release);
+            }
+        }
+        // The last frame of the burst
+        let end = start + Duration::from_secs(2);
+        let released = throttle.release(end);
+        assert!(matches!(released.last(), Some(RedrawEvent::Flush)));
+        sent.extend(released);
+        flush_times.push(end);
+
+        // At most 20 frames in the second of the burst, and the final one after it
+        assert!(flush_times.len() <= 22, "{} frames", flush_times.len());
+        assert!(flush_times
+            .windows(2)
+            .all(|pair| pair[1] - pair[0] >= Duration::from_millis(50)));
+        // No event is lost, and the last one is followed by a flush
+        let names = names(&sent);
+        let titles: Vec<_> = names.iter().filter(|name| *name != "flush").collect();
+        assert_eq!(titles.len(), 200);
+        assert_eq!(names[names.len() - 2..], ["199", "flush"]);
+    }
+
+    #[test]
+    fn test_slow_frames_pass_right_away() {
+        let mut throttle = RedrawThrottle::new(60).unwrap();
+        let start = Instant::now();
+        for frame in 0..5 {
+            let now = start + Duration::from_millis(frame * 100);
+            assert_eq!(names(&throttle.push(set_title("a"), now).0), ["a"]);
+            let (events, release_at) = throttle.push(RedrawEvent::Flush, now);
+            assert_eq!(names(&events), ["flush"]);
+            assert_eq!(release_at, None);
+        }
+        assert!(RedrawThrottle::new(0).is_none());
+    }
+}
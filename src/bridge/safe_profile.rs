@@ -0,0 +1,159 @@
+//! `--safe-profile`, which starts the embedded Neovim hardened for opening files from untrusted
+//! sources: with a minimal init of its own instead of the user config, without shada, swap files
+//! or modelines, and with the XDG directories in a temporary directory, so that neither the
+//! user's config and plugins, nor the state they wrote, are used. The directory is removed when
+//! Neovide exits.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+
+use crate::running_tracker::RunningTracker;
+
+/// Turns off what would let an opened file run code, `secure` also covers a later `:source`.
+const SAFE_INIT: &str = "set nomodeline noexrc secure\n";
+
+/// The XDG directories that are moved into the profile, each into a subdirectory of its own.
+const XDG_DIRS: &[(&str, &str)] = &[
+    ("XDG_CONFIG_HOME", "config"),
+    ("XDG_DATA_HOME", "data"),
+    ("XDG_STATE_HOME", "state"),
+    ("XDG_CACHE_HOME", "cache"),
+    ("XDG_RUNTIME_DIR", "run"),
+];
+
+/// How many random names are tried before giving up, when the directory already exists.
+const CREATE_ATTEMPTS: u32 = 16;
+
+static SAFE_PROFILE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// The directory of the profile, once `create_safe_profile` made it.
+pub fn safe_profile_dir() -> Option<&'static Path> {
+    SAFE_PROFILE_DIR.get().map(PathBuf::as_path)
+}
+
+fn init_path(dir: &Path) -> PathBuf {
+    dir.join("init.vim")
+}
+
+/// The arguments that harden Neovim, the init in `dir` instead of the user config, no shada and
+/// no swap files.
+pub fn safe_profile_args(dir: &Path) -> Vec<String> {
+    vec![
+        "-u".to_owned(),
+        init_path(dir).to_string_lossy().into_owned(),
+        "-i".to_owned(),
+        "NONE".to_owned(),
+        "-n".to_owned(),
+    ]
+}
+
+/// The environment that moves the XDG directories into `dir`.
+pub fn safe_profile_env(dir: &Path) -> Vec<(&'static str, PathBuf)> {
+    XDG_DIRS
+        .iter()
+        .map(|(key, name)| (*key, dir.join(name)))
+        .collect()
+}
+
+/// Creates a new directory with a random name in `parent`, that only the owner may look into,
+/// the runtime dir holds the socket of Neovim. Like `mkdtemp`, it fails rather than using a
+/// directory or a link that someone else put there in advance.
+fn create_private_dir(parent: &Path) -> io::Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    for _ in 0..CREATE_ATTEMPTS {
+        let dir = parent.join(format!(
+            "neovide-safe-profile-{:016x}",
+            rand::random::<u64>()
+        ));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "every name that was tried already exists",
+    ))
+}
+
+fn write_profile(dir: &Path) -> Result<()> {
+    for (_, path) in safe_profile_env(dir) {
+        fs::create_dir(&path)?;
+    }
+    fs::write(init_path(dir), SAFE_INIT)?;
+    Ok(())
+}
+
+/// Makes the temporary profile for this run, and removes it again when Neovide shuts down.
+pub fn create_safe_profile(running_tracker: &RunningTracker) -> Result<()> {
+    let temp_dir = env::temp_dir();
+    let dir = create_private_dir(&temp_dir).with_context(|| {
+        format!(
+            "Could not create the --safe-profile in {}",
+            temp_dir.display()
+        )
+    })?;
+    if let Err(error) = write_profile(&dir) {
+        fs::remove_dir_all(&dir).ok();
+        return Err(error)
+            .with_context(|| format!("Could not create the --safe-profile in {}", dir.display()));
+    }
+    log::info!("Using the --safe-profile in {}", dir.display());
+    running_tracker.on_shutdown({
+        let dir = dir.clone();
+        move || {
+            if let Err(error) = fs::remove_dir_all(&dir) {
+                log::warn!(
+                    "Could not remove the --safe-profile {}: {error}",
+                    dir.display()
+                );
+            }
+        }
+    });
+    SAFE_PROFILE_DIR.set(dir).ok();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_is_removed_on_shutdown() {
+        let running_tracker = RunningTracker::new();
+        create_safe_profile(&running_tracker).unwrap();
+        let dir = safe_profile_dir().unwrap();
+        assert_eq!(fs::read_to_string(init_path(dir)).unwrap(), SAFE_INIT);
+        assert!(dir.join("state").is_dir());
+
+        running_tracker.run_shutdown_callbacks();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_every_profile_gets_a_new_private_dir() {
+        let parent = env::temp_dir();
+        let first = create_private_dir(&parent).unwrap();
+        let second = create_private_dir(&parent).unwrap();
+        assert_ne!(first, second);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&first).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        fs::remove_dir(first).unwrap();
+        fs::remove_dir(second).unwrap();
+    }
+}
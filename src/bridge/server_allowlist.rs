@@ -0,0 +1,147 @@
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+
+use super::session::is_tcp_address;
+use crate::cmd_line::CmdLineSettings;
+
+/// A list of glob patterns restricting which addresses `--server` may connect to.
+///
+/// `*` matches any sequence of characters and `?` matches a single character. For TCP addresses
+/// a pattern without a `:` only needs to match the host, so `*.example.com` allows any port on
+/// any subdomain, while `*.example.com:6666` only allows that port. Socket and pipe paths are
+/// matched as a whole.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerAllowlist {
+    patterns: Vec<String>,
+}
+
+impl ServerAllowlist {
+    pub fn new(patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Combines `--server-allowlist` and the patterns in `--server-allowlist-file`, returns `None`
+    /// when neither is configured.
+    pub fn from_settings(cmdline_settings: &CmdLineSettings) -> Result<Option<Self>> {
+        let mut patterns = Vec::new();
+        if let Some(list) = &cmdline_settings.server_allowlist {
+            patterns.extend(parse_patterns(list, ','));
+        }
+        if let Some(path) = &cmdline_settings.server_allowlist_file {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("Could not read the server allowlist {path}"))?;
+            patterns.extend(parse_patterns(&contents, '\n'));
+        }
+        let configured = cmdline_settings.server_allowlist.is_some()
+            || cmdline_settings.server_allowlist_file.is_some();
+        Ok(configured.then(|| Self::new(patterns)))
+    }
+
+    pub fn is_allowed(&self, address: &str) -> bool {
+        let host = if is_tcp_address(address) {
+            address.rsplit_once(':').map(|(host, _)| host)
+        } else {
+            None
+        };
+        self.patterns.iter().any(|pattern| {
+            glob_match(pattern, address)
+                || (!pattern.contains(':') && host.is_some_and(|host| glob_match(pattern, host)))
+        })
+    }
+
+    pub fn check(&self, address: &str) -> Result<()> {
+        if !self.is_allowed(address) {
+            bail!("Connecting to {address} is not allowed by the server allowlist");
+        }
+        Ok(())
+    }
+}
+
+fn parse_patterns(list: &str, separator: char) -> impl Iterator<Item = String> + '_ {
+    list.split(separator)
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty() && !pattern.starts_with('#'))
+        .map(str::to_owned)
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` in the pattern and the text position it was tried at
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*.example.com", "dev.example.com"));
+        assert!(glob_match("10.0.0.?", "10.0.0.7"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("*.example.com", "example.org"));
+        assert!(!glob_match("10.0.0.?", "10.0.0.17"));
+    }
+
+    #[test]
+    fn test_allowed_addresses() {
+        let allowlist =
+            ServerAllowlist::new(["*.example.com", "localhost:6666", "/tmp/nvim*.sock"]);
+        assert!(allowlist.is_allowed("dev.example.com:7777"));
+        assert!(allowlist.is_allowed("localhost:6666"));
+        assert!(allowlist.is_allowed("/tmp/nvim.1234.sock"));
+        assert!(allowlist.check("dev.example.com:7777").is_ok());
+    }
+
+    #[test]
+    fn test_denied_addresses() {
+        let allowlist =
+            ServerAllowlist::new(["*.example.com", "localhost:6666", "/tmp/nvim*.sock"]);
+        assert!(!allowlist.is_allowed("evil.com:6666"));
+        assert!(!allowlist.is_allowed("localhost:7777"));
+        assert!(!allowlist.is_allowed("/home/user/nvim.sock"));
+        assert!(allowlist.check("evil.com:6666").is_err());
+    }
+
+    #[test]
+    fn test_empty_allowlist_denies_everything() {
+        assert!(!ServerAllowlist::default().is_allowed("localhost:6666"));
+    }
+
+    #[test]
+    fn test_parse_patterns_skips_comments_and_blanks() {
+        let patterns = parse_patterns(
+            "# managed hosts\n*.example.com\n\n  localhost:6666 \n",
+            '\n',
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(patterns, vec!["*.example.com", "localhost:6666"]);
+    }
+}
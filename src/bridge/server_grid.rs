@@ -0,0 +1,81 @@
+//! `--match-server-grid`, which attaches to a `--server` with the grid size it already has and
+//! sizes the window to it, instead of making the server resize to the window first, which shows
+//! as a flash of the old layout.
+
+use std::time::Duration;
+
+use nvim_rs::{Neovim, Value};
+use tokio::time::timeout;
+
+use super::NeovimWriter;
+use crate::{
+    settings::{clamped_grid_size, DEFAULT_GRID_SIZE},
+    units::GridSize,
+};
+
+/// How long the server may take to report its grid, the window size is used otherwise.
+const SERVER_GRID_TIMEOUT: Duration = Duration::from_secs(1);
+
+const GRID_LUA: &str = "return { vim.o.columns, vim.o.lines }";
+
+fn parse_grid_size(value: &Value) -> Option<GridSize<u32>> {
+    let columns = value[0].as_u64()?.try_into().ok()?;
+    let lines = value[1].as_u64()?.try_into().ok()?;
+    Some(GridSize::new(columns, lines))
+}
+
+/// Asks the server at `address` for its current grid size, `None` when it doesn't answer in
+/// time or with something else.
+pub async fn server_grid_size(nvim: &Neovim<NeovimWriter>, address: &str) -> Option<GridSize<u32>> {
+    let value = match timeout(SERVER_GRID_TIMEOUT, nvim.exec_lua(GRID_LUA, vec![])).await {
+        Ok(Ok(value)) => value,
+        Ok(Err(error)) => {
+            log::debug!("Could not get the grid size of {address}: {error}");
+            return None;
+        }
+        Err(_) => {
+            log::debug!("Getting the grid size of {address} timed out");
+            return None;
+        }
+    };
+    let grid_size = parse_grid_size(&value);
+    if grid_size.is_none() {
+        log::debug!("Unexpected grid size {value} from {address}");
+    }
+    grid_size
+}
+
+/// The grid size to attach with, the one of the server when it's known, then the one of the
+/// window, and the default otherwise.
+pub fn attach_grid_size(
+    requested: Option<GridSize<u32>>,
+    server: Option<GridSize<u32>>,
+) -> GridSize<u32> {
+    server
+        .or(requested)
+        .map_or(DEFAULT_GRID_SIZE, |v| clamped_grid_size(&v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::recording_nvim::recording_nvim;
+
+    #[test]
+    fn test_attach_uses_the_server_grid() {
+        let window = Some(GridSize::new(100, 50));
+        let server = parse_grid_size(&Value::from(vec![Value::from(132), Value::from(43)]));
+        assert_eq!(server, Some(GridSize::new(132, 43)));
+        assert_eq!(attach_grid_size(window, server), GridSize::new(132, 43));
+        assert_eq!(attach_grid_size(window, None), GridSize::new(100, 50));
+        assert_eq!(attach_grid_size(None, None), DEFAULT_GRID_SIZE);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_without_a_server_grid() {
+        let (nvim, requests) = recording_nvim();
+        assert_eq!(server_grid_size(&nvim, "localhost:6666").await, None);
+        assert_eq!(requests.lock()[0].0, "nvim_exec_lua");
+        assert_eq!(parse_grid_size(&Value::from(vec![Value::from(-1)])), None);
+    }
+}
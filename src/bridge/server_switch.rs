@@ -0,0 +1,119 @@
+//! `:NeovideSwitchServer`, which moves the `--server` connection to another address without
+//! restarting Neovide. The session with the current server is ended, and the reconnect loop
+//! connects to the new address from then on, the same way it reconnects after a lost connection.
+//! When a session with the address is already registered, it's activated instead.
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use super::{check_server_allowlist, sessions::activate_session};
+use crate::{cmd_line::CmdLineSettings, settings::Settings};
+
+/// The address of the last switch that the reconnect loop didn't apply yet.
+static REQUESTED_SERVER: Lazy<Mutex<Option<String>>> = Lazy::new(Mutex::default);
+/// Keeps a permit, so that a switch requested while connecting still ends the next session.
+static SWITCH_SERVER: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Checks `address` the same way `--server` is, the switch only works while connected to a server.
+fn validate_address(cmdline_settings: &CmdLineSettings, address: &str) -> Result<String> {
+    if cmdline_settings.server.is_none() {
+        bail!("Switching the server only works when connected with --server");
+    }
+    let address = address.trim();
+    if address.is_empty() {
+        bail!("No server address given");
+    }
+    if address.contains(char::is_whitespace) {
+        bail!("The server address {address:?} contains whitespace");
+    }
+    check_server_allowlist(cmdline_settings, address)?;
+    Ok(address.to_owned())
+}
+
+/// Activates the session with `address`, otherwise asks the reconnect loop to end the current
+/// session and connect to `address` instead.
+pub fn switch_server(settings: &Settings, address: &str) -> Result<()> {
+    let address = validate_address(&settings.get::<CmdLineSettings>(), address)?;
+    if activate_session(&address).is_ok() {
+        log::info!("Switched to the session with {address}");
+        return Ok(());
+    }
+    log::info!("Switch to the server {address} requested");
+    *REQUESTED_SERVER.lock() = Some(address);
+    SWITCH_SERVER.notify_one();
+    Ok(())
+}
+
+/// Completes once a switch was requested, it's applied with [`take_server_switch`]. The permit
+/// of a switch that was applied before anyone waited for it is skipped.
+pub async fn server_switch_requested() {
+    loop {
+        SWITCH_SERVER.notified().await;
+        if REQUESTED_SERVER.lock().is_some() {
+            return;
+        }
+    }
+}
+
+/// Makes the requested address the `--server`, and returns it. `None` when no switch is pending.
+pub fn take_server_switch(settings: &Settings) -> Option<String> {
+    let address = REQUESTED_SERVER.lock().take()?;
+    let mut cmdline_settings = settings.get::<CmdLineSettings>();
+    cmdline_settings.server = Some(address.clone());
+    settings.set(&cmdline_settings);
+    Some(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::bridge::NeovimInstance;
+
+    fn server_settings(address: Option<&str>) -> Settings {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server = address.map(str::to_owned);
+        settings.set(&cmdline_settings);
+        settings
+    }
+
+    #[tokio::test]
+    async fn test_switch_connects_to_the_new_address() {
+        let settings = server_settings(Some("localhost:6666"));
+        // What the session with the old server waits on
+        let old_session = tokio::spawn(server_switch_requested());
+        switch_server(&settings, " devbox:7777 ").unwrap();
+        assert!(timeout(Duration::from_secs(1), old_session).await.is_ok());
+
+        assert_eq!(
+            take_server_switch(&settings).as_deref(),
+            Some("devbox:7777")
+        );
+        assert!(matches!(
+            NeovimInstance::from_settings(&settings),
+            Ok(NeovimInstance::Server { address, .. }) if address == "devbox:7777"
+        ));
+        assert_eq!(take_server_switch(&settings), None);
+    }
+
+    #[test]
+    fn test_invalid_address_is_rejected() {
+        let cmdline_settings = server_settings(Some("localhost:6666")).get::<CmdLineSettings>();
+        assert!(validate_address(&cmdline_settings, "").is_err());
+        assert!(validate_address(&cmdline_settings, "dev box:6666").is_err());
+
+        let embedded = server_settings(None).get::<CmdLineSettings>();
+        assert!(validate_address(&embedded, "devbox:6666").is_err());
+
+        let mut allowlisted = cmdline_settings.clone();
+        allowlisted.server_allowlist = Some("127.0.0.1:*".to_owned());
+        assert!(validate_address(&allowlisted, "devbox:6666").is_err());
+        assert!(validate_address(&allowlisted, "127.0.0.1:6666").is_ok());
+    }
+}
@@ -4,39 +4,328 @@
 #[cfg(debug_assertions)]
 use core::fmt;
 use std::{
+    cell::Cell,
+    collections::VecDeque,
+    future::{poll_fn, Future},
     io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    pin::Pin,
     process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context as TaskContext, Poll},
 };
 
 use anyhow::Context;
 use nvim_rs::{error::LoopError, neovim::Neovim, Handler};
 use std::time::Duration;
 use tokio::{
-    io::{split, AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader},
-    net::TcpStream,
+    io::{split, AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader, BufWriter, ReadBuf},
+    net::{lookup_host, TcpStream},
     process::{Child, Command},
     spawn,
     task::JoinHandle,
     time::timeout,
 };
-use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+use tokio_util::{
+    compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt},
+    sync::CancellationToken,
+};
+
+#[cfg(unix)]
+use super::pty::{open_pty, split_pty};
+use super::{
+    docker::{docker_container, docker_exec_command, resolve_container},
+    pinned_address::connect_pinned,
+    preamble::{skip_preamble, Preamble},
+    socks5::Socks5Proxy,
+    spawn_error::explain_spawn_error,
+};
+use crate::cmd_line::{AddressFamily, CmdLineSettings};
 
 pub type NeovimWriter = Box<dyn futures::AsyncWrite + Send + Unpin + 'static>;
 
 type BoxedReader = Box<dyn AsyncRead + Send + Unpin + 'static>;
 type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin + 'static>;
 
+/// The sizes of the buffers between Neovide and the Neovim connection, see `--io-read-buffer`
+/// and `--io-write-buffer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoBufferSizes {
+    pub read: usize,
+    pub write: usize,
+}
+
+impl IoBufferSizes {
+    pub fn from_settings(cmdline_settings: &CmdLineSettings) -> Self {
+        Self {
+            read: cmdline_settings.io_read_buffer,
+            write: cmdline_settings.io_write_buffer,
+        }
+    }
+
+    fn wrap(
+        self,
+        reader: BoxedReader,
+        writer: BoxedWriter,
+    ) -> (BufReader<BoxedReader>, BufWriter<BoxedWriter>) {
+        (
+            BufReader::with_capacity(self.read, reader),
+            BufWriter::with_capacity(self.write, writer),
+        )
+    }
+}
+
+impl Default for IoBufferSizes {
+    fn default() -> Self {
+        // The same as the default buffers of tokio and nvim-rs
+        Self {
+            read: 8 * 1024,
+            write: 8 * 1024,
+        }
+    }
+}
+
+/// The buffered writer to Neovim, shared between nvim-rs and the session, so that the session can
+/// flush it before disconnecting.
+#[derive(Clone)]
+struct SharedWriter(Arc<parking_lot::Mutex<BufWriter<BoxedWriter>>>);
+
+impl SharedWriter {
+    fn new(writer: BufWriter<BoxedWriter>) -> Self {
+        Self(Arc::new(parking_lot::Mutex::new(writer)))
+    }
+
+    /// Writes out the buffered bytes, giving up after `limit`. Returns whether it finished.
+    async fn flush(&self, limit: Duration) -> bool {
+        let flush = poll_fn(|cx| Pin::new(&mut *self.0.lock()).poll_flush(cx));
+        match timeout(limit, flush).await {
+            Ok(Ok(())) => true,
+            Ok(Err(error)) => {
+                log::debug!("Flushing the writes to Neovim failed: {error}");
+                false
+            }
+            Err(_) => {
+                log::debug!("Flushing the writes to Neovim timed out");
+                false
+            }
+        }
+    }
+}
+
+// The lock is only held while polling, which never blocks
+impl AsyncWrite for SharedWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize>> {
+        record_write_progress(WriteProgress::Writing);
+        Pin::new(&mut *self.0.lock()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Result<()>> {
+        let poll = Pin::new(&mut *self.0.lock()).poll_flush(cx);
+        if matches!(poll, Poll::Ready(Ok(()))) {
+            record_write_progress(WriteProgress::Written);
+        }
+        poll
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut *self.0.lock()).poll_shutdown(cx)
+    }
+}
+
+/// How far the future polled by [`poll_tracking_writes`] got with its requests to Neovim.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WriteProgress {
+    #[default]
+    NotStarted,
+    /// A request is partly written, stopping now would leave half of it in the stream
+    Writing,
+    /// Everything it wrote was flushed
+    Written,
+}
+
+thread_local! {
+    static TRACKED_WRITES: Cell<Option<WriteProgress>> = const { Cell::new(None) };
+}
+
+/// Runs `poll`, and records in `progress` how the writes it makes to a session get on. nvim-rs
+/// writes a request in the task that makes it, so these are the writes of the polled future.
+pub fn poll_tracking_writes<T>(progress: &mut WriteProgress, poll: impl FnOnce() -> T) -> T {
+    let outer = TRACKED_WRITES.replace(Some(*progress));
+    let result = poll();
+    if let Some(tracked) = TRACKED_WRITES.replace(outer) {
+        *progress = tracked;
+    }
+    result
+}
+
+fn record_write_progress(progress: WriteProgress) {
+    TRACKED_WRITES.with(|tracked| match (tracked.get(), progress) {
+        (None, _) | (Some(WriteProgress::NotStarted), WriteProgress::Written) => {}
+        _ => tracked.set(Some(progress)),
+    });
+}
+
+/// How many transient read errors in a row are retried before the connection counts as dead.
+const MAX_TRANSIENT_READ_ERRORS: u32 = 8;
+
+/// Retries the reads from Neovim that fail with an error that clears up by itself, instead of
+/// letting nvim-rs end the session over it. Those are `Interrupted`, a signal arrived during the
+/// read, and `WouldBlock`, the descriptor had nothing to read yet. Any other error, and the end of
+/// the stream, are passed on as they are, and so are the transient ones once they keep coming.
+struct RetryTransientReads {
+    reader: BoxedReader,
+    errors_in_a_row: u32,
+}
+
+impl RetryTransientReads {
+    fn new(reader: BoxedReader) -> Self {
+        Self {
+            reader,
+            errors_in_a_row: 0,
+        }
+    }
+}
+
+fn is_transient(error: &Error) -> bool {
+    matches!(error.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock)
+}
+
+impl AsyncRead for RetryTransientReads {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        loop {
+            match Pin::new(&mut self.reader).poll_read(cx, buf) {
+                Poll::Ready(Err(error))
+                    if is_transient(&error) && self.errors_in_a_row < MAX_TRANSIENT_READ_ERRORS =>
+                {
+                    self.errors_in_a_row += 1;
+                    log::debug!("Retrying the read from Neovim after {error}");
+                    if error.kind() == ErrorKind::WouldBlock {
+                        // Polled again right away, since nothing else will wake the task
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                }
+                Poll::Ready(result) => {
+                    if result.is_ok() {
+                        self.errors_in_a_row = 0;
+                    }
+                    return Poll::Ready(result);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// How many of the last bytes read from Neovim are kept for the log of a protocol desync.
+const RECENT_BYTES: usize = 64;
+
+/// The last bytes read from Neovim, so that the ones a protocol desync happened on can be logged.
+#[derive(Clone, Default)]
+struct RecentBytes(Arc<parking_lot::Mutex<VecDeque<u8>>>);
+
+impl RecentBytes {
+    fn record(&self, bytes: &[u8]) {
+        let bytes = &bytes[bytes.len().saturating_sub(RECENT_BYTES)..];
+        let mut recent = self.0.lock();
+        recent.extend(bytes);
+        let excess = recent.len().saturating_sub(RECENT_BYTES);
+        recent.drain(..excess);
+    }
+
+    fn hex(&self) -> String {
+        let recent = self.0.lock();
+        let bytes: Vec<_> = recent.iter().map(|byte| format!("{byte:02x}")).collect();
+        bytes.join(" ")
+    }
+}
+
+/// Records what's read into `RecentBytes`.
+struct RecordReads {
+    reader: BoxedReader,
+    recent: RecentBytes,
+}
+
+impl AsyncRead for RecordReads {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let start = buf.filled().len();
+        let result = Pin::new(&mut self.reader).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.recent.record(&buf.filled()[start..]);
+        }
+        result
+    }
+}
+
+/// Kills and reaps the Neovim process once it's dropped, unless it's taken back with `disarm`.
+/// Holds the process while it's being launched, so that a launch that fails or is given up on
+/// halfway doesn't leave it running.
+pub struct ProcessGuard(Option<Child>);
+
+impl ProcessGuard {
+    pub fn new(process: Option<Child>) -> Self {
+        Self(process)
+    }
+
+    /// The launch succeeded, so the process is kept running.
+    pub fn disarm(mut self) -> Option<Child> {
+        self.0.take()
+    }
+}
+
+impl Drop for ProcessGuard {
+    fn drop(&mut self) {
+        let Some(mut process) = self.0.take() else {
+            return;
+        };
+        log::info!(
+            "Killing the Neovim process {:?} of a failed launch",
+            process.id()
+        );
+        if let Err(error) = process.start_kill() {
+            log::warn!("Could not kill the Neovim process: {error}");
+        }
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move { process.wait().await.ok() });
+        }
+    }
+}
+
+/// The id of the next session, counting up from 1 for the whole run of Neovide.
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
 pub struct NeovimSession {
     pub neovim: Neovim<NeovimWriter>,
     pub io_handle: JoinHandle<std::result::Result<(), Box<LoopError>>>,
     pub neovim_process: Option<Child>,
     pub stderr_task: Option<JoinHandle<Vec<String>>>,
+    /// Cancelled once Neovim started to quit, when its handler reports it
+    pub leaving: CancellationToken,
+    session_id: u64,
+    writer: SharedWriter,
+    recent_bytes: RecentBytes,
 }
 
 #[cfg(debug_assertions)]
 impl fmt::Debug for NeovimSession {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("NeovimSession")
+            .field("session_id", &self.session_id)
             .field("io_handle", &self.io_handle)
             .finish()
     }
@@ -46,8 +335,25 @@ impl NeovimSession {
     pub async fn new(
         instance: NeovimInstance,
         handler: impl Handler<Writer = NeovimWriter>,
+        buffer_sizes: IoBufferSizes,
     ) -> anyhow::Result<Self> {
+        let session_id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+        log::debug!("Starting session {session_id}");
+        let preamble = instance.preamble();
         let (reader, writer, stderr_reader, neovim_process) = instance.connect().await?;
+        let neovim_process = ProcessGuard::new(neovim_process);
+        let reader: BoxedReader = Box::new(RetryTransientReads::new(reader));
+        let recent_bytes = RecentBytes::default();
+        let reader: BoxedReader = Box::new(RecordReads {
+            reader,
+            recent: recent_bytes.clone(),
+        });
+        log::debug!("Using IO buffers of {buffer_sizes:?}");
+        let (mut reader, writer) = buffer_sizes.wrap(reader, writer);
+        if let Some(preamble) = &preamble {
+            skip_preamble(&mut reader, preamble).await?;
+        }
+        let writer = SharedWriter::new(writer);
         // Spawn a background task to read from stderr
         let stderr_task = stderr_reader.map(|reader| {
             tokio::spawn(async move {
@@ -67,7 +373,7 @@ impl NeovimSession {
             Duration::from_secs(5),
             Neovim::<NeovimWriter>::handshake(
                 reader.compat(),
-                Box::new(writer.compat_write()),
+                Box::new(writer.clone().compat_write()),
                 handler,
                 handshake_message,
             ),
@@ -91,17 +397,39 @@ impl NeovimSession {
             Ok((neovim, io)) => {
                 let io_handle = spawn(io);
 
-                log::debug!("Handshake successful");
+                log::debug!("Handshake of session {session_id} successful");
 
                 Ok(Self {
                     neovim,
                     io_handle,
-                    neovim_process,
+                    neovim_process: neovim_process.disarm(),
                     stderr_task,
+                    leaving: CancellationToken::new(),
+                    session_id,
+                    writer,
+                    recent_bytes,
                 })
             }
         }
     }
+
+    /// Tells the sessions apart in the logs and connection events, a reconnect or the fallback
+    /// Neovim gets a new one.
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Tries to write out what's still buffered for Neovim before disconnecting, for at most
+    /// `limit`. This is best-effort, a connection that is dead or too slow is given up on, and
+    /// returns whether everything was written.
+    pub async fn flush(&self, limit: Duration) -> bool {
+        self.writer.flush(limit).await
+    }
+
+    /// The last bytes read from Neovim in hex, for the log of a protocol desync.
+    pub fn recent_bytes(&self) -> String {
+        self.recent_bytes.hex()
+    }
 }
 
 /// An existing or future Neovim instance along with a means for establishing a connection.
@@ -110,23 +438,188 @@ pub enum NeovimInstance {
     /// A new embedded instance to be spawned by the given command.
     Embedded(Command),
 
+    /// Like `Embedded`, but with the stdin and stdout of the instance on a pseudo-terminal, see
+    /// `--nvim-pty`.
+    #[cfg(unix)]
+    EmbeddedPty(Command),
+
     /// An existing instance listening on `address`.
     ///
     /// Interprets `address` in the same way as `:help --server`: If it contains a `:` it's
     /// interpreted as a TCP/IPv4/IPv6 address. Otherwise it's interpreted as a named pipe or Unix
-    /// domain socket path. Spawns and connects to an embedded Neovim instance.
-    Server { address: String },
+    /// domain socket path. Spawns and connects to an embedded Neovim instance. A TCP address is
+    /// connected to through `proxy` when it's given, otherwise only to the resolved addresses of
+    /// `address_family`. With `pin_resolved_ip`, a hostname is resolved again only after its
+    /// pinned IP address failed that many times in a row, see `--pin-resolved-ip`. The banner of
+    /// `preamble` is skipped before the handshake, see `--connection-preamble`.
+    Server {
+        address: String,
+        proxy: Option<Socks5Proxy>,
+        address_family: AddressFamily,
+        pin_resolved_ip: Option<u32>,
+        preamble: Option<Preamble>,
+    },
+
+    /// A new embedded instance in the running Docker container `container`, a name or id, from
+    /// `--server docker://CONTAINER`. The container is resolved on every connect.
+    Docker { container: String },
+
+    /// An existing instance connected through the inherited descriptor `fd` of `--server-fd`,
+    /// a socket on Unix and a named pipe handle on Windows. The descriptor is owned by the
+    /// instance, so it can only be connected once.
+    Descriptor(i32),
 }
 
 impl NeovimInstance {
+    /// An embedded Neovim spawned by `command`, which needs to pass `--embed`.
+    pub fn embedded(command: Command) -> Self {
+        NeovimInstance::Embedded(command)
+    }
+
+    /// A running Neovim listening on `address`, in the format of `--server`.
+    pub fn server(address: impl Into<String>) -> Self {
+        NeovimInstance::Server {
+            address: address.into(),
+            proxy: None,
+            address_family: AddressFamily::Auto,
+            pin_resolved_ip: None,
+            preamble: None,
+        }
+    }
+
+    /// A running Neovim connected through the inherited `fd`, in the format of `--server-fd`.
+    pub fn descriptor(fd: i32) -> Self {
+        NeovimInstance::Descriptor(fd)
+    }
+
+    /// Connects to a server through the SOCKS5 `proxy`, does nothing for an embedded Neovim.
+    pub fn with_proxy(self, proxy: Option<Socks5Proxy>) -> Self {
+        match self {
+            NeovimInstance::Server {
+                address,
+                address_family,
+                pin_resolved_ip,
+                preamble,
+                ..
+            } => NeovimInstance::Server {
+                address,
+                proxy,
+                address_family,
+                pin_resolved_ip,
+                preamble,
+            },
+            embedded => embedded,
+        }
+    }
+
+    /// Starts an embedded Neovim on a pseudo-terminal when `pty` is set, does nothing for a server.
+    pub fn with_pty(self, pty: bool) -> Self {
+        match self {
+            #[cfg(unix)]
+            NeovimInstance::Embedded(command) if pty => NeovimInstance::EmbeddedPty(command),
+            #[cfg(not(unix))]
+            embedded @ NeovimInstance::Embedded(_) if pty => {
+                log::warn!("--nvim-pty is only supported on Unix");
+                embedded
+            }
+            instance => instance,
+        }
+    }
+
+    /// Only connects to the server addresses of `address_family` when its hostname resolves to
+    /// both IPv4 and IPv6 addresses, does nothing for an embedded Neovim.
+    pub fn with_address_family(self, address_family: AddressFamily) -> Self {
+        match self {
+            NeovimInstance::Server {
+                address,
+                proxy,
+                pin_resolved_ip,
+                preamble,
+                ..
+            } => NeovimInstance::Server {
+                address,
+                proxy,
+                address_family,
+                pin_resolved_ip,
+                preamble,
+            },
+            embedded => embedded,
+        }
+    }
+
+    /// Pins the IP address a server hostname resolved to, until it failed `reresolve_after` times
+    /// in a row, when `pin` is set. Does nothing for an embedded Neovim.
+    pub fn with_pinned_ip(self, pin: bool, reresolve_after: u32) -> Self {
+        match self {
+            NeovimInstance::Server {
+                address,
+                proxy,
+                address_family,
+                preamble,
+                ..
+            } => NeovimInstance::Server {
+                address,
+                proxy,
+                address_family,
+                pin_resolved_ip: pin.then_some(reresolve_after),
+                preamble,
+            },
+            embedded => embedded,
+        }
+    }
+
+    /// Skips the banner of `preamble` that the server sends before the RPC stream, does nothing
+    /// for an embedded Neovim.
+    pub fn with_preamble(self, preamble: Option<Preamble>) -> Self {
+        match self {
+            NeovimInstance::Server {
+                address,
+                proxy,
+                address_family,
+                pin_resolved_ip,
+                ..
+            } => NeovimInstance::Server {
+                address,
+                proxy,
+                address_family,
+                pin_resolved_ip,
+                preamble,
+            },
+            embedded => embedded,
+        }
+    }
+
+    fn preamble(&self) -> Option<Preamble> {
+        match self {
+            NeovimInstance::Server { preamble, .. } => preamble.clone(),
+            _ => None,
+        }
+    }
+
     async fn connect(
         self,
     ) -> Result<(BoxedReader, BoxedWriter, Option<BoxedReader>, Option<Child>)> {
         match self {
             NeovimInstance::Embedded(cmd) => Self::spawn_process(cmd).await,
-            NeovimInstance::Server { address } => Self::connect_to_server(address)
+            #[cfg(unix)]
+            NeovimInstance::EmbeddedPty(cmd) => Self::spawn_pty_process(cmd),
+            NeovimInstance::Server {
+                address,
+                proxy,
+                address_family,
+                pin_resolved_ip,
+                ..
+            } => Self::connect_to_server(address, proxy.as_ref(), address_family, pin_resolved_ip)
                 .await
                 .map(|(reader, writer)| (reader, writer, None, None)),
+            NeovimInstance::Docker { container } => {
+                let id = resolve_container(&container).await?;
+                log::info!("Resolved the Docker container {container} to {id}");
+                Self::spawn_process(docker_exec_command(&id)).await
+            }
+            NeovimInstance::Descriptor(fd) => {
+                Self::connect_to_descriptor(fd).map(|(reader, writer)| (reader, writer, None, None))
+            }
         }
     }
 
@@ -135,11 +628,13 @@ impl NeovimInstance {
     ) -> Result<(BoxedReader, BoxedWriter, Option<BoxedReader>, Option<Child>)> {
         log::debug!("Starting neovim with: {:?}", cmd);
 
+        let program = cmd.as_std().get_program().to_string_lossy().into_owned();
         let mut child = cmd
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .spawn()?;
+            .spawn()
+            .map_err(|error| explain_spawn_error(&program, error))?;
         let reader = Box::new(
             child
                 .stdout
@@ -163,13 +658,54 @@ impl NeovimInstance {
         Ok((reader, writer, Some(stderr_reader), Some(child)))
     }
 
-    async fn connect_to_server(address: String) -> Result<(BoxedReader, BoxedWriter)> {
+    /// Spawns the process with its stdin and stdout on a new pseudo-terminal, which carries the RPC
+    /// messages, and its stderr on a pipe.
+    #[cfg(unix)]
+    fn spawn_pty_process(
+        mut cmd: Command,
+    ) -> Result<(BoxedReader, BoxedWriter, Option<BoxedReader>, Option<Child>)> {
+        log::debug!("Starting neovim on a pseudo-terminal with: {:?}", cmd);
+
+        let program = cmd.as_std().get_program().to_string_lossy().into_owned();
+        let (master, slave) = open_pty()?;
+        let mut child = cmd
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave))
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| explain_spawn_error(&program, error))?;
+        // The command holds on to the terminal side until it's dropped, which would keep the
+        // reader from ever seeing the end
+        drop(cmd);
+
+        let (reader, writer) = split_pty(master)?;
+        let (reader, writer) = (Box::new(reader), Box::new(writer));
+        let stderr_reader = Box::new(
+            child
+                .stderr
+                .take()
+                .ok_or_else(|| Error::other("Can't open stderr"))?,
+        );
+
+        Ok((reader, writer, Some(stderr_reader), Some(child)))
+    }
+
+    async fn connect_to_server(
+        address: String,
+        proxy: Option<&Socks5Proxy>,
+        address_family: AddressFamily,
+        pin_resolved_ip: Option<u32>,
+    ) -> Result<(BoxedReader, BoxedWriter)> {
         log::debug!("Connecting to server at {address}");
-        if address.contains(':') {
-            let stream = timeout(Duration::from_secs(5), TcpStream::connect(&address)).await??;
+        if is_tcp_address(&address) {
+            let connect = connect_tcp(&address, proxy, address_family, pin_resolved_ip);
+            let stream = timeout(Duration::from_secs(5), connect).await??;
             log::debug!("TCP connect succeeded");
             Ok(Self::split(stream))
         } else {
+            if proxy.is_some() {
+                log::warn!("--proxy is ignored for the socket {address}");
+            }
             #[cfg(unix)]
             {
                 let stream = timeout(
@@ -203,6 +739,42 @@ impl NeovimInstance {
         }
     }
 
+    fn connect_to_descriptor(fd: i32) -> Result<(BoxedReader, BoxedWriter)> {
+        log::debug!("Connecting to the server through the descriptor {fd}");
+        #[cfg(unix)]
+        {
+            use std::os::unix::{io::FromRawFd, net::UnixStream};
+
+            // SAFETY: Only checks whether the descriptor is open, without touching it
+            if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+                return Err(Error::last_os_error());
+            }
+            // SAFETY: The descriptor is open, and --server-fd hands it over to Neovide
+            let stream = unsafe { UnixStream::from_raw_fd(fd) };
+            stream.set_nonblocking(true)?;
+            Ok(Self::split(tokio::net::UnixStream::from_std(stream)?))
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::io::RawHandle;
+
+            // SAFETY: --server-fd hands the handle, opened for overlapped IO, over to Neovide
+            let pipe = unsafe {
+                tokio::net::windows::named_pipe::NamedPipeClient::from_raw_handle(
+                    fd as usize as RawHandle,
+                )?
+            };
+            Ok(Self::split(pipe))
+        }
+
+        #[cfg(not(any(unix, windows)))]
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("--server-fd {fd} is not supported on this platform"),
+        ))
+    }
+
     fn split(
         stream: impl AsyncRead + AsyncWrite + Send + Unpin + 'static,
     ) -> (BoxedReader, BoxedWriter) {
@@ -210,3 +782,586 @@ impl NeovimInstance {
         (Box::new(reader), Box::new(writer))
     }
 }
+
+/// Uses the same rule as `NeovimInstance::Server` to decide whether `address` is a TCP address.
+pub fn is_tcp_address(address: &str) -> bool {
+    address.contains(':') && docker_container(address).is_none()
+}
+
+async fn resolve(address: String) -> Result<Vec<SocketAddr>> {
+    Ok(lookup_host(address).await?.collect())
+}
+
+/// Connects to the addresses `resolve` returns for `address` that are of `address_family`, in the
+/// order they were resolved in.
+async fn connect_tcp_with<R, F>(
+    address: &str,
+    address_family: AddressFamily,
+    resolve: R,
+) -> Result<TcpStream>
+where
+    R: FnOnce(String) -> F,
+    F: Future<Output = Result<Vec<SocketAddr>>>,
+{
+    if address_family == AddressFamily::Auto {
+        return TcpStream::connect(address).await;
+    }
+    let addresses: Vec<_> = resolve(address.to_owned())
+        .await?
+        .into_iter()
+        .filter(|resolved| address_family.matches(resolved))
+        .collect();
+    if addresses.is_empty() {
+        return Err(Error::new(
+            ErrorKind::AddrNotAvailable,
+            format!("{address} has no {address_family:?} address"),
+        ));
+    }
+    TcpStream::connect(addresses.as_slice()).await
+}
+
+/// Connects to a TCP `address` the way `NeovimInstance::Server` does: through the `proxy` when
+/// there is one, otherwise to the pinned IP address or to the resolved addresses of
+/// `address_family`.
+async fn connect_tcp(
+    address: &str,
+    proxy: Option<&Socks5Proxy>,
+    address_family: AddressFamily,
+    pin_resolved_ip: Option<u32>,
+) -> Result<TcpStream> {
+    match proxy {
+        Some(proxy) => {
+            if address_family != AddressFamily::Auto {
+                log::warn!("--address-family is ignored, the proxy resolves {address}");
+            }
+            proxy.connect(address).await
+        }
+        None => match pin_resolved_ip {
+            Some(reresolve_after) => {
+                connect_pinned(address, reresolve_after, || {
+                    connect_tcp_with(address, address_family, resolve)
+                })
+                .await
+            }
+            None => connect_tcp_with(address, address_family, resolve).await,
+        },
+    }
+}
+
+/// How a TCP `--server` address is connected to, so that a probe connects the same way.
+#[derive(Clone, Debug, Default)]
+pub struct TcpOptions {
+    pub proxy: Option<Socks5Proxy>,
+    pub address_family: AddressFamily,
+    /// See `NeovimInstance::Server`
+    pub pin_resolved_ip: Option<u32>,
+}
+
+impl TcpOptions {
+    pub fn from_settings(cmdline_settings: &CmdLineSettings) -> Self {
+        Self {
+            proxy: cmdline_settings.proxy.clone(),
+            address_family: cmdline_settings.address_family,
+            pin_resolved_ip: cmdline_settings
+                .pin_resolved_ip
+                .then_some(cmdline_settings.pin_reresolve_after),
+        }
+    }
+}
+
+/// Cheaply checks whether something is listening on a TCP `address` by opening and immediately
+/// dropping a connection, connected like the session would be with `options`. A pinned hostname
+/// isn't resolved again. Other transports are not probed and always succeed.
+pub async fn probe_server(
+    address: &str,
+    probe_timeout: Duration,
+    options: &TcpOptions,
+) -> Result<()> {
+    if !is_tcp_address(address) {
+        return Ok(());
+    }
+    let connect = connect_tcp(
+        address,
+        options.proxy.as_ref(),
+        options.address_family,
+        options.pin_resolved_ip,
+    );
+    timeout(probe_timeout, connect)
+        .await
+        .map_err(|_| Error::new(ErrorKind::TimedOut, "Probe timeout"))??;
+    Ok(())
+}
+
+/// Checks whether a server is listening on `address`, using any of the transports supported by
+/// `NeovimInstance::Server`.
+pub async fn is_server_running(
+    address: &str,
+    probe_timeout: Duration,
+    options: &TcpOptions,
+) -> bool {
+    if is_tcp_address(address) {
+        probe_server(address, probe_timeout, options).await.is_ok()
+    } else {
+        matches!(
+            timeout(
+                probe_timeout,
+                NeovimInstance::connect_to_server(
+                    address.to_owned(),
+                    None,
+                    AddressFamily::Auto,
+                    None
+                )
+            )
+            .await,
+            Ok(Ok(_))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::bridge::fake_server::{fake_server, socket_pair_session, TestHandler};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[tokio::test]
+    async fn test_address_family_preference_is_honored() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ipv4 = listener.local_addr().unwrap();
+        // Nothing listens on the IPv6 address, which comes first like it often does
+        let ipv6: SocketAddr = format!("[::1]:{}", ipv4.port()).parse().unwrap();
+        let both_families = |_| async move { Ok(vec![ipv6, ipv4]) };
+
+        let stream = connect_tcp_with("nvim.example:6666", AddressFamily::Ipv4, both_families)
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), ipv4);
+
+        assert!(
+            connect_tcp_with("nvim.example:6666", AddressFamily::Ipv6, both_families)
+                .await
+                .is_err()
+        );
+        let only_ipv4 = |_| async move { Ok(vec![ipv4]) };
+        let error = connect_tcp_with("nvim.example:6666", AddressFamily::Ipv6, only_ipv4)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AddrNotAvailable);
+    }
+
+    /// A reader that returns `results` in turn, and then the end of the stream.
+    struct ScriptedReader(Vec<Result<&'static [u8]>>);
+
+    impl AsyncRead for ScriptedReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut TaskContext<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<Result<()>> {
+            if self.0.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            Poll::Ready(self.0.remove(0).map(|data| buf.put_slice(data)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transient_read_errors_are_retried() {
+        use tokio::io::AsyncReadExt;
+
+        let reader = ScriptedReader(vec![
+            Ok(b"hello "),
+            Err(ErrorKind::Interrupted.into()),
+            Err(ErrorKind::WouldBlock.into()),
+            Ok(b"world"),
+        ]);
+        let mut reader = RetryTransientReads::new(Box::new(reader));
+        let mut read = String::new();
+        reader.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "hello world");
+
+        // Hard errors, and transient ones that don't stop, still end the connection
+        let reader = ScriptedReader(vec![Err(ErrorKind::ConnectionReset.into())]);
+        let mut reader = RetryTransientReads::new(Box::new(reader));
+        let error = reader.read_to_string(&mut read).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ConnectionReset);
+        let errors = (0..=MAX_TRANSIENT_READ_ERRORS)
+            .map(|_| Err(ErrorKind::Interrupted.into()))
+            .collect();
+        let mut reader = RetryTransientReads::new(Box::new(ScriptedReader(errors)));
+        let error = reader.read_to_string(&mut read).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_process_of_a_failed_launch_is_killed() {
+        let is_running = |pid| unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+        let spawn_sleep = || Command::new("sleep").arg("30").spawn().unwrap();
+
+        let process = spawn_sleep();
+        let pid = process.id().unwrap();
+        drop(ProcessGuard::new(Some(process)));
+        // Reaped as well, a zombie would still count as running
+        timeout(Duration::from_secs(5), async {
+            while is_running(pid) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The process of the failed launch is still running");
+
+        let mut process = ProcessGuard::new(Some(spawn_sleep())).disarm().unwrap();
+        assert!(is_running(process.id().unwrap()));
+        process.kill().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_connect_through_an_inherited_socket() {
+        let (session, server) = socket_pair_session().await;
+        assert!(session.neovim_process.is_none());
+        session.neovim.command("echo 'hello'").await.unwrap();
+
+        // Closing the session closes the descriptor, which ends the server
+        let NeovimSession {
+            neovim,
+            io_handle,
+            writer,
+            ..
+        } = session;
+        drop(neovim);
+        drop(writer);
+        io_handle.abort();
+        io_handle.await.ok();
+        assert_eq!(server.await.unwrap(), ["nvim_exec_lua", "nvim_command"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_banner_before_the_rpc_stream_is_skipped() {
+        let path = std::env::temp_dir().join(format!("neovide-preamble-{}", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"Welcome to the gateway\r\nREADY\r\n")
+                .await
+                .unwrap();
+            fake_server(stream).await
+        });
+
+        let instance = NeovimInstance::server(path.to_string_lossy())
+            .with_preamble(Some(Preamble::Until(b"READY\r\n".to_vec())));
+        let session = NeovimSession::new(instance, TestHandler, IoBufferSizes::default())
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).ok();
+        session.neovim.command("echo 'hello'").await.unwrap();
+
+        let NeovimSession {
+            neovim,
+            io_handle,
+            writer,
+            ..
+        } = session;
+        drop(neovim);
+        drop(writer);
+        io_handle.abort();
+        io_handle.await.ok();
+        assert_eq!(server.await.unwrap(), ["nvim_exec_lua", "nvim_command"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sessions_and_their_events_have_distinct_ids() {
+        use crate::{
+            bridge::{
+                connection_events::{subscribe, ConnectionEvent},
+                shutdown::{shutdown, ShutdownReason, ShutdownTimeouts},
+            },
+            running_tracker::RunningTracker,
+        };
+
+        let (first, _) = socket_pair_session().await;
+        let (second, _) = socket_pair_session().await;
+        let ids = [first.session_id(), second.session_id()];
+        assert!(ids[0] < ids[1]);
+
+        let mut events = subscribe();
+        for session in [first, second] {
+            session.io_handle.abort();
+            shutdown(
+                session,
+                ShutdownReason::ConnectionLost,
+                &ShutdownTimeouts::default(),
+                &RunningTracker::new(),
+            )
+            .await;
+        }
+        // Other tests may end sessions at the same time
+        let mut disconnected = Vec::new();
+        while disconnected.len() < ids.len() {
+            if let ConnectionEvent::Disconnected { session_id } = events.recv().await.unwrap() {
+                if ids.contains(&session_id) {
+                    disconnected.push(session_id);
+                }
+            }
+        }
+        assert_eq!(disconnected, ids);
+    }
+
+    #[test]
+    fn test_only_the_last_bytes_are_kept() {
+        let recent = RecentBytes::default();
+        recent.record(&[0xaa; 100]);
+        recent.record(&[0x93, 0x07]);
+        let hex = recent.hex();
+        assert_eq!(hex.split(' ').count(), RECENT_BYTES);
+        assert!(hex.ends_with("aa 93 07"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_corrupt_stream_is_a_protocol_desync() {
+        use std::os::unix::{io::IntoRawFd, net::UnixStream};
+
+        use nvim_rs::Value;
+        use tokio::io::AsyncReadExt;
+
+        use crate::bridge::close_cause::CloseCause;
+
+        let (client, server) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).unwrap();
+        let mut server = tokio::net::UnixStream::from_std(server).unwrap();
+        let server = tokio::spawn(async move {
+            let mut chunk = [0; 1024];
+            let read = server.read(&mut chunk).await.unwrap();
+            let Value::Array(request) = rmpv::decode::read_value(&mut &chunk[..read]).unwrap()
+            else {
+                panic!("The handshake is not a request");
+            };
+            let response = Value::Array(vec![
+                1.into(),
+                request[1].clone(),
+                Value::Nil,
+                "NeovideToNeovimMagicHandshakeMessage".into(),
+            ]);
+            let mut encoded = Vec::new();
+            rmpv::encode::write_value(&mut encoded, &response).unwrap();
+            // Followed by a message of a type that doesn't exist, as if the stream was cut off
+            // in the middle of one
+            encoded.extend_from_slice(&[0x93, 0x07, 0xc0, 0xc0]);
+            server.write_all(&encoded).await.unwrap();
+            // Keeps the stream open, so that only the framing can end the session
+            std::future::pending::<()>().await;
+        });
+
+        let session = NeovimSession::new(
+            NeovimInstance::descriptor(client.into_raw_fd()),
+            TestHandler,
+            IoBufferSizes::default(),
+        )
+        .await
+        .unwrap();
+        let result = timeout(Duration::from_secs(5), &mut { session.io_handle })
+            .await
+            .expect("The session did not end on the corrupt message");
+        server.abort();
+        assert_eq!(CloseCause::of(&result), CloseCause::Desync);
+        assert!(session.recent_bytes.hex().ends_with("93 07 c0 c0"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_closed_descriptor_fails() {
+        assert!(NeovimInstance::descriptor(-1).connect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_closed_port_fails() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        assert!(
+            probe_server(&address, Duration::from_secs(1), &TcpOptions::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_open_port_succeeds() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        assert!(
+            probe_server(&address, Duration::from_secs(1), &TcpOptions::default())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_honors_the_address_family() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let address = format!("localhost:{port}");
+        let probe = |address_family| {
+            let address = address.clone();
+            async move {
+                let options = TcpOptions {
+                    address_family,
+                    ..TcpOptions::default()
+                };
+                probe_server(&address, Duration::from_secs(1), &options).await
+            }
+        };
+
+        assert!(probe(AddressFamily::Ipv4).await.is_ok());
+        // Only listening on IPv4, so the session wouldn't connect either
+        assert!(probe(AddressFamily::Ipv6).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_running() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        assert!(is_server_running(&address, Duration::from_secs(1), &TcpOptions::default()).await);
+
+        drop(listener);
+        assert!(!is_server_running(&address, Duration::from_secs(1), &TcpOptions::default()).await);
+        assert!(
+            !is_server_running(
+                "/nonexistent/nvim.sock",
+                Duration::from_secs(1),
+                &TcpOptions::default()
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_buffer_sizes_are_applied() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let instance = NeovimInstance::server(address);
+        let ((reader, writer, _, _), (mut server, _)) =
+            tokio::try_join!(instance.connect(), listener.accept()).unwrap();
+
+        let buffer_sizes = IoBufferSizes {
+            read: 1024,
+            write: 16 * 1024,
+        };
+        let (mut reader, mut writer) = buffer_sizes.wrap(reader, writer);
+
+        // Writes that fit into the buffer are held back until it's flushed
+        writer.write_all(&[0; 8 * 1024]).await.unwrap();
+        assert_eq!(writer.buffer().len(), 8 * 1024);
+
+        // And reads never fill more than the buffer size at once
+        server.write_all(&[0; 4 * 1024]).await.unwrap();
+        let mut total = 0;
+        while total < 4 * 1024 {
+            let read = reader.fill_buf().await.unwrap().len();
+            assert!(read > 0 && read <= 1024);
+            reader.consume(read);
+            total += read;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_skips_non_tcp() {
+        assert!(probe_server(
+            "/nonexistent/nvim.sock",
+            Duration::from_secs(1),
+            &TcpOptions::default()
+        )
+        .await
+        .is_ok());
+    }
+
+    /// A writer into a pipe of 16 bytes, with 64 bytes buffered in it that wait for a flush.
+    async fn shared_writer_with_pending_writes() -> (SharedWriter, tokio::io::DuplexStream) {
+        let (client, server) = tokio::io::duplex(16);
+        let mut writer = SharedWriter::new(BufWriter::with_capacity(1024, Box::new(client)));
+        writer.write_all(&[7; 64]).await.unwrap();
+        (writer, server)
+    }
+
+    #[tokio::test]
+    async fn test_flush_drains_a_slow_connection() {
+        use tokio::io::AsyncReadExt;
+
+        let (writer, mut server) = shared_writer_with_pending_writes().await;
+        let reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut chunk = [0; 8];
+            while received.len() < 64 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                let read = server.read(&mut chunk).await.unwrap();
+                received.extend_from_slice(&chunk[..read]);
+            }
+            received
+        });
+
+        assert!(writer.flush(Duration::from_secs(5)).await);
+        assert_eq!(reader.await.unwrap(), vec![7; 64]);
+    }
+
+    #[tokio::test]
+    async fn test_flush_gives_up_on_a_dead_connection() {
+        // Nothing reads from the other end, so the pipe stays full
+        let (writer, _server) = shared_writer_with_pending_writes().await;
+        assert!(!writer.flush(Duration::from_millis(50)).await);
+
+        // A closed connection fails right away
+        let (writer, server) = shared_writer_with_pending_writes().await;
+        drop(server);
+        assert!(!writer.flush(Duration::from_secs(5)).await);
+    }
+
+    /// Everything the `sh -c script` started on a pseudo-terminal writes to stdout, after `input`.
+    #[cfg(unix)]
+    async fn run_on_pty(script: &str, input: &[u8]) -> Vec<u8> {
+        use tokio::io::AsyncReadExt;
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        let (mut reader, mut writer, _, child) =
+            NeovimInstance::spawn_pty_process(command).unwrap();
+        writer.write_all(input).await.unwrap();
+        writer.flush().await.unwrap();
+        let mut output = Vec::new();
+        timeout(Duration::from_secs(5), reader.read_to_end(&mut output))
+            .await
+            .unwrap()
+            .unwrap();
+        child.unwrap().wait().await.unwrap();
+        output
+    }
+
+    // There's no nvim in the test environment, so a shell stands in for it
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pty_process_sees_a_tty() {
+        let output = run_on_pty("if [ -t 0 ] && [ -t 1 ]; then echo tty; fi", b"").await;
+        assert_eq!(output, b"tty\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pty_passes_bytes_unchanged() {
+        // Control characters, carriage returns and newlines would all be translated without raw
+        // mode
+        let message = [0x93, 0x00, 0x03, 0x04, b'\r', b'\n', 0x1a, 0xff];
+        let output = run_on_pty("head -c 8", &message).await;
+        assert_eq!(output, message);
+    }
+}
@@ -0,0 +1,158 @@
+//! The `SessionReady` connection event, published once a session is fully usable: the version
+//! check, the setup and the attach are done, and Neovim sent the first complete redraw. Before
+//! that, a session can be connected but still show an empty grid.
+
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tokio::{
+    sync::oneshot::{self, Receiver, Sender},
+    time::timeout_at,
+};
+
+use super::connection_events::{publish, ConnectionEvent};
+
+/// Tells the session being attached about the first `flush` of a redraw.
+#[derive(Default)]
+pub struct FirstRedraw(Mutex<Option<Sender<()>>>);
+
+impl FirstRedraw {
+    /// Returns when the next `flush` arrives, replacing the wait of an earlier session.
+    pub fn watch(&self) -> Receiver<()> {
+        let (sender, receiver) = oneshot::channel();
+        *self.0.lock() = Some(sender);
+        receiver
+    }
+
+    /// Called for every `flush`, only the first one after `watch` is passed on.
+    pub fn flushed(&self) {
+        if let Some(sender) = self.0.lock().take() {
+            // Nobody waits anymore when the session ended or timed out
+            sender.send(()).ok();
+        }
+    }
+}
+
+/// Publishes `SessionReady` for `session_id` once the first redraw arrived, unless that's more
+/// than `limit` after `started_at`, without a limit it waits as long as the session lasts. Returns
+/// whether it was published.
+pub async fn announce_ready(
+    session_id: u64,
+    first_redraw: Receiver<()>,
+    started_at: Instant,
+    limit: Option<Duration>,
+) -> bool {
+    let first_redraw = async {
+        match limit {
+            Some(limit) => {
+                let deadline = tokio::time::Instant::from_std(started_at + limit);
+                timeout_at(deadline, first_redraw).await
+            }
+            None => Ok(first_redraw.await),
+        }
+    };
+    match first_redraw.await {
+        Ok(Ok(())) => {
+            log::debug!(
+                "Session {session_id} ready after {}ms",
+                started_at.elapsed().as_millis()
+            );
+            publish(ConnectionEvent::SessionReady { session_id });
+            true
+        }
+        Ok(Err(_)) => {
+            log::debug!("Session {session_id} ended before its first redraw");
+            false
+        }
+        Err(_) => {
+            log::warn!(
+                "Session {session_id} didn't get its first redraw within {}s, it's never \
+                 announced as ready",
+                limit.unwrap_or_default().as_secs()
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    use super::*;
+    use crate::bridge::connection_events::subscribe;
+
+    /// The `SessionReady` events of `session_id` received so far, other tests publish too.
+    fn ready_events(
+        events: &mut tokio::sync::broadcast::Receiver<ConnectionEvent>,
+        session_id: u64,
+    ) -> usize {
+        let mut count = 0;
+        loop {
+            match events.try_recv() {
+                Ok(ConnectionEvent::SessionReady { session_id: id }) if id == session_id => {
+                    count += 1
+                }
+                Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                Err(_) => return count,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ready_once_after_the_first_redraw() {
+        let session_id = 4201;
+        let mut events = subscribe();
+        let first_redraw = FirstRedraw::default();
+        // A flush of the previous session doesn't count
+        first_redraw.flushed();
+        let receiver = first_redraw.watch();
+
+        let announce = tokio::spawn(announce_ready(
+            session_id,
+            receiver,
+            Instant::now(),
+            Some(Duration::from_secs(5)),
+        ));
+        tokio::task::yield_now().await;
+        assert_eq!(ready_events(&mut events, session_id), 0);
+
+        first_redraw.flushed();
+        first_redraw.flushed();
+        assert!(announce.await.unwrap());
+        assert_eq!(ready_events(&mut events, session_id), 1);
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_without_a_redraw() {
+        let session_id = 4202;
+        let mut events = subscribe();
+        let first_redraw = FirstRedraw::default();
+        let receiver = first_redraw.watch();
+        let ready = announce_ready(
+            session_id,
+            receiver,
+            Instant::now(),
+            Some(Duration::from_millis(20)),
+        )
+        .await;
+        assert!(!ready);
+        first_redraw.flushed();
+        assert_eq!(ready_events(&mut events, session_id), 0);
+    }
+
+    #[tokio::test]
+    async fn test_no_limit_waits_for_the_redraw() {
+        let session_id = 4203;
+        let mut events = subscribe();
+        let first_redraw = FirstRedraw::default();
+        let receiver = first_redraw.watch();
+        // Long after any limit would have run out
+        let started_at = Instant::now() - Duration::from_secs(3600);
+        let announce = tokio::spawn(announce_ready(session_id, receiver, started_at, None));
+        tokio::task::yield_now().await;
+        first_redraw.flushed();
+        assert!(announce.await.unwrap());
+        assert_eq!(ready_events(&mut events, session_id), 1);
+    }
+}
@@ -0,0 +1,217 @@
+//! Keeps track of the Neovim sessions Neovide is attached to, keyed by their address, and which
+//! one of them is active. The active session is the one that receives the UI commands and whose
+//! grid is rendered. The reconnect loop attaches to one `--server` at a time, so for now that's
+//! the only registered session, and `:NeovideSwitchServer` activates a registered session instead
+//! of reconnecting when there is one for the address.
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use nvim_rs::Neovim;
+
+use super::{
+    session::NeovimWriter,
+    ui_commands::{CurrentNvim, CURRENT_NVIM},
+};
+
+#[derive(Debug)]
+pub struct SessionRegistry<T> {
+    sessions: Vec<(String, T)>,
+    active: Option<usize>,
+}
+
+impl<T> Default for SessionRegistry<T> {
+    fn default() -> Self {
+        Self {
+            sessions: Vec::new(),
+            active: None,
+        }
+    }
+}
+
+impl<T: Clone> SessionRegistry<T> {
+    /// Registers a session, replacing any previous session with the same address. The first
+    /// registered session becomes active.
+    pub fn register(&mut self, address: &str, session: T) {
+        match self.position(address) {
+            Some(index) => self.sessions[index].1 = session,
+            None => self.sessions.push((address.to_owned(), session)),
+        }
+        if self.active.is_none() {
+            self.active = self.position(address);
+        }
+    }
+
+    /// Removes a session, when it's the active one no session is active afterwards.
+    pub fn unregister(&mut self, address: &str) -> Option<T> {
+        let index = self.position(address)?;
+        match self.active {
+            Some(active) if active == index => self.active = None,
+            Some(active) if active > index => self.active = Some(active - 1),
+            _ => {}
+        }
+        Some(self.sessions.remove(index).1)
+    }
+
+    pub fn activate(&mut self, address: &str) -> Result<T> {
+        let Some(index) = self.position(address) else {
+            bail!("There is no session connected to {address}");
+        };
+        self.active = Some(index);
+        Ok(self.sessions[index].1.clone())
+    }
+
+    pub fn active(&self) -> Option<(&str, &T)> {
+        self.active
+            .map(|index| (self.sessions[index].0.as_str(), &self.sessions[index].1))
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = &str> {
+        self.sessions.iter().map(|(address, _)| address.as_str())
+    }
+
+    fn position(&self, address: &str) -> Option<usize> {
+        self.sessions.iter().position(|(other, _)| other == address)
+    }
+}
+
+static SESSIONS: Lazy<Mutex<SessionRegistry<Neovim<NeovimWriter>>>> =
+    Lazy::new(|| Mutex::new(SessionRegistry::default()));
+
+/// Registers a session in `sessions`, and makes the active session the `current` one.
+fn register_in(
+    sessions: &mut SessionRegistry<Neovim<NeovimWriter>>,
+    current: &mut CurrentNvim,
+    address: &str,
+    nvim: Neovim<NeovimWriter>,
+) {
+    sessions.register(address, nvim);
+    log::debug!(
+        "Registered session {address}, sessions: {}",
+        sessions.addresses().collect::<Vec<_>>().join(", ")
+    );
+    if let Some((active_address, active)) = sessions.active() {
+        current.update(Some(active.clone()), Some(active_address));
+    }
+}
+
+/// Registers a session, and makes it the current Neovim instance if no other session is active.
+pub fn register_session(address: &str, nvim: Neovim<NeovimWriter>) {
+    register_in(
+        &mut SESSIONS.lock(),
+        &mut CURRENT_NVIM.write(),
+        address,
+        nvim,
+    );
+}
+
+pub fn unregister_session(address: &str) {
+    SESSIONS.lock().unregister(address);
+}
+
+/// Activates the session connected to `address` in `sessions`, and makes it the `current` one.
+fn activate_in(
+    sessions: &mut SessionRegistry<Neovim<NeovimWriter>>,
+    current: &mut CurrentNvim,
+    address: &str,
+) -> Result<()> {
+    let nvim = sessions.activate(address)?;
+    current.update(Some(nvim), Some(address));
+    Ok(())
+}
+
+/// Switches the UI commands over to the session connected to `address`.
+pub fn activate_session(address: &str) -> Result<()> {
+    activate_in(&mut SESSIONS.lock(), &mut CURRENT_NVIM.write(), address)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use nvim_rs::Handler;
+    use tokio::io::{duplex, split};
+    use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestHandler;
+
+    #[async_trait]
+    impl Handler for TestHandler {
+        type Writer = NeovimWriter;
+    }
+
+    fn test_nvim() -> Neovim<NeovimWriter> {
+        let (stream, _) = duplex(64);
+        let (reader, writer) = split(stream);
+        let writer: NeovimWriter = Box::new(writer.compat_write());
+        let (nvim, _io) = Neovim::new(reader.compat(), writer, TestHandler);
+        nvim
+    }
+
+    #[test]
+    fn test_current_address_updates_on_reconnect() {
+        let mut sessions = SessionRegistry::default();
+        let mut current = CurrentNvim::default();
+        register_in(&mut sessions, &mut current, "localhost:6666", test_nvim());
+        assert_eq!(current.address(), Some("localhost:6666"));
+
+        // The connection is lost and shut down, then another server is connected
+        sessions.unregister("localhost:6666");
+        current.update(None, None);
+        assert_eq!(current.address(), None);
+        register_in(&mut sessions, &mut current, "localhost:7777", test_nvim());
+        assert_eq!(current.address(), Some("localhost:7777"));
+    }
+
+    #[test]
+    fn test_switch_between_two_sessions() {
+        let mut sessions = SessionRegistry::default();
+        let mut current = CurrentNvim::default();
+        register_in(&mut sessions, &mut current, "localhost:6666", test_nvim());
+        register_in(&mut sessions, &mut current, "localhost:7777", test_nvim());
+        assert_eq!(current.address(), Some("localhost:6666"));
+        assert_eq!(
+            sessions.addresses().collect::<Vec<_>>(),
+            vec!["localhost:6666", "localhost:7777"]
+        );
+
+        activate_in(&mut sessions, &mut current, "localhost:7777").unwrap();
+        assert_eq!(
+            sessions.active().map(|(address, _)| address),
+            Some("localhost:7777")
+        );
+        assert_eq!(current.address(), Some("localhost:7777"));
+
+        assert!(activate_in(&mut sessions, &mut current, "localhost:8888").is_err());
+        assert_eq!(current.address(), Some("localhost:7777"));
+        assert_eq!(
+            sessions.active().map(|(address, _)| address),
+            Some("localhost:7777")
+        );
+    }
+
+    #[test]
+    fn test_unregister_keeps_the_active_session() {
+        let mut sessions = SessionRegistry::default();
+        sessions.register("localhost:6666", "first");
+        sessions.register("localhost:7777", "second");
+        sessions.activate("localhost:7777").unwrap();
+
+        assert_eq!(sessions.unregister("localhost:6666"), Some("first"));
+        assert_eq!(sessions.active(), Some(("localhost:7777", &"second")));
+        assert_eq!(sessions.unregister("localhost:7777"), Some("second"));
+        assert_eq!(sessions.active(), None);
+    }
+
+    #[test]
+    fn test_reregister_replaces_the_session() {
+        let mut sessions = SessionRegistry::default();
+        sessions.register("localhost:6666", "old");
+        sessions.register("localhost:6666", "new");
+        assert_eq!(sessions.addresses().count(), 1);
+        assert_eq!(sessions.active(), Some(("localhost:6666", &"new")));
+    }
+}
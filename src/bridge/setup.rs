@@ -1,10 +1,15 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use nvim_rs::Neovim;
 use rmpv::Value;
 
-use super::api_info::{parse_api_info, ApiInformation};
+use super::{
+    api_info::{parse_api_info, ApiInformation},
+    clipboard::setup_clipboard,
+    show_message, MessageSeverity,
+};
 use crate::{
     bridge::NeovimWriter,
+    cmd_line::CmdLineSettings,
     settings::{SettingLocation, Settings},
 };
 
@@ -20,6 +25,50 @@ pub async fn get_api_information(nvim: &Neovim<NeovimWriter>) -> Result<ApiInfor
     parse_api_info(&api_info).context("Failed to parse Neovim api information")
 }
 
+/// Verifies that the server still runs the version in `cached`, without retrieving and parsing
+/// the whole API information again. Returns the cached information with the channel of the new
+/// connection.
+pub async fn quick_api_information(
+    nvim: &Neovim<NeovimWriter>,
+    cached: &ApiInformation,
+) -> Result<ApiInformation> {
+    let value = nvim
+        .exec_lua(
+            "local v = vim.version() return { v.major, v.minor, v.patch, vim.api.nvim_get_chan_info(0).id }",
+            vec![],
+        )
+        .await
+        .context("Error getting the version")?;
+    let numbers = value
+        .as_array()
+        .map(|values| values.iter().filter_map(Value::as_u64).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let [major, minor, patch, channel] = numbers[..] else {
+        bail!("Unexpected version {value}");
+    };
+    let version = &cached.version;
+    if (major, minor, patch) != (version.major, version.minor, version.patch) {
+        bail!("The Neovim version changed to {major}.{minor}.{patch}");
+    }
+    Ok(ApiInformation {
+        channel,
+        ..cached.clone()
+    })
+}
+
+/// Sets the `--set-var` variables, again on every attach so that they survive a reconnect.
+async fn set_cli_variables(
+    nvim: &Neovim<NeovimWriter>,
+    variables: &[(String, Value)],
+) -> Result<()> {
+    for (name, value) in variables {
+        nvim.set_var(name, value.clone())
+            .await
+            .with_context(|| format!("Could not set g:{name} from --set-var"))?;
+    }
+    Ok(())
+}
+
 pub async fn setup_neovide_specific_state(
     nvim: &Neovim<NeovimWriter>,
     should_handle_clipboard: bool,
@@ -31,6 +80,8 @@ pub async fn setup_neovide_specific_state(
         .await
         .context("Could not communicate with neovim process")?;
 
+    set_cli_variables(nvim, &settings.get::<CmdLineSettings>().set_var).await?;
+
     nvim.command("runtime! ginit.vim")
         .await
         .context("Error encountered in ginit.vim ")?;
@@ -59,8 +110,8 @@ pub async fn setup_neovide_specific_state(
     .await
     .context("Error setting client info")?;
 
-    let register_clipboard = should_handle_clipboard;
     let register_right_click = cfg!(target_os = "windows");
+    let forward_notifications = settings.get::<CmdLineSettings>().desktop_notifications;
 
     let setting_locations = settings.setting_locations();
     let global_variable_settings = setting_locations
@@ -87,14 +138,14 @@ pub async fn setup_neovide_specific_state(
             Value::from("neovide_version"),
             Value::from(crate_version!()),
         ),
-        (
-            Value::from("register_clipboard"),
-            Value::from(register_clipboard),
-        ),
         (
             Value::from("register_right_click"),
             Value::from(register_right_click),
         ),
+        (
+            Value::from("forward_notifications"),
+            Value::from(forward_notifications),
+        ),
         (
             Value::from("global_variable_settings"),
             Value::from(global_variable_settings),
@@ -106,5 +157,67 @@ pub async fn setup_neovide_specific_state(
         .await
         .context("Error when running Neovide init.lua")?;
 
+    // The clipboard isn't worth failing the whole launch for
+    if should_handle_clipboard && !setup_clipboard(nvim).await {
+        show_message(
+            nvim,
+            MessageSeverity::Warning,
+            &["The clipboard could not be set up and is disabled".to_owned()],
+        )
+        .await
+        .ok();
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bridge::recording_nvim::recording_nvim;
+
+    #[tokio::test]
+    async fn test_set_var_sets_typed_globals() {
+        let (nvim, requests) = recording_nvim();
+        let variables = vec![
+            ("session_id".to_owned(), Value::from(42)),
+            ("dark".to_owned(), Value::from(true)),
+        ];
+
+        set_cli_variables(&nvim, &variables).await.unwrap();
+
+        assert_eq!(
+            *requests.lock(),
+            vec![
+                (
+                    "nvim_set_var".to_owned(),
+                    Value::Array(vec!["session_id".into(), 42.into()])
+                ),
+                (
+                    "nvim_set_var".to_owned(),
+                    Value::Array(vec!["dark".into(), true.into()])
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_second_setup_sends_the_same_requests() {
+        let settings = Settings::new();
+        settings.set(&CmdLineSettings::default());
+        let api_information = ApiInformation::for_test(1);
+
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            let (nvim, requests) = recording_nvim();
+            setup_neovide_specific_state(&nvim, false, &api_information, &settings)
+                .await
+                .unwrap();
+            runs.push(requests.lock().clone());
+        }
+        assert_eq!(runs[0], runs[1]);
+        assert!(runs[0]
+            .iter()
+            .any(|(method, _)| method == "nvim_execute_lua"));
+    }
+}
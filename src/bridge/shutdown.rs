@@ -0,0 +1,498 @@
+//! The shutdown sequence shared by embedded and server sessions.
+//!
+//! The phases always run in the same order:
+//! 1. Quit: ask the embedded Neovim to quit and wait for its process to exit, killing it if it
+//!    doesn't
+//! 2. IO drain: give the IO task a grace period to read the remaining messages
+//! 3. Stderr drain: give the stderr task a grace period to log the remaining output
+//! 4. Finalize: clear the current Neovim instance and record the exit in the `RunningTracker`
+//!
+//! An embedded Neovim that started to quit but doesn't exit, for example because a prompt in a
+//! `VimLeavePre` autocommand is blocking it, is terminated after `--quit-kill-timeout` before the
+//! sequence starts.
+
+use std::{
+    future::{pending, Future},
+    process::ExitStatus,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use nvim_rs::Neovim;
+use tokio::{
+    process::Child,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    connection_events::{publish, ConnectionEvent},
+    session::NeovimSession,
+    ui_commands::update_current_nvim,
+    NeovimWriter,
+};
+use crate::{
+    cmd_line::{CmdLineSettings, IoDrain},
+    running_tracker::RunningTracker,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownReason {
+    /// The embedded Neovim quit, or closed its IO stream
+    NeovimExited,
+    /// The connection to a server was closed or lost, the server itself keeps running
+    ConnectionLost,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShutdownPhase {
+    Quit,
+    IoDrain,
+    StderrDrain,
+    Finalize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutdownTimeouts {
+    pub quit: Duration,
+    /// `None` waits until the IO stream is closed
+    pub io_drain: Option<Duration>,
+    pub stderr_drain: Duration,
+    /// How long the embedded Neovim may ignore a quit request before it's terminated, `None`
+    /// waits for it forever
+    pub ignored_quit: Option<Duration>,
+    /// How long a terminated Neovim has to exit before it's killed
+    pub terminate: Duration,
+}
+
+impl ShutdownTimeouts {
+    pub fn from_settings(cmdline_settings: &CmdLineSettings) -> Self {
+        let defaults = Self::default();
+        info!("IO drain mode: {:?}", cmdline_settings.io_drain);
+        Self {
+            io_drain: match cmdline_settings.io_drain {
+                IoDrain::Bounded => defaults.io_drain,
+                IoDrain::Full => None,
+            },
+            ignored_quit: match cmdline_settings.quit_kill_timeout {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            terminate: Duration::from_millis(cmdline_settings.quit_term_timeout),
+            ..defaults
+        }
+    }
+}
+
+impl Default for ShutdownTimeouts {
+    fn default() -> Self {
+        Self {
+            quit: Duration::from_millis(500),
+            io_drain: Some(Duration::from_millis(500)),
+            stderr_drain: Duration::from_millis(500),
+            ignored_quit: None,
+            terminate: Duration::from_secs(2),
+        }
+    }
+}
+
+/// The phases that did not finish before their timeout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShutdownReport {
+    pub timed_out: Vec<ShutdownPhase>,
+    /// The exit code of an embedded Neovim that crashed, 1 when it was killed by a signal
+    pub crash_code: Option<u8>,
+}
+
+impl ShutdownReport {
+    async fn phase<F: Future>(&mut self, phase: ShutdownPhase, limit: Duration, future: F) -> bool {
+        debug!("Shutdown phase {phase:?}");
+        let finished = timeout(limit, future).await.is_ok();
+        if !finished {
+            self.timed_out.push(phase);
+        }
+        finished
+    }
+}
+
+pub async fn shutdown(
+    session: NeovimSession,
+    reason: ShutdownReason,
+    timeouts: &ShutdownTimeouts,
+    running_tracker: &RunningTracker,
+) -> ShutdownReport {
+    let session_id = session.session_id();
+    let NeovimSession {
+        neovim,
+        mut io_handle,
+        mut neovim_process,
+        mut stderr_task,
+        ..
+    } = session;
+    let mut report = ShutdownReport::default();
+
+    let exit_status = match (reason, neovim_process.as_mut()) {
+        (ShutdownReason::NeovimExited, Some(process)) => {
+            quit_phase(&neovim, process, timeouts.quit, &mut report).await
+        }
+        _ => None,
+    };
+    io_drain_phase(&mut io_handle, timeouts.io_drain, &mut report).await;
+    stderr_drain_phase(stderr_task.as_mut(), timeouts.stderr_drain, &mut report).await;
+    report.crash_code = finalize_phase(reason, exit_status, running_tracker);
+    debug!("Session {session_id} shut down");
+    publish(ConnectionEvent::Disconnected { session_id });
+
+    report
+}
+
+async fn quit_phase(
+    nvim: &Neovim<NeovimWriter>,
+    process: &mut Child,
+    limit: Duration,
+    report: &mut ShutdownReport,
+) -> Option<ExitStatus> {
+    if let Ok(Some(status)) = process.try_wait() {
+        return Some(status);
+    }
+    let quit = async {
+        // Not forced, so that unsaved changes keep Neovim from quitting, then it's killed, which
+        // keeps the swap files for recovering them. Neovim doesn't answer when it quits.
+        if let Err(error) = nvim.command("qa").await {
+            debug!("Neovim did not quit right away: {error}");
+        }
+        process.wait().await
+    };
+    if !report.phase(ShutdownPhase::Quit, limit, quit).await {
+        warn!("The Neovim process did not quit, killing it");
+        process.start_kill().ok();
+    }
+    process.try_wait().ok().flatten()
+}
+
+/// Completes once the Neovim of a session is `leaving` for `limit` without exiting, never without
+/// a limit. `leaving` is cancelled by its `VimLeavePre`, so that a quit cancelled before, like
+/// the prompt of `neovide_confirm_quit`, doesn't count.
+pub async fn quit_ignored(leaving: &CancellationToken, limit: Option<Duration>) {
+    let Some(limit) = limit else {
+        return pending().await;
+    };
+    leaving.cancelled().await;
+    sleep(limit).await;
+}
+
+/// Asks the process to terminate with SIGTERM, returns whether the signal was sent.
+#[cfg(unix)]
+fn ask_to_terminate(process: &Child) -> bool {
+    let Some(pid) = process.id() else {
+        return false;
+    };
+    // SAFETY: kill only sends a signal, and the pid is the one of our own child that wasn't
+    // waited for yet, so it can't have been reused
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) == 0 }
+}
+
+/// There is no gentler way to stop a process on Windows than `TerminateProcess`.
+#[cfg(not(unix))]
+fn ask_to_terminate(_process: &Child) -> bool {
+    false
+}
+
+/// Stops a Neovim that ignored the quit request, killing it when it doesn't exit within `grace`
+/// after being asked to terminate.
+pub async fn terminate(process: &mut Child, grace: Duration) {
+    if ask_to_terminate(process) && timeout(grace, process.wait()).await.is_ok() {
+        info!("The Neovim process exited after being terminated");
+        return;
+    }
+    warn!("The Neovim process did not terminate, killing it");
+    process.start_kill().ok();
+    process.wait().await.ok();
+}
+
+async fn io_drain_phase<T>(
+    io_handle: &mut JoinHandle<T>,
+    limit: Option<Duration>,
+    report: &mut ShutdownReport,
+) {
+    // A finished handle must not be polled again
+    if io_handle.is_finished() {
+        return;
+    }
+    let Some(limit) = limit else {
+        debug!(
+            "Shutdown phase {:?}, waiting for the stream to close",
+            ShutdownPhase::IoDrain
+        );
+        (&mut *io_handle).await.ok();
+        return;
+    };
+    if !report
+        .phase(ShutdownPhase::IoDrain, limit, &mut *io_handle)
+        .await
+    {
+        info!("The IO stream was never closed, forcing Neovide to exit");
+        io_handle.abort();
+    }
+}
+
+async fn stderr_drain_phase<T>(
+    stderr_task: Option<&mut JoinHandle<T>>,
+    limit: Duration,
+    report: &mut ShutdownReport,
+) {
+    if let Some(stderr_task) = stderr_task.filter(|task| !task.is_finished()) {
+        report
+            .phase(ShutdownPhase::StderrDrain, limit, stderr_task)
+            .await;
+    }
+}
+
+/// Returns the exit code of a crashed embedded Neovim.
+fn finalize_phase(
+    reason: ShutdownReason,
+    exit_status: Option<ExitStatus>,
+    running_tracker: &RunningTracker,
+) -> Option<u8> {
+    debug!("Shutdown phase {:?}", ShutdownPhase::Finalize);
+    update_current_nvim(None, None);
+    // A clean quit is reported by Neovim itself through `neovide.quit`, so only a process that
+    // exited without doing that, for example by crashing, needs to be recorded here
+    if reason == ShutdownReason::NeovimExited && running_tracker.quit_reason().is_none() {
+        // A process killed by a signal has no code, which never counts as clean
+        let crash_code = exit_status
+            .map(|status| status.code())
+            .filter(|code| !code.is_some_and(|code| running_tracker.is_clean_exit(code)));
+        if let Some(code) = crash_code {
+            let code = code.unwrap_or(1) as u8;
+            running_tracker.quit_with_code(code, "Neovim exited unexpectedly");
+            return Some(code);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+
+    use nvim_rs::Value;
+    use tokio::spawn;
+
+    use super::*;
+    use crate::bridge::recording_nvim::recording_nvim;
+
+    const SHORT: Duration = Duration::from_millis(10);
+
+    #[tokio::test]
+    async fn test_io_drain_timeout_aborts_the_io_task() {
+        let mut report = ShutdownReport::default();
+        let mut io_handle = spawn(pending::<()>());
+        io_drain_phase(&mut io_handle, Some(SHORT), &mut report).await;
+        assert_eq!(report.timed_out, vec![ShutdownPhase::IoDrain]);
+        assert!(io_handle.await.unwrap_err().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_bounded_io_drain_gives_up_on_a_slow_stream() {
+        let mut report = ShutdownReport::default();
+        let mut io_handle = spawn(tokio::time::sleep(Duration::from_millis(200)));
+        io_drain_phase(&mut io_handle, Some(SHORT), &mut report).await;
+        assert_eq!(report.timed_out, vec![ShutdownPhase::IoDrain]);
+    }
+
+    #[tokio::test]
+    async fn test_full_io_drain_waits_for_a_slow_stream() {
+        let mut report = ShutdownReport::default();
+        let mut io_handle = spawn(tokio::time::sleep(Duration::from_millis(200)));
+        io_drain_phase(&mut io_handle, None, &mut report).await;
+        assert!(report.timed_out.is_empty());
+        assert!(io_handle.is_finished());
+    }
+
+    #[test]
+    fn test_timeouts_from_quit_kill_timeout() {
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.quit_kill_timeout = 5;
+        cmdline_settings.quit_term_timeout = 250;
+        let timeouts = ShutdownTimeouts::from_settings(&cmdline_settings);
+        assert_eq!(timeouts.ignored_quit, Some(Duration::from_secs(5)));
+        assert_eq!(timeouts.terminate, Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_timeouts_from_io_drain_mode() {
+        let mut cmdline_settings = CmdLineSettings::default();
+        assert_eq!(
+            ShutdownTimeouts::from_settings(&cmdline_settings),
+            ShutdownTimeouts::default()
+        );
+        cmdline_settings.io_drain = IoDrain::Full;
+        assert_eq!(
+            ShutdownTimeouts::from_settings(&cmdline_settings).io_drain,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_io_drain_finished_task_is_skipped() {
+        let mut report = ShutdownReport::default();
+        let mut io_handle = spawn(async {});
+        while !io_handle.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        io_drain_phase(&mut io_handle, Some(SHORT), &mut report).await;
+        assert!(report.timed_out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stderr_drain_timeout() {
+        let mut report = ShutdownReport::default();
+        let mut stderr_task = spawn(pending::<Vec<String>>());
+        stderr_drain_phase(Some(&mut stderr_task), SHORT, &mut report).await;
+        assert_eq!(report.timed_out, vec![ShutdownPhase::StderrDrain]);
+    }
+
+    #[tokio::test]
+    async fn test_stderr_drain_waits_for_output() {
+        let mut report = ShutdownReport::default();
+        let mut stderr_task = spawn(async { vec!["error".to_string()] });
+        stderr_drain_phase(Some(&mut stderr_task), Duration::from_secs(5), &mut report).await;
+        assert!(report.timed_out.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_quit_timeout_kills_the_process() {
+        let mut report = ShutdownReport::default();
+        let (nvim, requests) = recording_nvim();
+        let mut process = tokio::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        quit_phase(&nvim, &mut process, SHORT, &mut report).await;
+        assert_eq!(
+            *requests.lock(),
+            [(
+                "nvim_command".to_owned(),
+                Value::from(vec![Value::from("qa")])
+            )]
+        );
+        assert_eq!(report.timed_out, vec![ShutdownPhase::Quit]);
+        let status = timeout(Duration::from_secs(5), process.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!status.success());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_quit_phase_skips_an_exited_process() {
+        let mut report = ShutdownReport::default();
+        let (nvim, requests) = recording_nvim();
+        let mut process = tokio::process::Command::new("true").spawn().unwrap();
+        while process.try_wait().unwrap().is_none() {
+            sleep(SHORT).await;
+        }
+        let status = quit_phase(&nvim, &mut process, SHORT, &mut report).await;
+        assert!(status.unwrap().success());
+        assert!(requests.lock().is_empty());
+        assert!(report.timed_out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ignored_quit_only_completes_once_neovim_is_leaving() {
+        let leaving = CancellationToken::new();
+        assert!(timeout(SHORT, quit_ignored(&leaving, None)).await.is_err());
+        let ignored = spawn({
+            let leaving = leaving.clone();
+            async move { quit_ignored(&leaving, Some(SHORT)).await }
+        });
+        sleep(SHORT * 2).await;
+        assert!(!ignored.is_finished());
+        leaving.cancel();
+        timeout(Duration::from_secs(5), ignored)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_process_ignoring_quit_is_killed() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let signal_after_terminate = |script: &'static str| async move {
+            let mut process = tokio::process::Command::new("sh")
+                .args(["-c", script])
+                .spawn()
+                .unwrap();
+            // Lets the shell set up its trap
+            sleep(Duration::from_millis(100)).await;
+            timeout(Duration::from_secs(5), terminate(&mut process, SHORT))
+                .await
+                .unwrap();
+            process.try_wait().unwrap().unwrap().signal()
+        };
+
+        assert_eq!(
+            signal_after_terminate("exec sleep 10").await,
+            Some(libc::SIGTERM)
+        );
+        // Ignored signals stay ignored across exec
+        assert_eq!(
+            signal_after_terminate("trap '' TERM; exec sleep 10").await,
+            Some(libc::SIGKILL)
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_finalize_records_unexpected_exit() {
+        let status = tokio::process::Command::new("false")
+            .status()
+            .await
+            .unwrap();
+        let running_tracker = RunningTracker::new();
+        finalize_phase(ShutdownReason::NeovimExited, Some(status), &running_tracker);
+        assert_eq!(running_tracker.exit_code(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_finalize_uses_clean_exit_codes() {
+        let exit_code_after = |code: i32| async move {
+            let status = tokio::process::Command::new("sh")
+                .args(["-c", &format!("exit {code}")])
+                .status()
+                .await
+                .unwrap();
+            let running_tracker = RunningTracker::new();
+            running_tracker.set_clean_exit_codes(vec![0, 130]);
+            let crash_code =
+                finalize_phase(ShutdownReason::NeovimExited, Some(status), &running_tracker);
+            (running_tracker.exit_code(), crash_code)
+        };
+
+        assert_eq!(exit_code_after(130).await, (0, None));
+        assert_eq!(exit_code_after(2).await, (2, Some(2)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_finalize_ignores_lost_connections() {
+        let status = tokio::process::Command::new("false")
+            .status()
+            .await
+            .unwrap();
+        let running_tracker = RunningTracker::new();
+        finalize_phase(
+            ShutdownReason::ConnectionLost,
+            Some(status),
+            &running_tracker,
+        );
+        assert_eq!(running_tracker.exit_code(), 0);
+    }
+}
@@ -0,0 +1,173 @@
+//! Explains why the embedded Neovim couldn't be started, for the common causes that the plain OS
+//! error doesn't say how to fix.
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use super::command::resolve_binary;
+
+/// The error of `execve` for a binary of another architecture, or that isn't a binary at all.
+#[cfg(unix)]
+const EXEC_FORMAT_ERROR: i32 = libc::ENOEXEC;
+/// `ERROR_BAD_EXE_FORMAT`
+#[cfg(windows)]
+const EXEC_FORMAT_ERROR: i32 = 193;
+#[cfg(not(any(unix, windows)))]
+const EXEC_FORMAT_ERROR: i32 = -1;
+
+/// Why starting the Neovim binary failed, with what to do about it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpawnFailure {
+    /// A bare name that isn't on the PATH
+    NotOnPath(String),
+    /// A path that doesn't exist
+    Missing(String),
+    NotExecutable(String),
+    WrongArchitecture(String),
+}
+
+impl SpawnFailure {
+    /// The failure for the `error` of spawning `program`, `None` when it's none of the common
+    /// causes.
+    pub fn of(program: &str, error: &io::Error) -> Option<Self> {
+        let is_bare_name = Path::new(program).components().count() == 1;
+        let resolved = || {
+            resolve_binary(program)
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| program.to_owned())
+        };
+        if error.raw_os_error() == Some(EXEC_FORMAT_ERROR) {
+            return Some(SpawnFailure::WrongArchitecture(resolved()));
+        }
+        match error.kind() {
+            ErrorKind::NotFound if is_bare_name => {
+                Some(SpawnFailure::NotOnPath(program.to_owned()))
+            }
+            ErrorKind::NotFound => Some(SpawnFailure::Missing(program.to_owned())),
+            ErrorKind::PermissionDenied => Some(SpawnFailure::NotExecutable(resolved())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SpawnFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnFailure::NotOnPath(bin) => write!(
+                f,
+                "{bin} was not found on the PATH, install Neovim or set --neovim-bin or NEOVIM_BIN \
+                 to the path of nvim"
+            ),
+            SpawnFailure::Missing(path) => write!(
+                f,
+                "{path} doesn't exist, set --neovim-bin or NEOVIM_BIN to the path of nvim"
+            ),
+            SpawnFailure::NotExecutable(path) => write!(
+                f,
+                "{path} isn't executable, allow executing it, with chmod +x, or set --neovim-bin or \
+                 NEOVIM_BIN to another nvim"
+            ),
+            SpawnFailure::WrongArchitecture(path) => write!(
+                f,
+                "{path} can't run on this machine, it's probably built for another architecture, \
+                 install Neovim for {}",
+                std::env::consts::ARCH
+            ),
+        }
+    }
+}
+
+/// The failure, with the OS error as its source.
+#[derive(Debug)]
+struct SpawnError {
+    failure: SpawnFailure,
+    source: io::Error,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.failure.fmt(f)
+    }
+}
+
+impl Error for SpawnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Replaces the `error` of spawning `program` with an explanation of the common causes, keeping
+/// its kind.
+pub fn explain_spawn_error(program: &str, error: io::Error) -> io::Error {
+    match SpawnFailure::of(program, &error) {
+        Some(failure) => {
+            log::error!("Could not start Neovim: {failure}");
+            io::Error::new(
+                error.kind(),
+                SpawnError {
+                    failure,
+                    source: error,
+                },
+            )
+        }
+        None => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_binary() {
+        let error = io::Error::from(ErrorKind::NotFound);
+        let failure = SpawnFailure::of("neovide-test-missing-nvim", &error).unwrap();
+        assert_eq!(
+            failure.to_string(),
+            "neovide-test-missing-nvim was not found on the PATH, install Neovim or set \
+             --neovim-bin or NEOVIM_BIN to the path of nvim"
+        );
+
+        let failure = SpawnFailure::of("/opt/nvim/bin/nvim", &error).unwrap();
+        assert_eq!(
+            failure.to_string(),
+            "/opt/nvim/bin/nvim doesn't exist, set --neovim-bin or NEOVIM_BIN to the path of nvim"
+        );
+    }
+
+    #[test]
+    fn test_binary_that_cant_run() {
+        let error = io::Error::from(ErrorKind::PermissionDenied);
+        let failure = SpawnFailure::of("/opt/nvim/bin/nvim", &error).unwrap();
+        assert_eq!(
+            failure,
+            SpawnFailure::NotExecutable("/opt/nvim/bin/nvim".to_owned())
+        );
+        assert!(failure.to_string().contains("isn't executable"));
+
+        let error = io::Error::from_raw_os_error(EXEC_FORMAT_ERROR);
+        let failure = SpawnFailure::of("/opt/nvim/bin/nvim", &error).unwrap();
+        assert_eq!(
+            failure,
+            SpawnFailure::WrongArchitecture("/opt/nvim/bin/nvim".to_owned())
+        );
+        assert!(failure.to_string().contains(std::env::consts::ARCH));
+    }
+
+    #[test]
+    fn test_explained_error_keeps_the_cause() {
+        let error = explain_spawn_error("/opt/nvim/bin/nvim", ErrorKind::NotFound.into());
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        let error = anyhow::Error::from(error);
+        assert_eq!(error.chain().count(), 2);
+        assert!(format!("{error:#}").starts_with("/opt/nvim/bin/nvim doesn't exist"));
+
+        // Anything else is left alone
+        let error = explain_spawn_error("nvim", ErrorKind::OutOfMemory.into());
+        assert!(error.get_ref().is_none());
+    }
+}
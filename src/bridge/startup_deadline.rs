@@ -0,0 +1,93 @@
+//! The overall time budget of `--startup-deadline` for connecting to Neovim, on top of the
+//! timeouts of the individual steps.
+
+use std::{fmt, future::Future, time::Duration};
+
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use tokio::time::timeout;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LaunchPhase {
+    #[default]
+    Spawn,
+    Version,
+    Setup,
+    Attach,
+    Warmup,
+}
+
+impl fmt::Display for LaunchPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self {
+            LaunchPhase::Spawn => "starting or connecting to Neovim",
+            LaunchPhase::Version => "checking the Neovim version",
+            LaunchPhase::Setup => "setting up Neovide in Neovim",
+            LaunchPhase::Attach => "attaching the UI",
+            LaunchPhase::Warmup => "waiting for the connection to stabilize",
+        };
+        f.write_str(phase)
+    }
+}
+
+/// The phase a launch is in, so that a missed deadline can tell where it got stuck.
+#[derive(Default)]
+pub struct LaunchProgress(Mutex<LaunchPhase>);
+
+impl LaunchProgress {
+    pub fn enter(&self, phase: LaunchPhase) {
+        log::trace!("Launch phase: {phase}");
+        *self.0.lock() = phase;
+    }
+
+    pub fn phase(&self) -> LaunchPhase {
+        *self.0.lock()
+    }
+}
+
+/// Aborts `launch` when it takes longer than `deadline`, `None` waits forever.
+pub async fn with_deadline<T>(
+    deadline: Option<Duration>,
+    progress: &LaunchProgress,
+    launch: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(deadline) = deadline else {
+        return launch.await;
+    };
+    timeout(deadline, launch).await.unwrap_or_else(|_| {
+        Err(anyhow!(
+            "Startup did not finish within the deadline of {}s, it was stuck {}",
+            deadline.as_secs_f32(),
+            progress.phase()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_launch_exceeding_the_deadline_is_aborted() {
+        let progress = LaunchProgress::default();
+        let launch = async {
+            progress.enter(LaunchPhase::Version);
+            std::future::pending::<Result<()>>().await
+        };
+        let result = with_deadline(Some(Duration::from_millis(10)), &progress, launch).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Startup did not finish within the deadline of 0.01s, it was stuck checking the \
+             Neovim version"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_launch_within_the_deadline() {
+        let progress = LaunchProgress::default();
+        let result = with_deadline(Some(Duration::from_secs(5)), &progress, async { Ok(1) });
+        assert_eq!(result.await.unwrap(), 1);
+        let result = with_deadline(None, &progress, async { Ok(2) });
+        assert_eq!(result.await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,308 @@
+//! A tiny HTTP endpoint for `--status-port`, so that external dashboards can poll the health of a
+//! headless or kiosk Neovide. Every request gets the same JSON snapshot of the connection state,
+//! there's no other route and nothing can be changed through it. Whether the session is ready
+//! comes from the `SessionReady` connection events.
+
+use std::{
+    io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value as JsonValue};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    select,
+    sync::broadcast::{error::RecvError, Receiver},
+    time::{sleep, timeout},
+};
+
+use super::{
+    connection_events::ConnectionEvent, connection_state, ConnectionState, ConnectionStatus,
+};
+use crate::running_tracker::RunningTracker;
+
+// Only the request line is looked at, but the headers are read so that the client sees a clean
+// close instead of a reset
+const MAX_REQUEST_SIZE: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Running out of file descriptors fails every accept until a connection closes, the wait keeps
+// that from spinning
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+const MAX_ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(5);
+
+/// How long to wait before accepting again after `errors` accepts in a row failed.
+fn accept_error_delay(errors: u32) -> Duration {
+    ACCEPT_ERROR_DELAY
+        .saturating_mul(2u32.saturating_pow(errors.saturating_sub(1)))
+        .min(MAX_ACCEPT_ERROR_DELAY)
+}
+
+/// Whether the connected session of `state` is the one that `SessionReady` was last sent for.
+fn is_ready(state: &ConnectionState, ready_session: Option<u64>) -> bool {
+    state.status == ConnectionStatus::Connected
+        && state.session_id.is_some()
+        && state.session_id == ready_session
+}
+
+pub fn status_json(
+    state: &ConnectionState,
+    ready_session: Option<u64>,
+    uptime: Duration,
+    now: Instant,
+) -> JsonValue {
+    json!({
+        "status": state.status.to_string(),
+        "ready": is_ready(state, ready_session),
+        "address": state.address,
+        "attempt": state.attempt,
+        "nvim_version": state.nvim_version,
+        "channel": state.channel,
+        "rtt_ms": state.rtt.map(|rtt| rtt.as_millis() as u64),
+        "last_ping_ms": state
+            .since_last_ping(now)
+            .map(|elapsed| elapsed.as_millis() as u64),
+        "session_id": state.session_id,
+        "uptime_secs": uptime.as_secs(),
+    })
+}
+
+fn response(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+async fn read_request(stream: &mut TcpStream) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    Ok(())
+}
+
+async fn answer(
+    mut stream: TcpStream,
+    ready_session: Option<u64>,
+    running_tracker: &RunningTracker,
+) -> io::Result<()> {
+    timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await??;
+    let body = status_json(
+        &connection_state(),
+        ready_session,
+        running_tracker.uptime(),
+        Instant::now(),
+    )
+    .to_string();
+    stream.write_all(response(&body).as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn serve(
+    listener: TcpListener,
+    running_tracker: RunningTracker,
+    mut events: Receiver<ConnectionEvent>,
+) {
+    let mut ready_session = None;
+    let mut accept_errors = 0;
+    loop {
+        select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    accept_errors = 0;
+                    let running_tracker = running_tracker.clone();
+                    tokio::spawn(async move {
+                        if let Err(error) = answer(stream, ready_session, &running_tracker).await {
+                            log::debug!("Status request failed: {error}");
+                        }
+                    });
+                }
+                Err(error) => {
+                    accept_errors += 1;
+                    let delay = accept_error_delay(accept_errors);
+                    log::warn!(
+                        "Could not accept a status request, retrying in {}ms: {error}",
+                        delay.as_millis()
+                    );
+                    sleep(delay).await;
+                }
+            },
+            event = events.recv() => match event {
+                Ok(ConnectionEvent::SessionReady { session_id }) => {
+                    ready_session = Some(session_id);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+        }
+    }
+}
+
+/// Serves the status on `address` until Neovide quits, with the readiness of the `events`.
+pub async fn run_status_endpoint(
+    address: SocketAddr,
+    running_tracker: RunningTracker,
+    events: Receiver<ConnectionEvent>,
+) {
+    if !address.ip().is_loopback() {
+        log::warn!(
+            "The status endpoint on {address} is reachable from other machines, it shows the \
+             server address and Neovim version without any authentication"
+        );
+    }
+    match TcpListener::bind(address).await {
+        Ok(listener) => {
+            log::info!("Serving the status on http://{address}");
+            serve(listener, running_tracker, events).await;
+        }
+        Err(error) => log::error!("Could not start the status endpoint on {address}: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+    use tokio::sync::broadcast;
+    use winit::event_loop::EventLoopClosed;
+
+    use super::*;
+    use crate::{
+        bridge::{connection_events::subscribe, connection_state::update_connection_state},
+        window::{UserEvent, UserEventSender},
+    };
+
+    #[test]
+    fn test_status_json_for_a_snapshot() {
+        let now = Instant::now();
+        let state = ConnectionState {
+            status: ConnectionStatus::Connected,
+            address: Some("localhost:6666".to_owned()),
+            attempt: 0,
+            backoff: None,
+            nvim_version: Some("0.11.2".to_owned()),
+            channel: Some(3),
+            rtt: Some(Duration::from_millis(12)),
+            last_ping_ok: Some(now - Duration::from_millis(1500)),
+            session_id: Some(2),
+            capabilities: None,
+        };
+        assert_eq!(
+            status_json(&state, Some(2), Duration::from_secs(90), now),
+            json!({
+                "status": "connected",
+                "ready": true,
+                "address": "localhost:6666",
+                "attempt": 0,
+                "nvim_version": "0.11.2",
+                "channel": 3,
+                "rtt_ms": 12,
+                "last_ping_ms": 1500,
+                "session_id": 2,
+                "uptime_secs": 90,
+            })
+        );
+
+        let embedded = status_json(&ConnectionState::default(), None, Duration::ZERO, now);
+        assert_eq!(embedded["status"], "connecting");
+        assert_eq!(embedded["ready"], false);
+        assert!(embedded["address"].is_null());
+        assert!(embedded["rtt_ms"].is_null());
+        assert!(embedded["last_ping_ms"].is_null());
+    }
+
+    async fn get_status(address: SocketAddr) -> (String, JsonValue) {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.to_owned(), serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_accept_errors_back_off() {
+        let delays = (1..=8).map(accept_error_delay).collect::<Vec<_>>();
+        assert_eq!(delays[0], Duration::from_millis(100));
+        assert_eq!(delays[1], Duration::from_millis(200));
+        assert_eq!(delays[2], Duration::from_millis(400));
+        assert_eq!(delays[7], MAX_ACCEPT_ERROR_DELAY);
+        assert_eq!(accept_error_delay(u32::MAX), MAX_ACCEPT_ERROR_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_serves_the_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, RunningTracker::new(), subscribe()));
+
+        let (head, status) = get_status(address).await;
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains("Content-Type: application/json"));
+        assert!(status["status"].is_string());
+        assert!(status["ready"].is_boolean());
+        assert!(status["uptime_secs"].is_u64());
+    }
+
+    #[test]
+    fn test_only_the_connected_ready_session_is_ready() {
+        let mut state = ConnectionState {
+            status: ConnectionStatus::Connected,
+            session_id: Some(7),
+            ..ConnectionState::default()
+        };
+        assert!(is_ready(&state, Some(7)));
+        // The first redraw of the new session didn't arrive yet
+        assert!(!is_ready(&state, Some(6)));
+        assert!(!is_ready(&state, None));
+
+        state.status = ConnectionStatus::Reconnecting;
+        assert!(!is_ready(&state, Some(7)));
+    }
+
+    struct NoWindow;
+
+    impl UserEventSender for NoWindow {
+        fn send_user_event(&self, _event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+            Ok(())
+        }
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_endpoint_is_ready_after_session_ready() {
+        update_connection_state(&NoWindow, |state| {
+            state.status = ConnectionStatus::Connected;
+            state.session_id = Some(4301);
+        });
+        let (events, _) = broadcast::channel(4);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, RunningTracker::new(), events.subscribe()));
+        assert_eq!(get_status(address).await.1["ready"], false);
+
+        events
+            .send(ConnectionEvent::SessionReady { session_id: 4301 })
+            .unwrap();
+        timeout(Duration::from_secs(1), async {
+            while get_status(address).await.1["ready"] != true {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
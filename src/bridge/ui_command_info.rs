@@ -0,0 +1,197 @@
+//! A description of every UI command that `send_ui` accepts, for plugins that build UIs or
+//! documentation on top of Neovide, returned by `neovide.ui_commands()`. The names are the ones
+//! of the variants, which is also how they're written in a `--record-ui-commands` recording.
+
+use nvim_rs::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandKind {
+    /// Sent in order, one after the other
+    Serial,
+    Parallel,
+}
+
+impl CommandKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            CommandKind::Serial => "serial",
+            CommandKind::Parallel => "parallel",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UiCommandInfo {
+    pub name: &'static str,
+    pub kind: CommandKind,
+    /// The number of arguments
+    pub arity: usize,
+    pub description: &'static str,
+}
+
+const fn serial(name: &'static str, arity: usize, description: &'static str) -> UiCommandInfo {
+    UiCommandInfo {
+        name,
+        kind: CommandKind::Serial,
+        arity,
+        description,
+    }
+}
+
+const fn parallel(name: &'static str, arity: usize, description: &'static str) -> UiCommandInfo {
+    UiCommandInfo {
+        name,
+        kind: CommandKind::Parallel,
+        arity,
+        description,
+    }
+}
+
+pub const UI_COMMANDS: &[UiCommandInfo] = &[
+    serial("Keyboard", 1, "Sends keys typed in the window"),
+    serial(
+        "MouseButton",
+        5,
+        "Presses or releases a mouse button at a grid position",
+    ),
+    serial(
+        "Scroll",
+        4,
+        "Scrolls with the mouse wheel at a grid position",
+    ),
+    serial(
+        "Drag",
+        4,
+        "Drags with a mouse button held at a grid position",
+    ),
+    serial("Resize", 2, "Resizes the grid, like :NeovideResizeGrid"),
+    serial(
+        "SendRawInput",
+        1,
+        "Sends keys in Neovim notation as they are, like :NeovideSendInput",
+    ),
+    serial(
+        "SetReconnectNotice",
+        1,
+        "Shows a notice on the reconnect overlay, like :NeovideReconnectNotice",
+    ),
+    parallel("Quit", 0, "Asks Neovim to quit, like closing the window"),
+    parallel("Resize", 2, "Resizes the grid to fit the window"),
+    parallel("FileDrop", 1, "Opens a file dropped on the window"),
+    parallel(
+        "FocusLost",
+        0,
+        "Tells Neovim that the window lost the focus",
+    ),
+    parallel(
+        "FocusGained",
+        0,
+        "Tells Neovim that the window got the focus",
+    ),
+    parallel(
+        "DisplayAvailableFonts",
+        1,
+        "Shows the fonts that can be used for guifont",
+    ),
+    parallel("SetBackground", 1, "Sets 'background' to follow the theme"),
+    parallel("ShowError", 1, "Shows an error message in Neovim"),
+    parallel("ReloadSettings", 0, "Reads the g:neovide_ settings again"),
+    parallel(
+        "RefreshNeovideState",
+        0,
+        "Runs the Neovide specific setup of Neovim again",
+    ),
+    parallel(
+        "SetMultigrid",
+        1,
+        "Attaches the UI again with multigrid on or off",
+    ),
+    parallel(
+        "DumpSettings",
+        1,
+        "Writes the current settings to a file, or echoes them",
+    ),
+    parallel("PauseReconnect", 0, "Pauses reconnecting to the --server"),
+    parallel("ResumeReconnect", 0, "Resumes reconnecting to the --server"),
+    parallel(
+        "ReconnectNow",
+        0,
+        "Skips the wait for the next reconnect attempt",
+    ),
+    parallel(
+        "ShowReconnectState",
+        0,
+        "Echoes the backoff, attempt and pause state of the reconnect loop",
+    ),
+    parallel(
+        "SwitchToServer",
+        0,
+        "Switches from the fallback embedded Neovim back to the --server",
+    ),
+    parallel(
+        "SwitchServer",
+        1,
+        "Ends the session with the --server and connects to another address",
+    ),
+    parallel("QuitNeovide", 1, "Quits Neovide with an exit code"),
+];
+
+impl From<&UiCommandInfo> for Value {
+    fn from(info: &UiCommandInfo) -> Self {
+        Value::Map(vec![
+            ("name".into(), info.name.into()),
+            ("kind".into(), info.kind.as_str().into()),
+            ("arity".into(), (info.arity as u64).into()),
+            ("description".into(), info.description.into()),
+        ])
+    }
+}
+
+/// All the UI commands, as a list of maps for `neovide.ui_commands()`.
+pub fn ui_commands_value() -> Value {
+    Value::Array(UI_COMMANDS.iter().map(Value::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::VariantNames;
+
+    use super::*;
+    use crate::bridge::{ParallelCommand, SerialCommand};
+
+    fn names(kind: CommandKind) -> Vec<&'static str> {
+        UI_COMMANDS
+            .iter()
+            .filter(|info| info.kind == kind)
+            .map(|info| info.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_every_variant_is_described() {
+        // Fails when a variant is added, removed or renamed without updating the table
+        assert_eq!(names(CommandKind::Serial), SerialCommand::VARIANTS);
+        assert_eq!(names(CommandKind::Parallel), ParallelCommand::VARIANTS);
+        assert!(UI_COMMANDS.iter().all(|info| !info.description.is_empty()));
+    }
+
+    #[test]
+    fn test_commands_as_values() {
+        let Value::Array(commands) = ui_commands_value() else {
+            panic!("Not a list");
+        };
+        assert_eq!(commands.len(), UI_COMMANDS.len());
+        let quit_neovide = commands.last().unwrap();
+        let field = |key: &str| {
+            quit_neovide
+                .as_map()
+                .unwrap()
+                .iter()
+                .find(|(name, _)| name.as_str() == Some(key))
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(field("name"), Some("QuitNeovide".into()));
+        assert_eq!(field("kind"), Some("parallel".into()));
+        assert_eq!(field("arity"), Some(1.into()));
+    }
+}
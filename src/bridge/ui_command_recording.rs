@@ -0,0 +1,249 @@
+//! `--record-ui-commands`, which writes every UI command to a file as it's sent, for debugging
+//! and for reproducing a session. Each line is a JSON object with the command and the
+//! milliseconds since the recording started. The file is written by its own thread, so that
+//! sending a command only costs a clone. `--ui-commands-file` replays such a recording.
+
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{
+    connection_events::{subscribe, ConnectionEvent},
+    ui_commands::{send_ui, UiCommand},
+};
+use crate::clock::{Clock, SystemClock};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedCommand {
+    /// Milliseconds since the recording started
+    pub at_ms: u64,
+    pub command: UiCommand,
+}
+
+struct Recorder {
+    started_at: Instant,
+    sender: Sender<RecordedCommand>,
+}
+
+impl Recorder {
+    fn start(writer: impl Write + Send + 'static) -> io::Result<(Self, JoinHandle<()>)> {
+        let (sender, receiver) = channel();
+        let thread = thread::Builder::new()
+            .name("ui-command-recorder".to_owned())
+            .spawn(move || {
+                if let Err(error) = write_recording(writer, receiver) {
+                    log::error!("Recording the UI commands failed: {error}");
+                }
+            })?;
+        let recorder = Self {
+            started_at: Instant::now(),
+            sender,
+        };
+        Ok((recorder, thread))
+    }
+
+    fn record(&self, command: &UiCommand) {
+        let recorded = RecordedCommand {
+            at_ms: self.started_at.elapsed().as_millis() as u64,
+            command: command.clone(),
+        };
+        // The writer only stops after an error, which it already logged
+        self.sender.send(recorded).ok();
+    }
+}
+
+/// Writes the commands until the recorder is dropped, flushing whenever it has caught up.
+fn write_recording(writer: impl Write, receiver: Receiver<RecordedCommand>) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    while let Ok(recorded) = receiver.recv() {
+        for recorded in std::iter::once(recorded).chain(receiver.try_iter()) {
+            serde_json::to_writer(&mut writer, &recorded)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+static UI_COMMAND_RECORDER: Lazy<RwLock<Option<Recorder>>> = Lazy::new(|| RwLock::new(None));
+
+/// Records all UI commands sent from now on to `path`, replacing the file.
+pub fn start_recording(path: &Path) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Could not create the UI command recording {path:?}"))?;
+    let (recorder, _) = Recorder::start(file)?;
+    log::info!("Recording the UI commands to {path:?}");
+    *UI_COMMAND_RECORDER.write() = Some(recorder);
+    Ok(())
+}
+
+pub fn record_ui_command(command: &UiCommand) {
+    if let Some(recorder) = UI_COMMAND_RECORDER.read().as_ref() {
+        recorder.record(command);
+    }
+}
+
+/// Reads a recording back, in the order the commands were sent.
+fn read_recording(reader: impl BufRead) -> Result<Vec<RecordedCommand>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|(index, line)| {
+            let line = line?;
+            serde_json::from_str(&line)
+                .with_context(|| format!("Invalid UI command on line {}", index + 1))
+        })
+        .collect()
+}
+
+/// Loads the `--ui-commands-file` at `path`, a file written by `--record-ui-commands`.
+fn load_ui_commands_file(path: &Path) -> Result<Vec<RecordedCommand>> {
+    let file = File::open(path)
+        .with_context(|| format!("Could not open the UI commands file {path:?}"))?;
+    read_recording(BufReader::new(file))
+        .with_context(|| format!("Could not read the UI commands file {path:?}"))
+}
+
+/// Sends the commands of `recording` with `send`, as far apart as they were recorded. The first
+/// one is sent right away.
+async fn replay(
+    recording: Vec<RecordedCommand>,
+    clock: &dyn Clock,
+    mut send: impl FnMut(UiCommand),
+) {
+    let Some(first_at_ms) = recording.first().map(|recorded| recorded.at_ms) else {
+        return;
+    };
+    let started_at = clock.now();
+    for recorded in recording {
+        let due = started_at + Duration::from_millis(recorded.at_ms.saturating_sub(first_at_ms));
+        let wait = due.saturating_duration_since(clock.now());
+        if !wait.is_zero() {
+            clock.sleep(wait).await;
+        }
+        send(recorded.command);
+    }
+}
+
+/// Reads the `--ui-commands-file` at `path`, the returned task replays it through `send_ui` once
+/// the first session is ready.
+pub fn replay_ui_commands_file(path: &Path) -> Result<impl Future<Output = ()>> {
+    let recording = load_ui_commands_file(path)?;
+    // Subscribed before anything connects, so that the first session isn't missed
+    let mut events = subscribe();
+    let path = path.to_owned();
+    Ok(async move {
+        loop {
+            match events.recv().await {
+                Ok(ConnectionEvent::SessionReady { .. }) => break,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            }
+        }
+        log::info!("Replaying {} UI commands from {path:?}", recording.len());
+        replay(recording, &SystemClock, send_ui).await;
+        log::info!("Replayed the UI commands from {path:?}");
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+    use crate::{
+        bridge::{ParallelCommand, SerialCommand},
+        clock::MockClock,
+    };
+
+    #[tokio::test]
+    async fn test_recorded_commands_round_trip() {
+        let path =
+            env::temp_dir().join(format!("neovide-ui-commands-{}.jsonl", std::process::id()));
+        let commands: Vec<UiCommand> = vec![
+            SerialCommand::Keyboard("<C-w>".to_owned()).into(),
+            SerialCommand::MouseButton {
+                button: "left".to_owned(),
+                action: "press".to_owned(),
+                grid_id: 2,
+                position: (10, 4),
+                modifier_string: "S-".to_owned(),
+            }
+            .into(),
+            ParallelCommand::Resize {
+                width: 120,
+                height: 40,
+            }
+            .into(),
+            ParallelCommand::DumpSettings(Some(PathBuf::from("/tmp/settings.toml"))).into(),
+            ParallelCommand::Quit.into(),
+        ];
+
+        let (recorder, thread) = Recorder::start(File::create(&path).unwrap()).unwrap();
+        for command in &commands {
+            recorder.record(command);
+        }
+        drop(recorder);
+        thread.join().unwrap();
+
+        let recording = load_ui_commands_file(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert!(recording
+            .windows(2)
+            .all(|pair| pair[0].at_ms <= pair[1].at_ms));
+
+        let clock = MockClock::new();
+        let mut replayed = Vec::new();
+        replay(recording, &clock, |command| replayed.push(command)).await;
+        assert_eq!(replayed, commands);
+    }
+
+    #[tokio::test]
+    async fn test_replay_keeps_the_recorded_timing() {
+        let recording = [(500, "a"), (500, "b"), (1500, "c")]
+            .into_iter()
+            .map(|(at_ms, keys)| RecordedCommand {
+                at_ms,
+                command: SerialCommand::Keyboard(keys.to_owned()).into(),
+            })
+            .collect();
+        let clock = MockClock::new();
+        let started_at = clock.now();
+        let mut sent = Vec::new();
+        replay(recording, &clock, |command| {
+            sent.push((command, clock.now() - started_at))
+        })
+        .await;
+
+        // Counted from the first command, which is sent right away
+        let keyboard = |keys: &str| UiCommand::from(SerialCommand::Keyboard(keys.to_owned()));
+        assert_eq!(
+            sent,
+            [
+                (keyboard("a"), Duration::ZERO),
+                (keyboard("b"), Duration::ZERO),
+                (keyboard("c"), Duration::from_secs(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_line_is_reported() {
+        let recording = "{\"at_ms\":0,\"command\":{\"Parallel\":\"Quit\"}}\n\nnot json\n";
+        let error = read_recording(recording.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid UI command on line 3");
+    }
+}
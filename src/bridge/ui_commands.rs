@@ -1,29 +1,63 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    future::{poll_fn, Future},
+    path::PathBuf,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
 
 use once_cell::sync::Lazy;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
-use log::trace;
+use log::{trace, warn};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use nvim_rs::{call_args, error::CallError, rpc::model::IntoVal, Neovim, Value};
-use strum::AsRefStr;
-use tokio::sync::mpsc::unbounded_channel;
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, VariantNames};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    time::sleep,
+};
 
-use super::{show_error_message, Settings};
+use super::{
+    connection_state,
+    fallback::switch_to_server,
+    is_reconnect_paused, is_remote_session,
+    reconnect_notice::set_reconnect_notice,
+    reconnect_pause::{pause_reconnect, reconnect_now, resume_reconnect},
+    reconnect_state_message,
+    server_switch::switch_server,
+    session::{poll_tracking_writes, WriteProgress},
+    setup::{get_api_information, setup_neovide_specific_state},
+    should_handle_clipboard, show_error_message, show_message, ui_attach_options,
+    ui_command_recording::record_ui_command,
+    MessageSeverity, Settings,
+};
 use crate::{
     bridge::NeovimWriter,
     cmd_line::CmdLineSettings,
     profiling::{tracy_dynamic_zone, tracy_fiber_enter, tracy_fiber_leave},
+    running_tracker::{QuitReason, RunningTracker},
+    settings::{clamped_grid_size, DumpFormat, DEFAULT_GRID_SIZE},
+    units::GridSize,
     utils::handle_wslpaths,
+    window::{UserEvent, UserEventSender},
     LoggingSender,
 };
 
+const RPC_ERROR_INTERVAL: Duration = Duration::from_secs(5);
+
 // Serial commands are any commands which must complete before the next value is sent. This
 // includes keyboard and mouse input which would cause problems if sent out of order.
 //
 // When in doubt, use Parallel Commands.
-#[derive(Clone, Debug, AsRefStr)]
+#[derive(Clone, Debug, PartialEq, AsRefStr, VariantNames, Serialize, Deserialize)]
 pub enum SerialCommand {
     Keyboard(String),
     MouseButton {
@@ -45,16 +79,28 @@ pub enum SerialCommand {
         position: (u32, u32),
         modifier_string: String,
     },
+    /// Resizes the grid to `width` columns and `height` lines, for example from
+    /// `:NeovideResizeGrid`
+    Resize {
+        width: u64,
+        height: u64,
+    },
+    /// Keys in Neovim notation, like `<Esc>iHello<Esc>`, sent as they are for scripting, for
+    /// example from `:NeovideSendInput`
+    SendRawInput(String),
+    /// Remembers a notice from the server for the reconnect overlay, in order with the input
+    /// before it, an empty one clears it
+    SetReconnectNotice(String),
 }
 
 impl SerialCommand {
-    async fn execute(self, nvim: &Neovim<NeovimWriter>) {
+    async fn execute(self, nvim: &Neovim<NeovimWriter>) -> Result<()> {
         // Don't panic here unless there's absolutely no chance of continuing the program, Instead
-        // just log the error and hope that it's something temporary or recoverable A normal reason
-        // for failure is when neovim has already quit, and a command, for example mouse move is
-        // being sent
+        // just report the error and hope that it's something temporary or recoverable A normal
+        // reason for failure is when neovim has already quit, and a command, for example mouse
+        // move is being sent
         log::trace!("In Serial Command");
-        let result = match self {
+        match self {
             SerialCommand::Keyboard(input_command) => {
                 trace!("Keyboard Input Sent: {}", input_command);
                 nvim.input(&input_command)
@@ -111,24 +157,73 @@ impl SerialCommand {
                 )
                 .await
                 .context("Mouse Drag Failed"),
-        };
-
-        if let Err(error) = result {
-            log::error!("{:?}", error);
+            SerialCommand::SendRawInput(keys) => {
+                trace!("Raw input sent: {keys}");
+                nvim.input(&keys)
+                    .await
+                    .map(|_| ())
+                    .context("Raw input failed")
+            }
+            SerialCommand::Resize { width, height } => {
+                let grid_size = clamped_resize(width, height);
+                trace!("Grid resize requested: {grid_size:?}");
+                nvim.ui_try_resize(grid_size.width as i64, grid_size.height as i64)
+                    .await
+                    .context("Grid resize failed")?;
+                set_current_grid_size(grid_size);
+                Ok(())
+            }
+            SerialCommand::SetReconnectNotice(notice) => {
+                set_reconnect_notice(notice);
+                Ok(())
+            }
         }
     }
 }
 
-#[derive(Debug, Clone, AsRefStr)]
+/// The size a grid resize to `width` x `height` asks Neovim for.
+fn clamped_resize(width: u64, height: u64) -> GridSize<u32> {
+    let width = u32::try_from(width).unwrap_or(u32::MAX);
+    let height = u32::try_from(height).unwrap_or(u32::MAX);
+    clamped_grid_size(&GridSize::new(width, height))
+}
+
+#[derive(Debug, Clone, PartialEq, AsRefStr, VariantNames, Serialize, Deserialize)]
 pub enum ParallelCommand {
     Quit,
-    Resize { width: u64, height: u64 },
+    Resize {
+        width: u64,
+        height: u64,
+    },
     FileDrop(String),
     FocusLost,
     FocusGained,
     DisplayAvailableFonts(Vec<String>),
     SetBackground(String),
-    ShowError { lines: Vec<String> },
+    ShowError {
+        lines: Vec<String>,
+    },
+    ReloadSettings,
+    /// Runs the Neovide specific setup again, after the user config changed what it set up
+    RefreshNeovideState,
+    /// Detaches and attaches the UI again with multigrid on or off
+    SetMultigrid(bool),
+    /// Writes the current settings to `path`, or echoes them without one
+    DumpSettings(Option<PathBuf>),
+    /// Handled without Neovim, since it's usually sent while disconnected
+    PauseReconnect,
+    ResumeReconnect,
+    /// Skips the rest of the wait for the next reconnect attempt
+    ReconnectNow,
+    /// Echoes the state of the reconnect loop
+    ShowReconnectState,
+    SwitchToServer,
+    /// Ends the session with the `--server` and connects to this address instead
+    SwitchServer(String),
+    /// Quits Neovide with `code`, for a plugin that is done with it
+    QuitNeovide {
+        code: u8,
+    },
 }
 
 async fn display_available_fonts(
@@ -176,30 +271,117 @@ async fn display_available_fonts(
     Ok(())
 }
 
+/// Reattaches the UI, `--no-multigrid` is updated as well so that it's kept on reconnects.
+async fn set_multigrid(
+    nvim: &Neovim<NeovimWriter>,
+    settings: &Settings,
+    multigrid: bool,
+) -> Result<()> {
+    let state = if multigrid { "on" } else { "off" };
+    warn!("Reattaching the UI with multigrid {state}, the window flashes briefly");
+    let mut cmdline_settings = settings.get::<CmdLineSettings>();
+    cmdline_settings.no_multi_grid = !multigrid;
+    settings.set(&cmdline_settings);
+
+    nvim.ui_detach().await?;
+    // Goes through Neovim, so that the editor closes the old windows after the last event of the
+    // previous attach
+    nvim.exec_lua(
+        "vim.rpcnotify(vim.g.neovide_channel_id, 'neovide.ui_reattached')",
+        vec![],
+    )
+    .await?;
+    let grid_size = current_grid_size().unwrap_or(DEFAULT_GRID_SIZE);
+    nvim.ui_attach(
+        grid_size.width as i64,
+        grid_size.height as i64,
+        &ui_attach_options(multigrid),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn show_reconnect_state(nvim: &Neovim<NeovimWriter>) -> Result<()> {
+    let message = reconnect_state_message(&connection_state(), is_reconnect_paused());
+    show_message(nvim, MessageSeverity::Info, &[message]).await?;
+    Ok(())
+}
+
+async fn dump_settings(
+    nvim: &Neovim<NeovimWriter>,
+    settings: &Settings,
+    path: Option<PathBuf>,
+) -> Result<()> {
+    let message = match path {
+        Some(path) => {
+            settings.dump_to_file(&path)?;
+            format!("Settings written to {}", path.display())
+        }
+        None => settings.dump(DumpFormat::Toml)?,
+    };
+    nvim.echo(vec![Value::Array(vec![message.into()])], false, vec![])
+        .await?;
+    Ok(())
+}
+
+/// Runs `setup_neovide_specific_state` again on the current connection. It only sets up what
+/// Neovide itself needs, and is safe to run any number of times.
+async fn refresh_neovide_state(nvim: &Neovim<NeovimWriter>, settings: &Settings) -> Result<()> {
+    let api_information = get_api_information(nvim).await?;
+    let should_handle_clipboard = should_handle_clipboard(&settings.get::<CmdLineSettings>());
+    setup_neovide_specific_state(nvim, should_handle_clipboard, &api_information, settings).await
+}
+
 impl ParallelCommand {
-    async fn execute(self, nvim: &Neovim<NeovimWriter>, settings: &Settings) {
+    /// Runs the commands that don't need Neovim, since they're usually sent while disconnected.
+    /// Returns the other commands.
+    fn run_without_neovim(self) -> Option<Self> {
+        match self {
+            ParallelCommand::PauseReconnect => pause_reconnect(),
+            ParallelCommand::ResumeReconnect => resume_reconnect(),
+            ParallelCommand::ReconnectNow => reconnect_now(),
+            command => return Some(command),
+        }
+        None
+    }
+
+    async fn execute(
+        self,
+        nvim: &Neovim<NeovimWriter>,
+        settings: &Settings,
+        running_tracker: &RunningTracker,
+    ) -> Result<()> {
+        let Some(command) = self.run_without_neovim() else {
+            return Ok(());
+        };
         // Don't panic here unless there's absolutely no chance of continuing the program, Instead
-        // just log the error and hope that it's something temporary or recoverable A normal reason
-        // for failure is when neovim has already quit, and a command, for example mouse move is
-        // being sent
-        let result = match self {
+        // just report the error and hope that it's something temporary or recoverable A normal
+        // reason for failure is when neovim has already quit, and a command, for example mouse
+        // move is being sent
+        match command {
             ParallelCommand::Quit => {
                 // Ignore all errors, since neovim exits immediately before the response is sent.
                 // We could an RPC notify instead of request, but nvim-rs does currently not support it.
+                // In read-only mode, Neovide detaches without prompting.
                 let _ = nvim
                     .exec_lua(
                         include_str!("../../lua/exit_handler.lua"),
-                        vec![Value::Boolean(
-                            settings.get::<CmdLineSettings>().server.is_some(),
-                        )],
+                        vec![
+                            Value::Boolean(is_remote_session(&settings.get::<CmdLineSettings>())),
+                            Value::Boolean(is_read_only()),
+                        ],
                     )
                     .await;
                 Ok(())
             }
-            ParallelCommand::Resize { width, height } => nvim
-                .ui_try_resize(width.max(10) as i64, height.max(3) as i64)
-                .await
-                .context("Resize failed"),
+            ParallelCommand::Resize { width, height } => {
+                let (width, height) = (width.max(10), height.max(3));
+                nvim.ui_try_resize(width as i64, height as i64)
+                    .await
+                    .context("Resize failed")?;
+                set_current_grid_size(GridSize::new(width as u32, height as u32));
+                Ok(())
+            }
             ParallelCommand::FocusLost => {
                 nvim.ui_set_focus(false).await.context("FocusLost failed")
             }
@@ -228,6 +410,37 @@ impl ParallelCommand {
                 .await
                 .context("DisplayAvailableFonts failed"),
 
+            ParallelCommand::ReloadSettings => settings
+                .read_initial_values(nvim)
+                .await
+                .context("ReloadSettings failed"),
+            ParallelCommand::RefreshNeovideState => refresh_neovide_state(nvim, settings)
+                .await
+                .context("RefreshNeovideState failed"),
+            ParallelCommand::SetMultigrid(multigrid) => set_multigrid(nvim, settings, multigrid)
+                .await
+                .context("SetMultigrid failed"),
+            ParallelCommand::DumpSettings(path) => dump_settings(nvim, settings, path)
+                .await
+                .context("DumpSettings failed"),
+            // Already run without Neovim
+            ParallelCommand::PauseReconnect
+            | ParallelCommand::ResumeReconnect
+            | ParallelCommand::ReconnectNow => Ok(()),
+            ParallelCommand::ShowReconnectState => show_reconnect_state(nvim)
+                .await
+                .context("ShowReconnectState failed"),
+            ParallelCommand::SwitchToServer => {
+                switch_to_server();
+                Ok(())
+            }
+            ParallelCommand::SwitchServer(address) => {
+                switch_server(settings, &address).context("SwitchServer failed")
+            }
+            ParallelCommand::QuitNeovide { code } => {
+                send_ui(quit_neovide(running_tracker, code));
+                Ok(())
+            }
             ParallelCommand::ShowError { lines } => {
                 // nvim.err_write(&message).await.ok();
                 // NOTE: https://github.com/neovim/neovim/issues/5067
@@ -237,15 +450,11 @@ impl ParallelCommand {
                     .await
                     .context("ShowError failed")
             }
-        };
-
-        if let Err(error) = result {
-            log::error!("{:?}", error);
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum UiCommand {
     Serial(SerialCommand),
     Parallel(ParallelCommand),
@@ -263,6 +472,24 @@ impl From<ParallelCommand> for UiCommand {
     }
 }
 
+impl UiCommand {
+    /// Whether the command changes the state of Neovim, rather than just the state of the UI.
+    /// These are dropped in read-only mode.
+    fn is_input(&self) -> bool {
+        matches!(
+            self,
+            UiCommand::Serial(
+                SerialCommand::Keyboard(_)
+                    | SerialCommand::SendRawInput(_)
+                    | SerialCommand::MouseButton { .. }
+                    | SerialCommand::Scroll { .. }
+                    | SerialCommand::Drag { .. }
+            ) | UiCommand::Parallel(ParallelCommand::FileDrop(_))
+                | UiCommand::Parallel(ParallelCommand::SetBackground(_))
+        )
+    }
+}
+
 impl AsRef<str> for UiCommand {
     fn as_ref(&self) -> &str {
         match self {
@@ -272,23 +499,207 @@ impl AsRef<str> for UiCommand {
     }
 }
 
+/// Logs failed UI commands and forwards them to the window as `UserEvent::RpcError`. Each command
+/// is forwarded at most once per `RPC_ERROR_INTERVAL`, so that for example a failing mouse move
+/// doesn't flood the window.
+pub struct RpcErrorReporter {
+    sender: Box<dyn UserEventSender + Send>,
+    last_reported: HashMap<String, Instant>,
+}
+
+impl RpcErrorReporter {
+    pub fn new(sender: impl UserEventSender + Send + 'static) -> Self {
+        Self {
+            sender: Box::new(sender),
+            last_reported: HashMap::new(),
+        }
+    }
+
+    fn report(&mut self, command: &str, error: &anyhow::Error, now: Instant) {
+        log::error!("{:?}", error);
+        if self
+            .last_reported
+            .get(command)
+            .is_some_and(|last| now.duration_since(*last) < RPC_ERROR_INTERVAL)
+        {
+            return;
+        }
+        self.last_reported.insert(command.to_owned(), now);
+        self.sender
+            .send_user_event(UserEvent::RpcError {
+                command: command.to_owned(),
+                message: format!("{error:#}"),
+            })
+            .ok();
+    }
+}
+
+/// One `--ui-command-timeout`, the seconds that the named command, or without a name every other
+/// command, waits for Neovim's answer. 0 waits forever.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CommandTimeout {
+    command: Option<String>,
+    seconds: u64,
+}
+
+impl CommandTimeout {
+    /// Parses `SECONDS` or `COMMAND=SECONDS`, the command being named like `Keyboard` or
+    /// `FileDrop`.
+    pub fn parse(value: &str) -> std::result::Result<CommandTimeout, String> {
+        let (command, seconds) = match value.split_once('=') {
+            Some((command, seconds)) => (Some(command.trim()), seconds),
+            None => (None, value),
+        };
+        if let Some(command) = command {
+            if !SerialCommand::VARIANTS.contains(&command)
+                && !ParallelCommand::VARIANTS.contains(&command)
+            {
+                return Err(format!("unknown UI command `{command}`"));
+            }
+        }
+        let seconds = seconds
+            .trim()
+            .parse()
+            .map_err(|_| format!("expected a timeout in seconds, got `{value}`"))?;
+        Ok(CommandTimeout {
+            command: command.map(str::to_owned),
+            seconds,
+        })
+    }
+}
+
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long `command` waits for Neovim's answer. The last timeout naming the command wins over the
+/// last one without a name, `None` waits forever.
+fn command_timeout(timeouts: &[CommandTimeout], command: &str) -> Option<Duration> {
+    let named = timeouts
+        .iter()
+        .rev()
+        .find(|timeout| timeout.command.as_deref() == Some(command));
+    let timeout = named.or_else(|| {
+        timeouts
+            .iter()
+            .rev()
+            .find(|timeout| timeout.command.is_none())
+    });
+    match timeout.map(|timeout| timeout.seconds) {
+        None => Some(DEFAULT_COMMAND_TIMEOUT),
+        Some(0) => None,
+        Some(seconds) => Some(Duration::from_secs(seconds)),
+    }
+}
+
+/// Abandons `execution` of `command` when Neovim doesn't answer within `limit`.
+///
+/// Only the wait for the answer is timed, from when the request was written out. A request is
+/// never cut off halfway, that would corrupt the stream. For serial commands the next command is
+/// started right away. Neovim handles the requests in the order they arrive, the abandoned one
+/// included, so the input stays in order, only the answer of the abandoned command is ignored.
+async fn with_timeout(
+    command: &str,
+    limit: Option<Duration>,
+    execution: impl Future<Output = Result<()>>,
+) -> Result<()> {
+    let Some(limit) = limit else {
+        return execution.await;
+    };
+    let mut execution = pin!(execution);
+    let mut progress = WriteProgress::default();
+    let mut deadline = None;
+    poll_fn(|cx| {
+        let poll = poll_tracking_writes(&mut progress, || execution.as_mut().poll(cx));
+        if poll.is_ready() {
+            return poll;
+        }
+        if deadline.is_none() && progress != WriteProgress::Written {
+            return Poll::Pending;
+        }
+        let deadline = deadline.get_or_insert_with(|| Box::pin(sleep(limit)));
+        if deadline.as_mut().poll(cx).is_ready() && progress != WriteProgress::Writing {
+            return Poll::Ready(Err(anyhow!(
+                "{command} timed out after {}s and was abandoned",
+                limit.as_secs_f32()
+            )));
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+fn report_result(reporter: &Mutex<RpcErrorReporter>, command: &str, result: Result<()>) {
+    if let Err(error) = result {
+        reporter.lock().report(command, &error, Instant::now());
+    }
+}
+
 static UI_COMMAND_CHANNEL: Lazy<RwLock<Option<LoggingSender<UiCommand>>>> =
     Lazy::new(|| RwLock::new(None));
-static CURRENT_NVIM: Lazy<Arc<RwLock<Option<Neovim<NeovimWriter>>>>> =
-    Lazy::new(|| Arc::new(RwLock::new(None)));
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+static CURRENT_GRID_SIZE: RwLock<Option<GridSize<u32>>> = RwLock::new(None);
+/// The Neovim instance that receives the UI commands, and the server it's connected to.
+#[derive(Default)]
+pub struct CurrentNvim {
+    nvim: Option<Neovim<NeovimWriter>>,
+    address: Option<String>,
+}
+
+impl CurrentNvim {
+    /// `address` is the server `nvim` is connected to, `None` for an embedded Neovim.
+    pub fn update(&mut self, nvim: Option<Neovim<NeovimWriter>>, address: Option<&str>) {
+        let address = nvim.as_ref().and(address).map(str::to_owned);
+        if nvim.is_some() {
+            log::debug!(
+                "Updated current Neovim handle, connected to {}",
+                address.as_deref().unwrap_or("embedded")
+            );
+        } else {
+            log::debug!("Cleared current Neovim handle");
+        }
+        self.nvim = nvim;
+        self.address = address;
+    }
+
+    pub fn nvim(&self) -> Option<Neovim<NeovimWriter>> {
+        self.nvim.clone()
+    }
 
-pub fn update_current_nvim(nvim: Option<Neovim<NeovimWriter>>) {
-    if nvim.is_some() {
-        log::debug!("Updated current Neovim handle");
-    } else {
-        log::debug!("Cleared current Neovim handle");
+    /// `None` when it's embedded or there is none.
+    pub fn address(&self) -> Option<&str> {
+        self.address.as_deref()
     }
-    *CURRENT_NVIM.write() = nvim;
 }
 
-pub fn start_ui_command_handler(nvim: Neovim<NeovimWriter>, settings: Arc<Settings>) {
+pub static CURRENT_NVIM: Lazy<Arc<RwLock<CurrentNvim>>> = Lazy::new(Arc::default);
+
+/// Sets the Neovim instance that receives the UI commands, `address` is the server it's connected
+/// to, `None` for an embedded Neovim.
+pub fn update_current_nvim(nvim: Option<Neovim<NeovimWriter>>, address: Option<&str>) {
+    CURRENT_NVIM.write().update(nvim, address);
+}
+
+/// The server address of the current Neovim instance, `None` when it's embedded or there is none.
+pub fn current_address() -> Option<String> {
+    CURRENT_NVIM.read().address().map(str::to_owned)
+}
+
+/// Records the exit code of `ParallelCommand::QuitNeovide`, and returns the command that quits the
+/// way closing the window does.
+fn quit_neovide(running_tracker: &RunningTracker, code: u8) -> ParallelCommand {
+    running_tracker.set_quit_reason(QuitReason::PluginRequested);
+    running_tracker.quit_with_code(code, "requested by plugin");
+    ParallelCommand::Quit
+}
+
+pub fn start_ui_command_handler(
+    nvim: Neovim<NeovimWriter>,
+    settings: Arc<Settings>,
+    running_tracker: RunningTracker,
+    error_reporter: RpcErrorReporter,
+) {
     log::debug!("Starting UI command handler");
-    update_current_nvim(Some(nvim.clone()));
+    // A server connection sets its address when the session is registered
+    update_current_nvim(Some(nvim.clone()), None);
 
     let mut sender_guard = UI_COMMAND_CHANNEL.write();
     if sender_guard.is_some() {
@@ -296,13 +707,19 @@ pub fn start_ui_command_handler(nvim: Neovim<NeovimWriter>, settings: Arc<Settin
         return;
     }
 
-    let (serial_tx, mut serial_rx) = unbounded_channel::<SerialCommand>();
+    let (serial_tx, serial_rx) = unbounded_channel::<SerialCommand>();
     let (sender, mut ui_command_receiver) = unbounded_channel();
     *sender_guard = Some(LoggingSender::attach(sender, "UIComand"));
     drop(sender_guard);
 
+    let error_reporter = Arc::new(Mutex::new(error_reporter));
+    let parallel_reporter = error_reporter.clone();
+    let timeouts = settings.get::<CmdLineSettings>().ui_command_timeout;
+
     let nvim_holder = CURRENT_NVIM.clone();
+    let parallel_timeouts = timeouts.clone();
     tokio::spawn(async move {
+        let timeouts = parallel_timeouts;
         loop {
             match ui_command_receiver.recv().await {
                 Some(UiCommand::Serial(serial_command)) => {
@@ -311,11 +728,24 @@ pub fn start_ui_command_handler(nvim: Neovim<NeovimWriter>, settings: Arc<Settin
                 }
                 Some(UiCommand::Parallel(parallel_command)) => {
                     tracy_dynamic_zone!(parallel_command.as_ref());
-                    let nvim_opt = { nvim_holder.read().clone() };
+                    let Some(parallel_command) = parallel_command.run_without_neovim() else {
+                        continue;
+                    };
+                    let nvim_opt = { nvim_holder.read().nvim() };
                     if let Some(nvim) = nvim_opt {
                         let settings = settings.clone();
+                        let running_tracker = running_tracker.clone();
+                        let reporter = parallel_reporter.clone();
+                        let limit = command_timeout(&timeouts, parallel_command.as_ref());
                         tokio::spawn(async move {
-                            parallel_command.execute(&nvim, settings.as_ref()).await;
+                            let command = parallel_command.as_ref().to_owned();
+                            let execution = parallel_command.execute(
+                                &nvim,
+                                settings.as_ref(),
+                                &running_tracker,
+                            );
+                            let result = with_timeout(&command, limit, execution).await;
+                            report_result(&reporter, &command, result);
                         });
                     }
                 }
@@ -325,28 +755,75 @@ pub fn start_ui_command_handler(nvim: Neovim<NeovimWriter>, settings: Arc<Settin
         log::info!("ui command receiver finished");
     });
 
-    let nvim_holder = CURRENT_NVIM.clone();
-    tokio::spawn(async move {
+    tokio::spawn(run_serial_commands(
+        serial_rx,
+        CURRENT_NVIM.clone(),
+        timeouts,
+        error_reporter,
+    ));
+}
+
+/// Executes the serial commands one after the other, on the current Neovim instance.
+async fn run_serial_commands(
+    mut serial_rx: UnboundedReceiver<SerialCommand>,
+    nvim_holder: Arc<RwLock<CurrentNvim>>,
+    timeouts: Vec<CommandTimeout>,
+    error_reporter: Arc<Mutex<RpcErrorReporter>>,
+) {
+    tracy_fiber_enter!("Serial command");
+    loop {
+        tracy_fiber_leave();
+        let res = serial_rx.recv().await;
         tracy_fiber_enter!("Serial command");
-        loop {
-            tracy_fiber_leave();
-            let res = serial_rx.recv().await;
-            tracy_fiber_enter!("Serial command");
-            match res {
-                Some(serial_command) => {
-                    let nvim_opt = { nvim_holder.read().clone() };
-                    if let Some(nvim) = nvim_opt {
-                        tracy_dynamic_zone!(serial_command.as_ref());
-                        tracy_fiber_leave();
-                        serial_command.execute(&nvim).await;
-                        tracy_fiber_enter!("Serial command");
-                    }
+        match res {
+            Some(serial_command) => {
+                let nvim_opt = { nvim_holder.read().nvim() };
+                if let Some(nvim) = nvim_opt {
+                    tracy_dynamic_zone!(serial_command.as_ref());
+                    tracy_fiber_leave();
+                    let command = serial_command.as_ref().to_owned();
+                    let limit = command_timeout(&timeouts, &command);
+                    let execution = serial_command.execute(&nvim);
+                    let result = with_timeout(&command, limit, execution).await;
+                    report_result(&error_reporter, &command, result);
+                    tracy_fiber_enter!("Serial command");
                 }
-                None => break,
             }
+            None => break,
         }
-        log::info!("serial command receiver finished");
-    });
+    }
+    log::info!("serial command receiver finished");
+}
+
+fn set_current_grid_size(grid_size: GridSize<u32>) {
+    *CURRENT_GRID_SIZE.write() = Some(grid_size);
+}
+
+/// The grid size last requested from Neovim, which is also used when reconnecting.
+pub fn current_grid_size() -> Option<GridSize<u32>> {
+    *CURRENT_GRID_SIZE.read()
+}
+
+/// Enables or disables the read-only mode and lets the window know when it changed.
+pub fn set_read_only(proxy: &impl UserEventSender, read_only: bool) {
+    if READ_ONLY.swap(read_only, Ordering::Relaxed) == read_only {
+        return;
+    }
+    log::info!(
+        "Read-only mode {}",
+        if read_only { "enabled" } else { "disabled" }
+    );
+    proxy
+        .send_user_event(UserEvent::ReadOnlyChanged(read_only))
+        .ok();
+}
+
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+fn is_blocked(command: &UiCommand, read_only: bool) -> bool {
+    read_only && command.is_input()
 }
 
 pub fn send_ui<T>(command: T)
@@ -354,7 +831,415 @@ where
     T: Into<UiCommand>,
 {
     let command: UiCommand = command.into();
+    if is_blocked(&command, is_read_only()) {
+        // Unlike a key press, sending input on purpose shouldn't fail silently
+        if matches!(command, UiCommand::Serial(SerialCommand::SendRawInput(_))) {
+            warn!("Dropped {} in read-only mode", command.as_ref());
+        } else {
+            trace!("Dropped {} in read-only mode", command.as_ref());
+        }
+        return;
+    }
     if let Some(sender) = UI_COMMAND_CHANNEL.read().as_ref() {
+        record_ui_command(&command);
         let _ = sender.send(command);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Sender};
+
+    use anyhow::anyhow;
+    use serial_test::serial;
+    use winit::event_loop::EventLoopClosed;
+
+    use super::*;
+    #[cfg(unix)]
+    use crate::bridge::fake_server::{stalling_socket_pair_session, Stall};
+    use crate::{bridge::recording_nvim::recording_nvim, settings::MIN_GRID_SIZE};
+
+    struct ChannelSender(Sender<UserEvent>);
+
+    impl UserEventSender for ChannelSender {
+        fn send_user_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+            self.0.send(event).map_err(|error| EventLoopClosed(error.0))
+        }
+    }
+
+    #[serial]
+    #[test]
+    fn test_reconnect_commands_run_without_neovim() {
+        use crate::bridge::reconnect_pause::is_reconnect_paused;
+
+        assert!(ParallelCommand::PauseReconnect
+            .run_without_neovim()
+            .is_none());
+        assert!(is_reconnect_paused());
+        assert!(ParallelCommand::ResumeReconnect
+            .run_without_neovim()
+            .is_none());
+        assert!(!is_reconnect_paused());
+        assert!(ParallelCommand::ReconnectNow.run_without_neovim().is_none());
+        assert!(matches!(
+            ParallelCommand::Quit.run_without_neovim(),
+            Some(ParallelCommand::Quit)
+        ));
+    }
+
+    #[test]
+    fn test_quit_neovide_sets_the_exit_code_and_quits() {
+        let running_tracker = RunningTracker::new();
+        let next = quit_neovide(&running_tracker, 42);
+        assert!(matches!(next, ParallelCommand::Quit));
+        assert_eq!(running_tracker.exit_code(), 42);
+        assert_eq!(
+            running_tracker.quit_reason(),
+            Some(QuitReason::PluginRequested)
+        );
+
+        // Neovim quitting afterwards doesn't replace the reason
+        running_tracker.set_quit_reason(QuitReason::NvimRequested);
+        assert_eq!(
+            running_tracker.quit_reason(),
+            Some(QuitReason::PluginRequested)
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stalled_command_does_not_wedge_the_queue() {
+        let (session, _server) = stalling_socket_pair_session(Some(Stall::Input("stalled"))).await;
+        let nvim_holder = Arc::new(RwLock::new(CurrentNvim::default()));
+        nvim_holder
+            .write()
+            .update(Some(session.neovim.clone()), None);
+        let (events_tx, events) = channel();
+        let reporter = Arc::new(Mutex::new(RpcErrorReporter::new(ChannelSender(events_tx))));
+
+        let (serial_tx, serial_rx) = unbounded_channel();
+        serial_tx
+            .send(SerialCommand::Keyboard("stalled".to_owned()))
+            .unwrap();
+        serial_tx
+            .send(SerialCommand::MouseButton {
+                button: "left".to_owned(),
+                action: "press".to_owned(),
+                grid_id: 1,
+                position: (0, 0),
+                modifier_string: "".to_owned(),
+            })
+            .unwrap();
+        drop(serial_tx);
+
+        let timeouts = vec![CommandTimeout::parse("Keyboard=1").unwrap()];
+        let commands = run_serial_commands(serial_rx, nvim_holder, timeouts, reporter);
+        tokio::time::timeout(Duration::from_secs(10), commands)
+            .await
+            .expect("The queue is wedged");
+
+        // Only the stalled command failed, the mouse button after it was answered
+        let errors: Vec<_> = events
+            .try_iter()
+            .map(|event| match event {
+                UserEvent::RpcError { command, message } => (command, message),
+                _ => panic!("Unexpected event"),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![(
+                "Keyboard".to_owned(),
+                "Keyboard timed out after 1s and was abandoned".to_owned()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_command_is_not_timed_before_it_is_written() {
+        // Waiting for the writer, for example behind a request that Neovim doesn't read
+        let unwritten = with_timeout(
+            "Keyboard",
+            Some(Duration::from_millis(1)),
+            std::future::pending(),
+        );
+        assert!(tokio::time::timeout(Duration::from_millis(50), unwritten)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_command_timeouts() {
+        let timeouts: Vec<_> = ["10", "FileDrop=120", "Keyboard=0"]
+            .into_iter()
+            .map(|timeout| CommandTimeout::parse(timeout).unwrap())
+            .collect();
+        assert_eq!(
+            command_timeout(&timeouts, "FileDrop"),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(command_timeout(&timeouts, "Keyboard"), None);
+        assert_eq!(
+            command_timeout(&timeouts, "Resize"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            command_timeout(&[], "Resize"),
+            Some(DEFAULT_COMMAND_TIMEOUT)
+        );
+
+        assert!(CommandTimeout::parse("Keybaord=5").is_err());
+        assert!(CommandTimeout::parse("Keyboard=soon").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_without_timeout_commands_finish() {
+        let result = with_timeout("Resize", None, async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(())
+        })
+        .await;
+        assert!(result.is_ok());
+    }
+
+    #[serial]
+    #[test]
+    fn test_read_only_changes_are_sent_to_the_window() {
+        let (sender, events) = channel();
+        let proxy = ChannelSender(sender);
+
+        set_read_only(&proxy, true);
+        set_read_only(&proxy, true);
+        assert!(is_read_only());
+        set_read_only(&proxy, false);
+
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                UserEvent::ReadOnlyChanged(true),
+                UserEvent::ReadOnlyChanged(false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_only_drops_input() {
+        let keyboard = UiCommand::from(SerialCommand::Keyboard("i".to_string()));
+        let file_drop = UiCommand::from(ParallelCommand::FileDrop("file.txt".to_string()));
+        let resize = UiCommand::from(ParallelCommand::Resize {
+            width: 80,
+            height: 24,
+        });
+
+        assert!(is_blocked(&keyboard, true));
+        assert!(is_blocked(
+            &UiCommand::from(SerialCommand::SendRawInput("<Esc>".to_string())),
+            true
+        ));
+        assert!(is_blocked(&file_drop, true));
+        assert!(!is_blocked(&resize, true));
+        assert!(!is_blocked(&UiCommand::from(ParallelCommand::Quit), true));
+        assert!(!is_blocked(&keyboard, false));
+        let grid_resize = UiCommand::from(SerialCommand::Resize {
+            width: 80,
+            height: 24,
+        });
+        assert!(!is_blocked(&grid_resize, true));
+    }
+
+    #[tokio::test]
+    async fn test_raw_input_is_sent_as_is() {
+        let (nvim, requests) = recording_nvim();
+        // Fails to decode the nil answer instead of the number of bytes written, after sending
+        let _ = SerialCommand::SendRawInput("<Esc>iHello<Esc>".to_string())
+            .execute(&nvim)
+            .await;
+        assert_eq!(
+            *requests.lock(),
+            vec![(
+                "nvim_input".to_string(),
+                Value::Array(vec![Value::from("<Esc>iHello<Esc>")])
+            )]
+        );
+    }
+
+    #[test]
+    fn test_grid_resize_is_clamped() {
+        assert_eq!(clamped_resize(120, 40), GridSize::new(120, 40));
+        assert_eq!(clamped_resize(1, 2), MIN_GRID_SIZE);
+        assert_eq!(
+            clamped_resize(u64::MAX, 100_000),
+            GridSize::new(10000, 1000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resize_command_requests_the_clamped_size() {
+        let (nvim, requests) = recording_nvim();
+        SerialCommand::Resize {
+            width: 1,
+            height: 2,
+        }
+        .execute(&nvim)
+        .await
+        .unwrap();
+        assert_eq!(
+            *requests.lock(),
+            vec![(
+                "nvim_ui_try_resize".to_string(),
+                Value::Array(vec![
+                    Value::from(MIN_GRID_SIZE.width),
+                    Value::from(MIN_GRID_SIZE.height)
+                ])
+            )]
+        );
+        // Kept for the next connection, and for `neovide.grid_size()`
+        assert_eq!(current_grid_size(), Some(MIN_GRID_SIZE));
+    }
+
+    #[test]
+    fn test_failing_command_sends_rpc_error() {
+        let (sender, receiver) = channel();
+        let reporter = Mutex::new(RpcErrorReporter::new(ChannelSender(sender)));
+        let result = Err(anyhow!("Invalid buffer id").context("Resize failed"));
+        report_result(&reporter, "Resize", result);
+
+        match receiver.try_recv() {
+            Ok(UserEvent::RpcError { command, message }) => {
+                assert_eq!(command, "Resize");
+                assert_eq!(message, "Resize failed: Invalid buffer id");
+            }
+            _ => panic!("Expected an RpcError event"),
+        }
+        report_result(&reporter, "Resize", Ok(()));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_rpc_errors_are_rate_limited_per_command() {
+        let (sender, receiver) = channel();
+        let mut reporter = RpcErrorReporter::new(ChannelSender(sender));
+        let start = Instant::now();
+        let error = anyhow!("failed");
+
+        reporter.report("Keyboard", &error, start);
+        reporter.report("Keyboard", &error, start + Duration::from_secs(1));
+        reporter.report("Drag", &error, start + Duration::from_secs(1));
+        reporter.report("Keyboard", &error, start + RPC_ERROR_INTERVAL);
+
+        let commands = receiver
+            .try_iter()
+            .map(|event| match event {
+                UserEvent::RpcError { command, .. } => command,
+                _ => panic!("Expected an RpcError event"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["Keyboard", "Drag", "Keyboard"]);
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_quit_detaches_in_read_only_mode() {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server = Some("localhost:6666".to_owned());
+        settings.set(&cmdline_settings);
+        let (nvim, requests) = recording_nvim();
+        let running_tracker = RunningTracker::new();
+        let (sender, _events) = channel();
+        let proxy = ChannelSender(sender);
+
+        set_read_only(&proxy, true);
+        let result = ParallelCommand::Quit
+            .execute(&nvim, &settings, &running_tracker)
+            .await;
+        set_read_only(&proxy, false);
+        result.unwrap();
+
+        let requests = requests.lock();
+        let (method, args) = &requests[0];
+        assert_eq!(method, "nvim_exec_lua");
+        let args = args.as_array().unwrap();
+        assert!(args[0].as_str().unwrap().contains("chanclose"));
+        assert_eq!(
+            args[1],
+            Value::Array(vec![Value::Boolean(true), Value::Boolean(true)])
+        );
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_quit_with_server_fd_is_remote() {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.server_fd = Some(3);
+        settings.set(&cmdline_settings);
+        let (nvim, requests) = recording_nvim();
+
+        ParallelCommand::Quit
+            .execute(&nvim, &settings, &RunningTracker::new())
+            .await
+            .unwrap();
+
+        let requests = requests.lock();
+        let (method, args) = &requests[0];
+        assert_eq!(method, "nvim_exec_lua");
+        // Detaching is offered, instead of quitting the Neovim on the other end of the socket
+        assert_eq!(
+            args.as_array().unwrap()[1],
+            Value::Array(vec![Value::Boolean(true), Value::Boolean(false)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quit_neovide_is_executed_like_any_parallel_command() {
+        let settings = Settings::new();
+        settings.set(&CmdLineSettings::default());
+        let (nvim, requests) = recording_nvim();
+        let running_tracker = RunningTracker::new();
+
+        ParallelCommand::QuitNeovide { code: 7 }
+            .execute(&nvim, &settings, &running_tracker)
+            .await
+            .unwrap();
+        assert_eq!(running_tracker.exit_code(), 7);
+        assert_eq!(
+            running_tracker.quit_reason(),
+            Some(QuitReason::PluginRequested)
+        );
+        // The quit itself is sent on as a command of its own
+        assert!(requests.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_set_multigrid_reattaches_with_new_options() {
+        let settings = Settings::new();
+        settings.set(&CmdLineSettings::default());
+        let (nvim, requests) = recording_nvim();
+
+        ParallelCommand::SetMultigrid(false)
+            .execute(&nvim, &settings, &RunningTracker::new())
+            .await
+            .unwrap();
+
+        let requests = requests.lock().clone();
+        let methods = requests
+            .iter()
+            .map(|(method, _)| method.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            vec!["nvim_ui_detach", "nvim_exec_lua", "nvim_ui_attach"]
+        );
+        let options = requests[2].1.as_array().unwrap()[2].as_map().unwrap();
+        let option = |name: &str| {
+            options
+                .iter()
+                .find(|(key, _)| key.as_str() == Some(name))
+                .and_then(|(_, value)| value.as_bool())
+        };
+        assert_eq!(option("ext_multigrid"), Some(false));
+        assert_eq!(option("ext_linegrid"), Some(true));
+        assert!(settings.get::<CmdLineSettings>().no_multi_grid);
+    }
+}
@@ -0,0 +1,92 @@
+//! The delays between the attempts of the version check, which some servers reject for a moment
+//! while they're still initializing. Starting short and backing off gets through quickly once the
+//! server is ready, without hammering one that needs a bit longer.
+
+use std::time::Duration;
+
+use crate::cmd_line::CmdLineSettings;
+
+/// How often the version is checked before giving up.
+pub const VERSION_CHECK_ATTEMPTS: u32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionCheckBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub jitter: bool,
+}
+
+impl VersionCheckBackoff {
+    /// Retries right away, for tests.
+    #[cfg(test)]
+    pub const NONE: Self = Self {
+        initial: Duration::ZERO,
+        max: Duration::ZERO,
+        jitter: false,
+    };
+
+    pub fn from_settings(cmdline_settings: &CmdLineSettings) -> Self {
+        let initial = Duration::from_millis(cmdline_settings.version_check_delay);
+        Self {
+            initial,
+            max: Duration::from_millis(cmdline_settings.version_check_max_delay).max(initial),
+            jitter: cmdline_settings.version_check_jitter,
+        }
+    }
+
+    /// The delay after the failed attempt `attempt`, counting from 0. It doubles from `initial`
+    /// up to `max`, and with jitter `random`, between 0 and 1, picks a delay between half of that
+    /// and all of it.
+    pub fn delay(&self, attempt: u32, random: f64) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max);
+        if self.jitter {
+            delay.mul_f64(0.5 + 0.5 * random.clamp(0.0, 1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays_double_up_to_the_cap() {
+        let backoff = VersionCheckBackoff {
+            initial: Duration::from_millis(250),
+            max: Duration::from_millis(1000),
+            jitter: false,
+        };
+        let delays: Vec<_> = (0..VERSION_CHECK_ATTEMPTS)
+            .map(|attempt| backoff.delay(attempt, 0.0).as_millis())
+            .collect();
+        assert_eq!(delays, [250, 500, 1000, 1000, 1000]);
+        assert_eq!(backoff.delay(u32::MAX, 0.0), backoff.max);
+    }
+
+    #[test]
+    fn test_jittered_delays_stay_within_bounds() {
+        let backoff = VersionCheckBackoff {
+            initial: Duration::from_millis(250),
+            max: Duration::from_millis(1000),
+            jitter: true,
+        };
+        for attempt in 0..VERSION_CHECK_ATTEMPTS {
+            let full = backoff.delay(attempt, 1.0);
+            assert!(full <= backoff.max);
+            for random in [0.0, 0.3, 0.999] {
+                let delay = backoff.delay(attempt, random);
+                assert!(delay >= full / 2 && delay <= full, "{delay:?}");
+            }
+        }
+        // The whole check is bounded by the attempts even with the largest delays
+        let total: Duration = (0..VERSION_CHECK_ATTEMPTS)
+            .map(|attempt| backoff.delay(attempt, 1.0))
+            .sum();
+        assert!(total <= backoff.max * VERSION_CHECK_ATTEMPTS);
+    }
+}
@@ -0,0 +1,260 @@
+//! `--restore-session-on-reconnect`, which remembers the working directory and the open files with
+//! their cursor positions while connected to a server, and opens them again after reconnecting to
+//! a server that started fresh. Once the connection is lost it can't be asked anymore, so the
+//! state is captured with a ping every `VIEW_STATE_INTERVAL`, and restoring uses the last capture
+//! that succeeded.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use nvim_rs::{Neovim, Value};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::time::timeout;
+
+use super::NeovimWriter;
+
+/// How long capturing or restoring may take, the state is skipped when the server is too slow.
+const VIEW_STATE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the state is captured at most, it runs Lua on the server that goes through every
+/// buffer.
+const VIEW_STATE_INTERVAL: Duration = Duration::from_secs(30);
+
+const CAPTURE_LUA: &str = "
+local current = vim.api.nvim_get_current_buf()
+local files = {}
+for _, buf in ipairs(vim.api.nvim_list_bufs()) do
+  local name = vim.api.nvim_buf_get_name(buf)
+  if vim.bo[buf].buflisted and vim.bo[buf].buftype == '' and name ~= '' then
+    local pos = buf == current and vim.api.nvim_win_get_cursor(0)
+      or vim.api.nvim_buf_get_mark(buf, '\"')
+    table.insert(files, { name, pos[1], pos[2], buf == current })
+  end
+end
+return { vim.fn.getcwd(), files }
+";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenFile {
+    pub path: String,
+    /// 1-based, 0 when the file was never visited
+    pub line: u64,
+    /// 0-based byte column
+    pub column: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ViewState {
+    pub cwd: String,
+    pub files: Vec<OpenFile>,
+    /// The index of the file in the current window
+    pub current: Option<usize>,
+}
+
+impl ViewState {
+    fn parse(value: &Value) -> Result<Self> {
+        let files = match &value[1] {
+            Value::Array(files) => files.as_slice(),
+            // An empty Lua table can't be told apart from an empty map
+            Value::Map(map) if map.is_empty() => &[],
+            _ => bail!("Unexpected view state {value}"),
+        };
+        let Some(cwd) = value[0].as_str() else {
+            bail!("Unexpected view state {value}");
+        };
+        let mut state = ViewState {
+            cwd: cwd.to_owned(),
+            ..ViewState::default()
+        };
+        for file in files {
+            let (Some(path), Some(line), Some(column)) =
+                (file[0].as_str(), file[1].as_u64(), file[2].as_u64())
+            else {
+                bail!("Unexpected open file {file}");
+            };
+            if file[3].as_bool() == Some(true) {
+                state.current = Some(state.files.len());
+            }
+            state.files.push(OpenFile {
+                path: path.to_owned(),
+                line,
+                column,
+            });
+        }
+        Ok(state)
+    }
+
+    /// The Ex commands that open the files again, the current one last so that it ends up in the
+    /// window.
+    pub fn restore_commands(&self) -> Vec<String> {
+        let mut commands = vec![format!(
+            "execute 'cd ' . fnameescape({})",
+            vim_string(&self.cwd)
+        )];
+        let mut current = None;
+        for (index, file) in self.files.iter().enumerate() {
+            // A newline can't be passed through an Ex command
+            if file.path.contains('\n') {
+                continue;
+            }
+            if self.current == Some(index) {
+                current = Some(file);
+            } else {
+                commands.push(format!(
+                    "execute 'badd +{} ' . fnameescape({})",
+                    file.line.max(1),
+                    vim_string(&file.path)
+                ));
+            }
+        }
+        if let Some(file) = current {
+            commands.push(format!(
+                "execute 'edit ' . fnameescape({})",
+                vim_string(&file.path)
+            ));
+            commands.push(format!(
+                "call cursor({}, {})",
+                file.line.max(1),
+                file.column + 1
+            ));
+        }
+        commands
+    }
+}
+
+/// `text` as a single quoted Vim string.
+fn vim_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// When the state of a connection was captured last, to keep to the `VIEW_STATE_INTERVAL`.
+#[derive(Default)]
+pub struct CaptureInterval(Option<Instant>);
+
+impl CaptureInterval {
+    /// Whether to capture again `now`, which then counts as the last capture.
+    pub fn is_due(&mut self, now: Instant) -> bool {
+        let due = self.0.is_none_or(|captured_at| {
+            now.saturating_duration_since(captured_at) >= VIEW_STATE_INTERVAL
+        });
+        if due {
+            self.0 = Some(now);
+        }
+        due
+    }
+}
+
+/// The last captured state, with the address of its server.
+static VIEW_STATE: Lazy<Mutex<Option<(String, ViewState)>>> = Lazy::new(|| Mutex::new(None));
+
+async fn capture(nvim: &Neovim<NeovimWriter>) -> Result<ViewState> {
+    let value = timeout(VIEW_STATE_TIMEOUT, nvim.exec_lua(CAPTURE_LUA, vec![])).await??;
+    ViewState::parse(&value)
+}
+
+/// Remembers the state of the server at `address`, keeping the previous capture when this one
+/// fails.
+pub async fn capture_view_state(nvim: &Neovim<NeovimWriter>, address: &str) {
+    match capture(nvim).await {
+        Ok(state) => *VIEW_STATE.lock() = Some((address.to_owned(), state)),
+        Err(error) => log::debug!("Could not capture the view state of {address}: {error:#}"),
+    }
+}
+
+/// Opens the files captured for `address` again, unless the server still has files open itself,
+/// which means it kept its state over the disconnect.
+pub async fn restore_view_state(nvim: &Neovim<NeovimWriter>, address: &str) {
+    let captured = match VIEW_STATE.lock().as_ref() {
+        Some((captured_address, state)) if captured_address == address => state.clone(),
+        _ => {
+            log::debug!("No view state of {address} was captured, nothing to restore");
+            return;
+        }
+    };
+    match capture(nvim).await {
+        Ok(current) if current.files.is_empty() => {}
+        Ok(_) => {
+            log::debug!("{address} kept its open files, not restoring the view state");
+            return;
+        }
+        Err(error) => {
+            log::debug!("Could not check the view state of {address}: {error:#}");
+            return;
+        }
+    }
+    log::info!("Restoring {} open files of {address}", captured.files.len());
+    for command in captured.restore_commands() {
+        match timeout(VIEW_STATE_TIMEOUT, nvim.command(&command)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => log::warn!("Restoring the view state with {command} failed: {error}"),
+            Err(_) => {
+                log::warn!("Restoring the view state timed out");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_interval() {
+        let mut interval = CaptureInterval::default();
+        let start = Instant::now();
+        assert!(interval.is_due(start));
+        assert!(!interval.is_due(start + Duration::from_secs(5)));
+        assert!(!interval.is_due(start + Duration::from_secs(29)));
+        assert!(interval.is_due(start + Duration::from_secs(30)));
+        assert!(!interval.is_due(start + Duration::from_secs(35)));
+    }
+
+    fn captured_value() -> Value {
+        let file = |name: &str, line: u64, column: u64, current: bool| {
+            Value::from(vec![
+                Value::from(name),
+                line.into(),
+                column.into(),
+                current.into(),
+            ])
+        };
+        Value::from(vec![
+            Value::from("/home/me/it's a project"),
+            Value::from(vec![
+                file("/home/me/it's a project/README.md", 0, 0, false),
+                file("/home/me/it's a project/src/main.rs", 42, 7, true),
+                file("/home/me/it's a project/Cargo.toml", 3, 0, false),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn test_captured_state_restore_commands() {
+        let state = ViewState::parse(&captured_value()).unwrap();
+        assert_eq!(state.current, Some(1));
+        assert_eq!(
+            state.restore_commands(),
+            vec![
+                "execute 'cd ' . fnameescape('/home/me/it''s a project')",
+                "execute 'badd +1 ' . fnameescape('/home/me/it''s a project/README.md')",
+                "execute 'badd +3 ' . fnameescape('/home/me/it''s a project/Cargo.toml')",
+                "execute 'edit ' . fnameescape('/home/me/it''s a project/src/main.rs')",
+                "call cursor(42, 8)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nothing_open_only_restores_the_cwd() {
+        let state = ViewState {
+            cwd: "/tmp".to_owned(),
+            ..ViewState::default()
+        };
+        assert_eq!(
+            state.restore_commands(),
+            vec!["execute 'cd ' . fnameescape('/tmp')"]
+        );
+        assert!(ViewState::parse(&Value::from("unexpected")).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! The time source of the reconnect loop and the reconnect indicator, so that tests can go through
+//! a whole backoff cycle without waiting for it.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant, SystemTime},
+};
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// The wall-clock time, which unlike `now` keeps going while the system is suspended.
+    fn wall_clock(&self) -> SystemTime;
+
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// Like `sleep`, for how long to wait for other work, like an answer of Neovim. Unlike a
+    /// sleep, it doesn't advance a mock clock, it only ends once the clock was moved past it.
+    fn timeout(&self, duration: Duration) -> Sleep {
+        self.sleep(duration)
+    }
+}
+
+/// More wall-clock time than monotonic time passing is taken as a suspend, rather than the
+/// small adjustments of NTP.
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Notices a suspend since it was created, by comparing how much time passed on both clocks. The
+/// monotonic clock, and so every timer, stands still while the system is suspended.
+pub struct SuspendDetector {
+    started_at: Instant,
+    wall_started_at: SystemTime,
+}
+
+impl SuspendDetector {
+    pub fn new(clock: &dyn Clock) -> Self {
+        Self {
+            started_at: clock.now(),
+            wall_started_at: clock.wall_clock(),
+        }
+    }
+
+    /// How long the system was suspended since the start, `None` when it wasn't.
+    pub fn suspended_for(&self, clock: &dyn Clock) -> Option<Duration> {
+        let elapsed = clock.now().saturating_duration_since(self.started_at);
+        // A wall clock set backwards counts as no time passing
+        let wall_elapsed = clock
+            .wall_clock()
+            .duration_since(self.wall_started_at)
+            .unwrap_or_default();
+        let suspended = wall_elapsed.saturating_sub(elapsed);
+        (suspended > SUSPEND_THRESHOLD).then_some(suspended)
+    }
+}
+
+/// The clock used outside of tests, backed by `Instant` and the tokio timer.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_clock(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A clock that only moves when told to, sleeping advances it to the end of the sleep right away.
+#[cfg(test)]
+pub struct MockClock {
+    now: std::sync::Arc<parking_lot::Mutex<(Instant, SystemTime)>>,
+    advanced: std::sync::Arc<tokio::sync::Notify>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            now: std::sync::Arc::new(parking_lot::Mutex::new((Instant::now(), SystemTime::now()))),
+            advanced: std::sync::Arc::default(),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock();
+        now.0 += duration;
+        now.1 += duration;
+        drop(now);
+        self.advanced.notify_waiters();
+    }
+
+    /// Moves only the wall clock, like a suspend of `duration` does.
+    pub fn suspend(&self, duration: Duration) {
+        self.now.lock().1 += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.lock().0
+    }
+
+    fn wall_clock(&self) -> SystemTime {
+        self.now.lock().1
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self.advance(duration);
+        Box::pin(tokio::task::yield_now())
+    }
+
+    fn timeout(&self, duration: Duration) -> Sleep {
+        let end = self.now() + duration;
+        let now = self.now.clone();
+        let advanced = self.advanced.clone();
+        Box::pin(async move {
+            loop {
+                // Created before the check, so that an advance in between isn't missed
+                let notified = advanced.notified();
+                if now.lock().0 >= end {
+                    return;
+                }
+                notified.await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_clock_sleep_advances_time() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        clock.sleep(Duration::from_secs(30)).await;
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(clock.now() - start, Duration::from_millis(30_005));
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock_timeout_waits_for_the_clock() {
+        let clock = MockClock::new();
+        let timeout = tokio::spawn(clock.timeout(Duration::from_secs(2)));
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(1));
+        tokio::task::yield_now().await;
+        assert!(!timeout.is_finished());
+
+        clock.sleep(Duration::from_secs(1)).await;
+        tokio::time::timeout(Duration::from_secs(1), timeout)
+            .await
+            .expect("The timeout didn't end")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_suspend_is_detected() {
+        let clock = MockClock::new();
+        let detector = SuspendDetector::new(&clock);
+        clock.advance(Duration::from_secs(60));
+        clock.suspend(Duration::from_secs(2));
+        assert_eq!(detector.suspended_for(&clock), None);
+
+        clock.suspend(Duration::from_secs(60 * 60));
+        assert_eq!(
+            detector.suspended_for(&clock),
+            Some(Duration::from_secs(60 * 60 + 2))
+        );
+    }
+}
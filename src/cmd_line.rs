@@ -1,12 +1,25 @@
-use std::{iter, mem};
+use std::{
+    iter, mem,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
 
-use crate::{dimensions::Dimensions, frame::Frame, settings::*, utils::handle_wslpaths};
+use crate::{
+    bridge::{CommandTimeout, Preamble, Socks5Proxy},
+    dimensions::Dimensions,
+    frame::Frame,
+    settings::*,
+    utils::handle_wslpaths,
+    window::validate_title_template,
+};
 
 use anyhow::Result;
 use clap::{
     builder::{styling, FalseyValueParser, Styles},
     ArgAction, Parser,
 };
+use rmpv::Value;
+use serde::{Serialize, Serializer};
 use winit::window::CursorIcon;
 
 #[cfg(target_os = "windows")]
@@ -22,7 +35,7 @@ fn get_styles() -> Styles {
         .placeholder(styling::AnsiColor::Cyan.on_default())
 }
 
-#[derive(Clone, Debug, Parser)]
+#[derive(Clone, Debug, Parser, Serialize)]
 #[command(version, about, long_about = None, styles = get_styles())]
 pub struct CmdLineSettings {
     /// Files to open (usually plainly appended to NeoVim args, except when --wsl is used)
@@ -45,10 +58,447 @@ pub struct CmdLineSettings {
     #[arg(long = "log")]
     pub log_to_file: bool,
 
-    /// Connect to the named pipe or socket at ADDRESS
+    /// How many of the most recent log records to keep in memory
+    #[arg(
+        long = "log-ring-size",
+        env = "NEOVIDE_LOG_RING_SIZE",
+        value_name = "LINES",
+        default_value = "500"
+    )]
+    pub log_ring_size: usize,
+
+    /// Put LABEL in front of the connection log records, to tell the logs of several Neovides
+    /// apart
+    #[arg(
+        long = "instance-label",
+        env = "NEOVIDE_INSTANCE_LABEL",
+        value_name = "LABEL",
+        default_value = ""
+    )]
+    pub instance_label: String,
+
+    /// Connect to the named pipe or socket at ADDRESS, `auto` connects to the most recently used
+    /// server socket
     #[arg(long, alias = "remote-tcp", value_name = "ADDRESS")]
     pub server: Option<String>,
 
+    /// The directory that `--server auto` scans for server sockets
+    #[arg(
+        long = "server-discovery-dir",
+        env = "NEOVIDE_SERVER_DISCOVERY_DIR",
+        value_name = "DIR"
+    )]
+    pub server_discovery_dir: Option<String>,
+
+    /// Connect to the server at ADDRESS if it's running, otherwise start an embedded NeoVim
+    #[arg(
+        long = "server-or-embedded",
+        env = "NEOVIDE_SERVER_OR_EMBEDDED",
+        value_name = "ADDRESS",
+        conflicts_with = "server"
+    )]
+    pub server_or_embedded: Option<String>,
+
+    /// Connect to NeoVim through the inherited, already connected socket FD, or named pipe handle
+    /// on Windows, Neovide quits when it closes instead of reconnecting
+    #[arg(
+        long = "server-fd",
+        env = "NEOVIDE_SERVER_FD",
+        value_name = "FD",
+        conflicts_with_all = ["server", "server_or_embedded"]
+    )]
+    pub server_fd: Option<i32>,
+
+    /// Render Neovim without sending it any input
+    #[arg(
+        long = "read-only",
+        env = "NEOVIDE_READ_ONLY",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub read_only: bool,
+
+    /// Comma separated glob patterns of the addresses --server is allowed to connect to
+    #[arg(
+        long = "server-allowlist",
+        env = "NEOVIDE_SERVER_ALLOWLIST",
+        value_name = "PATTERNS"
+    )]
+    pub server_allowlist: Option<String>,
+
+    /// File with one glob pattern per line of the addresses --server is allowed to connect to
+    #[arg(
+        long = "server-allowlist-file",
+        env = "NEOVIDE_SERVER_ALLOWLIST_FILE",
+        value_name = "FILE"
+    )]
+    pub server_allowlist_file: Option<String>,
+
+    /// Timeout in milliseconds for the TCP probe done before each reconnect attempt, 0 disables
+    /// probing
+    #[arg(
+        long = "probe-timeout",
+        env = "NEOVIDE_PROBE_TIMEOUT",
+        value_name = "MS",
+        default_value = "500"
+    )]
+    pub probe_timeout: u64,
+
+    /// Before showing a --server as connected, ping it until a round trip takes less than this
+    /// many milliseconds, 0 skips the warmup
+    #[arg(
+        long = "connect-warmup",
+        env = "NEOVIDE_CONNECT_WARMUP",
+        value_name = "MS",
+        default_value = "0"
+    )]
+    pub connect_warmup: u64,
+
+    /// How many pings in a row have to be fast for the --connect-warmup, it gives up and uses the
+    /// connection anyway after three times as many
+    #[arg(
+        long = "connect-warmup-pings",
+        env = "NEOVIDE_CONNECT_WARMUP_PINGS",
+        value_name = "COUNT",
+        default_value = "5"
+    )]
+    pub connect_warmup_pings: u32,
+
+    /// Reconnect to the --server as soon as the network changes, instead of waiting for a ping to
+    /// time out
+    #[arg(
+        long = "reconnect-on-network-change",
+        env = "NEOVIDE_RECONNECT_ON_NETWORK_CHANGE",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub reconnect_on_network_change: bool,
+
+    /// Quit instead of reconnecting when the --server closes the connection itself, for example
+    /// because it quit
+    #[arg(
+        long = "no-reconnect-on-clean-close",
+        env = "NEOVIDE_NO_RECONNECT_ON_CLEAN_CLOSE",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub no_reconnect_on_clean_close: bool,
+
+    /// Reopen the files and the working directory of the --server after reconnecting to it, when
+    /// it started fresh
+    #[arg(
+        long = "restore-session-on-reconnect",
+        env = "NEOVIDE_RESTORE_SESSION_ON_RECONNECT",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub restore_session_on_reconnect: bool,
+
+    /// Run this command, with the --server address as its last argument, every time the
+    /// connection is made. Only read from the command line
+    #[arg(long = "on-connect-cmd", value_name = "COMMAND")]
+    pub on_connect_cmd: Option<String>,
+
+    /// Play this sound file when the connection to the --server is lost
+    #[arg(
+        long = "disconnect-sound",
+        env = "NEOVIDE_DISCONNECT_SOUND",
+        value_name = "PATH"
+    )]
+    pub disconnect_sound: Option<String>,
+
+    /// Play this sound file when the connection to the --server is made again
+    #[arg(
+        long = "reconnect-sound",
+        env = "NEOVIDE_RECONNECT_SOUND",
+        value_name = "PATH"
+    )]
+    pub reconnect_sound: Option<String>,
+
+    /// Attach to the --server with the grid size it already has, and size the window to match
+    #[arg(
+        long = "match-server-grid",
+        env = "NEOVIDE_MATCH_SERVER_GRID",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub match_server_grid: bool,
+
+    /// For how many seconds a reconnect to the same server may skip the full version check, 0
+    /// always does the full check
+    #[arg(
+        long = "api-info-cache-ttl",
+        env = "NEOVIDE_API_INFO_CACHE_TTL",
+        value_name = "SECONDS",
+        default_value = "60"
+    )]
+    pub api_info_cache_ttl: u64,
+
+    /// Milliseconds to wait after the first failed version check, doubling for every further one
+    #[arg(
+        long = "version-check-delay",
+        env = "NEOVIDE_VERSION_CHECK_DELAY",
+        value_name = "MS",
+        default_value = "250"
+    )]
+    pub version_check_delay: u64,
+
+    /// The longest wait between two version checks in milliseconds
+    #[arg(
+        long = "version-check-max-delay",
+        env = "NEOVIDE_VERSION_CHECK_MAX_DELAY",
+        value_name = "MS",
+        default_value = "1000"
+    )]
+    pub version_check_max_delay: u64,
+
+    /// Wait a random time between half and all of each delay between version checks
+    #[arg(
+        long = "version-check-jitter",
+        env = "NEOVIDE_VERSION_CHECK_JITTER",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub version_check_jitter: bool,
+
+    /// Don't check the NeoVim version, only ask it once for the channel of Neovide. Compatibility
+    /// isn't guaranteed
+    #[arg(
+        long = "skip-version-check",
+        env = "NEOVIDE_SKIP_VERSION_CHECK",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub skip_version_check: bool,
+
+    /// After this many failed reconnect attempts to --server, start an embedded NeoVim until
+    /// :NeovideSwitchToServer, 0 never does
+    #[arg(
+        long = "fallback-embedded-after",
+        env = "NEOVIDE_FALLBACK_EMBEDDED_AFTER",
+        value_name = "ATTEMPTS",
+        default_value = "0"
+    )]
+    pub fallback_embedded_after: u32,
+
+    /// Give up reconnecting to --server once it has been unreachable for this many seconds, and
+    /// quit with exit code 75, 0 reconnects forever
+    #[arg(
+        long = "reconnect-max-duration",
+        env = "NEOVIDE_RECONNECT_MAX_DURATION",
+        value_name = "SECONDS",
+        default_value = "0"
+    )]
+    pub reconnect_max_duration: u64,
+
+    /// Don't show the reconnect overlay until a dropped --server connection stayed lost for this
+    /// many milliseconds, 0 shows it right away
+    #[arg(
+        long = "disconnect-grace",
+        env = "NEOVIDE_DISCONNECT_GRACE",
+        value_name = "MS",
+        default_value = "0"
+    )]
+    pub disconnect_grace: u64,
+
+    /// Abort connecting to NeoVim when spawning, the version check, the setup and attaching take
+    /// longer than this many seconds together, 0 waits forever
+    #[arg(
+        long = "startup-deadline",
+        env = "NEOVIDE_STARTUP_DEADLINE",
+        value_name = "SECONDS",
+        default_value = "0"
+    )]
+    pub startup_deadline: u64,
+
+    /// How many seconds after starting to connect a session has to get its first redraw to be
+    /// announced as ready, 0 waits as long as the session lasts
+    #[arg(
+        long = "session-ready-timeout",
+        env = "NEOVIDE_SESSION_READY_TIMEOUT",
+        value_name = "SECONDS",
+        default_value = "30"
+    )]
+    pub session_ready_timeout: u64,
+
+    /// How many times to retry spawning the embedded NeoVim if launching it fails
+    #[arg(
+        long = "embedded-retries",
+        env = "NEOVIDE_EMBEDDED_RETRIES",
+        value_name = "COUNT",
+        default_value = "0"
+    )]
+    pub embedded_retries: u32,
+
+    /// Log a heartbeat with the connection status every this many seconds, 0 disables it
+    #[arg(
+        long = "heartbeat-interval",
+        env = "NEOVIDE_HEARTBEAT_INTERVAL",
+        value_name = "SECONDS",
+        default_value = "0"
+    )]
+    pub heartbeat_interval: u64,
+
+    /// Serve the connection status as JSON over HTTP on this port, 0 disables it
+    #[arg(
+        long = "status-port",
+        env = "NEOVIDE_STATUS_PORT",
+        value_name = "PORT",
+        default_value = "0"
+    )]
+    pub status_port: u16,
+
+    /// The address --status-port binds to, anything but localhost exposes the status to other
+    /// machines
+    #[arg(
+        long = "status-bind",
+        env = "NEOVIDE_STATUS_BIND",
+        value_name = "ADDRESS",
+        default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST)
+    )]
+    pub status_bind: IpAddr,
+
+    /// Log the full API information of NeoVim after every attach, which is very verbose
+    #[arg(long = "dump-api-info", env = "NEOVIDE_DUMP_API_INFO", value_parser = FalseyValueParser::new())]
+    pub dump_api_info: bool,
+
+    /// Write the settings Neovide starts with to this file and exit, JSON for a .json file,
+    /// otherwise TOML
+    #[arg(long = "dump-settings", value_name = "PATH")]
+    pub dump_settings: Option<PathBuf>,
+
+    /// Record every UI command sent to NeoVim to this file, with the time it was sent, for
+    /// debugging and replaying a session
+    #[arg(
+        long = "record-ui-commands",
+        env = "NEOVIDE_RECORD_UI_COMMANDS",
+        value_name = "PATH"
+    )]
+    pub record_ui_commands: Option<PathBuf>,
+
+    /// Replay the UI commands of a --record-ui-commands file, with the same timing, once the
+    /// first session is ready
+    #[arg(
+        long = "ui-commands-file",
+        env = "NEOVIDE_UI_COMMANDS_FILE",
+        value_name = "PATH"
+    )]
+    pub ui_commands_file: Option<PathBuf>,
+
+    /// Show the vim.notify messages of NeoVim as desktop notifications while Neovide isn't focused
+    #[arg(
+        long = "desktop-notifications",
+        env = "NEOVIDE_DESKTOP_NOTIFICATIONS",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub desktop_notifications: bool,
+
+    /// The lowest vim.notify level that is shown as a desktop notification
+    #[arg(
+        long = "desktop-notifications-level",
+        env = "NEOVIDE_DESKTOP_NOTIFICATIONS_LEVEL",
+        value_name = "LEVEL",
+        default_value = "warn"
+    )]
+    pub desktop_notifications_level: NotificationLevel,
+
+    /// Whether Neovide provides the clipboard to NeoVim, auto does when NeoVim runs on another
+    /// machine, in WSL, or behind ssh
+    #[arg(
+        long = "handle-clipboard",
+        env = "NEOVIDE_HANDLE_CLIPBOARD",
+        value_name = "MODE",
+        default_value = "auto"
+    )]
+    pub handle_clipboard: HandleClipboard,
+
+    /// How long to wait for the remaining output when NeoVim quits, bounded waits up to 500ms,
+    /// full waits until the stream is closed
+    #[arg(
+        long = "io-drain",
+        env = "NEOVIDE_IO_DRAIN",
+        value_name = "MODE",
+        default_value = "bounded"
+    )]
+    pub io_drain: IoDrain,
+
+    /// Seconds to wait for the embedded NeoVim to exit once it started to quit, from its
+    /// VimLeavePre, before terminating it, 0 waits forever
+    #[arg(
+        long = "quit-kill-timeout",
+        env = "NEOVIDE_QUIT_KILL_TIMEOUT",
+        value_name = "SECONDS",
+        default_value = "0"
+    )]
+    pub quit_kill_timeout: u64,
+
+    /// Milliseconds a terminated NeoVim has to exit before it's killed
+    #[arg(
+        long = "quit-term-timeout",
+        env = "NEOVIDE_QUIT_TERM_TIMEOUT",
+        value_name = "MS",
+        default_value = "2000"
+    )]
+    pub quit_term_timeout: u64,
+
+    /// The exit codes of the embedded NeoVim that count as a clean exit instead of a crash
+    #[arg(
+        long = "clean-exit-codes",
+        env = "NEOVIDE_CLEAN_EXIT_CODES",
+        value_name = "CODES",
+        value_delimiter = ',',
+        default_value = "0"
+    )]
+    pub clean_exit_codes: Vec<i32>,
+
+    /// Start a new embedded NeoVim when it crashes, that is exits with a code that isn't one of
+    /// --clean-exit-codes, instead of quitting
+    #[arg(
+        long = "restart-on-crash",
+        env = "NEOVIDE_RESTART_ON_CRASH",
+        value_parser = FalseyValueParser::new(),
+        conflicts_with = "hold"
+    )]
+    pub restart_on_crash: bool,
+
+    /// Keep the window open when the embedded NeoVim crashes, until it's closed
+    #[arg(long = "hold", env = "NEOVIDE_HOLD", value_parser = FalseyValueParser::new())]
+    pub hold: bool,
+
+    /// Seconds after which a UI command that NeoVim hasn't answered is abandoned, 30 by default and
+    /// 0 waits forever. COMMAND=SECONDS sets it for one command, like Keyboard=5, can be repeated
+    #[arg(
+        long = "ui-command-timeout",
+        env = "NEOVIDE_UI_COMMAND_TIMEOUT",
+        value_name = "[COMMAND=]SECONDS",
+        action = ArgAction::Append,
+        value_delimiter = ',',
+        value_parser = CommandTimeout::parse
+    )]
+    pub ui_command_timeout: Vec<CommandTimeout>,
+
+    /// Quit after this many seconds, whatever NeoVim is doing, 0 never quits
+    #[arg(
+        long = "quit-after-seconds",
+        env = "NEOVIDE_QUIT_AFTER_SECONDS",
+        value_name = "SECONDS",
+        default_value = "0"
+    )]
+    pub quit_after_seconds: u64,
+
+    /// Size in bytes of the buffer for reading from NeoVim, at least 1024
+    #[arg(
+        long = "io-read-buffer",
+        env = "NEOVIDE_IO_READ_BUFFER",
+        value_name = "BYTES",
+        default_value = "8192",
+        value_parser = parse_io_buffer_size
+    )]
+    pub io_read_buffer: usize,
+
+    /// Size in bytes of the buffer for writing to NeoVim, at least 1024
+    #[arg(
+        long = "io-write-buffer",
+        env = "NEOVIDE_IO_WRITE_BUFFER",
+        value_name = "BYTES",
+        default_value = "8192",
+        value_parser = parse_io_buffer_size
+    )]
+    pub io_write_buffer: usize,
+
     /// Run NeoVim in WSL rather than on the host
     #[arg(long, env = "NEOVIDE_WSL")]
     pub wsl: bool,
@@ -62,6 +512,25 @@ pub struct CmdLineSettings {
     #[arg(long = "no-multigrid", env = "NEOVIDE_NO_MULTIGRID", value_parser = FalseyValueParser::new())]
     pub no_multi_grid: bool,
 
+    /// Show the first frame after attaching only once it's complete, instead of painting it as it
+    /// arrives
+    #[arg(
+        long = "coalesce-first-frame",
+        env = "NEOVIDE_COALESCE_FIRST_FRAME",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub coalesce_first_frame: bool,
+
+    /// Show at most this many frames per second, coalescing the ones NeoVim sends faster, 0 shows
+    /// all of them
+    #[arg(
+        long = "max-redraw-rate",
+        env = "NEOVIDE_MAX_REDRAW_RATE",
+        value_name = "FPS",
+        default_value = "0"
+    )]
+    pub max_redraw_rate: u32,
+
     /// Which mouse cursor icon to use
     #[arg(
         long = "mouse-cursor-icon",
@@ -70,6 +539,21 @@ pub struct CmdLineSettings {
     )]
     pub mouse_cursor_icon: MouseCursorIcon,
 
+    /// Window title with the placeholders {title}, {status}, {address}, {attempt},
+    /// {nvim_version} and {rtt}
+    #[arg(
+        long = "title-template",
+        env = "NEOVIDE_TITLE_TEMPLATE",
+        value_name = "TEMPLATE",
+        value_parser = validate_title_template
+    )]
+    pub title_template: Option<String>,
+
+    /// A label that's always shown in the window, for example to tell the clients of a shared
+    /// session apart
+    #[arg(long = "banner", env = "NEOVIDE_BANNER", value_name = "TEXT")]
+    pub banner: Option<String>,
+
     /// Sets title hidden for the window
     #[arg(long = "title-hidden", env = "NEOVIDE_TITLE_HIDDEN", value_parser = FalseyValueParser::new())]
     pub title_hidden: bool,
@@ -113,10 +597,157 @@ pub struct CmdLineSettings {
     #[arg(long = "no-vsync", action = ArgAction::SetTrue, value_parser = FalseyValueParser::new())]
     _no_vsync: bool,
 
+    /// Set an environment variable for the embedded NeoVim, can be repeated
+    #[arg(
+        long = "nvim-env",
+        value_name = "KEY=VALUE",
+        action = ArgAction::Append,
+        value_parser = parse_env_var
+    )]
+    pub nvim_env: Vec<(String, String)>,
+
+    /// Set a global variable in NeoVim on every attach, parsed as JSON when possible and as a string
+    /// otherwise, can be repeated
+    #[arg(
+        long = "set-var",
+        value_name = "NAME=VALUE",
+        action = ArgAction::Append,
+        value_parser = parse_set_var
+    )]
+    #[serde(serialize_with = "serialize_set_var")]
+    pub set_var: Vec<(String, Value)>,
+
+    /// Remove an environment variable from the embedded NeoVim, can be repeated
+    #[arg(long = "nvim-env-remove", value_name = "KEY", action = ArgAction::Append)]
+    pub nvim_env_remove: Vec<String>,
+
+    /// Connect to a TCP --server through this SOCKS5 proxy, socks5://[user:pass@]host:port
+    #[arg(
+        long = "proxy",
+        env = "NEOVIDE_PROXY",
+        value_name = "URL",
+        value_parser = Socks5Proxy::parse
+    )]
+    pub proxy: Option<Socks5Proxy>,
+
+    /// Which addresses to connect to when a --server hostname resolves to both IPv4 and IPv6 ones
+    #[arg(
+        long = "address-family",
+        env = "NEOVIDE_ADDRESS_FAMILY",
+        value_name = "FAMILY",
+        default_value = "auto"
+    )]
+    pub address_family: AddressFamily,
+
+    /// Keep reconnecting to the IP address the --server hostname resolved to for the first
+    /// connection
+    #[arg(
+        long = "pin-resolved-ip",
+        env = "NEOVIDE_PIN_RESOLVED_IP",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub pin_resolved_ip: bool,
+
+    /// After how many failed connects in a row to the pinned IP address the hostname is resolved
+    /// again
+    #[arg(
+        long = "pin-reresolve-after",
+        env = "NEOVIDE_PIN_RERESOLVE_AFTER",
+        value_name = "FAILURES",
+        default_value = "3"
+    )]
+    pub pin_reresolve_after: u32,
+
+    /// Skip the banner the --server sends before the RPC stream, `bytes:N` skips N bytes and
+    /// `until:DELIMITER` everything up to and including DELIMITER
+    #[arg(
+        long = "connection-preamble",
+        env = "NEOVIDE_CONNECTION_PREAMBLE",
+        value_name = "SPEC",
+        value_parser = Preamble::parse
+    )]
+    pub connection_preamble: Option<Preamble>,
+
+    /// The comma separated kinds of connection errors after which to keep reconnecting to the
+    /// --server, any other error stops with a message
+    #[arg(
+        long = "reconnect-on",
+        env = "NEOVIDE_RECONNECT_ON",
+        value_name = "KINDS",
+        value_delimiter = ',',
+        default_value = "refused,timeout,network,other"
+    )]
+    pub reconnect_on: Vec<LaunchErrorKind>,
+
+    /// After how many seconds without any keyboard or mouse input to run the --idle-action, 0
+    /// never does
+    #[arg(
+        long = "idle-timeout",
+        env = "NEOVIDE_IDLE_TIMEOUT",
+        value_name = "SECONDS",
+        default_value = "0"
+    )]
+    pub idle_timeout: u64,
+
+    /// What to do after the --idle-timeout, disconnecting only applies to a --server and quits
+    /// otherwise
+    #[arg(
+        long = "idle-action",
+        env = "NEOVIDE_IDLE_ACTION",
+        value_name = "ACTION",
+        default_value = "disconnect"
+    )]
+    pub idle_action: IdleAction,
+
+    /// Start the embedded NeoVim in its own process group, a new session on Unix, so that signals
+    /// sent to Neovide don't reach it
+    #[arg(
+        long = "nvim-process-group",
+        env = "NEOVIDE_NVIM_PROCESS_GROUP",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub nvim_process_group: bool,
+
+    /// Start the embedded NeoVim hardened for untrusted files: with a minimal init, without shada
+    /// and with the XDG directories in a temporary directory
+    #[arg(
+        long = "safe-profile",
+        env = "NEOVIDE_SAFE_PROFILE",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub safe_profile: bool,
+
+    /// Write the exit code, and on the next line why Neovide quit, to this file right before
+    /// exiting
+    #[arg(
+        long = "exit-code-file",
+        env = "NEOVIDE_EXIT_CODE_FILE",
+        value_name = "PATH"
+    )]
+    pub exit_code_file: Option<PathBuf>,
+
+    /// Start the embedded NeoVim on a pseudo-terminal instead of pipes, so that it sees a TTY.
+    /// Unix only
+    #[arg(
+        long = "nvim-pty",
+        env = "NEOVIDE_NVIM_PTY",
+        value_parser = FalseyValueParser::new()
+    )]
+    pub nvim_pty: bool,
+
     /// Which NeoVim binary to invoke headlessly instead of `nvim` found on $PATH
     #[arg(long = "neovim-bin", env = "NEOVIM_BIN")]
     pub neovim_bin: Option<String>,
 
+    /// Refuse to start the embedded NeoVim unless the SHA-256 of its binary is this hex digest
+    #[arg(
+        long = "nvim-sha256",
+        env = "NEOVIDE_NVIM_SHA256",
+        value_name = "HASH",
+        value_parser = parse_sha256
+    )]
+    pub nvim_sha256: Option<String>,
+
     /// The app ID to show to the compositor (Wayland only, useful for setting WM rules)
     #[arg(
         long = "wayland_app_id",
@@ -151,7 +782,7 @@ pub struct CmdLineSettings {
 }
 
 // geometry, size and maximized are mutually exclusive
-#[derive(Clone, Debug, Args, PartialEq)]
+#[derive(Clone, Debug, Args, PartialEq, Serialize)]
 #[group(required = false, multiple = false)]
 pub struct GeometryArgs {
     /// The initial grid size of the window [<columns>x<lines>]. Defaults to columns/lines from init.vim/lua if no value is given.
@@ -168,7 +799,96 @@ pub struct GeometryArgs {
     pub maximized: bool,
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HandleClipboard {
+    Auto,
+    Always,
+    Never,
+}
+
+/// The levels of `vim.log.levels`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl NotificationLevel {
+    /// Converts a `vim.log.levels` value, `None` for `OFF` and unknown levels.
+    pub fn from_vim(level: i64) -> Option<Self> {
+        match level {
+            0 => Some(Self::Trace),
+            1 => Some(Self::Debug),
+            2 => Some(Self::Info),
+            3 => Some(Self::Warn),
+            4 => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoDrain {
+    Bounded,
+    Full,
+}
+
+/// Why connecting to the --server failed, for --reconnect-on.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LaunchErrorKind {
+    /// Nothing is listening at the address
+    Refused,
+    /// Connecting or the handshake took too long
+    Timeout,
+    /// Any other network error, like a connection reset while connecting
+    Network,
+    /// The proxy rejected the credentials, or the access was denied
+    Auth,
+    /// The other end doesn't speak the Neovim or SOCKS5 protocol
+    Protocol,
+    /// The Neovim version isn't supported
+    Version,
+    /// Anything that doesn't fit the other kinds
+    Other,
+}
+
+/// What --idle-timeout does once it runs out.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdleAction {
+    /// Drop the connection to the --server, which is made again on the next input
+    Disconnect,
+    Quit,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressFamily {
+    #[default]
+    Auto,
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    pub fn matches(&self, address: &SocketAddr) -> bool {
+        match self {
+            AddressFamily::Auto => true,
+            AddressFamily::Ipv4 => address.is_ipv4(),
+            AddressFamily::Ipv6 => address.is_ipv6(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum MouseCursorIcon {
     Arrow,
     IBeam,
@@ -183,6 +903,66 @@ impl MouseCursorIcon {
     }
 }
 
+fn parse_env_var(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("expected KEY=VALUE, got `{value}`")),
+    }
+}
+
+/// Parses `name=value` for `--set-var`, `name` being a valid Vim variable name without the `g:`.
+fn parse_set_var(value: &str) -> Result<(String, Value), String> {
+    let (name, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got `{value}`"))?;
+    let valid_name = name.chars().enumerate().all(|(index, c)| {
+        c == '_' || c.is_ascii_alphabetic() || (index > 0 && (c.is_ascii_digit() || c == '#'))
+    });
+    if name.is_empty() || !valid_name {
+        return Err(format!("`{name}` is not a valid variable name"));
+    }
+    let value = match serde_json::from_str(value) {
+        Ok(json) => json_to_value(json),
+        Err(_) => Value::from(value),
+    };
+    Ok((name.to_owned(), value))
+}
+
+/// Dumps the `--set-var` values as the JSON they were parsed from.
+fn serialize_set_var<S: Serializer>(
+    variables: &[(String, Value)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(
+        variables
+            .iter()
+            .filter_map(|(name, value)| Some((name, value_to_json(value.clone())?))),
+    )
+}
+
+const MIN_IO_BUFFER_SIZE: usize = 1024;
+
+fn parse_io_buffer_size(value: &str) -> Result<usize, String> {
+    let size = value
+        .parse::<usize>()
+        .map_err(|_| format!("expected a size in bytes, got `{value}`"))?;
+    if size < MIN_IO_BUFFER_SIZE {
+        return Err(format!(
+            "the buffer size must be at least {MIN_IO_BUFFER_SIZE} bytes"
+        ));
+    }
+    Ok(size)
+}
+
+fn parse_sha256(value: &str) -> Result<String, String> {
+    if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "expected 64 hex digits of a SHA-256 digest, got `{value}`"
+        ));
+    }
+    Ok(value.to_ascii_lowercase())
+}
+
 impl Default for CmdLineSettings {
     fn default() -> Self {
         Self::parse_from(iter::empty::<String>())
@@ -369,6 +1149,114 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_nvim_env() {
+        let settings = Settings::new();
+        let args: Vec<String> = [
+            "neovide",
+            "--nvim-env",
+            "TERM=xterm-256color",
+            "--nvim-env=EMPTY=",
+            "--nvim-env-remove",
+            "NVIM_APPNAME",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        let cmdline_settings = settings.get::<CmdLineSettings>();
+        assert_eq!(
+            cmdline_settings.nvim_env,
+            vec![
+                ("TERM".to_string(), "xterm-256color".to_string()),
+                ("EMPTY".to_string(), "".to_string())
+            ]
+        );
+        assert_eq!(cmdline_settings.nvim_env_remove, vec!["NVIM_APPNAME"]);
+    }
+
+    #[test]
+    fn test_nvim_env_requires_key_value() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide", "--nvim-env", "TERM"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert!(handle_command_line_arguments(args, &settings).is_err());
+    }
+
+    #[test]
+    fn test_set_var_parses_json_values() {
+        let settings = Settings::new();
+        let args: Vec<String> = [
+            "neovide",
+            "--set-var",
+            "session_id=42",
+            "--set-var=project={\"name\": \"neovide\", \"tags\": [1, true]}",
+            "--set-var",
+            "greeting=hello world",
+            "--set-var",
+            "empty=",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        let project = Value::Map(vec![
+            ("name".into(), "neovide".into()),
+            ("tags".into(), Value::Array(vec![1.into(), true.into()])),
+        ]);
+        assert_eq!(
+            settings.get::<CmdLineSettings>().set_var,
+            vec![
+                ("session_id".to_owned(), Value::from(42)),
+                ("project".to_owned(), project),
+                ("greeting".to_owned(), Value::from("hello world")),
+                ("empty".to_owned(), Value::from("")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_var_requires_a_valid_name() {
+        for value in ["session_id", "=1", "1st=1", "my-var=1", "g:var=1"] {
+            let settings = Settings::new();
+            let args = ["neovide", "--set-var", value].map(str::to_owned).to_vec();
+            assert!(
+                handle_command_line_arguments(args, &settings).is_err(),
+                "{value} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_io_buffer_sizes() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide", "--io-read-buffer", "65536"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        let cmdline_settings = settings.get::<CmdLineSettings>();
+        assert_eq!(cmdline_settings.io_read_buffer, 65536);
+        assert_eq!(cmdline_settings.io_write_buffer, 8192);
+    }
+
+    #[test]
+    fn test_io_buffer_size_minimum() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide", "--io-write-buffer", "512"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        assert!(handle_command_line_arguments(args, &settings).is_err());
+    }
+
     #[test]
     fn test_log_to_file() {
         let settings = Settings::new();
@@ -534,6 +1422,187 @@ mod tests {
         assert_eq!(settings.get::<CmdLineSettings>().vsync, false);
     }
 
+    #[test]
+    fn test_clean_exit_codes() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(settings.get::<CmdLineSettings>().clean_exit_codes, vec![0]);
+
+        let args: Vec<String> = ["neovide", "--clean-exit-codes", "0,130"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(
+            settings.get::<CmdLineSettings>().clean_exit_codes,
+            vec![0, 130]
+        );
+    }
+
+    #[test]
+    fn test_restart_on_crash_conflicts_with_hold() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide", "--restart-on-crash"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert!(settings.get::<CmdLineSettings>().restart_on_crash);
+        assert!(!settings.get::<CmdLineSettings>().hold);
+
+        let args: Vec<String> = ["neovide", "--restart-on-crash", "--hold"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert!(handle_command_line_arguments(args, &settings).is_err());
+    }
+
+    #[test]
+    fn test_address_family() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(
+            settings.get::<CmdLineSettings>().address_family,
+            AddressFamily::Auto
+        );
+
+        let args: Vec<String> = ["neovide", "--address-family", "ipv4"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(
+            settings.get::<CmdLineSettings>().address_family,
+            AddressFamily::Ipv4
+        );
+    }
+
+    #[test]
+    fn test_coalesce_first_frame() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert!(!settings.get::<CmdLineSettings>().coalesce_first_frame);
+
+        let args: Vec<String> = ["neovide", "--coalesce-first-frame"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert!(settings.get::<CmdLineSettings>().coalesce_first_frame);
+    }
+
+    #[test]
+    fn test_reconnect_on() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(
+            settings.get::<CmdLineSettings>().reconnect_on,
+            vec![
+                LaunchErrorKind::Refused,
+                LaunchErrorKind::Timeout,
+                LaunchErrorKind::Network,
+                LaunchErrorKind::Other
+            ]
+        );
+
+        let args: Vec<String> = ["neovide", "--reconnect-on", "refused,auth"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(
+            settings.get::<CmdLineSettings>().reconnect_on,
+            vec![LaunchErrorKind::Refused, LaunchErrorKind::Auth]
+        );
+    }
+
+    #[test]
+    fn test_connect_warmup() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(settings.get::<CmdLineSettings>().connect_warmup, 0);
+
+        let args: Vec<String> = [
+            "neovide",
+            "--connect-warmup",
+            "150",
+            "--connect-warmup-pings",
+            "8",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(settings.get::<CmdLineSettings>().connect_warmup, 150);
+        assert_eq!(settings.get::<CmdLineSettings>().connect_warmup_pings, 8);
+    }
+
+    #[test]
+    fn test_disconnect_grace() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(settings.get::<CmdLineSettings>().disconnect_grace, 0);
+
+        let args: Vec<String> = ["neovide", "--disconnect-grace", "1500"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(settings.get::<CmdLineSettings>().disconnect_grace, 1500);
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(settings.get::<CmdLineSettings>().idle_timeout, 0);
+        assert_eq!(
+            settings.get::<CmdLineSettings>().idle_action,
+            IdleAction::Disconnect
+        );
+
+        let args: Vec<String> = ["neovide", "--idle-timeout", "600", "--idle-action", "quit"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert_eq!(settings.get::<CmdLineSettings>().idle_timeout, 600);
+        assert_eq!(
+            settings.get::<CmdLineSettings>().idle_action,
+            IdleAction::Quit
+        );
+    }
+
+    #[test]
+    fn test_no_reconnect_on_clean_close() {
+        let settings = Settings::new();
+        let args: Vec<String> = ["neovide"].iter().map(|s| s.to_string()).collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert!(
+            !settings
+                .get::<CmdLineSettings>()
+                .no_reconnect_on_clean_close
+        );
+
+        let args: Vec<String> = ["neovide", "--no-reconnect-on-clean-close"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        handle_command_line_arguments(args, &settings).expect("Could not parse arguments");
+        assert!(
+            settings
+                .get::<CmdLineSettings>()
+                .no_reconnect_on_clean_close
+        );
+    }
+
     #[test]
     fn test_no_vsync_environment() {
         let settings = Settings::new();
@@ -0,0 +1,105 @@
+//! Shows desktop notifications through the notification service of the platform, for
+//! `--desktop-notifications`.
+
+use notify_rust::Notification;
+use tokio::runtime::Handle;
+
+use crate::{
+    cmd_line::{CmdLineSettings, NotificationLevel},
+    settings::Settings,
+    window::WindowSettings,
+};
+
+const TITLE: &str = "Neovide";
+
+/// Shows `message` as a desktop notification on the `runtime`, without waiting for it.
+pub fn show(runtime: &Handle, message: &str) {
+    let mut notification = Notification::new();
+    notification.appname(TITLE).summary(TITLE).body(message);
+    // Talking to the notification service blocks, on Linux through D-Bus
+    runtime.spawn_blocking(move || {
+        if let Err(error) = notification.show() {
+            log::warn!("Could not show a desktop notification: {error}");
+        }
+    });
+}
+
+fn should_forward(
+    cmdline_settings: &CmdLineSettings,
+    level: NotificationLevel,
+    focused: bool,
+    when_focused: bool,
+) -> bool {
+    cmdline_settings.desktop_notifications
+        && level >= cmdline_settings.desktop_notifications_level
+        && (!focused || when_focused)
+}
+
+/// Forwards a `vim.notify` message to `show`, when `--desktop-notifications` asks for it while the
+/// window has the `focused` state.
+pub fn forward_notification(
+    settings: &Settings,
+    message: &str,
+    level: NotificationLevel,
+    focused: bool,
+    show: impl FnOnce(&str),
+) {
+    let when_focused = settings
+        .get::<WindowSettings>()
+        .desktop_notifications_when_focused;
+    if should_forward(
+        &settings.get::<CmdLineSettings>(),
+        level,
+        focused,
+        when_focused,
+    ) {
+        show(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn notifications_settings() -> Settings {
+        let settings = Settings::new();
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.desktop_notifications = true;
+        settings.set(&cmdline_settings);
+        settings.register::<WindowSettings>();
+        settings
+    }
+
+    #[test]
+    fn test_notification_triggers_the_forwarding_hook() {
+        let settings = notifications_settings();
+        let shown = RefCell::new(Vec::new());
+        let show = |message: &str| shown.borrow_mut().push(message.to_owned());
+
+        forward_notification(
+            &settings,
+            "Build failed",
+            NotificationLevel::Error,
+            false,
+            show,
+        );
+        forward_notification(&settings, "Saved", NotificationLevel::Info, false, show);
+        forward_notification(&settings, "Focused", NotificationLevel::Error, true, show);
+        assert_eq!(*shown.borrow(), vec!["Build failed".to_owned()]);
+    }
+
+    #[test]
+    fn test_focused_window_is_not_notified() {
+        let mut cmdline_settings = CmdLineSettings::default();
+        cmdline_settings.desktop_notifications = true;
+        let level = NotificationLevel::Error;
+        assert!(!should_forward(&cmdline_settings, level, true, false));
+        assert!(should_forward(&cmdline_settings, level, true, true));
+        assert!(should_forward(&cmdline_settings, level, false, false));
+
+        cmdline_settings.desktop_notifications = false;
+        assert!(!should_forward(&cmdline_settings, level, false, false));
+    }
+}
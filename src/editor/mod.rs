@@ -378,6 +378,18 @@ impl Editor {
             RedrawEvent::NeovideSetRedraw(enable) => self
                 .draw_command_batcher
                 .set_enabled(enable, &self.event_loop_proxy),
+            RedrawEvent::NeovideCloseWindows => {
+                // Grid 1 is resized and cleared by the new attach
+                let grids: Vec<u64> = self
+                    .windows
+                    .keys()
+                    .copied()
+                    .filter(|&grid| grid != 1)
+                    .collect();
+                for grid in grids {
+                    self.close_window(grid);
+                }
+            }
             _ => {}
         };
     }
@@ -1,6 +1,5 @@
 use std::{
     io::{stdout, IsTerminal},
-    process::ExitCode,
     sync::Arc,
 };
 
@@ -70,19 +69,19 @@ pub fn handle_startup_errors(
     err: Error,
     event_loop: EventLoop<UserEvent>,
     settings: Arc<Settings>,
-) -> ExitCode {
+) -> u8 {
     // Command line output is always printed to the stdout/stderr
     if let Some(clap_error) = err.downcast_ref::<ClapError>() {
         #[cfg(target_os = "windows")]
         windows_attach_to_console();
         let _ = clap_error.print();
-        ExitCode::from(clap_error.exit_code() as u8)
+        clap_error.exit_code() as u8
     } else if stdout().is_terminal() {
         // The logger already writes to stderr
         log::error!("{}", &format_and_log_error_message(err));
-        ExitCode::from(1)
+        1
     } else {
         show_error_window(&format_and_log_error_message(err), event_loop, settings);
-        ExitCode::from(1)
+        1
     }
 }
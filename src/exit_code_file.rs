@@ -0,0 +1,64 @@
+//! `--exit-code-file`, which writes the exit code and why Neovide quit to a file right before it
+//! exits, for launchers that can't wait for the process, like when it forked into the background.
+//! The file has the code on the first line and the reason on the second.
+
+use std::{fs, path::PathBuf};
+
+use once_cell::sync::OnceCell;
+
+use crate::running_tracker::RunningTracker;
+
+static EXIT_CODE_FILE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Sets the file once the command line is parsed, exits before that aren't written.
+pub fn set_exit_code_file(path: Option<PathBuf>) {
+    if let Some(path) = path {
+        EXIT_CODE_FILE.set(path).ok();
+    }
+}
+
+fn contents(code: u8, reason: &str) -> String {
+    format!("{code}\n{reason}\n")
+}
+
+/// The reason for a normal exit, from what the running tracker recorded.
+pub fn quit_reason(running_tracker: &RunningTracker) -> String {
+    running_tracker
+        .quit_reason()
+        .map_or_else(|| "Unknown".to_owned(), |reason| format!("{reason:?}"))
+}
+
+/// Writes `code` and `reason` to the `--exit-code-file`, if there is one.
+pub fn write_exit_code(code: u8, reason: &str) {
+    let Some(path) = EXIT_CODE_FILE.get() else {
+        return;
+    };
+    if let Err(error) = fs::write(path, contents(code, reason)) {
+        // The logger may already be gone
+        eprintln!("Could not write the exit code to {path:?}: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::running_tracker::QuitReason;
+
+    #[test]
+    fn test_file_has_the_code_and_reason() {
+        let running_tracker = RunningTracker::new();
+        running_tracker.set_quit_reason(QuitReason::PluginRequested);
+        running_tracker.quit_with_code(3, "requested by plugin");
+
+        let path = env::temp_dir().join(format!("neovide-exit-code-{}", std::process::id()));
+        set_exit_code_file(Some(path.clone()));
+        write_exit_code(running_tracker.exit_code(), &quit_reason(&running_tracker));
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(written, "3\nPluginRequested\n");
+
+        assert_eq!(quit_reason(&RunningTracker::new()), "Unknown");
+    }
+}
@@ -1,11 +1,11 @@
 use core::fmt;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use clap::{builder::PossibleValue, ValueEnum};
 
 // Options for the frame decorations
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Deserialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum Frame {
     #[default]
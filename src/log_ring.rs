@@ -0,0 +1,221 @@
+//! Keeps the most recent log records in memory, so that they can be inspected without a log file,
+//! either through the log overlay or in the backtraces file after a panic. The records of the
+//! connection to Neovim are kept separately as well, for the connection log overlay.
+
+use std::collections::VecDeque;
+
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+
+const DEFAULT_LOG_RING_SIZE: usize = 500;
+const CONNECTION_LOG_SIZE: usize = 200;
+
+/// The target of connect, disconnect, reconnect and ping records, the debug ones are only kept in
+/// the connection log.
+pub const CONNECTION_LOG_TARGET: &str = "neovide::connection";
+
+pub struct LogRing {
+    capacity: usize,
+    lines: VecDeque<String>,
+}
+
+impl LogRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.lines.len() > capacity {
+            self.lines.pop_front();
+        }
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &String> {
+        self.lines.iter()
+    }
+}
+
+// Records are logged from the window thread and any of the runtime threads
+static LOG_RING: Lazy<Mutex<LogRing>> =
+    Lazy::new(|| Mutex::new(LogRing::new(DEFAULT_LOG_RING_SIZE)));
+
+static CONNECTION_LOG: Lazy<Mutex<LogRing>> =
+    Lazy::new(|| Mutex::new(LogRing::new(CONNECTION_LOG_SIZE)));
+
+/// The `--instance-label`, which tells the connection records of several Neovides apart.
+static INSTANCE_LABEL: OnceCell<String> = OnceCell::new();
+
+#[cfg(not(test))]
+pub fn set_instance_label(label: &str) {
+    if !label.is_empty() {
+        INSTANCE_LABEL.set(label.to_owned()).ok();
+    }
+}
+
+/// The message of `record`, with the instance label in front when it's a connection record.
+fn record_message(record: &log::Record) -> String {
+    match INSTANCE_LABEL.get() {
+        Some(label) if record.target() == CONNECTION_LOG_TARGET => {
+            format!("[{label}] {}", record.args())
+        }
+        _ => record.args().to_string(),
+    }
+}
+
+/// The format of the log file and stderr, `flexi_logger::default_format` with the instance label.
+#[cfg(not(test))]
+pub fn format_record(
+    w: &mut dyn std::io::Write,
+    _now: &mut flexi_logger::DeferredNow,
+    record: &log::Record,
+) -> std::io::Result<()> {
+    write!(
+        w,
+        "{} [{}] {}",
+        record.level(),
+        record.module_path().unwrap_or("<unnamed>"),
+        record_message(record)
+    )
+}
+
+#[cfg(not(test))]
+pub fn set_log_ring_size(size: usize) {
+    LOG_RING.lock().set_capacity(size);
+}
+
+/// The retained log records, oldest first.
+pub fn recent_log_lines() -> Vec<String> {
+    LOG_RING.lock().lines().cloned().collect()
+}
+
+/// The retained connection records, oldest first.
+pub fn connection_log_lines() -> Vec<String> {
+    CONNECTION_LOG.lock().lines().cloned().collect()
+}
+
+fn record_line(target: &str, level: log::Level, line: String) {
+    if target == CONNECTION_LOG_TARGET {
+        CONNECTION_LOG.lock().push(line.clone());
+        // The pings would push everything else out of the log ring
+        if level > log::Level::Info {
+            return;
+        }
+    }
+    LOG_RING.lock().push(line);
+}
+
+/// A `flexi_logger` writer that records every log line in the log ring.
+#[cfg(not(test))]
+pub struct LogRingWriter;
+
+#[cfg(not(test))]
+impl flexi_logger::writers::LogWriter for LogRingWriter {
+    fn write(
+        &self,
+        now: &mut flexi_logger::DeferredNow,
+        record: &log::Record,
+    ) -> std::io::Result<()> {
+        let line = format!(
+            "{} {} [{}] {}",
+            now.format("%H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record_message(record)
+        );
+        record_line(record.target(), record.level(), line);
+        Ok(())
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_keeps_the_last_records() {
+        let mut ring = LogRing::new(3);
+        for i in 0..5 {
+            ring.push(format!("record {i}"));
+        }
+        assert_eq!(
+            ring.lines().collect::<Vec<_>>(),
+            vec!["record 2", "record 3", "record 4"]
+        );
+    }
+
+    #[test]
+    fn test_shrinking_drops_the_oldest_records() {
+        let mut ring = LogRing::new(3);
+        for i in 0..3 {
+            ring.push(format!("record {i}"));
+        }
+        ring.set_capacity(1);
+        assert_eq!(ring.lines().collect::<Vec<_>>(), vec!["record 2"]);
+    }
+
+    #[test]
+    fn test_zero_capacity_keeps_nothing() {
+        let mut ring = LogRing::new(0);
+        ring.push("record".to_string());
+        assert_eq!(ring.lines().count(), 0);
+    }
+
+    #[test]
+    fn test_connection_records_are_kept_for_the_overlay() {
+        record_line(
+            CONNECTION_LOG_TARGET,
+            log::Level::Info,
+            "connected".to_owned(),
+        );
+        record_line(
+            CONNECTION_LOG_TARGET,
+            log::Level::Debug,
+            "ping 12ms".to_owned(),
+        );
+        record_line(
+            "neovide::renderer",
+            log::Level::Info,
+            "font loaded".to_owned(),
+        );
+
+        let connection_log = connection_log_lines();
+        assert!(connection_log.ends_with(&["connected".to_owned(), "ping 12ms".to_owned()]));
+        assert!(!connection_log.contains(&"font loaded".to_owned()));
+        let log = recent_log_lines();
+        assert!(log.contains(&"connected".to_owned()));
+        assert!(!log.contains(&"ping 12ms".to_owned()));
+    }
+
+    #[test]
+    fn test_connection_records_include_the_instance_label() {
+        INSTANCE_LABEL.set("devbox".to_owned()).ok();
+        let record = |target| {
+            record_message(
+                &log::Record::builder()
+                    .target(target)
+                    .args(format_args!("Connected {:?}", 1))
+                    .build(),
+            )
+        };
+        assert_eq!(record(CONNECTION_LOG_TARGET), "[devbox] Connected 1");
+        assert_eq!(record("neovide::renderer"), "Connected 1");
+    }
+}
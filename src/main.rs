@@ -17,11 +17,15 @@ extern crate clap;
 mod bridge;
 mod channel_utils;
 mod clipboard;
+mod clock;
 mod cmd_line;
+mod desktop_notification;
 mod dimensions;
 mod editor;
 mod error_handling;
+mod exit_code_file;
 mod frame;
+mod log_ring;
 mod profiling;
 mod renderer;
 mod running_tracker;
@@ -47,22 +51,23 @@ use std::{
 };
 
 use anyhow::Result;
-use log::trace;
+use log::{info, trace, warn};
 use std::env::var;
 use std::panic::PanicHookInfo;
 use std::path::PathBuf;
 use time::macros::format_description;
 use time::OffsetDateTime;
+use tokio::task::JoinHandle;
 use winit::{error::EventLoopError, event_loop::EventLoopProxy};
 
 #[cfg(not(test))]
 use flexi_logger::{Cleanup, Criterion, Duplicate, FileSpec, Logger, Naming};
 
 use backtrace::Backtrace;
-use bridge::NeovimRuntime;
+use bridge::{NeovimRuntime, ReconnectSettings};
 use cmd_line::CmdLineSettings;
 use error_handling::handle_startup_errors;
-use renderer::{cursor_renderer::CursorSettings, RendererSettings};
+use renderer::{cursor_renderer::CursorSettings, ReconnectIndicatorSettings, RendererSettings};
 use running_tracker::RunningTracker;
 use window::{
     create_event_loop, determine_window_size, UpdateLoop, UserEvent, WindowSettings, WindowSize,
@@ -79,6 +84,8 @@ pub use profiling::startup_profiler;
 const DEFAULT_BACKTRACES_FILE: &str = "neovide_backtraces.log";
 const BACKTRACES_FILE_ENV_VAR: &str = "NEOVIDE_BACKTRACES";
 const REQUEST_MESSAGE: &str = "This is a bug and we would love for it to be reported to https://github.com/neovide/neovide/issues";
+// Enough for the embedded Neovim to notice the closed event loop and to be killed
+const NEOVIM_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
 
 fn main() -> ExitCode {
     set_hook(Box::new(|panic_info| {
@@ -105,21 +112,37 @@ fn main() -> ExitCode {
     let running_tracker = RunningTracker::new();
     let settings = Arc::new(Settings::new());
 
-    match setup(
+    let (code, reason) = match setup(
         event_loop.create_proxy(),
         running_tracker.clone(),
         settings.clone(),
     ) {
-        Err(err) => handle_startup_errors(err, event_loop, settings.clone()),
-        Ok((window_size, initial_config, runtime)) => {
+        Err(err) => (
+            handle_startup_errors(err, event_loop, settings.clone()),
+            "StartupError".to_owned(),
+        ),
+        Ok(None) => (0, "SettingsDumped".to_owned()),
+        Ok(Some((window_size, initial_config, runtime, neovim_task))) => {
             let mut update_loop = UpdateLoop::new(
                 window_size,
                 initial_config,
                 event_loop.create_proxy(),
+                running_tracker.clone(),
                 settings.clone(),
+                runtime.handle(),
             );
 
             let result = event_loop.run_app(&mut update_loop);
+            if let Some(reason) = running_tracker.quit_reason() {
+                info!("Neovide quit: {reason:?}");
+            }
+            // An embedded Neovim that's still running notices that the event loop is gone and
+            // shuts down on its own
+            if let Some(task) = neovim_task {
+                if !runtime.wait_for(task, NEOVIM_SHUTDOWN_TIMEOUT) {
+                    warn!("The embedded Neovim did not shut down in time");
+                }
+            }
 
             // Wait a little bit more and force Nevoim to exit after that.
             // This should not be required, but Neovim through libuv spawns childprocesses that inherits all the handles
@@ -129,19 +152,28 @@ fn main() -> ExitCode {
             runtime.runtime.shutdown_timeout(Duration::from_millis(500));
 
             match result {
-                Ok(_) => running_tracker.exit_code(),
-                Err(EventLoopError::ExitFailure(code)) => ExitCode::from(code as u8),
-                _ => ExitCode::FAILURE,
+                Ok(_) => (
+                    running_tracker.exit_code(),
+                    exit_code_file::quit_reason(&running_tracker),
+                ),
+                Err(EventLoopError::ExitFailure(code)) => (code as u8, "EventLoopExit".to_owned()),
+                Err(error) => (1, format!("EventLoopError: {error}")),
             }
         }
-    }
+    };
+    running_tracker.run_shutdown_callbacks();
+    exit_code_file::write_exit_code(code, &reason);
+    ExitCode::from(code)
 }
 
+/// What the event loop starts with, `None` from [`setup`] when there's nothing to run.
+type Startup = (WindowSize, Config, NeovimRuntime, Option<JoinHandle<()>>);
+
 fn setup(
     proxy: EventLoopProxy<UserEvent>,
     running_tracker: RunningTracker,
     settings: Arc<Settings>,
-) -> Result<(WindowSize, Config, NeovimRuntime)> {
+) -> Result<Option<Startup>> {
     //  --------------
     // | Architecture |
     //  --------------
@@ -214,6 +246,8 @@ fn setup(
     settings.register::<WindowSettings>();
     settings.register::<RendererSettings>();
     settings.register::<CursorSettings>();
+    settings.register::<ReconnectSettings>();
+    settings.register::<ReconnectIndicatorSettings>();
 
     let config = Config::init();
     Config::watch_config_file(config.clone(), proxy.clone());
@@ -227,11 +261,19 @@ fn setup(
             eprintln!("{stderr_msg}");
 
             log_panic_to_file(panic_info, &backtrace, &path);
+            // Overwritten by the normal exit when the panic wasn't on the main thread
+            exit_code_file::write_exit_code(101, "Panic");
         }
     }));
 
     //Will exit if -h or -v
     cmd_line::handle_command_line_arguments(args().collect(), settings.as_ref())?;
+    running_tracker.set_clean_exit_codes(settings.get::<CmdLineSettings>().clean_exit_codes);
+    exit_code_file::set_exit_code_file(settings.get::<CmdLineSettings>().exit_code_file);
+    if let Some(path) = settings.get::<CmdLineSettings>().dump_settings {
+        settings.dump_to_file(&path)?;
+        return Ok(None);
+    }
     #[cfg(not(target_os = "windows"))]
     maybe_disown(&settings);
 
@@ -256,18 +298,21 @@ fn setup(
     };
 
     let mut runtime = NeovimRuntime::new()?;
-    runtime.launch(proxy, grid_size, running_tracker, settings)?;
-    Ok((window_size, config, runtime))
+    let neovim_task = runtime.launch(proxy, grid_size, running_tracker, settings)?;
+    Ok(Some((window_size, config, runtime, neovim_task)))
 }
 
 #[cfg(not(test))]
 pub fn init_logger(settings: &Settings) {
     let cmdline_settings = settings.get::<CmdLineSettings>();
 
+    log_ring::set_log_ring_size(cmdline_settings.log_ring_size);
+    log_ring::set_instance_label(&cmdline_settings.instance_label);
     let logger = if cmdline_settings.log_to_file {
         Logger::try_with_env_or_str("neovide")
             .expect("Could not init logger")
-            .log_to_file(FileSpec::default())
+            .format(log_ring::format_record)
+            .log_to_file_and_writer(FileSpec::default(), Box::new(log_ring::LogRingWriter))
             .rotate(
                 Criterion::Size(10_000_000),
                 Naming::Timestamps,
@@ -275,7 +320,18 @@ pub fn init_logger(settings: &Settings) {
             )
             .duplicate_to_stderr(Duplicate::Error)
     } else {
-        Logger::try_with_env_or_str("neovide = error").expect("Could not init logger")
+        // Without an explicit filter only the errors are kept, plus the connection records for the
+        // connection log, and only the errors are printed
+        let stderr_level = if var("RUST_LOG").is_ok() {
+            Duplicate::All
+        } else {
+            Duplicate::Error
+        };
+        Logger::try_with_env_or_str("neovide = error, neovide::connection = debug")
+            .expect("Could not init logger")
+            .format(log_ring::format_record)
+            .log_to_writer(Box::new(log_ring::LogRingWriter))
+            .duplicate_to_stderr(stderr_level)
     };
 
     logger.start().expect("Could not start logger");
@@ -293,6 +349,9 @@ fn maybe_disown(settings: &Settings) {
     }
 
     if let Ok(current_exe) = env::current_exe() {
+        // Written before the background process starts, so that it's always what it writes when
+        // it exits that ends up in the file
+        exit_code_file::write_exit_code(0, "Forked");
         assert!(process::Command::new(current_exe)
             .stdin(process::Stdio::null())
             .stdout(process::Stdio::null())
@@ -375,7 +434,13 @@ fn generate_panic_log_message(panic_info: &PanicHookInfo, backtrace: &Backtrace)
     let partial_panic_msg = generate_panic_message(panic_info);
     let full_panic_msg = format!("{timestamp} - {partial_panic_msg}");
 
-    format!("{full_panic_msg}\n{backtrace:?}\n")
+    let recent_log_lines = log_ring::recent_log_lines();
+    if recent_log_lines.is_empty() {
+        format!("{full_panic_msg}\n{backtrace:?}\n")
+    } else {
+        let recent_log = recent_log_lines.join("\n");
+        format!("{full_panic_msg}\n{backtrace:?}\nRecent log records:\n{recent_log}\n")
+    }
 }
 
 fn generate_panic_message(panic_info: &PanicHookInfo) -> String {
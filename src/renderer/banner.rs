@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use nvim_rs::Value;
+use skia_safe::{Canvas, Color, Paint, Point, Rect};
+
+use crate::cmd_line::CmdLineSettings;
+use crate::profiling::tracy_zone;
+use crate::renderer::{
+    fonts::font_loader::{FontLoader, FontPair},
+    reconnect_indicator::{first_loaded, font_candidates},
+    ReconnectIndicatorSettings,
+};
+use crate::settings::{ParseFromValue, Settings};
+
+const FONT_SIZE: f32 = 14.0;
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+
+/// Where the `--banner` is drawn in the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BannerPosition {
+    TopLeft,
+    Top,
+    TopRight,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl ParseFromValue for BannerPosition {
+    fn parse_from_value(&mut self, value: Value) {
+        match value.as_str() {
+            Some("top_left") => *self = BannerPosition::TopLeft,
+            Some("top") => *self = BannerPosition::Top,
+            Some("top_right") => *self = BannerPosition::TopRight,
+            Some("bottom_left") => *self = BannerPosition::BottomLeft,
+            Some("bottom") => *self = BannerPosition::Bottom,
+            Some("bottom_right") => *self = BannerPosition::BottomRight,
+            _ => log::error!(
+                "Setting banner_position expected one of `top_left`, `top`, `top_right`, \
+                 `bottom_left`, `bottom` or `bottom_right`, but received {value:?}"
+            ),
+        }
+    }
+}
+
+impl From<BannerPosition> for Value {
+    fn from(position: BannerPosition) -> Self {
+        Value::from(match position {
+            BannerPosition::TopLeft => "top_left",
+            BannerPosition::Top => "top",
+            BannerPosition::TopRight => "top_right",
+            BannerPosition::BottomLeft => "bottom_left",
+            BannerPosition::Bottom => "bottom",
+            BannerPosition::BottomRight => "bottom_right",
+        })
+    }
+}
+
+/// The text of the banner, which also tells when this client is read-only.
+fn banner_text(text: Option<&str>, read_only: bool) -> Option<String> {
+    let text = text.filter(|text| !text.is_empty())?;
+    Some(if read_only {
+        format!("{text} (read-only)")
+    } else {
+        text.to_owned()
+    })
+}
+
+/// The box of a banner `text_width` wide at `position`, in a canvas of `width` by `height`.
+fn banner_rect(
+    position: BannerPosition,
+    width: f32,
+    height: f32,
+    text_width: f32,
+    font_size: f32,
+) -> Rect {
+    let box_width = text_width + PADDING * 2.0;
+    let box_height = font_size + PADDING * 2.0;
+    let left = match position {
+        BannerPosition::TopLeft | BannerPosition::BottomLeft => MARGIN,
+        BannerPosition::Top | BannerPosition::Bottom => (width - box_width) / 2.0,
+        BannerPosition::TopRight | BannerPosition::BottomRight => width - MARGIN - box_width,
+    };
+    let top = match position {
+        BannerPosition::TopLeft | BannerPosition::Top | BannerPosition::TopRight => MARGIN,
+        _ => height - MARGIN - box_height,
+    };
+    Rect::from_xywh(left, top, box_width, box_height)
+}
+
+/// A label from `--banner` that's always drawn, whatever the connection does, for example to
+/// tell the clients of a shared session apart. It's only drawn, the input goes through to
+/// Neovim as usual.
+pub struct Banner {
+    text: Option<String>,
+    read_only: bool,
+    /// `None` when no font could be loaded, then nothing is drawn
+    font: Option<Arc<FontPair>>,
+    /// The `reconnect_font` that `font` was loaded for
+    font_family: String,
+    loader: FontLoader,
+    settings: Arc<Settings>,
+}
+
+impl Banner {
+    pub fn new(settings: Arc<Settings>) -> Self {
+        let text = settings.get::<CmdLineSettings>().banner;
+        let font_family = settings.get::<ReconnectIndicatorSettings>().font;
+        let mut loader = FontLoader::new(FONT_SIZE);
+        let font = text
+            .is_some()
+            .then(|| Self::load_font(&mut loader, &font_family))
+            .flatten();
+        Self {
+            text,
+            read_only: false,
+            font,
+            font_family,
+            loader,
+            settings,
+        }
+    }
+
+    fn load_font(loader: &mut FontLoader, family: &str) -> Option<Arc<FontPair>> {
+        first_loaded(
+            loader,
+            &font_candidates(family),
+            |loader, key| loader.get_or_load(key),
+            FontLoader::get_or_load_last_resort,
+        )
+    }
+
+    /// Uses the same font as the reconnect indicator, reloaded when `reconnect_font` changed.
+    pub fn update_font(&mut self) {
+        let family = self.settings.get::<ReconnectIndicatorSettings>().font;
+        if self.text.is_some() && family != self.font_family {
+            self.font = Self::load_font(&mut self.loader, &family);
+            self.font_family = family;
+        }
+    }
+
+    /// Whether there's a banner to draw.
+    pub fn is_shown(&self) -> bool {
+        self.font.is_some() && banner_text(self.text.as_deref(), false).is_some()
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn draw(&self, canvas: &Canvas, position: BannerPosition) {
+        tracy_zone!("banner_draw");
+        let (Some(text), Some(font)) = (
+            banner_text(self.text.as_deref(), self.read_only),
+            &self.font,
+        ) else {
+            return;
+        };
+        canvas.save();
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+
+        let size = canvas.base_layer_size();
+        let text_width = font.skia_font.measure_str(&text, Some(&paint)).0;
+        let rect = banner_rect(
+            position,
+            size.width as f32,
+            size.height as f32,
+            text_width,
+            FONT_SIZE,
+        );
+        paint.set_color(Color::from_argb(200, 40, 40, 40));
+        canvas.draw_rect(rect, &paint);
+
+        paint.set_color(Color::WHITE);
+        let text_pos = Point::new(rect.left + PADDING, rect.bottom - PADDING - FONT_SIZE * 0.2);
+        canvas.draw_str(&text, text_pos, &font.skia_font, &paint);
+
+        canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner_at_the_configured_position() {
+        let text = banner_text(Some("Shared session"), false).unwrap();
+        assert_eq!(text, "Shared session");
+        let (width, height, text_width) = (800.0, 600.0, 100.0);
+        let box_width = text_width + PADDING * 2.0;
+        let box_height = FONT_SIZE + PADDING * 2.0;
+
+        let top_left = banner_rect(
+            BannerPosition::TopLeft,
+            width,
+            height,
+            text_width,
+            FONT_SIZE,
+        );
+        assert_eq!(
+            top_left,
+            Rect::from_xywh(MARGIN, MARGIN, box_width, box_height)
+        );
+        let top = banner_rect(BannerPosition::Top, width, height, text_width, FONT_SIZE);
+        assert_eq!(top.center_x(), width / 2.0);
+        assert_eq!(top.top, MARGIN);
+        let bottom_right = banner_rect(
+            BannerPosition::BottomRight,
+            width,
+            height,
+            text_width,
+            FONT_SIZE,
+        );
+        assert_eq!(bottom_right.right, width - MARGIN);
+        assert_eq!(bottom_right.bottom, height - MARGIN);
+
+        let mut position = BannerPosition::Top;
+        position.parse_from_value(Value::from("bottom_left"));
+        assert_eq!(position, BannerPosition::BottomLeft);
+        position.parse_from_value(Value::from("middle"));
+        assert_eq!(position, BannerPosition::BottomLeft);
+    }
+
+    #[test]
+    fn test_read_only_is_shown_in_the_banner() {
+        assert_eq!(
+            banner_text(Some("pairing"), true).as_deref(),
+            Some("pairing (read-only)")
+        );
+        assert_eq!(banner_text(None, true), None);
+        assert_eq!(banner_text(Some(""), false), None);
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use skia_safe::{Canvas, Color, Paint, Rect};
+
+use crate::{
+    log_ring::{connection_log_lines, recent_log_lines},
+    profiling::tracy_zone,
+    renderer::{fonts::font_loader::*, reconnect_indicator::first_loaded, RendererSettings},
+    settings::Settings,
+};
+
+const MARGIN: f32 = 16.0;
+
+static CONNECTION_LOG_SHOWN: AtomicBool = AtomicBool::new(false);
+static LOG_OVERLAY_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Shows or hides the connection log, from `neovide_connection_log_toggle_key`.
+pub fn toggle_connection_log() {
+    CONNECTION_LOG_SHOWN.fetch_xor(true, Ordering::Relaxed);
+}
+
+/// Shows or hides the whole log, from `neovide_log_overlay_toggle_key`.
+pub fn toggle_log_overlay() {
+    LOG_OVERLAY_SHOWN.fetch_xor(true, Ordering::Relaxed);
+}
+
+/// Shows the tail of the in-memory log while `neovide_log_overlay` is set or the overlay is toggled
+/// on, or only the connection records while the connection log is toggled on.
+pub struct LogOverlay {
+    /// `None` when not even the embedded fonts could be loaded, then nothing is drawn
+    font: Option<Arc<FontPair>>,
+    settings: Arc<Settings>,
+}
+
+/// The most recent lines that fit into `max_lines`.
+fn tail(lines: &[String], max_lines: usize) -> &[String] {
+    &lines[lines.len().saturating_sub(max_lines)..]
+}
+
+impl LogOverlay {
+    pub fn new(font_size: f32, settings: Arc<Settings>) -> Self {
+        let mut font_loader = FontLoader::new(font_size);
+        let font = first_loaded(
+            &mut font_loader,
+            &[FontKey::default()],
+            |loader, key| loader.get_or_load(key),
+            FontLoader::get_or_load_last_resort,
+        );
+        if font.is_none() {
+            log::error!("Could not load any font for the log overlay");
+        }
+        Self { font, settings }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.shows_whole_log() || CONNECTION_LOG_SHOWN.load(Ordering::Relaxed)
+    }
+
+    fn shows_whole_log(&self) -> bool {
+        self.settings.get::<RendererSettings>().log_overlay
+            || LOG_OVERLAY_SHOWN.load(Ordering::Relaxed)
+    }
+
+    fn lines(&self) -> Vec<String> {
+        if self.shows_whole_log() {
+            recent_log_lines()
+        } else {
+            connection_log_lines()
+        }
+    }
+
+    pub fn draw(&self, root_canvas: &Canvas) {
+        tracy_zone!("log_overlay_draw");
+        if !self.is_enabled() {
+            return;
+        }
+        let Some(font) = &self.font else {
+            return;
+        };
+
+        let size = root_canvas.base_layer_size();
+        let rect = Rect::from_xywh(
+            MARGIN,
+            MARGIN,
+            size.width as f32 / 2.0,
+            size.height as f32 - MARGIN * 2.0,
+        );
+        root_canvas.save();
+        root_canvas.clip_rect(rect, None, Some(false));
+
+        let mut paint = Paint::default();
+        paint.set_color(Color::from_argb(180, 30, 30, 30));
+        root_canvas.draw_paint(&paint);
+
+        let line_height = font.skia_font.size() * 1.2;
+        let max_lines = (rect.height() / line_height) as usize;
+        let lines = self.lines();
+
+        paint.set_color(Color::from_argb(255, 220, 220, 220));
+        let mut y = rect.top;
+        for line in tail(&lines, max_lines) {
+            y += line_height;
+            root_canvas.draw_str(line, (rect.left + 4.0, y), &font.skia_font, &paint);
+        }
+
+        root_canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_keeps_the_newest_lines() {
+        let lines = (0..5).map(|i| i.to_string()).collect::<Vec<_>>();
+        assert_eq!(tail(&lines, 2), ["3", "4"]);
+        assert_eq!(tail(&lines, 10).len(), 5);
+    }
+}
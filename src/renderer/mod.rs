@@ -1,13 +1,16 @@
 pub mod animation_utils;
+mod banner;
 pub mod box_drawing;
 pub mod cursor_renderer;
 pub mod fonts;
 pub mod grid_renderer;
+mod log_overlay;
 pub mod opengl;
 pub mod profiler;
 mod reconnect_indicator;
 mod rendered_layer;
 mod rendered_window;
+mod toast;
 mod vsync;
 
 #[cfg(target_os = "windows")]
@@ -35,6 +38,7 @@ use winit::{
 
 use crate::{
     bridge::EditorMode,
+    clock::SystemClock,
     cmd_line::CmdLineSettings,
     editor::{Cursor, Style, WindowType},
     profiling::{tracy_create_gpu_context, tracy_named_frame, tracy_zone},
@@ -57,11 +61,16 @@ use skia_safe::graphics::{
 #[cfg(feature = "gpu_profiling")]
 use crate::profiling::GpuCtx;
 
+use banner::{Banner, BannerPosition};
 use cursor_renderer::CursorRenderer;
 pub use fonts::caching_shaper::CachingShaper;
 pub use grid_renderer::GridRenderer;
+use log_overlay::LogOverlay;
+pub use log_overlay::{toggle_connection_log, toggle_log_overlay};
 use reconnect_indicator::ReconnectIndicator;
+pub use reconnect_indicator::{ReconnectIndicatorSettings, ReconnectIndicatorSettingsChanged};
 pub use rendered_window::{LineFragment, RenderedWindow, WindowDrawCommand, WindowDrawDetails};
+use toast::Toasts;
 
 pub use vsync::VSync;
 
@@ -102,10 +111,12 @@ pub struct RendererSettings {
     light_radius: f32,
     debug_renderer: bool,
     profiler: bool,
+    log_overlay: bool,
     underline_stroke_scale: f32,
     text_gamma: f32,
     text_contrast: f32,
     experimental_layer_grouping: bool,
+    banner_position: BannerPosition,
 }
 
 impl Default for RendererSettings {
@@ -124,10 +135,12 @@ impl Default for RendererSettings {
             light_radius: 5.,
             debug_renderer: false,
             profiler: false,
+            log_overlay: false,
             underline_stroke_scale: 1.,
             text_gamma: 0.0,
             text_contrast: 0.5,
             experimental_layer_grouping: false,
+            banner_position: BannerPosition::Top,
         }
     }
 }
@@ -161,7 +174,10 @@ pub struct Renderer {
     pub window_regions: Vec<WindowDrawDetails>,
 
     profiler: profiler::Profiler,
+    log_overlay: LogOverlay,
     reconnect_indicator: ReconnectIndicator,
+    toasts: Toasts,
+    banner: Banner,
     pub os_scale_factor: f64,
     pub user_scale_factor: f64,
 
@@ -190,7 +206,12 @@ impl Renderer {
         let window_regions = Vec::new();
 
         let profiler = profiler::Profiler::new(12.0, settings.clone());
-        let reconnect_indicator = ReconnectIndicator::new(settings.clone());
+        let log_overlay = LogOverlay::new(12.0, settings.clone());
+        let mut reconnect_indicator =
+            ReconnectIndicator::new(settings.clone(), Arc::new(SystemClock));
+        reconnect_indicator.set_scale_factor(os_scale_factor);
+        let toasts = Toasts::new();
+        let banner = Banner::new(settings.clone());
 
         Renderer {
             rendered_windows,
@@ -199,7 +220,10 @@ impl Renderer {
             current_mode,
             window_regions,
             profiler,
+            log_overlay,
             reconnect_indicator,
+            toasts,
+            banner,
             os_scale_factor,
             user_scale_factor,
             settings,
@@ -208,21 +232,71 @@ impl Renderer {
 
     pub fn handle_event(&mut self, event: &WindowEvent) {
         self.cursor_renderer.handle_event(event);
+        if let WindowEvent::Focused(focused) = event {
+            self.reconnect_indicator.set_focused(*focused);
+        }
+    }
+
+    /// The name of the monitor the window is on, which `reconnect_overlay_monitor` is matched to.
+    pub fn set_monitor(&mut self, monitor: Option<String>) {
+        self.reconnect_indicator.set_monitor(monitor);
+    }
+
+    pub fn start_connect(&mut self, address: String) {
+        self.reconnect_indicator.connect(address);
+    }
+
+    pub fn stabilize_connect(&mut self, address: String) {
+        self.reconnect_indicator.stabilize(address);
     }
 
     pub fn start_reconnect(&mut self, address: String, wait: Duration) {
         self.reconnect_indicator.start(address, wait);
     }
 
+    pub fn pause_reconnect(&mut self, address: String) {
+        self.reconnect_indicator.pause(address);
+    }
+
+    pub fn idle_disconnect(&mut self, address: String) {
+        self.reconnect_indicator.idle(address);
+    }
+
     pub fn stop_reconnect(&mut self) {
         self.reconnect_indicator.stop();
     }
 
+    pub fn fail_connect(&mut self, address: String, error: String) {
+        self.reconnect_indicator.fail(address, error);
+    }
+
+    /// Refreshes what's derived from the settings once they are read from Neovim.
+    pub fn handle_settings_loaded(&mut self) {
+        self.reconnect_indicator.update_from_settings();
+        self.banner.update_font();
+    }
+
+    pub fn show_toast(&mut self, message: String) {
+        self.toasts.show(message);
+    }
+
+    /// Shows the read-only mode in the `--banner`, or in a notice when there's no banner.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.banner.set_read_only(read_only);
+        let notice = read_only && !self.banner.is_shown();
+        self.toasts
+            .set_sticky(notice.then(|| "read-only".to_string()));
+    }
+
     pub fn font_names(&self) -> Vec<String> {
         self.grid_renderer.font_names()
     }
 
     pub fn prepare_frame(&mut self) -> ShouldRender {
+        // The cursor doesn't blink on a frozen frame
+        if self.reconnect_indicator.is_freezing() {
+            return ShouldRender::Wait;
+        }
         self.cursor_renderer.prepare_frame()
     }
 
@@ -341,7 +415,13 @@ impl Renderer {
             .draw(&mut self.grid_renderer, root_canvas);
 
         self.profiler.draw(root_canvas, dt);
+        self.log_overlay.draw(root_canvas);
         self.reconnect_indicator.draw(root_canvas);
+        self.toasts.draw(root_canvas);
+        self.banner.draw(
+            root_canvas,
+            self.settings.get::<RendererSettings>().banner_position,
+        );
 
         root_canvas.restore();
 
@@ -350,6 +430,12 @@ impl Renderer {
     }
 
     pub fn animate_frame(&mut self, grid_rect: &GridRect<f32>, dt: f32) -> bool {
+        if self.reconnect_indicator.is_freezing() {
+            // Keeps the windows and the cursor where the last frame before the disconnect had
+            // them, only the status line of the indicator changes
+            self.reconnect_indicator.update(dt);
+            return true;
+        }
         let windows = {
             let (mut root_windows, mut floating_windows): (
                 Vec<&mut RenderedWindow>,
@@ -385,7 +471,11 @@ impl Renderer {
             .animate(&self.current_mode, &self.grid_renderer, dt);
         self.reconnect_indicator.update(dt);
 
-        animating |= self.reconnect_indicator.is_active();
+        animating |= self.reconnect_indicator.is_shown() || self.reconnect_indicator.is_pending();
+        self.toasts.update();
+        animating |= self.toasts.is_active();
+        // Keep redrawing so that new log records show up
+        animating |= self.log_overlay.is_enabled();
 
         animating
     }
@@ -425,6 +515,7 @@ impl Renderer {
 
     pub fn handle_os_scale_factor_change(&mut self, os_scale_factor: f64) {
         self.os_scale_factor = os_scale_factor;
+        self.reconnect_indicator.set_scale_factor(os_scale_factor);
         self.grid_renderer
             .handle_scale_factor_update(self.os_scale_factor * self.user_scale_factor);
     }
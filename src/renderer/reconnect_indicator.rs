@@ -1,107 +1,1663 @@
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::mem;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use skia_safe::{paint::Style, Canvas, Color, Paint, Path, Point, Rect};
+use nvim_rs::Value;
+use skia_safe::{
+    canvas::SaveLayerRec, font::Edging, image_filters::blur, paint::Style, BlendMode, Canvas,
+    Color, Font, ImageFilter, Paint, Path, Point, Rect,
+};
 
+use crate::bridge::reconnect_notice;
+use crate::clock::Clock;
 use crate::profiling::tracy_zone;
-use crate::renderer::fonts::font_loader::{FontKey, FontLoader, FontPair};
-use crate::settings::Settings;
+use crate::renderer::fonts::{
+    font_loader::{FontKey, FontLoader, FontPair},
+    font_options::FontDescription,
+};
+use crate::settings::{ParseFromValue, Settings};
+
+/// How the content behind the reconnect overlay is hidden.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconnectOverlay {
+    Dim,
+    Blur,
+}
+
+impl ParseFromValue for ReconnectOverlay {
+    fn parse_from_value(&mut self, value: Value) {
+        match value.as_str() {
+            Some("dim") => *self = ReconnectOverlay::Dim,
+            Some("blur") => *self = ReconnectOverlay::Blur,
+            _ => log::error!(
+                "Setting reconnect_overlay expected one of `dim` or `blur`, but received {value:?}"
+            ),
+        }
+    }
+}
+
+impl From<ReconnectOverlay> for Value {
+    fn from(overlay: ReconnectOverlay) -> Self {
+        match overlay {
+            ReconnectOverlay::Dim => Value::from("dim"),
+            ReconnectOverlay::Blur => Value::from("blur"),
+        }
+    }
+}
+
+/// What the reconnect message shows while waiting for the next attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconnectDisplay {
+    /// The seconds until the next attempt
+    Countdown,
+    /// How long the connection has been down, over all the attempts
+    Elapsed,
+}
+
+impl ParseFromValue for ReconnectDisplay {
+    fn parse_from_value(&mut self, value: Value) {
+        match value.as_str() {
+            Some("countdown") => *self = ReconnectDisplay::Countdown,
+            Some("elapsed") => *self = ReconnectDisplay::Elapsed,
+            _ => log::error!(
+                "Setting reconnect_display expected one of `countdown` or `elapsed`, but received \
+                 {value:?}"
+            ),
+        }
+    }
+}
+
+impl From<ReconnectDisplay> for Value {
+    fn from(display: ReconnectDisplay) -> Self {
+        match display {
+            ReconnectDisplay::Countdown => Value::from("countdown"),
+            ReconnectDisplay::Elapsed => Value::from("elapsed"),
+        }
+    }
+}
+
+/// The animation drawn above the reconnect message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconnectAnimationStyle {
+    /// A quarter circle arc spinning once per second
+    Spinner,
+    /// A ring that empties until the next attempt
+    Ring,
+}
+
+impl ReconnectAnimationStyle {
+    fn animation(self) -> Box<dyn ReconnectAnimation> {
+        match self {
+            ReconnectAnimationStyle::Spinner => Box::new(Spinner::new(FONT_SIZE)),
+            ReconnectAnimationStyle::Ring => Box::new(Ring::new(FONT_SIZE)),
+        }
+    }
+}
+
+impl ParseFromValue for ReconnectAnimationStyle {
+    fn parse_from_value(&mut self, value: Value) {
+        match value.as_str() {
+            Some("spinner") => *self = ReconnectAnimationStyle::Spinner,
+            Some("ring") => *self = ReconnectAnimationStyle::Ring,
+            _ => log::error!(
+                "Setting reconnect_animation expected one of `spinner` or `ring`, but received \
+                 {value:?}"
+            ),
+        }
+    }
+}
+
+impl From<ReconnectAnimationStyle> for Value {
+    fn from(style: ReconnectAnimationStyle) -> Self {
+        match style {
+            ReconnectAnimationStyle::Spinner => Value::from("spinner"),
+            ReconnectAnimationStyle::Ring => Value::from("ring"),
+        }
+    }
+}
+
+/// The `g:neovide_reconnect_` settings of the overlay, the ones of the reconnect loop itself are
+/// the `ReconnectSettings` of the bridge.
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "reconnect"]
+pub struct ReconnectIndicatorSettings {
+    min_display: f32,
+    overlay_delay: f32,
+    /// Blocks the input while the overlay is shown, since there's no Neovim to receive it
+    pub modal: bool,
+    pub dim: bool,
+    pub overlay: ReconnectOverlay,
+    pub blur_radius: f32,
+    /// The font family of the message, the default font when empty
+    pub font: String,
+    pub display: ReconnectDisplay,
+    pub animation: ReconnectAnimationStyle,
+    /// Anti-aliases the message and the spinner, off for crisper text on low-DPI displays
+    pub anti_alias: bool,
+    /// Positions the glyphs of the message at subpixels
+    pub subpixel: bool,
+    /// Keeps the last frame as it is while disconnected, with only a status line over it
+    pub freeze_last_frame: bool,
+    /// Only draws the overlay while the window has the focus
+    pub overlay_focus_only: bool,
+    /// Only draws the overlay while the window is on the monitor with this name, any when empty
+    pub overlay_monitor: String,
+    /// The color of the message and the animation, white when empty
+    pub color: String,
+    /// The color the content is dimmed with, translucent black when empty
+    pub dim_color: String,
+}
+
+impl Default for ReconnectIndicatorSettings {
+    fn default() -> Self {
+        Self {
+            min_display: 0.0,
+            overlay_delay: 0.0,
+            modal: true,
+            dim: true,
+            overlay: ReconnectOverlay::Dim,
+            blur_radius: 8.0,
+            font: String::new(),
+            display: ReconnectDisplay::Countdown,
+            animation: ReconnectAnimationStyle::Spinner,
+            anti_alias: true,
+            subpixel: true,
+            freeze_last_frame: false,
+            overlay_focus_only: false,
+            overlay_monitor: String::new(),
+            color: String::new(),
+            dim_color: String::new(),
+        }
+    }
+}
+
+/// The longest a delay from the settings is taken to be, so that adding it to an `Instant` can't
+/// overflow.
+const MAX_SETTING_DELAY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// A delay in seconds from the settings, negative delays are none, and huge or infinite ones are
+/// capped at `MAX_SETTING_DELAY`.
+fn setting_delay(secs: f32) -> Duration {
+    Duration::try_from_secs_f32(secs.max(0.0))
+        .unwrap_or(Duration::MAX)
+        .min(MAX_SETTING_DELAY)
+}
+
+/// Tracks when the indicator becomes visible, so that it can stay hidden for blips shorter than
+/// the overlay delay, and a `stop` can be deferred until it has been shown for the minimum display
+/// time.
+#[derive(Default)]
+struct Visibility {
+    shown_at: Option<Instant>,
+    hide_at: Option<Instant>,
+}
+
+impl Visibility {
+    fn show(&mut self, now: Instant, delay: Duration) {
+        if !self.is_started(now) {
+            self.shown_at = Some(now + delay);
+        }
+        self.hide_at = None;
+    }
+
+    fn hide(&mut self, now: Instant, min_display: Duration) {
+        match self.shown_at {
+            // Stopped within the delay, so it's never shown
+            Some(shown_at) if now < shown_at => *self = Self::default(),
+            Some(shown_at) => self.hide_at = Some(now.max(shown_at + min_display)),
+            None => {}
+        }
+    }
+
+    /// Whether it's started and not hidden yet, including while the overlay delay is running.
+    fn is_started(&self, now: Instant) -> bool {
+        self.shown_at.is_some() && self.hide_at.is_none_or(|hide_at| now < hide_at)
+    }
+
+    fn is_visible(&self, now: Instant) -> bool {
+        self.is_started(now) && self.shown_at.is_some_and(|shown_at| shown_at <= now)
+    }
+}
+
+/// Returns when a countdown of `wait` started at `now` ends, moved forward to the next whole
+/// wall-clock second, so that the displayed seconds tick down together with the system clock. The
+/// next attempt therefore starts while the countdown still shows 1s, like the window title.
+fn snapped_end_time(now: Instant, wall_clock: SystemTime, wait: Duration) -> Instant {
+    let subsec = wall_clock
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| Duration::from_nanos(since_epoch.subsec_nanos().into()))
+        .unwrap_or_default();
+    if wait.is_zero() || subsec.is_zero() {
+        now + wait
+    } else {
+        now + wait + (Duration::from_secs(1) - subsec)
+    }
+}
+
+/// Whole seconds, rounded up.
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// The number of seconds shown for a countdown ending at `end_time`. The snapping adds up to a
+/// second, which isn't shown, so the countdown doesn't start above the `wait_secs` it was started
+/// with.
+fn displayed_secs(end_time: Instant, now: Instant, wait_secs: u64) -> u64 {
+    ceil_secs(end_time.saturating_duration_since(now)).min(wait_secs)
+}
+
+/// How the indicator renders, from `neovide_reconnect_anti_alias` and
+/// `neovide_reconnect_subpixel`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TextRendering {
+    anti_alias: bool,
+    subpixel: bool,
+}
+
+impl TextRendering {
+    fn new(settings: &ReconnectIndicatorSettings) -> Self {
+        Self {
+            anti_alias: settings.anti_alias,
+            subpixel: settings.subpixel,
+        }
+    }
+
+    fn edging(self) -> Edging {
+        if self.anti_alias {
+            Edging::AntiAlias
+        } else {
+            Edging::Alias
+        }
+    }
+
+    fn paint(self) -> Paint {
+        let mut paint = Paint::default();
+        paint.set_anti_alias(self.anti_alias);
+        paint
+    }
+
+    /// `font` set up to render like this, the loaded one is shared with the cache of the loader.
+    fn font(self, font: &Font) -> Font {
+        let mut font = font.clone();
+        font.set_subpixel(self.subpixel);
+        font.set_edging(self.edging());
+        font
+    }
+}
+
+/// The colors of the overlay, from `neovide_reconnect_color` and `neovide_reconnect_dim_color`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct OverlayColors {
+    foreground: Color,
+    dim: Color,
+}
+
+impl OverlayColors {
+    fn new(settings: &ReconnectIndicatorSettings) -> Self {
+        Self {
+            foreground: parse_color("reconnect_color", &settings.color, Color::WHITE),
+            dim: parse_color(
+                "reconnect_dim_color",
+                &settings.dim_color,
+                Color::from_argb(160, 0, 0, 0),
+            ),
+        }
+    }
+}
+
+/// A CSS color like `#ff8800` or `rgba(0, 0, 0, 0.5)`, `default` when it's empty or invalid.
+fn parse_color(setting: &str, value: &str, default: Color) -> Color {
+    if value.is_empty() {
+        return default;
+    }
+    match csscolorparser::parse(value) {
+        Ok(color) => {
+            let [r, g, b, a] = color.to_rgba8();
+            Color::from_argb(a, r, g, b)
+        }
+        Err(error) => {
+            log::warn!("Setting {setting} expected a color, but received {value:?}: {error}");
+            default
+        }
+    }
+}
+
+/// The animation drawn above the reconnect message, replaceable through
+/// `ReconnectIndicator::set_animation`.
+pub trait ReconnectAnimation {
+    /// Starts the animation over, for a wait of `wait` until the next attempt.
+    fn reset(&mut self, wait: Duration);
+    /// Advances the animation, only called while the indicator is visible.
+    fn update(&mut self, dt: f32);
+    /// Draws the animation centered on `center`, `remaining` is the time until the next attempt.
+    /// `paint` has the color and the anti-aliasing of the settings.
+    fn draw(&self, canvas: &Canvas, center: Point, remaining: Duration, paint: &Paint);
+}
+
+/// The default animation, a quarter circle arc spinning once per second.
+pub struct Spinner {
+    radius: f32,
+    angle: f32,
+}
+
+impl Spinner {
+    pub fn new(radius: f32) -> Self {
+        Self { radius, angle: 0.0 }
+    }
+}
+
+impl ReconnectAnimation for Spinner {
+    fn reset(&mut self, _wait: Duration) {
+        self.angle = 0.0;
+    }
+
+    fn update(&mut self, dt: f32) {
+        self.angle += dt * std::f32::consts::PI * 2.0;
+        if self.angle > std::f32::consts::PI * 2.0 {
+            self.angle -= std::f32::consts::PI * 2.0;
+        }
+    }
+
+    fn draw(&self, canvas: &Canvas, center: Point, _remaining: Duration, paint: &Paint) {
+        let mut paint = paint.clone();
+        paint.set_style(Style::Stroke);
+        paint.set_stroke_width(4.0);
+        let rect = Rect::from_xywh(
+            center.x - self.radius,
+            center.y - self.radius,
+            self.radius * 2.0,
+            self.radius * 2.0,
+        );
+        let mut path = Path::new();
+        let start_angle = self.angle.to_degrees();
+        let sweep_angle = 90.0;
+        path.arc_to(rect, start_angle, sweep_angle, true);
+        canvas.draw_path(&path, &paint);
+    }
+}
+
+/// A ring that empties clockwise from the top over the wait until the next attempt.
+pub struct Ring {
+    radius: f32,
+    wait: Duration,
+}
+
+impl Ring {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            wait: Duration::ZERO,
+        }
+    }
+
+    /// The part of the ring that's left, full at the start of the wait.
+    fn fraction(&self, remaining: Duration) -> f32 {
+        if self.wait.is_zero() {
+            return 0.0;
+        }
+        (remaining.as_secs_f32() / self.wait.as_secs_f32()).clamp(0.0, 1.0)
+    }
+}
+
+impl ReconnectAnimation for Ring {
+    fn reset(&mut self, wait: Duration) {
+        self.wait = wait;
+    }
+
+    fn update(&mut self, _dt: f32) {}
+
+    fn draw(&self, canvas: &Canvas, center: Point, remaining: Duration, paint: &Paint) {
+        let mut paint = paint.clone();
+        paint.set_style(Style::Stroke);
+        paint.set_stroke_width(4.0);
+        let alpha = paint.alpha();
+        paint.set_alpha(alpha / 3);
+        canvas.draw_circle(center, self.radius, &paint);
+
+        let rect = Rect::from_xywh(
+            center.x - self.radius,
+            center.y - self.radius,
+            self.radius * 2.0,
+            self.radius * 2.0,
+        );
+        paint.set_alpha(alpha);
+        canvas.draw_arc(rect, -90.0, 360.0 * self.fraction(remaining), false, &paint);
+    }
+}
+
+/// What the indicator is showing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Phase {
+    /// The first connection to the server, which has no countdown
+    Connecting,
+    /// Connected, but waiting for the connection to get fast enough
+    Stabilizing,
+    /// Counting down to the next reconnect attempt
+    Reconnecting,
+    Paused,
+    /// Disconnected by the idle timeout until the next input
+    Idle,
+    /// Stopped reconnecting because of the error
+    Failed(String),
+}
+
+impl Phase {
+    /// Whether the animation is shown, which nothing is waited for in the other phases
+    fn is_waiting(&self) -> bool {
+        matches!(
+            self,
+            Phase::Connecting | Phase::Stabilizing | Phase::Reconnecting
+        )
+    }
+}
+
+fn message(address: &str, phase: &Phase, secs: u64) -> String {
+    match phase {
+        Phase::Connecting => format!("Connecting to {address}…"),
+        Phase::Stabilizing => format!("Stabilizing the connection to {address}…"),
+        Phase::Reconnecting => format!("Reconnecting to {address} in {secs}s"),
+        Phase::Paused => format!("Reconnect to {address} paused"),
+        Phase::Idle => {
+            format!("Disconnected from {address} while idle, press any key to reconnect")
+        }
+        Phase::Failed(error) => format!("Could not connect to {address}: {error}"),
+    }
+}
+
+/// The message and, below it, the notice the server set before the connection was lost.
+fn overlay_messages(text: String, notice: Option<String>) -> Vec<String> {
+    std::iter::once(text).chain(notice).collect()
+}
+
+/// Formats `elapsed` like `45s`, `2m13s` or `1h05m00s`.
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h{minutes:02}m{secs:02}s")
+    } else if minutes > 0 {
+        format!("{minutes}m{secs:02}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+fn elapsed_message(address: &str, elapsed: Duration) -> String {
+    format!(
+        "Disconnected from {address} for {}, reconnecting…",
+        format_elapsed(elapsed)
+    )
+}
+
+/// The share of the canvas width the message may take up before it's wrapped.
+const MAX_TEXT_WIDTH: f32 = 0.8;
+
+/// Breaks `text` into lines no wider than `max_width`, between words where possible, and between
+/// characters for a word that doesn't fit on a line of its own, like a long socket path.
+fn wrap_text(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let joined = if line.is_empty() {
+            word.to_owned()
+        } else {
+            format!("{line} {word}")
+        };
+        if measure(&joined) <= max_width {
+            line = joined;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(mem::take(&mut line));
+        }
+        for c in word.chars() {
+            line.push(c);
+            // A single character wider than the line is still drawn
+            if measure(&line) > max_width && line.chars().count() > 1 {
+                line.pop();
+                lines.push(mem::replace(&mut line, c.to_string()));
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Whether the warning about a font that can't be measured was logged already.
+static UNMEASURABLE_FONT_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Whether `width` is a usable measurement of a text that isn't empty.
+fn is_measured(width: f32) -> bool {
+    width.is_finite() && width > 0.0
+}
+
+/// Wraps `text` to fit the canvas, and centers every line horizontally on `center_x`, starting at
+/// the baseline `top` and growing downward.
+///
+/// When the font measures `text` as zero wide or not at all, which some exotic fonts do, centering
+/// would move the lines anywhere, so they are left aligned in the margin instead, and wrapped as if
+/// every character was half as wide as the line is high.
+fn layout_text(
+    text: &str,
+    canvas_width: f32,
+    center_x: f32,
+    top: f32,
+    line_height: f32,
+    measure: impl Fn(&str) -> f32,
+) -> Vec<(String, Point)> {
+    let max_width = canvas_width * MAX_TEXT_WIDTH;
+    if text.is_empty() || is_measured(measure(text)) {
+        return wrap_text(text, max_width, &measure)
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| {
+                let position = Point::new(
+                    center_x - measure(&line) / 2.0,
+                    top + index as f32 * line_height,
+                );
+                (line, position)
+            })
+            .collect();
+    }
+
+    if !UNMEASURABLE_FONT_WARNED.swap(true, Ordering::Relaxed) {
+        log::warn!("The reconnect font can't measure {text:?}, left aligning the message");
+    }
+    let estimate = |line: &str| line.chars().count() as f32 * line_height * 0.5;
+    let margin = (canvas_width - max_width) / 2.0;
+    wrap_text(text, max_width, estimate)
+        .into_iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let position = Point::new(margin, top + index as f32 * line_height);
+            (line, position)
+        })
+        .collect()
+}
+
+/// What's drawn behind the reconnect overlay.
+#[derive(Debug, PartialEq)]
+enum Background<F> {
+    None,
+    Dim,
+    Blur(F),
+}
+
+/// The background for the settings, dimming when `make_blur` can't make a blur filter, which
+/// depends on the backend.
+fn background<F>(
+    settings: &ReconnectIndicatorSettings,
+    make_blur: impl FnOnce(f32) -> Option<F>,
+) -> Background<F> {
+    if !settings.dim || settings.freeze_last_frame {
+        return Background::None;
+    }
+    if settings.overlay == ReconnectOverlay::Blur && settings.blur_radius > 0.0 {
+        if let Some(filter) = make_blur(settings.blur_radius) {
+            return Background::Blur(filter);
+        }
+        log::debug!("Blur is not supported, dimming the reconnect overlay instead");
+    }
+    Background::Dim
+}
+
+/// Whether the overlay is drawn on a window with `focused` and on `monitor`. A monitor without a
+/// name doesn't hide it, since it can't be told apart from the configured one.
+fn overlay_allowed(
+    settings: &ReconnectIndicatorSettings,
+    focused: bool,
+    monitor: Option<&str>,
+) -> bool {
+    if settings.overlay_focus_only && !focused {
+        return false;
+    }
+    settings.overlay_monitor.is_empty()
+        || monitor.is_none_or(|monitor| monitor == settings.overlay_monitor)
+}
+
+fn update_animation(animation: &mut dyn ReconnectAnimation, visible: bool, dt: f32) {
+    if visible {
+        animation.update(dt);
+    }
+}
+
+const FONT_SIZE: f32 = 24.0;
+const STATUS_FONT_SIZE: f32 = 12.0;
+const STATUS_MARGIN: f32 = 6.0;
+const STATUS_PADDING: f32 = 4.0;
+
+/// The sizes of the overlay in physical pixels, the logical sizes multiplied by the scale factor
+/// of the window, so that it looks the same on every DPI.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Metrics {
+    scale: f32,
+    font_size: f32,
+    /// The animation is laid out as if it's as large as the default spinner
+    animation_radius: f32,
+    status_font_size: f32,
+    status_margin: f32,
+    status_padding: f32,
+}
+
+impl Metrics {
+    /// Falls back to a scale of 1 for a scale factor that isn't known.
+    fn new(scale: f32) -> Self {
+        let scale = if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        };
+        Self {
+            scale,
+            font_size: FONT_SIZE * scale,
+            animation_radius: FONT_SIZE * scale,
+            status_font_size: STATUS_FONT_SIZE * scale,
+            status_margin: STATUS_MARGIN * scale,
+            status_padding: STATUS_PADDING * scale,
+        }
+    }
+}
+
+/// The box of the status line of `neovide_reconnect_freeze_last_frame`, in the bottom left corner
+/// of a canvas `height` high.
+fn status_line_rect(metrics: &Metrics, height: f32, text_width: f32) -> Rect {
+    let box_height = metrics.status_font_size + metrics.status_padding * 2.0;
+    Rect::from_xywh(
+        metrics.status_margin,
+        height - metrics.status_margin - box_height,
+        text_width + metrics.status_padding * 2.0,
+        box_height,
+    )
+}
+
+/// The fonts to try for the message, the configured `family` first.
+pub(super) fn font_candidates(family: &str) -> Vec<FontKey> {
+    let configured = (!family.is_empty()).then(|| FontKey {
+        font_desc: Some(FontDescription {
+            family: family.to_owned(),
+            style: None,
+        }),
+        ..FontKey::default()
+    });
+    configured.into_iter().chain([FontKey::default()]).collect()
+}
+
+/// The first of `candidates` that `loader` loads, `last_resort` when none do.
+pub(super) fn first_loaded<L, T>(
+    loader: &mut L,
+    candidates: &[FontKey],
+    mut load: impl FnMut(&mut L, &FontKey) -> Option<T>,
+    last_resort: impl FnOnce(&mut L) -> Option<T>,
+) -> Option<T> {
+    candidates
+        .iter()
+        .find_map(|key| {
+            let font = load(loader, key);
+            if font.is_none() {
+                log::warn!("Could not load the font {key}");
+            }
+            font
+        })
+        .or_else(|| last_resort(loader))
+}
 
 pub struct ReconnectIndicator {
-    font: Arc<FontPair>,
-    active: bool,
+    /// `None` when not even the embedded fonts could be loaded, then only the animation is drawn
+    font: Option<Arc<FontPair>>,
+    /// The `reconnect_font` that `font` was loaded for
+    font_family: String,
+    loader: FontLoader,
+    visibility: Visibility,
     address: String,
     end_time: Instant,
-    angle: f32,
-    #[allow(dead_code)]
+    /// The seconds of the wait the countdown was started with, rounded up
+    wait_secs: u64,
+    /// When the connection was lost, for `reconnect_display` set to `elapsed`
+    disconnected_since: Option<Instant>,
+    phase: Phase,
+    animation: Box<dyn ReconnectAnimation>,
+    /// The `reconnect_animation` that `animation` was made for, `None` after `set_animation`
+    animation_style: Option<ReconnectAnimationStyle>,
+    colors: OverlayColors,
+    /// Whether the window has the focus, for `reconnect_overlay_focus_only`
+    focused: bool,
+    /// The name of the monitor the window is on, for `reconnect_overlay_monitor`
+    monitor: Option<String>,
+    /// The scale factor of the window
+    scale_factor: f32,
     settings: Arc<Settings>,
+    clock: Arc<dyn Clock>,
 }
 
 impl ReconnectIndicator {
-    pub fn new(settings: Arc<Settings>) -> Self {
-        let font_key = FontKey::default();
-        let mut loader = FontLoader::new(24.0);
-        let font = loader.get_or_load(&font_key).expect("Font load failed");
+    pub fn new(settings: Arc<Settings>, clock: Arc<dyn Clock>) -> Self {
+        let reconnect_settings = settings.get::<ReconnectIndicatorSettings>();
+        let font_family = reconnect_settings.font.clone();
+        let mut loader = FontLoader::new(FONT_SIZE);
+        let font = Self::load_font(&mut loader, &font_family);
+        let animation_style = reconnect_settings.animation;
         Self {
             font,
-            active: false,
+            font_family,
+            loader,
+            visibility: Visibility::default(),
             address: String::new(),
-            end_time: Instant::now(),
-            angle: 0.0,
+            end_time: clock.now(),
+            wait_secs: 0,
+            disconnected_since: None,
+            phase: Phase::Reconnecting,
+            animation: animation_style.animation(),
+            animation_style: Some(animation_style),
+            colors: OverlayColors::new(&reconnect_settings),
+            focused: true,
+            monitor: None,
+            scale_factor: 1.0,
             settings,
+            clock,
+        }
+    }
+
+    fn load_font(loader: &mut FontLoader, family: &str) -> Option<Arc<FontPair>> {
+        let font = first_loaded(
+            loader,
+            &font_candidates(family),
+            |loader, key| loader.get_or_load(key),
+            FontLoader::get_or_load_last_resort,
+        );
+        if font.is_none() {
+            log::error!("Could not load any font for the reconnect indicator");
         }
+        font
+    }
+
+    /// Reloads the font when `reconnect_font` changed since it was loaded, and the animation when
+    /// `reconnect_animation` did. The colors are read again as well.
+    pub fn update_from_settings(&mut self) {
+        let settings = self.settings.get::<ReconnectIndicatorSettings>();
+        self.colors = OverlayColors::new(&settings);
+        if settings.font != self.font_family {
+            self.font = Self::load_font(&mut self.loader, &settings.font);
+            self.font_family = settings.font;
+        }
+        if self
+            .animation_style
+            .is_some_and(|style| style != settings.animation)
+        {
+            self.set_animation(settings.animation.animation());
+            self.animation_style = Some(settings.animation);
+        }
+    }
+
+    /// Replaces the animation drawn above the message, which then stays regardless of
+    /// `reconnect_animation`.
+    pub fn set_animation(&mut self, animation: Box<dyn ReconnectAnimation>) {
+        self.animation = animation;
+        self.animation_style = None;
+    }
+
+    /// Shows that the first connection to `address` is being made, until `stop`, or `start` when
+    /// it failed.
+    pub fn connect(&mut self, address: String) {
+        self.update_from_settings();
+        self.address = address;
+        self.phase = Phase::Connecting;
+        let overlay_delay = self
+            .settings
+            .get::<ReconnectIndicatorSettings>()
+            .overlay_delay;
+        self.visibility
+            .show(self.clock.now(), setting_delay(overlay_delay));
+    }
+
+    /// Keeps showing the connecting overlay while the connection stabilizes, until `stop`.
+    pub fn stabilize(&mut self, address: String) {
+        self.update_from_settings();
+        self.address = address;
+        self.phase = Phase::Stabilizing;
+        let overlay_delay = self
+            .settings
+            .get::<ReconnectIndicatorSettings>()
+            .overlay_delay;
+        self.visibility
+            .show(self.clock.now(), setting_delay(overlay_delay));
     }
 
     pub fn start(&mut self, address: String, wait: Duration) {
+        self.update_from_settings();
         self.address = address;
-        self.end_time = Instant::now() + wait;
-        self.angle = 0.0;
-        self.active = true;
+        self.phase = Phase::Reconnecting;
+        self.end_time = snapped_end_time(self.clock.now(), self.clock.wall_clock(), wait);
+        self.wait_secs = ceil_secs(wait);
+        self.animation
+            .reset(self.end_time.saturating_duration_since(self.clock.now()));
+        self.disconnected_since.get_or_insert(self.clock.now());
+        let overlay_delay = self
+            .settings
+            .get::<ReconnectIndicatorSettings>()
+            .overlay_delay;
+        self.visibility
+            .show(self.clock.now(), setting_delay(overlay_delay));
     }
 
+    /// Shows that reconnecting is paused until the next `start`, without a countdown.
+    pub fn pause(&mut self, address: String) {
+        self.update_from_settings();
+        self.address = address;
+        self.phase = Phase::Paused;
+        self.visibility.show(self.clock.now(), Duration::ZERO);
+    }
+
+    /// Shows that the idle timeout disconnected from `address`, until the next `connect`.
+    pub fn idle(&mut self, address: String) {
+        self.update_from_settings();
+        self.address = address;
+        self.phase = Phase::Idle;
+        self.visibility.show(self.clock.now(), Duration::ZERO);
+    }
+
+    /// Shows that reconnecting stopped because of `error`, until the window is closed.
+    pub fn fail(&mut self, address: String, error: String) {
+        self.update_from_settings();
+        self.address = address;
+        self.phase = Phase::Failed(error);
+        self.visibility.show(self.clock.now(), Duration::ZERO);
+    }
+
+    /// Hides the indicator, but not before it has been visible for `reconnect_min_display`
+    /// seconds.
     pub fn stop(&mut self) {
-        self.active = false;
+        let min_display = self
+            .settings
+            .get::<ReconnectIndicatorSettings>()
+            .min_display;
+        self.disconnected_since = None;
+        self.visibility
+            .hide(self.clock.now(), setting_delay(min_display));
     }
 
     pub fn is_active(&self) -> bool {
-        self.active
+        self.visibility.is_visible(self.clock.now())
+    }
+
+    /// Whether the indicator is started but still waiting for `reconnect_overlay_delay`, frames
+    /// have to keep being rendered to show it once the delay is over.
+    pub fn is_pending(&self) -> bool {
+        let now = self.clock.now();
+        self.visibility.is_started(now) && !self.visibility.is_visible(now)
+    }
+
+    /// Whether the renderer should keep the last frame as it is, see
+    /// `neovide_reconnect_freeze_last_frame`.
+    pub fn is_freezing(&self) -> bool {
+        self.is_active()
+            && self
+                .settings
+                .get::<ReconnectIndicatorSettings>()
+                .freeze_last_frame
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    pub fn set_monitor(&mut self, monitor: Option<String>) {
+        self.monitor = monitor;
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor as f32;
+    }
+
+    /// Whether the overlay is drawn, it can be active but restricted to the focused window or
+    /// another monitor.
+    pub fn is_shown(&self) -> bool {
+        self.is_active()
+            && overlay_allowed(
+                &self.settings.get::<ReconnectIndicatorSettings>(),
+                self.focused,
+                self.monitor.as_deref(),
+            )
     }
 
     pub fn update(&mut self, dt: f32) {
-        if self.active {
-            self.angle += dt * std::f32::consts::PI * 2.0;
-            if self.angle > std::f32::consts::PI * 2.0 {
-                self.angle -= std::f32::consts::PI * 2.0;
+        let visible = self.is_shown() && self.phase.is_waiting();
+        update_animation(self.animation.as_mut(), visible, dt);
+    }
+
+    /// The lines of text drawn at `now`, the state of the connection and the notice below it.
+    fn messages(&self, settings: &ReconnectIndicatorSettings, now: Instant) -> Vec<String> {
+        let text = match (&self.phase, settings.display, self.disconnected_since) {
+            (Phase::Reconnecting, ReconnectDisplay::Elapsed, Some(since)) => {
+                elapsed_message(&self.address, now.saturating_duration_since(since))
             }
-        }
+            _ => message(
+                &self.address,
+                &self.phase,
+                displayed_secs(self.end_time, now, self.wait_secs),
+            ),
+        };
+        overlay_messages(text, reconnect_notice())
     }
 
     pub fn draw(&self, canvas: &Canvas) {
         tracy_zone!("reconnect_indicator_draw");
-        if !self.active {
+        if !self.is_shown() {
+            return;
+        }
+        let now = self.clock.now();
+        let settings = self.settings.get::<ReconnectIndicatorSettings>();
+        let messages = self.messages(&settings, now);
+        let rendering = TextRendering::new(&settings);
+        let metrics = Metrics::new(self.scale_factor);
+        if settings.freeze_last_frame {
+            self.draw_status_line(canvas, &messages.join(" · "), rendering, &metrics);
             return;
         }
-        let remaining = self.end_time.saturating_duration_since(Instant::now());
-        let secs = remaining.as_secs_f32().ceil() as u64;
-        let text = format!("Reconnecting to {} in {}s", self.address, secs);
 
         canvas.save();
 
-        let mut paint = Paint::default();
-        paint.set_anti_alias(true);
+        let mut paint = rendering.paint();
 
-        let spinner_radius = self.font.skia_font.size();
         let size = canvas.base_layer_size();
-        let center = (size.width as f32 / 2.0, size.height as f32 / 2.0);
+        let center = Point::new(size.width as f32 / 2.0, size.height as f32 / 2.0);
+
+        // Hide the content behind the overlay while reconnecting
+        let make_blur =
+            |radius| -> Option<ImageFilter> { blur((radius, radius), None, None, None) };
+        match background(&settings, make_blur) {
+            Background::Blur(filter) => {
+                let blur_paint = Paint::default().set_blend_mode(BlendMode::Src).to_owned();
+                let save_layer_rec = SaveLayerRec::default().backdrop(&filter).paint(&blur_paint);
+                canvas.save_layer(&save_layer_rec);
+                canvas.restore();
+            }
+            Background::Dim => {
+                paint.set_color(self.colors.dim);
+                canvas.draw_paint(&paint);
+            }
+            Background::None => {}
+        }
 
-        // Dim the background while reconnecting
-        paint.set_color(Color::from_argb(160, 0, 0, 0));
-        canvas.draw_paint(&paint);
+        paint.set_color(self.colors.foreground);
+        if self.phase.is_waiting() {
+            // Scaled around its center, which scales the radius and the stroke of any animation
+            canvas.save();
+            canvas.translate(center);
+            canvas.scale((metrics.scale, metrics.scale));
+            self.animation.draw(
+                canvas,
+                Point::new(0.0, 0.0),
+                self.end_time.saturating_duration_since(now),
+                &paint,
+            );
+            canvas.restore();
+        }
 
-        // Draw the spinner
-        paint.set_color(Color::WHITE);
-        paint.set_style(Style::Stroke);
-        paint.set_stroke_width(4.0);
-        let rect = Rect::from_xywh(
-            center.0 - spinner_radius,
-            center.1 - spinner_radius,
-            spinner_radius * 2.0,
-            spinner_radius * 2.0,
+        if let Some(font) = &self.font {
+            let mut font = rendering.font(&font.skia_font);
+            font.set_size(metrics.font_size);
+            let line_height = font.metrics().0;
+            let mut top = center.y + metrics.animation_radius + metrics.font_size * 2.0;
+            for message in &messages {
+                let lines = layout_text(
+                    message,
+                    size.width as f32,
+                    center.x,
+                    top,
+                    line_height,
+                    |line| font.measure_str(line, Some(&paint)).0,
+                );
+                top += lines.len() as f32 * line_height;
+                for (line, position) in lines {
+                    canvas.draw_str(line, position, &font, &paint);
+                }
+            }
+        }
+
+        canvas.restore();
+    }
+
+    /// Only the message, small in a corner, so that the frozen frame stays visible.
+    fn draw_status_line(
+        &self,
+        canvas: &Canvas,
+        text: &str,
+        rendering: TextRendering,
+        metrics: &Metrics,
+    ) {
+        let Some(font) = &self.font else {
+            return;
+        };
+        let mut font = rendering.font(&font.skia_font);
+        font.set_size(metrics.status_font_size);
+        let mut paint = rendering.paint();
+
+        let text_width = font.measure_str(text, Some(&paint)).0;
+        let rect = status_line_rect(metrics, canvas.base_layer_size().height as f32, text_width);
+        paint.set_color(Color::from_argb(200, 40, 40, 40));
+        canvas.draw_rect(rect, &paint);
+
+        paint.set_color(self.colors.foreground);
+        let position = Point::new(
+            rect.left + metrics.status_padding,
+            rect.bottom - metrics.status_padding - metrics.status_font_size * 0.2,
         );
-        let mut path = Path::new();
-        let start_angle = self.angle.to_degrees();
-        let sweep_angle = 90.0;
-        path.arc_to(rect, start_angle, sweep_angle, true);
-        canvas.draw_path(&path, &paint);
+        canvas.draw_str(text, position, &font, &paint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use skia_safe::surfaces;
+
+    use super::*;
+    use crate::{bridge::set_reconnect_notice, clock::MockClock};
+
+    #[derive(Debug, PartialEq)]
+    enum AnimationCall {
+        Reset(Duration),
+        Update(f32),
+        Draw { center: Point, remaining: Duration },
+    }
 
-        let width = self.font.skia_font.measure_str(&text, Some(&paint)).0;
-        let text_pos = Point::new(
-            center.0 - width / 2.0,
-            center.1 + spinner_radius + self.font.skia_font.size()*2.0,
+    type AnimationCalls = Rc<RefCell<Vec<AnimationCall>>>;
+
+    /// Records the calls the indicator makes
+    struct RecordingAnimation {
+        calls: AnimationCalls,
+    }
+
+    impl ReconnectAnimation for RecordingAnimation {
+        fn reset(&mut self, wait: Duration) {
+            self.calls.borrow_mut().push(AnimationCall::Reset(wait));
+        }
+
+        fn update(&mut self, dt: f32) {
+            self.calls.borrow_mut().push(AnimationCall::Update(dt));
+        }
+
+        fn draw(&self, _canvas: &Canvas, center: Point, remaining: Duration, _paint: &Paint) {
+            self.calls
+                .borrow_mut()
+                .push(AnimationCall::Draw { center, remaining });
+        }
+    }
+
+    fn recording_indicator() -> (ReconnectIndicator, Arc<MockClock>, AnimationCalls) {
+        let settings = Arc::new(Settings::new());
+        settings.register::<ReconnectIndicatorSettings>();
+        let clock = Arc::new(MockClock::new());
+        let mut indicator = ReconnectIndicator::new(settings, clock.clone());
+        let calls = AnimationCalls::default();
+        indicator.set_animation(Box::new(RecordingAnimation {
+            calls: calls.clone(),
+        }));
+        (indicator, clock, calls)
+    }
+
+    #[test]
+    fn test_animation_only_updates_while_visible() {
+        let (mut indicator, _clock, calls) = recording_indicator();
+        indicator.update(0.5);
+        indicator.start("localhost:6666".to_owned(), Duration::from_secs(3));
+        let Some(AnimationCall::Reset(wait)) = calls.borrow_mut().pop() else {
+            panic!("The animation wasn't reset");
+        };
+        // Snapped to the next second of the wall clock
+        assert!(wait >= Duration::from_secs(3) && wait < Duration::from_secs(4));
+
+        indicator.update(0.25);
+        indicator.pause("localhost:6666".to_owned());
+        indicator.update(0.125);
+        assert_eq!(*calls.borrow(), vec![AnimationCall::Update(0.25)]);
+    }
+
+    #[test]
+    fn test_animation_is_drawn_with_the_remaining_time() {
+        let (mut indicator, clock, calls) = recording_indicator();
+        let mut surface = surfaces::raster_n32_premul((800, 600)).unwrap();
+        indicator.draw(surface.canvas());
+        assert!(calls.borrow().is_empty());
+
+        indicator.start("localhost:6666".to_owned(), Duration::from_secs(3));
+        let AnimationCall::Reset(wait) = calls.borrow_mut().remove(0) else {
+            panic!("The animation wasn't reset");
+        };
+        clock.advance(Duration::from_secs(1));
+        indicator.draw(surface.canvas());
+        // Translated to the center of the window
+        assert_eq!(
+            *calls.borrow(),
+            vec![AnimationCall::Draw {
+                center: Point::new(0.0, 0.0),
+                remaining: wait - Duration::from_secs(1),
+            }]
         );
-        paint.set_style(Style::Fill);
-        canvas.draw_str(text, text_pos, &self.font.skia_font, &paint);
 
-        canvas.restore();
+        // Nothing is waited for while paused
+        calls.borrow_mut().clear();
+        indicator.pause("localhost:6666".to_owned());
+        indicator.draw(surface.canvas());
+        assert!(calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_animation_follows_the_setting() {
+        let settings = Arc::new(Settings::new());
+        settings.register::<ReconnectIndicatorSettings>();
+        let mut indicator = ReconnectIndicator::new(settings.clone(), Arc::new(MockClock::new()));
+        assert_eq!(
+            indicator.animation_style,
+            Some(ReconnectAnimationStyle::Spinner)
+        );
+
+        let mut reconnect_settings = settings.get::<ReconnectIndicatorSettings>();
+        reconnect_settings.animation = ReconnectAnimationStyle::Ring;
+        settings.set(&reconnect_settings);
+        indicator.update_from_settings();
+        assert_eq!(
+            indicator.animation_style,
+            Some(ReconnectAnimationStyle::Ring)
+        );
+
+        // A custom animation isn't replaced
+        indicator.set_animation(Box::new(Spinner::new(FONT_SIZE)));
+        reconnect_settings.animation = ReconnectAnimationStyle::Spinner;
+        settings.set(&reconnect_settings);
+        indicator.update_from_settings();
+        assert_eq!(indicator.animation_style, None);
+    }
+
+    #[test]
+    fn test_overlay_colors() {
+        let mut settings = ReconnectIndicatorSettings::default();
+        assert_eq!(
+            OverlayColors::new(&settings),
+            OverlayColors {
+                foreground: Color::WHITE,
+                dim: Color::from_argb(160, 0, 0, 0),
+            }
+        );
+
+        settings.color = "#ff8800".to_owned();
+        settings.dim_color = "not a color".to_owned();
+        assert_eq!(
+            OverlayColors::new(&settings),
+            OverlayColors {
+                foreground: Color::from_argb(255, 255, 136, 0),
+                dim: Color::from_argb(160, 0, 0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_ring_empties_over_the_wait() {
+        let mut ring = Ring::new(10.0);
+        assert_eq!(ring.fraction(Duration::from_secs(1)), 0.0);
+        ring.reset(Duration::from_secs(4));
+        assert_eq!(ring.fraction(Duration::from_secs(4)), 1.0);
+        assert_eq!(ring.fraction(Duration::from_secs(1)), 0.25);
+        assert_eq!(ring.fraction(Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn test_paint_follows_the_rendering_settings() {
+        let mut settings = ReconnectIndicatorSettings::default();
+        let rendering = TextRendering::new(&settings);
+        assert_eq!(
+            rendering,
+            TextRendering {
+                anti_alias: true,
+                subpixel: true
+            }
+        );
+        assert!(rendering.paint().is_anti_alias());
+        assert_eq!(rendering.edging(), Edging::AntiAlias);
+
+        settings.anti_alias = false;
+        settings.subpixel = false;
+        let rendering = TextRendering::new(&settings);
+        assert!(!rendering.paint().is_anti_alias());
+        assert_eq!(rendering.edging(), Edging::Alias);
+        assert!(!rendering.subpixel);
+    }
+
+    #[test]
+    fn test_unfocused_window_skips_the_overlay() {
+        let mut settings = ReconnectIndicatorSettings::default();
+        assert!(overlay_allowed(&settings, false, Some("DP-1")));
+
+        settings.overlay_focus_only = true;
+        assert!(!overlay_allowed(&settings, false, Some("DP-1")));
+        assert!(overlay_allowed(&settings, true, Some("DP-1")));
+
+        settings.overlay_monitor = "HDMI-1".to_owned();
+        assert!(!overlay_allowed(&settings, true, Some("DP-1")));
+        assert!(overlay_allowed(&settings, true, Some("HDMI-1")));
+        assert!(overlay_allowed(&settings, true, None));
+    }
+
+    #[test]
+    fn test_spinner_wraps_around() {
+        let mut spinner = Spinner::new(10.0);
+        spinner.update(0.75);
+        spinner.update(0.5);
+        assert!((spinner.angle - std::f32::consts::PI * 0.5).abs() < 1e-4);
+
+        spinner.reset(Duration::from_secs(1));
+        assert_eq!(spinner.angle, 0.0);
+    }
+
+    #[test]
+    fn test_notice_is_an_extra_line() {
+        let text = message("localhost:6666", &Phase::Reconnecting, 3);
+        assert_eq!(
+            overlay_messages(text.clone(), Some("Maintenance in 5 min".to_owned())),
+            [text.clone(), "Maintenance in 5 min".to_owned()]
+        );
+        assert_eq!(overlay_messages(text.clone(), None), [text]);
+    }
+
+    #[serial_test::serial]
+    #[test]
+    fn test_notice_is_drawn_below_the_message() {
+        let (mut indicator, clock, _calls) = recording_indicator();
+        indicator.start("localhost:6666".to_owned(), Duration::from_secs(3));
+        let settings = indicator.settings.get::<ReconnectIndicatorSettings>();
+
+        set_reconnect_notice("Maintenance in 5 min".to_owned());
+        let messages = indicator.messages(&settings, clock.now());
+        set_reconnect_notice(String::new());
+        assert_eq!(
+            messages,
+            [
+                "Reconnecting to localhost:6666 in 3s".to_owned(),
+                "Maintenance in 5 min".to_owned()
+            ]
+        );
+        assert_eq!(indicator.messages(&settings, clock.now()).len(), 1);
+    }
+
+    #[test]
+    fn test_paused_message() {
+        assert_eq!(
+            message("localhost:6666", &Phase::Reconnecting, 3),
+            "Reconnecting to localhost:6666 in 3s"
+        );
+        assert_eq!(
+            message("localhost:6666", &Phase::Paused, 3),
+            "Reconnect to localhost:6666 paused"
+        );
+        assert_eq!(
+            message("localhost:6666", &Phase::Connecting, 0),
+            "Connecting to localhost:6666…"
+        );
+        assert_eq!(
+            message(
+                "localhost:6666",
+                &Phase::Failed("The proxy rejected the credentials".to_owned()),
+                0
+            ),
+            "Could not connect to localhost:6666: The proxy rejected the credentials"
+        );
+        assert_eq!(
+            message("localhost:6666", &Phase::Idle, 0),
+            "Disconnected from localhost:6666 while idle, press any key to reconnect"
+        );
+        assert_eq!(
+            message("localhost:6666", &Phase::Stabilizing, 0),
+            "Stabilizing the connection to localhost:6666…"
+        );
+        assert!(!Phase::Failed(String::new()).is_waiting());
+        assert!(!Phase::Paused.is_waiting());
+    }
+
+    #[test]
+    fn test_elapsed_across_minute_boundaries() {
+        let at = |secs: u64| format_elapsed(Duration::from_millis(secs * 1000 + 999));
+        assert_eq!(at(0), "0s");
+        assert_eq!(at(59), "59s");
+        assert_eq!(at(60), "1m00s");
+        assert_eq!(at(133), "2m13s");
+        assert_eq!(at(3599), "59m59s");
+        assert_eq!(at(3600), "1h00m00s");
+        assert_eq!(at(3905), "1h05m05s");
+        assert_eq!(
+            elapsed_message("localhost:6666", Duration::from_secs(133)),
+            "Disconnected from localhost:6666 for 2m13s, reconnecting…"
+        );
+
+        let mut display = ReconnectDisplay::Countdown;
+        display.parse_from_value(Value::from("elapsed"));
+        assert_eq!(display, ReconnectDisplay::Elapsed);
+        display.parse_from_value(Value::from("total"));
+        assert_eq!(display, ReconnectDisplay::Elapsed);
+    }
+
+    #[test]
+    fn test_end_time_snaps_to_wall_clock_second() {
+        let now = Instant::now();
+        let wall_clock = UNIX_EPOCH + Duration::from_millis(1_000_250);
+        let end_time = snapped_end_time(now, wall_clock, Duration::from_secs(3));
+        assert_eq!(end_time - now, Duration::from_millis(3750));
+
+        let on_boundary = UNIX_EPOCH + Duration::from_secs(1000);
+        let end_time = snapped_end_time(now, on_boundary, Duration::from_secs(3));
+        assert_eq!(end_time - now, Duration::from_secs(3));
+
+        let end_time = snapped_end_time(now, wall_clock, Duration::ZERO);
+        assert_eq!(end_time, now);
+    }
+
+    #[test]
+    fn test_displayed_secs() {
+        let start = Instant::now();
+        // Started 250ms into a wall-clock second, so a 3s countdown ends 3.75s later
+        let end_time = start + Duration::from_millis(3750);
+        let at = |millis| displayed_secs(end_time, start + Duration::from_millis(millis), 3);
+
+        // The extra fraction of a second isn't shown
+        assert_eq!(at(0), 3);
+        assert_eq!(at(749), 3);
+        // 750ms later the wall clock reaches a whole second, and the display ticks
+        assert_eq!(at(750), 3);
+        assert_eq!(at(1750), 2);
+        assert_eq!(at(1751), 2);
+        assert_eq!(at(2750), 1);
+        // The next attempt starts while it still shows 1s
+        assert_eq!(at(3000), 1);
+        assert_eq!(at(3749), 1);
+        assert_eq!(at(3750), 0);
+        assert_eq!(at(10_000), 0);
+    }
+
+    #[test]
+    fn test_early_stop_waits_for_min_display() {
+        let start = Instant::now();
+        let min_display = Duration::from_secs(2);
+        let mut visibility = Visibility::default();
+
+        visibility.show(start, Duration::ZERO);
+        visibility.hide(start + Duration::from_millis(100), min_display);
+
+        assert!(visibility.is_visible(start + Duration::from_millis(100)));
+        assert!(visibility.is_visible(start + Duration::from_millis(1900)));
+        assert!(!visibility.is_visible(start + min_display));
+    }
+
+    #[test]
+    fn test_stop_without_min_display_hides_immediately() {
+        let start = Instant::now();
+        let mut visibility = Visibility::default();
+
+        visibility.show(start, Duration::ZERO);
+        visibility.hide(start, Duration::ZERO);
+
+        assert!(!visibility.is_visible(start));
+    }
+
+    #[test]
+    fn test_restart_cancels_pending_hide() {
+        let start = Instant::now();
+        let mut visibility = Visibility::default();
+
+        visibility.show(start, Duration::ZERO);
+        visibility.hide(start, Duration::from_secs(1));
+        visibility.show(start + Duration::from_millis(500), Duration::ZERO);
+
+        assert!(visibility.is_visible(start + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_quick_stop_within_delay_shows_nothing() {
+        let start = Instant::now();
+        let delay = Duration::from_millis(300);
+        let mut visibility = Visibility::default();
+
+        visibility.show(start, delay);
+        assert!(!visibility.is_visible(start));
+        assert!(visibility.is_started(start));
+
+        // Even with a minimum display time, the overlay was never visible
+        visibility.hide(start + Duration::from_millis(200), Duration::from_secs(2));
+        for millis in [200, 300, 1000, 5000] {
+            assert!(!visibility.is_visible(start + Duration::from_millis(millis)));
+        }
+        assert!(!visibility.is_started(start + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn test_shown_after_delay() {
+        let start = Instant::now();
+        let delay = Duration::from_millis(300);
+        let mut visibility = Visibility::default();
+
+        visibility.show(start, delay);
+        // Another attempt doesn't restart the delay
+        visibility.show(start + Duration::from_millis(100), delay);
+
+        assert!(!visibility.is_visible(start + Duration::from_millis(299)));
+        assert!(visibility.is_visible(start + delay));
+        visibility.hide(start + Duration::from_secs(1), Duration::ZERO);
+        assert!(!visibility.is_visible(start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_huge_overlay_delay_is_capped() {
+        assert_eq!(setting_delay(-1.0), Duration::ZERO);
+        assert_eq!(setting_delay(f32::NAN), Duration::ZERO);
+        assert_eq!(setting_delay(1.5), Duration::from_millis(1500));
+        // What `let g:neovide_reconnect_overlay_delay = 1e39` ends up as
+        assert_eq!(setting_delay(f32::INFINITY), MAX_SETTING_DELAY);
+        assert_eq!(setting_delay(f32::MAX), MAX_SETTING_DELAY);
+
+        let (mut indicator, clock, _calls) = recording_indicator();
+        let mut reconnect_settings = indicator.settings.get::<ReconnectIndicatorSettings>();
+        reconnect_settings.overlay_delay = f32::INFINITY;
+        indicator.settings.set(&reconnect_settings);
+        indicator.connect("localhost:6666".to_owned());
+        indicator.start("localhost:6666".to_owned(), Duration::from_secs(3));
+        clock.advance(Duration::from_secs(60));
+        assert!(indicator.is_pending());
+        assert!(!indicator.is_active());
+    }
+
+    #[test]
+    fn test_huge_min_display_is_capped() {
+        let (mut indicator, clock, _calls) = recording_indicator();
+        let mut reconnect_settings = indicator.settings.get::<ReconnectIndicatorSettings>();
+        reconnect_settings.min_display = f32::INFINITY;
+        indicator.settings.set(&reconnect_settings);
+        indicator.start("localhost:6666".to_owned(), Duration::from_secs(3));
+        indicator.stop();
+        clock.advance(Duration::from_secs(60));
+        assert!(indicator.is_active());
+        clock.advance(MAX_SETTING_DELAY);
+        assert!(!indicator.is_active());
+    }
+
+    #[test]
+    fn test_overlay_parsing() {
+        let mut overlay = ReconnectOverlay::Dim;
+        overlay.parse_from_value(Value::from("blur"));
+        assert_eq!(overlay, ReconnectOverlay::Blur);
+        // Unknown values keep the previous mode
+        overlay.parse_from_value(Value::from("frosted"));
+        assert_eq!(overlay, ReconnectOverlay::Blur);
+        overlay.parse_from_value(Value::from("dim"));
+        assert_eq!(overlay, ReconnectOverlay::Dim);
+        assert_eq!(Value::from(ReconnectOverlay::Blur), Value::from("blur"));
+
+        let mut radius = 8.0;
+        radius.parse_from_value(Value::from(12.5));
+        assert_eq!(radius, 12.5);
+    }
+
+    #[test]
+    fn test_blur_falls_back_to_dim() {
+        let blur = ReconnectIndicatorSettings {
+            overlay: ReconnectOverlay::Blur,
+            blur_radius: 12.0,
+            ..ReconnectIndicatorSettings::default()
+        };
+        assert_eq!(background(&blur, Some), Background::Blur(12.0));
+        assert_eq!(background(&blur, |_| None::<f32>), Background::Dim);
+
+        let no_radius = ReconnectIndicatorSettings {
+            blur_radius: 0.0,
+            ..blur.clone()
+        };
+        assert_eq!(background(&no_radius, Some), Background::Dim);
+        let undimmed = ReconnectIndicatorSettings { dim: false, ..blur };
+        assert_eq!(background(&undimmed, Some), Background::None);
+        assert_eq!(
+            background(&ReconnectIndicatorSettings::default(), Some),
+            Background::Dim
+        );
+    }
+
+    #[test]
+    fn test_frozen_frame_is_not_dimmed() {
+        let frozen = ReconnectIndicatorSettings {
+            freeze_last_frame: true,
+            overlay: ReconnectOverlay::Blur,
+            ..ReconnectIndicatorSettings::default()
+        };
+        assert_eq!(background(&frozen, Some), Background::None);
+        let dimmed = ReconnectIndicatorSettings {
+            freeze_last_frame: false,
+            ..frozen
+        };
+        assert_eq!(background(&dimmed, Some), Background::Blur(8.0));
+
+        let rect = status_line_rect(&Metrics::new(1.0), 600.0, 120.0);
+        assert_eq!(rect.left, STATUS_MARGIN);
+        assert_eq!(rect.bottom, 600.0 - STATUS_MARGIN);
+        assert_eq!(rect.width(), 120.0 + STATUS_PADDING * 2.0);
+        assert_eq!(rect.height(), STATUS_FONT_SIZE + STATUS_PADDING * 2.0);
+    }
+
+    #[test]
+    fn test_scale_factor_scales_the_metrics() {
+        let logical = Metrics::new(1.0);
+        let doubled = Metrics::new(2.0);
+        assert_eq!(doubled.font_size, logical.font_size * 2.0);
+        assert_eq!(doubled.animation_radius, logical.animation_radius * 2.0);
+        assert_eq!(doubled.status_padding, logical.status_padding * 2.0);
+        assert_eq!(
+            status_line_rect(&doubled, 600.0, 240.0).height(),
+            status_line_rect(&logical, 600.0, 120.0).height() * 2.0
+        );
+        assert_eq!(Metrics::new(0.0), logical);
+        assert_eq!(Metrics::new(f32::NAN), logical);
+    }
+
+    #[test]
+    fn test_font_candidates() {
+        assert_eq!(font_candidates(""), vec![FontKey::default()]);
+        let candidates = font_candidates("Iosevka");
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].font_desc.as_ref().unwrap().family, "Iosevka");
+        assert_eq!(candidates[1], FontKey::default());
+    }
+
+    #[test]
+    fn test_font_load_failure_falls_through() {
+        let candidates = font_candidates("Missing");
+        let mut tried = Vec::new();
+        // Only the default font loads
+        let font = first_loaded(
+            &mut tried,
+            &candidates,
+            |tried, key| {
+                tried.push(key.clone());
+                key.font_desc.is_none().then_some("default")
+            },
+            |_| Some("last resort"),
+        );
+        assert_eq!(font, Some("default"));
+        assert_eq!(tried, candidates);
+
+        let font = first_loaded(&mut (), &candidates, |_, _| None, |_| Some("last resort"));
+        assert_eq!(font, Some("last resort"));
+
+        // Nothing loads at all, which leaves the indicator without text instead of panicking
+        let font: Option<&str> = first_loaded(&mut (), &candidates, |_, _| None, |_| None);
+        assert_eq!(font, None);
+    }
+
+    // Every character is 10 pixels wide
+    fn measure(text: &str) -> f32 {
+        text.chars().count() as f32 * 10.0
+    }
+
+    #[test]
+    fn test_wrap_text_between_words() {
+        assert_eq!(
+            wrap_text("Reconnecting to localhost:6666 in 3s", 200.0, measure),
+            vec!["Reconnecting to", "localhost:6666 in 3s"]
+        );
+        assert_eq!(wrap_text("short", 200.0, measure), vec!["short"]);
+        assert_eq!(wrap_text("", 200.0, measure), vec![""]);
+    }
+
+    #[test]
+    fn test_unmeasurable_font_still_lays_out_visible_text() {
+        let text = message("localhost:6666", &Phase::Reconnecting, 3);
+        let canvas_width = 400.0;
+        for broken in [0.0, f32::NAN] {
+            let lines = layout_text(&text, canvas_width, canvas_width / 2.0, 100.0, 20.0, |_| {
+                broken
+            });
+
+            assert!(!lines.is_empty());
+            for (index, (line, position)) in lines.iter().enumerate() {
+                let width = line.chars().count() as f32 * 10.0;
+                assert!(position.x > 0.0 && position.x + width <= canvas_width);
+                assert_eq!(position.y, 100.0 + index as f32 * 20.0);
+            }
+            let joined: Vec<_> = lines.iter().map(|(line, _)| line.as_str()).collect();
+            assert_eq!(joined.join(" "), text);
+        }
+    }
+
+    #[test]
+    fn test_long_address_stays_within_bounds() {
+        let address = format!(
+            "/run/user/1000/{}/nvim.sock",
+            "very-long-directory".repeat(8)
+        );
+        let text = message(&address, &Phase::Reconnecting, 3);
+        let canvas_width = 400.0;
+        let lines = layout_text(
+            &text,
+            canvas_width,
+            canvas_width / 2.0,
+            100.0,
+            30.0,
+            measure,
+        );
+
+        assert!(lines.len() > 1);
+        for (index, (line, position)) in lines.iter().enumerate() {
+            let width = measure(line);
+            assert!(width <= canvas_width * MAX_TEXT_WIDTH, "{line} is too wide");
+            assert!(position.x >= 0.0 && position.x + width <= canvas_width);
+            // Centered, and growing downward one line at a time
+            assert_eq!(position.x + width / 2.0, canvas_width / 2.0);
+            assert_eq!(position.y, 100.0 + index as f32 * 30.0);
+        }
+        let wrapped: String = lines
+            .iter()
+            .map(|(line, _)| line.replace(' ', ""))
+            .collect();
+        assert_eq!(wrapped, text.replace(' ', ""));
     }
 }
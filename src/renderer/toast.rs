@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use skia_safe::{Canvas, Color, Paint, Point, Rect};
+
+use crate::profiling::tracy_zone;
+use crate::renderer::fonts::font_loader::{FontKey, FontLoader, FontPair};
+use crate::renderer::reconnect_indicator::first_loaded;
+
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+const MAX_TOASTS: usize = 3;
+const MARGIN: f32 = 16.0;
+const PADDING: f32 = 8.0;
+
+/// The messages currently on screen, oldest first.
+#[derive(Default)]
+struct ToastQueue {
+    messages: VecDeque<(String, Instant)>,
+}
+
+impl ToastQueue {
+    fn push(&mut self, message: String, now: Instant) {
+        self.messages.push_back((message, now + TOAST_DURATION));
+        while self.messages.len() > MAX_TOASTS {
+            self.messages.pop_front();
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        self.messages.retain(|(_, hide_at)| now < *hide_at);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// Short lived notices drawn in the bottom right corner of the window, below them an optional
+/// sticky notice stays until it's cleared.
+pub struct Toasts {
+    /// `None` when not even the embedded fonts could be loaded, then nothing is drawn
+    font: Option<Arc<FontPair>>,
+    queue: ToastQueue,
+    sticky: Option<String>,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        let mut loader = FontLoader::new(16.0);
+        let font = first_loaded(
+            &mut loader,
+            &[FontKey::default()],
+            |loader, key| loader.get_or_load(key),
+            FontLoader::get_or_load_last_resort,
+        );
+        if font.is_none() {
+            log::error!("Could not load any font for the toasts");
+        }
+        Self {
+            font,
+            queue: ToastQueue::default(),
+            sticky: None,
+        }
+    }
+
+    pub fn show(&mut self, message: String) {
+        self.queue.push(message, Instant::now());
+    }
+
+    pub fn set_sticky(&mut self, message: Option<String>) {
+        self.sticky = message;
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    pub fn update(&mut self) {
+        self.queue.prune(Instant::now());
+    }
+
+    pub fn draw(&self, canvas: &Canvas) {
+        tracy_zone!("toasts_draw");
+        if !self.is_active() && self.sticky.is_none() {
+            return;
+        }
+        let Some(font) = &self.font else {
+            return;
+        };
+        canvas.save();
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+
+        let size = canvas.base_layer_size();
+        let font_size = font.skia_font.size();
+        let height = font_size + PADDING * 2.0;
+        let mut bottom = size.height as f32 - MARGIN;
+
+        // The newest message is at the bottom, just above the sticky one
+        let messages = self.queue.messages.iter().map(|(message, _)| message).rev();
+        for message in self.sticky.iter().chain(messages) {
+            let width = font.skia_font.measure_str(message, Some(&paint)).0 + PADDING * 2.0;
+            let rect = Rect::from_xywh(
+                size.width as f32 - MARGIN - width,
+                bottom - height,
+                width,
+                height,
+            );
+            paint.set_color(Color::from_argb(200, 40, 40, 40));
+            canvas.draw_rect(rect, &paint);
+
+            paint.set_color(Color::WHITE);
+            let text_pos = Point::new(rect.left + PADDING, rect.bottom - PADDING - font_size * 0.2);
+            canvas.draw_str(message, text_pos, &font.skia_font, &paint);
+
+            bottom -= height + PADDING;
+        }
+
+        canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toasts_expire() {
+        let start = Instant::now();
+        let mut queue = ToastQueue::default();
+        queue.push("first".to_string(), start);
+
+        queue.prune(start + TOAST_DURATION - Duration::from_millis(1));
+        assert!(!queue.is_empty());
+        queue.prune(start + TOAST_DURATION);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_toast_is_dropped() {
+        let start = Instant::now();
+        let mut queue = ToastQueue::default();
+        for i in 0..MAX_TOASTS + 1 {
+            queue.push(format!("message {i}"), start);
+        }
+        assert_eq!(queue.messages.len(), MAX_TOASTS);
+        assert_eq!(queue.messages.front().unwrap().0, "message 1");
+    }
+}
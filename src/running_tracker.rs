@@ -1,22 +1,53 @@
 use std::{
-    process::ExitCode,
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use log::info;
+use parking_lot::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Why Neovide is quitting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuitReason {
+    /// Neovim exited on its own, for example through `:qa`
+    NvimRequested,
+    /// The user closed the window
+    UserClosed,
+    /// A plugin asked Neovide to quit with an exit code, through `neovide.quit()`
+    PluginRequested,
+    /// The program embedding the bridge shut it down, through `NeovimRuntime::request_shutdown`
+    EmbedderRequested,
+    /// The `--quit-after-seconds` timer ran out
+    TimedOut,
+    /// There was no input for the `--idle-timeout`
+    Idle,
+}
+
+type ShutdownCallback = Box<dyn FnOnce() + Send>;
 
 #[derive(Clone)]
 pub struct RunningTracker {
     exit_code: Arc<AtomicU8>,
+    quit_reason: Arc<Mutex<Option<QuitReason>>>,
+    clean_exit_codes: Arc<Mutex<Vec<i32>>>,
+    shutdown_callbacks: Arc<Mutex<Vec<ShutdownCallback>>>,
+    quit_requested: CancellationToken,
+    started_at: Instant,
 }
 
 impl RunningTracker {
     pub fn new() -> Self {
         Self {
             exit_code: Arc::new(AtomicU8::new(0)),
+            quit_reason: Arc::new(Mutex::new(None)),
+            clean_exit_codes: Arc::new(Mutex::new(vec![0])),
+            shutdown_callbacks: Arc::new(Mutex::new(Vec::new())),
+            quit_requested: CancellationToken::new(),
+            started_at: Instant::now(),
         }
     }
 
@@ -25,7 +56,143 @@ impl RunningTracker {
         info!("Quit with code {}: {}", code, reason);
     }
 
-    pub fn exit_code(&self) -> ExitCode {
-        ExitCode::from(self.exit_code.load(Ordering::Acquire))
+    pub fn exit_code(&self) -> u8 {
+        self.exit_code.load(Ordering::Acquire)
+    }
+
+    /// Records why Neovide is quitting. The first reason is kept, since closing the window makes
+    /// Neovim quit as well, which would otherwise be reported as `NvimRequested`.
+    pub fn set_quit_reason(&self, reason: QuitReason) {
+        let mut quit_reason = self.quit_reason.lock();
+        if quit_reason.is_none() {
+            info!("Quit reason: {reason:?}");
+            *quit_reason = Some(reason);
+        }
+    }
+
+    /// Called by the bridge when it notices that the window is gone, so that it stops instead of
+    /// serving a UI that will never come back.
+    pub fn request_quit(&self) {
+        info!("The event loop has shut down, quitting");
+        self.set_quit_reason(QuitReason::UserClosed);
+        self.quit_requested.cancel();
+    }
+
+    /// Completes once [`request_quit`](Self::request_quit) was called, even before the wait
+    /// started. Unlike the quit reason, that isn't forgotten by `reset_quit`, the window is
+    /// gone for good.
+    pub async fn wait_for_quit_request(&self) {
+        self.quit_requested.cancelled().await;
+    }
+
+    /// Forgets an earlier quit, when the Neovim that quit was only a temporary one, like the
+    /// fallback of `--fallback-embedded-after`.
+    pub fn reset_quit(&self) {
+        self.exit_code.store(0, Ordering::Release);
+        *self.quit_reason.lock() = None;
+    }
+
+    pub fn quit_reason(&self) -> Option<QuitReason> {
+        *self.quit_reason.lock()
+    }
+
+    /// How long Neovide has been running.
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Sets the exit codes of `--clean-exit-codes`, only 0 by default.
+    pub fn set_clean_exit_codes(&self, codes: Vec<i32>) {
+        *self.clean_exit_codes.lock() = codes;
+    }
+
+    /// Whether an embedded Neovim exiting with `code` quit on purpose, rather than crashing.
+    pub fn is_clean_exit(&self, code: i32) -> bool {
+        self.clean_exit_codes.lock().contains(&code)
+    }
+
+    /// Runs `callback` right before Neovide exits, after Neovim is gone, for cleaning up.
+    pub fn on_shutdown(&self, callback: impl FnOnce() + Send + 'static) {
+        self.shutdown_callbacks.lock().push(Box::new(callback));
+    }
+
+    /// Runs the callbacks of `on_shutdown` once, the last one registered first.
+    pub fn run_shutdown_callbacks(&self) {
+        let callbacks = std::mem::take(&mut *self.shutdown_callbacks.lock());
+        for callback in callbacks.into_iter().rev() {
+            callback();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nvim_requested_quit() {
+        let running_tracker = RunningTracker::new();
+        assert_eq!(running_tracker.quit_reason(), None);
+        running_tracker.set_quit_reason(QuitReason::NvimRequested);
+        running_tracker.quit_with_code(0, "Quit from neovim");
+        assert_eq!(
+            running_tracker.quit_reason(),
+            Some(QuitReason::NvimRequested)
+        );
+    }
+
+    #[test]
+    fn test_clean_exit_codes() {
+        let running_tracker = RunningTracker::new();
+        assert!(running_tracker.is_clean_exit(0));
+        assert!(!running_tracker.is_clean_exit(130));
+
+        running_tracker.set_clean_exit_codes(vec![0, 130]);
+        assert!(running_tracker.is_clean_exit(130));
+        assert!(!running_tracker.is_clean_exit(1));
+    }
+
+    #[test]
+    fn test_reset_quit() {
+        let running_tracker = RunningTracker::new();
+        running_tracker.set_quit_reason(QuitReason::NvimRequested);
+        running_tracker.reset_quit();
+        assert_eq!(running_tracker.quit_reason(), None);
+        running_tracker.set_quit_reason(QuitReason::UserClosed);
+        assert_eq!(running_tracker.quit_reason(), Some(QuitReason::UserClosed));
+    }
+
+    #[test]
+    fn test_shutdown_callbacks_run_once_in_reverse() {
+        let running_tracker = RunningTracker::new();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        for name in ["first", "second"] {
+            let calls = calls.clone();
+            running_tracker.on_shutdown(move || calls.lock().push(name));
+        }
+        running_tracker.clone().run_shutdown_callbacks();
+        running_tracker.run_shutdown_callbacks();
+        assert_eq!(*calls.lock(), vec!["second", "first"]);
+    }
+
+    #[tokio::test]
+    async fn test_quit_request_is_kept_for_later_waits() {
+        let running_tracker = RunningTracker::new();
+        running_tracker.clone().request_quit();
+        running_tracker.reset_quit();
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            running_tracker.wait_for_quit_request(),
+        )
+        .await
+        .expect("The quit request was forgotten");
+    }
+
+    #[test]
+    fn test_user_closed_is_kept_when_nvim_quits_afterwards() {
+        let running_tracker = RunningTracker::new();
+        running_tracker.set_quit_reason(QuitReason::UserClosed);
+        running_tracker.set_quit_reason(QuitReason::NvimRequested);
+        assert_eq!(running_tracker.quit_reason(), Some(QuitReason::UserClosed));
     }
 }
@@ -1,3 +1,4 @@
+mod dump;
 mod font;
 mod from_value;
 mod window_size;
@@ -16,6 +17,7 @@ use std::{
 use winit::event_loop::EventLoopProxy;
 
 use crate::{bridge::NeovimWriter, window::UserEvent};
+pub use dump::{json_to_value, value_to_json, DumpFormat};
 pub use from_value::ParseFromValue;
 pub use window_size::{
     clamped_grid_size, load_last_window_settings, neovide_std_datapath, save_window_size,
@@ -25,6 +27,9 @@ pub use window_size::{
 mod config;
 pub use config::{Config, HotReloadConfigs};
 
+// Aliases that only log a deprecation warning
+const DEPRECATED_SETTINGS: [&str; 1] = ["transparency"];
+
 pub trait SettingGroup {
     type ChangedEvent: Debug + Clone + Send + Sync + Any;
     fn register(settings: &Settings);
@@ -99,12 +104,11 @@ impl Settings {
     }
 
     pub async fn read_initial_values(&self, nvim: &Neovim<NeovimWriter>) -> Result<()> {
-        let deprecated_settings = ["transparency".to_owned()];
         let keys: Vec<SettingLocation> = self
             .updaters
             .read()
             .keys()
-            .filter(|key| !matches!(key, SettingLocation::NeovideGlobal(name) if deprecated_settings.contains(name)))
+            .filter(|key| !matches!(key, SettingLocation::NeovideGlobal(name) if DEPRECATED_SETTINGS.contains(&name.as_str())))
             .cloned()
             .collect();
 
@@ -189,6 +193,8 @@ pub enum SettingsChanged {
     Window(crate::window::WindowSettingsChanged),
     Cursor(crate::renderer::cursor_renderer::CursorSettingsChanged),
     Renderer(crate::renderer::RendererSettingsChanged),
+    Reconnect(crate::bridge::ReconnectSettingsChanged),
+    ReconnectIndicator(crate::renderer::ReconnectIndicatorSettingsChanged),
     #[cfg(test)]
     Test(tests::TestSettingsChanged),
 }
@@ -222,7 +228,7 @@ mod tests {
     use crate::{
         bridge::{
             create_nvim_command,
-            session::{NeovimInstance, NeovimSession},
+            session::{IoBufferSizes, NeovimInstance, NeovimSession},
         },
         cmd_line::CmdLineSettings,
         error_handling::ResultPanicExplanation,
@@ -326,11 +332,12 @@ mod tests {
         //create_nvim_command tries to read from CmdLineSettings.neovim_args
         settings.set::<CmdLineSettings>(&CmdLineSettings::default());
 
-        let command = create_nvim_command(&settings);
+        let command = create_nvim_command(&settings).unwrap();
         let instance = NeovimInstance::Embedded(command);
-        let NeovimSession { neovim: nvim, .. } = NeovimSession::new(instance, NeovimHandler())
-            .await
-            .unwrap_or_explained_panic("Could not locate or start the neovim process");
+        let NeovimSession { neovim: nvim, .. } =
+            NeovimSession::new(instance, NeovimHandler(), IoBufferSizes::default())
+                .await
+                .unwrap_or_explained_panic("Could not locate or start the neovim process");
         nvim.set_var("neovide_bar", Value::from("bar_set".to_owned()))
             .await
             .expect("Could not set neovide_bar variable");
@@ -1,21 +1,23 @@
 use std::sync::Arc;
 
 use crate::{
-    bridge::{send_ui, SerialCommand},
+    bridge::{
+        is_read_only, is_reconnect_paused, send_ui, set_read_only, ParallelCommand, SerialCommand,
+    },
+    renderer::{toggle_connection_log, toggle_log_overlay},
     settings::Settings,
+    window::{UserEvent, WindowSettings},
 };
 
 #[allow(unused_imports)]
 use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
 use winit::{
     event::{ElementState, Ime, KeyEvent, Modifiers, WindowEvent},
+    event_loop::EventLoopProxy,
     keyboard::{Key, KeyCode, KeyLocation, NamedKey, PhysicalKey},
 };
 #[cfg(target_os = "macos")]
-use {
-    crate::{window::settings::OptionAsMeta, window::WindowSettings},
-    winit::keyboard::ModifiersKeyState,
-};
+use {crate::window::settings::OptionAsMeta, winit::keyboard::ModifiersKeyState};
 
 use crate::profiling::tracy_named_frame;
 
@@ -23,24 +25,108 @@ fn is_ascii_alphabetic_char(text: &str) -> bool {
     text.len() == 1 && text.chars().next().unwrap().is_ascii_alphabetic()
 }
 
+/// Normalizes a key in Neovim notation, so that for example `<C-S-r>` and `<S-C-R>` compare equal
+fn normalize_key(key: &str) -> String {
+    let Some(inner) = key
+        .strip_prefix('<')
+        .and_then(|key| key.strip_suffix('>'))
+        .filter(|inner| !inner.is_empty())
+    else {
+        return key.to_string();
+    };
+    // The key itself may be a `-`, as in `<C-->`
+    let (modifiers, key) = match inner.strip_suffix('-') {
+        Some(modifiers) if modifiers.ends_with('-') => (&modifiers[..modifiers.len() - 1], "-"),
+        _ => inner
+            .rsplit_once('-')
+            .filter(|(_, key)| !key.is_empty())
+            .unwrap_or(("", inner)),
+    };
+    let mut modifiers = modifiers
+        .split('-')
+        .filter(|modifier| !modifier.is_empty())
+        .map(str::to_uppercase)
+        .collect::<Vec<_>>();
+    modifiers.sort();
+    modifiers.push(key.to_lowercase());
+    format!("<{}>", modifiers.join("-"))
+}
+
+fn is_key(text: &str, key: &str) -> bool {
+    !key.is_empty() && normalize_key(text) == normalize_key(key)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum KeyAction {
+    ToggleReadOnly,
+    ToggleReconnectPause,
+    ReconnectNow,
+    ToggleConnectionLog,
+    ToggleLogOverlay,
+    Send(String),
+    Drop,
+}
+
+/// What a key press does, only the toggle keys work while the input is blocked. The reconnect now
+/// key is only taken while `reconnecting`, at other times it goes to Neovim like any other key.
+fn key_action(
+    text: String,
+    window_settings: &WindowSettings,
+    input_blocked: bool,
+    reconnecting: bool,
+) -> KeyAction {
+    if is_key(&text, &window_settings.read_only_toggle_key) {
+        KeyAction::ToggleReadOnly
+    } else if is_key(&text, &window_settings.reconnect_pause_toggle_key) {
+        KeyAction::ToggleReconnectPause
+    } else if reconnecting && is_key(&text, &window_settings.reconnect_now_key) {
+        KeyAction::ReconnectNow
+    } else if is_key(&text, &window_settings.connection_log_toggle_key) {
+        KeyAction::ToggleConnectionLog
+    } else if is_key(&text, &window_settings.log_overlay_toggle_key) {
+        KeyAction::ToggleLogOverlay
+    } else if input_blocked {
+        KeyAction::Drop
+    } else {
+        KeyAction::Send(text)
+    }
+}
+
 pub struct KeyboardManager {
     modifiers: Modifiers,
     ime_preedit: (String, Option<(usize, usize)>),
     meta_is_pressed: bool, // see note on 'meta' below
-    #[allow(dead_code)]
+    input_blocked: bool,
+    reconnecting: bool,
     settings: Arc<Settings>,
+    /// For telling the window about the read-only toggle
+    proxy: EventLoopProxy<UserEvent>,
 }
 
 impl KeyboardManager {
-    pub fn new(settings: Arc<Settings>) -> Self {
+    pub fn new(settings: Arc<Settings>, proxy: EventLoopProxy<UserEvent>) -> Self {
         KeyboardManager {
             modifiers: Modifiers::default(),
             ime_preedit: ("".to_string(), None),
             meta_is_pressed: false,
+            input_blocked: false,
+            reconnecting: false,
             settings,
+            proxy,
         }
     }
 
+    /// Drops the key presses except the toggle keys, for example while the reconnect overlay is
+    /// modal.
+    pub fn set_input_blocked(&mut self, input_blocked: bool) {
+        self.input_blocked = input_blocked;
+    }
+
+    /// Whether the reconnect overlay is shown, which enables the reconnect now key.
+    pub fn set_reconnecting(&mut self, reconnecting: bool) {
+        self.reconnecting = reconnecting;
+    }
+
     pub fn handle_event(&mut self, event: &WindowEvent) {
         match event {
             WindowEvent::KeyboardInput {
@@ -53,10 +139,33 @@ impl KeyboardManager {
                     if let Some(text) = self.format_key(key_event) {
                         log::trace!("Key pressed {} {:?}", text, self.modifiers.state());
                         tracy_named_frame!("keyboard input");
-                        send_ui(SerialCommand::Keyboard(text));
+                        let window_settings = self.settings.get::<WindowSettings>();
+                        let action = key_action(
+                            text,
+                            &window_settings,
+                            self.input_blocked,
+                            self.reconnecting,
+                        );
+                        match action {
+                            KeyAction::ToggleReadOnly => {
+                                set_read_only(&self.proxy, !is_read_only())
+                            }
+                            // Sent through the UI commands, which work while disconnected
+                            KeyAction::ToggleReconnectPause => send_ui(if is_reconnect_paused() {
+                                ParallelCommand::ResumeReconnect
+                            } else {
+                                ParallelCommand::PauseReconnect
+                            }),
+                            KeyAction::ReconnectNow => send_ui(ParallelCommand::ReconnectNow),
+                            KeyAction::ToggleConnectionLog => toggle_connection_log(),
+                            KeyAction::ToggleLogOverlay => toggle_log_overlay(),
+                            KeyAction::Send(text) => send_ui(SerialCommand::Keyboard(text)),
+                            KeyAction::Drop => log::trace!("Dropped blocked key press"),
+                        }
                     }
                 }
             }
+            WindowEvent::Ime(Ime::Commit(_)) if self.input_blocked => {}
             WindowEvent::Ime(Ime::Commit(text)) => {
                 log::trace!("Ime commit {text}");
                 send_ui(SerialCommand::Keyboard(self.format_key_text(text, false)));
@@ -363,3 +472,72 @@ fn get_special_key(key_event: &KeyEvent) -> Option<&str> {
 // avoid confusing users who have a post-2017 keyboard and are not aware of this
 // history, it is probably best to refer to this physical key as the 'option'
 // key, and not as the 'alt' key.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key("<C-S-r>"), normalize_key("<S-C-R>"));
+        assert_eq!(normalize_key("<c-f12>"), normalize_key("<C-F12>"));
+        assert_eq!(normalize_key("<C-->"), "<C-->");
+        assert_eq!(normalize_key("a"), "a");
+        assert_ne!(normalize_key("<C-r>"), normalize_key("<M-r>"));
+    }
+
+    #[test]
+    fn test_empty_key_never_matches() {
+        assert!(is_key("<S-C-F12>", "<C-S-F12>"));
+        assert!(!is_key("<S-C-F12>", ""));
+    }
+
+    #[test]
+    fn test_input_passes_through_when_not_blocked() {
+        let window_settings = WindowSettings {
+            reconnect_pause_toggle_key: "<C-S-F11>".to_owned(),
+            connection_log_toggle_key: "<C-S-F10>".to_owned(),
+            log_overlay_toggle_key: "<F12>".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            key_action("j".to_owned(), &window_settings, false, false),
+            KeyAction::Send("j".to_owned())
+        );
+        assert_eq!(
+            key_action("j".to_owned(), &window_settings, true, false),
+            KeyAction::Drop
+        );
+        assert_eq!(
+            key_action("<C-S-F11>".to_owned(), &window_settings, true, true),
+            KeyAction::ToggleReconnectPause
+        );
+        assert_eq!(
+            key_action("<S-C-F10>".to_owned(), &window_settings, true, true),
+            KeyAction::ToggleConnectionLog
+        );
+        assert_eq!(
+            key_action("<F12>".to_owned(), &window_settings, true, false),
+            KeyAction::ToggleLogOverlay
+        );
+    }
+
+    #[test]
+    fn test_reconnect_now_key_only_while_reconnecting() {
+        let window_settings = WindowSettings::default();
+        assert_eq!(
+            key_action("<C-r>".to_owned(), &window_settings, false, true),
+            KeyAction::ReconnectNow
+        );
+        // Swallowed even when the overlay isn't modal, there's no Neovim to send it to
+        assert_eq!(
+            key_action("<C-r>".to_owned(), &window_settings, true, true),
+            KeyAction::ReconnectNow
+        );
+        // Redo, while connected
+        assert_eq!(
+            key_action("<C-r>".to_owned(), &window_settings, false, false),
+            KeyAction::Send("<C-r>".to_owned())
+        );
+    }
+}
@@ -2,6 +2,7 @@ mod error_window;
 mod keyboard_manager;
 mod mouse_manager;
 mod settings;
+mod title_template;
 mod update_loop;
 mod window_wrapper;
 
@@ -9,11 +10,11 @@ mod window_wrapper;
 pub mod macos;
 
 #[cfg(target_os = "linux")]
-use std::env;
+use std::{env, time::Duration};
 
 use winit::{
     dpi::{PhysicalSize, Size},
-    event_loop::{ActiveEventLoop, EventLoop},
+    event_loop::{ActiveEventLoop, EventLoop, EventLoopClosed, EventLoopProxy},
     window::{Cursor, Icon, Theme, Window},
 };
 
@@ -41,7 +42,8 @@ use keyboard_manager::KeyboardManager;
 use mouse_manager::MouseManager;
 
 use crate::{
-    cmd_line::{CmdLineSettings, GeometryArgs},
+    bridge::NeovimCapabilities,
+    cmd_line::{CmdLineSettings, GeometryArgs, NotificationLevel},
     frame::Frame,
     renderer::{build_window_config, DrawCommand, WindowConfig},
     settings::{
@@ -52,6 +54,7 @@ use crate::{
 };
 pub use error_window::show_error_window;
 pub use settings::{WindowSettings, WindowSettingsChanged};
+pub use title_template::validate_title_template;
 pub use update_loop::ShouldRender;
 pub use update_loop::UpdateLoop;
 pub use window_wrapper::WinitWindowWrapper;
@@ -80,6 +83,8 @@ pub enum WindowCommand {
     Minimize,
     #[allow(dead_code)] // Theme change is only used on macOS right now
     ThemeChanged(Option<Theme>),
+    /// Sizes the window to the grid of the server, for `--match-server-grid`
+    ResizeGrid(GridSize<u32>),
     #[cfg(windows)]
     RegisterRightClick,
     #[cfg(windows)]
@@ -92,14 +97,69 @@ pub enum UserEvent {
     WindowCommand(WindowCommand),
     SettingsChanged(SettingsChanged),
     ConfigsChanged(Box<HotReloadConfigs>),
-    #[allow(dead_code)]
     RedrawRequested,
     NeovimExited,
+    /// The read-only mode was enabled or disabled, see `set_read_only`
+    ReadOnlyChanged(bool),
+    /// The first connection to the `--server` is being made, followed by `ReconnectStop` when it
+    /// succeeds, and by `ReconnectStart` when it fails
+    ConnectStart {
+        address: String,
+    },
+    /// Attached to the `--server`, but waiting for `--connect-warmup` before `ReconnectStop`
+    ConnectStabilizing {
+        address: String,
+    },
     ReconnectStart {
         address: String,
         wait: u64,
     },
     ReconnectStop,
+    /// Connecting to the `--server` failed with an error that `--reconnect-on` doesn't retry,
+    /// which is shown until the window is closed
+    ConnectFailed {
+        address: String,
+        message: String,
+    },
+    /// Reconnecting is paused until `ReconnectStart`
+    ReconnectPaused {
+        address: String,
+    },
+    /// The `--idle-timeout` disconnected from the `--server`, which is connected again on the
+    /// next input
+    IdleDisconnected {
+        address: String,
+    },
+    /// Replaces the window title until `ReconnectStop`, so that screen readers announce the
+    /// reconnect state
+    ReconnectTitle(String),
+    /// A UI command sent by Neovide failed
+    RpcError {
+        command: String,
+        message: String,
+    },
+    /// Sent after every attach, including reconnects
+    NeovimCapabilities(NeovimCapabilities),
+    /// The settings were read from Neovim, sent on every connection including reconnects, so
+    /// that state derived from them can be refreshed
+    SettingsLoaded,
+    /// The shared `ConnectionState` changed
+    ConnectionStateChanged,
+    /// A problem that doesn't stop Neovide, shown as a toast
+    Notice(String),
+    /// A `vim.notify` message, shown as a desktop notification when `--desktop-notifications`
+    /// asks for it
+    DesktopNotification {
+        message: String,
+        level: NotificationLevel,
+    },
+    /// Sent every `--heartbeat-interval`, `rtt` is the round trip time of the last ping
+    Heartbeat {
+        connected: bool,
+        rtt: Option<Duration>,
+    },
+    /// Only sent to find out whether the event loop is still running, does nothing
+    Probe,
 }
 
 impl From<Vec<DrawCommand>> for UserEvent {
@@ -126,6 +186,24 @@ impl From<HotReloadConfigs> for UserEvent {
     }
 }
 
+/// Anything that can deliver a `UserEvent` to the window event loop, usually an
+/// `EventLoopProxy<UserEvent>`.
+pub trait UserEventSender {
+    fn send_user_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>>;
+}
+
+impl UserEventSender for EventLoopProxy<UserEvent> {
+    fn send_user_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+        self.send_event(event)
+    }
+}
+
+/// Forces a new frame to be rendered, even if nothing changed. This is the supported way to
+/// trigger a redraw from outside the window, for example after changing an overlay.
+pub fn request_redraw(proxy: &impl UserEventSender) -> Result<(), EventLoopClosed<UserEvent>> {
+    proxy.send_user_event(UserEvent::RedrawRequested)
+}
+
 pub fn create_event_loop() -> EventLoop<UserEvent> {
     let mut builder = EventLoop::with_user_event();
     #[cfg(target_os = "macos")]
@@ -304,3 +382,29 @@ pub fn load_icon() -> Icon {
     }
     Icon::from_rgba(rgba, width, height).expect("Failed to create icon object")
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingSender {
+        events: RefCell<Vec<UserEvent>>,
+    }
+
+    impl UserEventSender for RecordingSender {
+        fn send_user_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<UserEvent>> {
+            self.events.borrow_mut().push(event);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_request_redraw_sends_redraw_requested() {
+        let sender = RecordingSender::default();
+        request_redraw(&sender).unwrap();
+        assert_eq!(*sender.events.borrow(), vec![UserEvent::RedrawRequested]);
+    }
+}
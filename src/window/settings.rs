@@ -34,6 +34,12 @@ pub struct WindowSettings {
     #[cfg(target_os = "macos")]
     pub macos_simple_fullscreen: bool,
     pub input_ime: bool,
+    pub read_only_toggle_key: String,
+    pub reconnect_pause_toggle_key: String,
+    pub reconnect_now_key: String,
+    pub connection_log_toggle_key: String,
+    pub log_overlay_toggle_key: String,
+    pub desktop_notifications_when_focused: bool,
     pub show_border: bool,
     pub cursor_hack: bool,
 
@@ -80,6 +86,12 @@ impl Default for WindowSettings {
             #[cfg(target_os = "macos")]
             macos_simple_fullscreen: false,
             input_ime: true,
+            read_only_toggle_key: "".to_string(),
+            reconnect_pause_toggle_key: "".to_string(),
+            reconnect_now_key: "<C-r>".to_string(),
+            connection_log_toggle_key: "".to_string(),
+            log_overlay_toggle_key: "".to_string(),
+            desktop_notifications_when_focused: false,
             mouse_move_event: false,
             observed_lines: None,
             observed_columns: None,
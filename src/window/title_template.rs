@@ -0,0 +1,129 @@
+//! Formats `--title-template`, which can show the connection state in the window title.
+
+use crate::bridge::ConnectionState;
+
+/// Checks that every `{` is closed by a `}` before the next one opens, and that there are no
+/// stray `}`. Unknown placeholders are allowed, they're shown as they are.
+pub fn validate_title_template(template: &str) -> Result<String, String> {
+    let mut open = None;
+    for (index, c) in template.char_indices() {
+        match (c, open) {
+            ('{', Some(start)) => return Err(format!("unclosed placeholder at {start}")),
+            ('{', None) => open = Some(index),
+            ('}', None) => return Err(format!("unexpected `}}` at {index}")),
+            ('}', Some(_)) => open = None,
+            _ => {}
+        }
+    }
+    match open {
+        Some(start) => Err(format!("unclosed placeholder at {start}")),
+        None => Ok(template.to_owned()),
+    }
+}
+
+fn placeholder(name: &str, state: &ConnectionState, title: &str) -> Option<String> {
+    let value = match name {
+        "title" => title.to_owned(),
+        "status" => state.status.to_string(),
+        "address" => state
+            .address
+            .clone()
+            .unwrap_or_else(|| "embedded".to_owned()),
+        "attempt" => state.attempt.to_string(),
+        "nvim_version" => state.nvim_version.clone().unwrap_or_default(),
+        "rtt" => state
+            .rtt
+            .map(|rtt| format!("{}ms", rtt.as_millis()))
+            .unwrap_or_default(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Replaces the placeholders in a validated `template`, `{title}` is the title set by Neovim.
+pub fn format_title(template: &str, state: &ConnectionState, title: &str) -> String {
+    let mut formatted = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        formatted.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        match placeholder(name, state, title) {
+            Some(value) => formatted.push_str(&value),
+            None => formatted.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    formatted.push_str(rest);
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::bridge::ConnectionStatus;
+
+    fn reconnecting() -> ConnectionState {
+        ConnectionState {
+            status: ConnectionStatus::Reconnecting,
+            address: Some("localhost:6666".to_owned()),
+            attempt: 3,
+            backoff: Some(Duration::from_secs(4)),
+            nvim_version: Some("0.11.2".to_owned()),
+            channel: Some(3),
+            rtt: None,
+            last_ping_ok: None,
+            session_id: None,
+            capabilities: None,
+        }
+    }
+
+    #[test]
+    fn test_format_placeholders() {
+        let state = reconnecting();
+        assert_eq!(
+            format_title("{title} [{status} {address}]", &state, "init.lua"),
+            "init.lua [reconnecting localhost:6666]"
+        );
+        assert_eq!(
+            format_title("attempt {attempt} of nvim {nvim_version}", &state, ""),
+            "attempt 3 of nvim 0.11.2"
+        );
+        assert_eq!(format_title("Neovide", &state, "init.lua"), "Neovide");
+    }
+
+    #[test]
+    fn test_format_connected_embedded() {
+        let state = ConnectionState {
+            status: ConnectionStatus::Connected,
+            rtt: Some(Duration::from_millis(12)),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_title("{address} {status} {rtt}", &state, ""),
+            "embedded connected 12ms"
+        );
+        assert_eq!(format_title("{rtt}", &reconnecting(), ""), "");
+    }
+
+    #[test]
+    fn test_unknown_placeholders_are_kept() {
+        assert_eq!(
+            format_title("{title} {unknown}", &reconnecting(), "init.lua"),
+            "init.lua {unknown}"
+        );
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_title_template("{title} - {status}").is_ok());
+        assert!(validate_title_template("{unknown}").is_ok());
+        assert!(validate_title_template("{title").is_err());
+        assert!(validate_title_template("title}").is_err());
+        assert!(validate_title_template("{{title}}").is_err());
+    }
+}
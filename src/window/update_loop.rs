@@ -3,6 +3,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use tokio::runtime::Handle;
 use winit::{
     application::ApplicationHandler,
     event::WindowEvent,
@@ -13,6 +14,7 @@ use super::{save_window_size, CmdLineSettings, UserEvent, WindowSettings, WinitW
 use crate::{
     profiling::{tracy_plot, tracy_zone},
     renderer::DrawCommand,
+    running_tracker::RunningTracker,
     settings::{Config, Settings},
     WindowSize,
 };
@@ -98,7 +100,9 @@ impl UpdateLoop {
         initial_window_size: WindowSize,
         initial_config: Config,
         proxy: EventLoopProxy<UserEvent>,
+        running_tracker: RunningTracker,
         settings: Arc<Settings>,
+        runtime: Handle,
     ) -> Self {
         let previous_frame_start = Instant::now();
         let last_dt = 0.0;
@@ -113,8 +117,14 @@ impl UpdateLoop {
         let cmd_line_settings = settings.get::<CmdLineSettings>();
         let idle = cmd_line_settings.idle;
 
-        let window_wrapper =
-            WinitWindowWrapper::new(initial_window_size, initial_config, settings.clone());
+        let window_wrapper = WinitWindowWrapper::new(
+            initial_window_size,
+            initial_config,
+            running_tracker,
+            settings.clone(),
+            runtime,
+            proxy.clone(),
+        );
 
         Self {
             idle,
@@ -336,6 +346,9 @@ impl ApplicationHandler<UserEvent> for UpdateLoop {
             WindowEvent::RedrawRequested => {
                 self.redraw_requested();
             }
+            WindowEvent::CloseRequested if self.window_wrapper.is_connect_failed() => {
+                self.proxy.send_event(UserEvent::NeovimExited).ok();
+            }
             WindowEvent::Focused(focused_event) => {
                 self.focused = if focused_event {
                     FocusedState::Focused
@@ -368,6 +381,7 @@ impl ApplicationHandler<UserEvent> for UpdateLoop {
             UserEvent::RedrawRequested => {
                 self.redraw_requested();
             }
+            UserEvent::Probe => {}
             UserEvent::DrawCommandBatch(batch) if self.pending_render => {
                 // Buffer the draw commands if we have a pending render, we have already decided what to
                 // draw, so it's not a good idea to process them now.
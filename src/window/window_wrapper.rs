@@ -2,6 +2,7 @@ use std::{sync::Arc, time::Duration};
 
 use log::trace;
 use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use tokio::runtime::Handle;
 use winit::{
     dpi,
     event::{Ime, WindowEvent},
@@ -10,7 +11,8 @@ use winit::{
 };
 
 use super::{
-    KeyboardManager, MouseManager, UserEvent, WindowCommand, WindowSettings, WindowSettingsChanged,
+    title_template::format_title, KeyboardManager, MouseManager, UserEvent, WindowCommand,
+    WindowSettings, WindowSettingsChanged,
 };
 
 #[cfg(target_os = "macos")]
@@ -20,11 +22,16 @@ use {
 };
 
 use crate::{
-    bridge::{send_ui, ParallelCommand, SerialCommand},
+    bridge::{
+        connection_state, record_input, send_ui, ConnectionState, ParallelCommand, SerialCommand,
+    },
+    desktop_notification,
     profiling::{tracy_frame, tracy_gpu_collect, tracy_gpu_zone, tracy_plot, tracy_zone},
     renderer::{
-        create_skia_renderer, DrawCommand, Renderer, RendererSettingsChanged, SkiaRenderer, VSync,
+        create_skia_renderer, DrawCommand, ReconnectIndicatorSettings, Renderer,
+        RendererSettingsChanged, SkiaRenderer, VSync,
     },
+    running_tracker::{QuitReason, RunningTracker},
     settings::{
         clamped_grid_size, Config, HotReloadConfigs, Settings, SettingsChanged, DEFAULT_GRID_SIZE,
         MIN_GRID_SIZE,
@@ -67,6 +74,25 @@ pub fn set_background(background: &str) {
     send_ui(ParallelCommand::SetBackground(background.to_string()));
 }
 
+/// Whether `event` is someone using the window, which resets the idle timeout.
+fn is_user_input(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::Touch(_)
+            | WindowEvent::Ime(Ime::Commit(_) | Ime::Preedit(..))
+    )
+}
+
+/// Closing the window asks Neovim to quit, remembering that it was the user who did.
+fn quit_by_user(running_tracker: &RunningTracker) {
+    running_tracker.set_quit_reason(QuitReason::UserClosed);
+    send_ui(ParallelCommand::Quit);
+}
+
 #[derive(PartialEq, PartialOrd)]
 enum UIState {
     Initing, // Running init.vim/lua
@@ -83,6 +109,15 @@ pub struct WinitWindowWrapper {
     keyboard_manager: KeyboardManager,
     mouse_manager: MouseManager,
     title: String,
+    /// Shown instead of the Neovim title while reconnecting
+    reconnect_title: Option<String>,
+    /// What the window title was last set to
+    shown_title: String,
+    /// Mouse and keyboard input is dropped while the reconnect overlay is modal
+    input_blocked: bool,
+    /// Connecting to the server failed with an error that isn't retried
+    connect_failed: bool,
+    focused: bool,
     font_changed_last_frame: bool,
     saved_inner_size: dpi::PhysicalSize<u32>,
     saved_grid_size: Option<GridSize<u32>>,
@@ -98,14 +133,20 @@ pub struct WinitWindowWrapper {
     #[cfg(target_os = "macos")]
     pub macos_feature: Option<MacosWindowFeature>,
 
+    running_tracker: RunningTracker,
     settings: Arc<Settings>,
+    /// The runtime of the Neovim connection, for the work that would block the event loop
+    runtime: Handle,
 }
 
 impl WinitWindowWrapper {
     pub fn new(
         initial_window_size: WindowSize,
         initial_config: Config,
+        running_tracker: RunningTracker,
         settings: Arc<Settings>,
+        runtime: Handle,
+        proxy: EventLoopProxy<UserEvent>,
     ) -> Self {
         let saved_inner_size = Default::default();
         let renderer = Renderer::new(1.0, initial_config, settings.clone());
@@ -113,9 +154,14 @@ impl WinitWindowWrapper {
         Self {
             skia_renderer: None,
             renderer,
-            keyboard_manager: KeyboardManager::new(settings.clone()),
+            keyboard_manager: KeyboardManager::new(settings.clone(), proxy),
             mouse_manager: MouseManager::new(settings.clone()),
             title: String::from("Neovide"),
+            reconnect_title: None,
+            shown_title: String::new(),
+            input_blocked: false,
+            connect_failed: false,
+            focused: true,
             font_changed_last_frame: false,
             saved_inner_size,
             saved_grid_size: None,
@@ -135,7 +181,9 @@ impl WinitWindowWrapper {
             ime_area: Default::default(),
             #[cfg(target_os = "macos")]
             macos_feature: None,
+            running_tracker,
             settings,
+            runtime,
         }
     }
 
@@ -195,15 +243,106 @@ impl WinitWindowWrapper {
         }
     }
 
+    /// Blocks the input while reconnecting when `g:neovide_reconnect_modal` is set, otherwise the
+    /// last grid can still be scrolled and read.
+    fn block_input_while_reconnecting(&mut self) {
+        let modal = self.settings.get::<ReconnectIndicatorSettings>().modal;
+        self.input_blocked = modal;
+        self.keyboard_manager.set_input_blocked(modal);
+        self.keyboard_manager.set_reconnecting(true);
+    }
+
+    pub fn start_connect(&mut self, address: String) {
+        self.block_input_while_reconnecting();
+        self.renderer.start_connect(address);
+        if self.ui_state == UIState::Initing {
+            self.ui_state = UIState::WaitingForWindowCreate;
+        }
+    }
+
+    pub fn stabilize_connect(&mut self, address: String) {
+        self.block_input_while_reconnecting();
+        self.renderer.stabilize_connect(address);
+        if self.ui_state == UIState::Initing {
+            self.ui_state = UIState::WaitingForWindowCreate;
+        }
+    }
+
     pub fn start_reconnect(&mut self, address: String, wait: Duration) {
+        self.block_input_while_reconnecting();
         self.renderer.start_reconnect(address, wait);
         if self.ui_state == UIState::Initing {
             self.ui_state = UIState::WaitingForWindowCreate;
         }
     }
 
+    pub fn pause_reconnect(&mut self, address: String) {
+        self.block_input_while_reconnecting();
+        self.renderer.pause_reconnect(address);
+        if self.ui_state == UIState::Initing {
+            self.ui_state = UIState::WaitingForWindowCreate;
+        }
+    }
+
+    /// Shows that the idle timeout disconnected, the input isn't blocked so that it can reconnect.
+    pub fn idle_disconnect(&mut self, address: String) {
+        self.renderer.idle_disconnect(address);
+        if self.ui_state == UIState::Initing {
+            self.ui_state = UIState::WaitingForWindowCreate;
+        }
+    }
+
+    /// Shows that connecting failed for good, closing the window then quits right away.
+    pub fn fail_connect(&mut self, address: String, error: String) {
+        self.block_input_while_reconnecting();
+        self.renderer.fail_connect(address, error);
+        self.connect_failed = true;
+        if self.ui_state == UIState::Initing {
+            self.ui_state = UIState::WaitingForWindowCreate;
+        }
+    }
+
+    /// Whether there's no Neovim to ask about quitting, since connecting failed.
+    pub fn is_connect_failed(&self) -> bool {
+        self.connect_failed
+    }
+
     pub fn stop_reconnect(&mut self) {
+        self.input_blocked = false;
+        self.keyboard_manager.set_input_blocked(false);
+        self.keyboard_manager.set_reconnecting(false);
         self.renderer.stop_reconnect();
+        self.reconnect_title = None;
+        self.update_title();
+    }
+
+    pub fn handle_reconnect_title(&mut self, title: String) {
+        self.reconnect_title = Some(title);
+        self.update_title();
+    }
+
+    fn displayed_title(&self) -> String {
+        displayed_title(
+            self.settings
+                .get::<CmdLineSettings>()
+                .title_template
+                .as_deref(),
+            self.reconnect_title.as_deref(),
+            &self.title,
+        )
+    }
+
+    /// Sets the window title, but only when it changed, since the connection state changes on
+    /// every ping.
+    fn update_title(&mut self) {
+        let title = self.displayed_title();
+        if title == self.shown_title {
+            return;
+        }
+        if let Some(skia_renderer) = &self.skia_renderer {
+            skia_renderer.window().set_title(&title);
+            self.shown_title = title;
+        }
     }
 
     pub fn handle_window_command(&mut self, command: WindowCommand) {
@@ -226,6 +365,10 @@ impl WinitWindowWrapper {
             WindowCommand::ThemeChanged(new_theme) => {
                 self.handle_theme_changed(new_theme);
             }
+            WindowCommand::ResizeGrid(grid_size) => {
+                self.requested_columns = Some(grid_size.width);
+                self.requested_lines = Some(grid_size.height);
+            }
             #[cfg(windows)]
             WindowCommand::RegisterRightClick => register_right_click(),
             #[cfg(windows)]
@@ -317,9 +460,7 @@ impl WinitWindowWrapper {
 
     pub fn handle_title_changed(&mut self, new_title: String) {
         self.title = new_title;
-        if let Some(skia_renderer) = &self.skia_renderer {
-            skia_renderer.window().set_title(&self.title);
-        }
+        self.update_title();
     }
 
     pub fn handle_theme_changed(&mut self, new_theme: Option<Theme>) {
@@ -334,14 +475,16 @@ impl WinitWindowWrapper {
     }
 
     pub fn handle_quit(&mut self) {
-        send_ui(ParallelCommand::Quit);
+        quit_by_user(&self.running_tracker);
     }
 
     pub fn handle_focus_lost(&mut self) {
+        self.focused = false;
         send_ui(ParallelCommand::FocusLost);
     }
 
     pub fn handle_focus_gained(&mut self) {
+        self.focused = true;
         send_ui(ParallelCommand::FocusGained);
         // Got focus back after being minimized previously
         if self.is_minimized {
@@ -357,12 +500,17 @@ impl WinitWindowWrapper {
         let skia_renderer = self.skia_renderer.as_mut().unwrap();
         let vsync = self.vsync.as_mut().unwrap();
 
-        self.mouse_manager.handle_event(
-            &event,
-            &self.keyboard_manager,
-            &self.renderer,
-            skia_renderer.window(),
-        );
+        if is_user_input(&event) {
+            record_input();
+        }
+        if !self.input_blocked {
+            self.mouse_manager.handle_event(
+                &event,
+                &self.keyboard_manager,
+                &self.renderer,
+                skia_renderer.window(),
+            );
+        }
         self.keyboard_manager.handle_event(&event);
         self.renderer.handle_event(&event);
         let mut should_render = true;
@@ -408,6 +556,9 @@ impl WinitWindowWrapper {
             WindowEvent::Moved(_) => {
                 tracy_zone!("Moved");
                 vsync.update(skia_renderer.window());
+                let monitor = skia_renderer.window().current_monitor();
+                self.renderer
+                    .set_monitor(monitor.and_then(|monitor| monitor.name()));
             }
             WindowEvent::Ime(Ime::Enabled) => {
                 log::info!("Ime enabled");
@@ -440,15 +591,65 @@ impl WinitWindowWrapper {
             UserEvent::SettingsChanged(SettingsChanged::Renderer(e)) => {
                 self.handle_render_settings_changed(e);
             }
+            UserEvent::SettingsLoaded => {
+                self.renderer.handle_settings_loaded();
+            }
             UserEvent::ConfigsChanged(config) => {
                 self.handle_config_changed(*config);
             }
+            UserEvent::ConnectStart { address } => {
+                self.start_connect(address);
+            }
+            UserEvent::ConnectStabilizing { address } => {
+                self.stabilize_connect(address);
+            }
             UserEvent::ReconnectStart { address, wait } => {
                 self.start_reconnect(address, Duration::from_secs(wait));
             }
+            UserEvent::ReconnectPaused { address } => {
+                self.pause_reconnect(address);
+            }
+            UserEvent::IdleDisconnected { address } => {
+                self.idle_disconnect(address);
+            }
+            UserEvent::ConnectFailed { address, message } => {
+                self.fail_connect(address, message);
+            }
             UserEvent::ReconnectStop => {
                 self.stop_reconnect();
             }
+            UserEvent::ReconnectTitle(title) => {
+                self.handle_reconnect_title(title);
+            }
+            UserEvent::DesktopNotification { message, level } => {
+                desktop_notification::forward_notification(
+                    &self.settings,
+                    &message,
+                    level,
+                    self.focused,
+                    |message| desktop_notification::show(&self.runtime, message),
+                );
+            }
+            UserEvent::RpcError { message, .. } | UserEvent::Notice(message) => {
+                self.renderer.show_toast(message);
+            }
+            UserEvent::ConnectionStateChanged => {
+                self.update_title();
+            }
+            UserEvent::ReadOnlyChanged(read_only) => {
+                self.renderer.set_read_only(read_only);
+            }
+            UserEvent::Heartbeat { connected, rtt } => {
+                let rtt = rtt.map_or("unknown".to_owned(), |rtt| format!("{}ms", rtt.as_millis()));
+                log::info!("Heartbeat, connected: {connected}, rtt: {rtt}");
+            }
+            UserEvent::NeovimCapabilities(capabilities) => {
+                log::info!(
+                    "Attached to Neovim {} with UI options: {}",
+                    capabilities.version,
+                    capabilities.ui_options.join(", ")
+                );
+            }
             _ => {}
         }
     }
@@ -500,7 +701,8 @@ impl WinitWindowWrapper {
 
         let maximized = matches!(self.initial_window_size, WindowSize::Maximized);
 
-        let window_config = create_window(event_loop, maximized, &self.title, &self.settings);
+        self.shown_title = self.displayed_title();
+        let window_config = create_window(event_loop, maximized, &self.shown_title, &self.settings);
         let window = &window_config.window;
 
         let WindowSettings {
@@ -639,6 +841,9 @@ impl WinitWindowWrapper {
             }
         }
 
+        self.renderer
+            .set_monitor(window.current_monitor().and_then(|monitor| monitor.name()));
+
         self.vsync = Some(VSync::new(
             vsync_enabled,
             skia_renderer.as_ref(),
@@ -923,3 +1128,58 @@ impl WinitWindowWrapper {
         }
     }
 }
+
+/// The title of the window, the reconnect title while reconnecting, and otherwise the Neovim
+/// `title` through the `--title-template`, if there is one.
+fn displayed_title(template: Option<&str>, reconnect_title: Option<&str>, title: &str) -> String {
+    displayed_title_of(template, reconnect_title, title, &connection_state())
+}
+
+fn displayed_title_of(
+    template: Option<&str>,
+    reconnect_title: Option<&str>,
+    title: &str,
+    state: &ConnectionState,
+) -> String {
+    match (reconnect_title, template) {
+        (Some(reconnect_title), _) => reconnect_title.to_owned(),
+        (None, Some(template)) => format_title(template, state, title),
+        (None, None) => title.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_title_takes_precedence_over_the_template() {
+        let state = ConnectionState::default();
+        assert_eq!(
+            displayed_title_of(Some("{title} [{address}]"), None, "init.lua", &state),
+            "init.lua [embedded]"
+        );
+        assert_eq!(
+            displayed_title_of(
+                Some("{title} [{address}]"),
+                Some("Reconnecting to localhost:6666 (3s)"),
+                "init.lua",
+                &state
+            ),
+            "Reconnecting to localhost:6666 (3s)"
+        );
+        assert_eq!(
+            displayed_title_of(None, None, "init.lua", &state),
+            "init.lua"
+        );
+    }
+
+    #[test]
+    fn test_closing_the_window_is_a_user_quit() {
+        let running_tracker = RunningTracker::new();
+        quit_by_user(&running_tracker);
+        // Neovim quitting because of it doesn't replace the reason
+        running_tracker.set_quit_reason(QuitReason::NvimRequested);
+        assert_eq!(running_tracker.quit_reason(), Some(QuitReason::UserClosed));
+    }
+}